@@ -74,7 +74,8 @@ document.querySelectorAll("[data-consumable-select-dialog]").forEach((dialog) =>
     const value = searchInput.value.toLowerCase().trim();
     let lastVisible = null;
     dialog.querySelectorAll(".option").forEach((item) => {
-      const text = item.textContent.toLowerCase();
+      const aliases = item.getAttribute("data-consumable-aliases") ?? "";
+      const text = `${item.textContent} ${aliases}`.toLowerCase();
       item.classList.remove("filtered-last-child");
       if (text.includes(value)) {
         item.removeAttribute("hidden");
@@ -106,6 +107,20 @@ document.querySelectorAll("[data-consumable-select-dialog]").forEach((dialog) =>
       const opener = document.querySelector("[data-consumable-select-open]");
       opener.setAttribute('href', consumableUrl);
 
+      const servingSelect = document.querySelector("[data-serving-select]");
+      if (servingSelect) {
+        const foodId = consumableType === "food" ? consumableId : null;
+        const defaultOption = servingSelect.querySelector("[data-serving-default]");
+        defaultOption.textContent = option.getAttribute("data-consumable-unit");
+        servingSelect.querySelectorAll("[data-food-id]").forEach((servingOption) => {
+          const matches = servingOption.getAttribute("data-food-id") === foodId;
+          servingOption.toggleAttribute("hidden", !matches);
+          if (!matches && servingOption.selected) {
+            servingSelect.value = "";
+          }
+        });
+      }
+
       dialog.close();
     });
   });
@@ -151,6 +166,44 @@ document.querySelectorAll("[data-consumable-select-dialog]").forEach((dialog) =>
   });
 });
 
+document.querySelectorAll("[data-barcode-lookup]").forEach((button) => {
+  const form = button.closest("form");
+  const status = form.querySelector("[data-barcode-status]");
+  button.addEventListener("click", async () => {
+    const code = form.elements["barcode"].value.trim();
+    if (!code) {
+      return;
+    }
+
+    status.textContent = "";
+    const response = await fetch(`/foods/barcode/${encodeURIComponent(code)}`);
+    if (response.status === 404) {
+      status.textContent = "No product found.";
+      return;
+    }
+    if (!response.ok) {
+      status.textContent = "Invalid barcode.";
+      return;
+    }
+
+    const product = await response.json();
+    if (product.food_id) {
+      window.location.href = `/foods/${product.food_id}`;
+      return;
+    }
+    Object.entries(product).forEach(([name, value]) => {
+      const input = form.elements[name];
+      if (input) {
+        input.value = value ?? "";
+      }
+    });
+    const nutrients = form.querySelector(".nutrient-inputs");
+    if (nutrients && [...nutrients.querySelectorAll("input")].some((input) => input.value)) {
+      nutrients.open = true;
+    }
+  });
+});
+
 if ("serviceWorker" in navigator) {
   navigator.serviceWorker.register("/assets/service-worker.js");
 }
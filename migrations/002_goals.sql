@@ -0,0 +1,15 @@
+create table goals (
+    id text not null,
+    user_id text not null,
+    weekday integer default null,
+    kcal real not null,
+    fat real not null,
+    carbs real not null,
+    protein real not null,
+    created_at datetime not null,
+    updated_at datetime not null,
+    primary key (id),
+    foreign key (user_id) references users(id)
+);
+
+create unique index goals_user_id_weekday on goals (user_id, coalesce(weekday, -1));
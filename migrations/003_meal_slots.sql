@@ -0,0 +1,30 @@
+create table meal_slots (
+    id text not null,
+    user_id text not null,
+    name text not null,
+    position integer not null,
+    created_at datetime not null,
+    updated_at datetime not null,
+    primary key (id),
+    foreign key (user_id) references users(id)
+);
+
+alter table consumptions add column meal_slot_id text default null references meal_slots(id);
+
+insert into meal_slots (id, user_id, name, position, created_at, updated_at)
+select
+    lower(hex(randomblob(16))),
+    u.id,
+    s.name,
+    s.position,
+    datetime(),
+    datetime()
+from
+    users u
+cross join
+    (
+        select 'Breakfast' as name, 1 as position
+        union all select 'Lunch', 2
+        union all select 'Dinner', 3
+        union all select 'Snacks', 4
+    ) s;
@@ -0,0 +1,22 @@
+create table ingredients_new (
+    id text not null,
+    user_id text not null,
+    recipe_id text not null,
+    food_id text default null,
+    sub_recipe_id text default null,
+    quantity real not null,
+    created_at datetime not null,
+    updated_at datetime not null,
+    primary key (id),
+    foreign key (user_id) references users(id),
+    foreign key (recipe_id) references recipes(id),
+    foreign key (food_id) references foods(id),
+    foreign key (sub_recipe_id) references recipes(id)
+);
+
+insert into ingredients_new (id, user_id, recipe_id, food_id, quantity, created_at, updated_at)
+select id, user_id, recipe_id, food_id, quantity, created_at, updated_at from ingredients;
+
+drop table ingredients;
+
+alter table ingredients_new rename to ingredients;
@@ -0,0 +1,11 @@
+create table api_tokens (
+    id text not null,
+    user_id text not null,
+    name text not null,
+    token_hash text not null,
+    last_used_at datetime default null,
+    created_at datetime not null,
+    primary key (id),
+    unique (token_hash),
+    foreign key (user_id) references users(id)
+);
@@ -0,0 +1,3 @@
+create index consumptions_user_id_consumed_at on consumptions (user_id, consumed_at);
+
+create index weights_user_id_measured_at on weights (user_id, measured_at);
@@ -0,0 +1,21 @@
+create index consumptions_food_id_consumed_at on consumptions (food_id, consumed_at);
+
+create index consumptions_recipe_id_consumed_at on consumptions (recipe_id, consumed_at);
+
+create index consumptions_meal_slot_id on consumptions (meal_slot_id);
+
+create index foods_user_id on foods (user_id);
+
+create index recipes_user_id on recipes (user_id);
+
+create index ingredients_recipe_id on ingredients (recipe_id);
+
+create index ingredients_food_id on ingredients (food_id);
+
+create index ingredients_sub_recipe_id on ingredients (sub_recipe_id);
+
+create index meal_slots_user_id on meal_slots (user_id);
+
+create index goals_user_id on goals (user_id);
+
+create index sessions_created_at on sessions (created_at);
@@ -0,0 +1,22 @@
+alter table foods add column unit text not null default 'g';
+
+alter table foods add column basis real not null default 1;
+
+create table servings (
+    id text not null,
+    user_id text not null,
+    food_id text not null,
+    name text not null,
+    amount real not null,
+    created_at datetime not null,
+    updated_at datetime not null,
+    primary key (id),
+    foreign key (user_id) references users(id),
+    foreign key (food_id) references foods(id)
+);
+
+create index servings_food_id on servings (food_id);
+
+alter table consumptions add column serving_id text default null references servings(id);
+
+alter table ingredients add column serving_id text default null references servings(id);
@@ -0,0 +1,19 @@
+alter table foods add column fiber real default null;
+
+alter table foods add column sugar real default null;
+
+alter table foods add column saturated_fat real default null;
+
+alter table foods add column salt real default null;
+
+alter table foods add column alcohol real default null;
+
+alter table foods add column potassium real default null;
+
+alter table foods add column calcium real default null;
+
+alter table foods add column iron real default null;
+
+alter table foods add column vitamin_c real default null;
+
+alter table users add column nutrients text not null default '';
@@ -0,0 +1,22 @@
+alter table foods add column barcode text default null;
+
+create index foods_user_id_barcode on foods (user_id, barcode);
+
+create table products (
+    barcode text not null,
+    name text not null,
+    kcal real not null,
+    fat real not null,
+    carbs real not null,
+    protein real not null,
+    fiber real default null,
+    sugar real default null,
+    saturated_fat real default null,
+    salt real default null,
+    alcohol real default null,
+    potassium real default null,
+    calcium real default null,
+    iron real default null,
+    vitamin_c real default null,
+    primary key (barcode)
+);
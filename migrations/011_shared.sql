@@ -0,0 +1,7 @@
+alter table foods add column shared_at datetime default null;
+
+alter table recipes add column shared_at datetime default null;
+
+create index foods_shared_at on foods (shared_at, id);
+
+create index recipes_shared_at on recipes (shared_at, id);
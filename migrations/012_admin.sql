@@ -0,0 +1,5 @@
+alter table users add column is_admin boolean not null default false;
+
+alter table users add column disabled_at datetime default null;
+
+update users set is_admin = true where id = (select id from users order by created_at limit 1);
@@ -0,0 +1,5 @@
+alter table sessions add column last_seen_at datetime default null;
+
+alter table sessions add column user_agent text default null;
+
+create index sessions_user_id on sessions (user_id);
@@ -0,0 +1,7 @@
+create table login_attempts (
+    key text not null,
+    failures integer not null,
+    last_failed_at datetime not null,
+    locked_until datetime default null,
+    primary key (key)
+);
@@ -0,0 +1,9 @@
+alter table sessions add column remember boolean not null default false;
+
+update sessions set remember = true;
+
+alter table sessions add column rotated_at datetime default null;
+
+alter table sessions add column previous_id text default null;
+
+create index sessions_previous_id on sessions (previous_id);
@@ -0,0 +1,13 @@
+create table food_aliases (
+    id text not null,
+    user_id text not null,
+    food_id text not null,
+    name text not null,
+    created_at datetime not null,
+    updated_at datetime not null,
+    primary key (id),
+    foreign key (user_id) references users(id),
+    foreign key (food_id) references foods(id)
+);
+
+create index food_aliases_food_id on food_aliases (food_id);
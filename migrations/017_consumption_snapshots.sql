@@ -0,0 +1,144 @@
+alter table consumptions add column unit_kcal real default null;
+
+alter table consumptions add column unit_fat real default null;
+
+alter table consumptions add column unit_carbs real default null;
+
+alter table consumptions add column unit_protein real default null;
+
+alter table consumptions add column unit_fiber real default null;
+
+alter table consumptions add column unit_sugar real default null;
+
+alter table consumptions add column unit_saturated_fat real default null;
+
+alter table consumptions add column unit_salt real default null;
+
+alter table consumptions add column unit_alcohol real default null;
+
+alter table consumptions add column unit_potassium real default null;
+
+alter table consumptions add column unit_calcium real default null;
+
+alter table consumptions add column unit_iron real default null;
+
+alter table consumptions add column unit_vitamin_c real default null;
+
+with recursive
+    recipe_tree (root_id, recipe_id, factor, depth) as (
+        select id, id, 1.0, 0 from recipes
+        union all
+        select
+            t.root_id,
+            i.sub_recipe_id,
+            t.factor * i.quantity / sr.quantity,
+            t.depth + 1
+        from
+            recipe_tree t
+        join
+            ingredients i
+                on
+                    t.recipe_id = i.recipe_id
+        join
+            recipes sr
+                on
+                    i.sub_recipe_id = sr.id
+        where
+            t.depth < 16
+    ),
+    recipe_macros (
+        recipe_id,
+        kcal,
+        fat,
+        carbs,
+        protein,
+        fiber,
+        sugar,
+        saturated_fat,
+        salt,
+        alcohol,
+        potassium,
+        calcium,
+        iron,
+        vitamin_c
+    ) as (
+        select
+            t.root_id,
+            sum(f.kcal / f.basis * i.quantity * coalesce(s.amount, 1.0) * t.factor),
+            sum(f.fat / f.basis * i.quantity * coalesce(s.amount, 1.0) * t.factor),
+            sum(f.carbs / f.basis * i.quantity * coalesce(s.amount, 1.0) * t.factor),
+            sum(f.protein / f.basis * i.quantity * coalesce(s.amount, 1.0) * t.factor),
+            sum(f.fiber / f.basis * i.quantity * coalesce(s.amount, 1.0) * t.factor),
+            sum(f.sugar / f.basis * i.quantity * coalesce(s.amount, 1.0) * t.factor),
+            sum(f.saturated_fat / f.basis * i.quantity * coalesce(s.amount, 1.0) * t.factor),
+            sum(f.salt / f.basis * i.quantity * coalesce(s.amount, 1.0) * t.factor),
+            sum(f.alcohol / f.basis * i.quantity * coalesce(s.amount, 1.0) * t.factor),
+            sum(f.potassium / f.basis * i.quantity * coalesce(s.amount, 1.0) * t.factor),
+            sum(f.calcium / f.basis * i.quantity * coalesce(s.amount, 1.0) * t.factor),
+            sum(f.iron / f.basis * i.quantity * coalesce(s.amount, 1.0) * t.factor),
+            sum(f.vitamin_c / f.basis * i.quantity * coalesce(s.amount, 1.0) * t.factor)
+        from
+            recipe_tree t
+        join
+            ingredients i
+                on
+                    t.recipe_id = i.recipe_id
+        join
+            foods f
+                on
+                    i.food_id = f.id
+        left join
+            servings s
+                on
+                    i.serving_id = s.id
+        group by
+            t.root_id
+    )
+update consumptions
+set
+    unit_kcal = v.kcal,
+    unit_fat = v.fat,
+    unit_carbs = v.carbs,
+    unit_protein = v.protein,
+    unit_fiber = v.fiber,
+    unit_sugar = v.sugar,
+    unit_saturated_fat = v.saturated_fat,
+    unit_salt = v.salt,
+    unit_alcohol = v.alcohol,
+    unit_potassium = v.potassium,
+    unit_calcium = v.calcium,
+    unit_iron = v.iron,
+    unit_vitamin_c = v.vitamin_c
+from (
+    select
+        c.id,
+        coalesce(f.kcal / f.basis, m.kcal / r.quantity, 0.0) as kcal,
+        coalesce(f.fat / f.basis, m.fat / r.quantity, 0.0) as fat,
+        coalesce(f.carbs / f.basis, m.carbs / r.quantity, 0.0) as carbs,
+        coalesce(f.protein / f.basis, m.protein / r.quantity, 0.0) as protein,
+        coalesce(f.fiber / f.basis, m.fiber / r.quantity) as fiber,
+        coalesce(f.sugar / f.basis, m.sugar / r.quantity) as sugar,
+        coalesce(f.saturated_fat / f.basis, m.saturated_fat / r.quantity) as saturated_fat,
+        coalesce(f.salt / f.basis, m.salt / r.quantity) as salt,
+        coalesce(f.alcohol / f.basis, m.alcohol / r.quantity) as alcohol,
+        coalesce(f.potassium / f.basis, m.potassium / r.quantity) as potassium,
+        coalesce(f.calcium / f.basis, m.calcium / r.quantity) as calcium,
+        coalesce(f.iron / f.basis, m.iron / r.quantity) as iron,
+        coalesce(f.vitamin_c / f.basis, m.vitamin_c / r.quantity) as vitamin_c
+    from
+        consumptions c
+    left join
+        foods f
+            on
+                c.food_id = f.id
+    left join
+        recipes r
+            on
+                c.recipe_id = r.id
+    left join
+        recipe_macros m
+            on
+                r.id = m.recipe_id
+) v
+where
+    consumptions.id = v.id;
@@ -0,0 +1 @@
+alter table users add column weight_rule text not null default 'first';
@@ -0,0 +1,3 @@
+alter table sessions add column csrf_secret text not null default '';
+
+update sessions set csrf_secret = lower(hex(randomblob(32)));
@@ -0,0 +1,1001 @@
+//! JSON API under `/api/v1`. Requests authenticate with an API token that
+//! is sent as `Authorization: Bearer <token>`. Tokens are managed on the
+//! account page.
+
+use axum::extract::rejection::{JsonRejection, PathRejection, QueryRejection};
+use axum::extract::{FromRequest, FromRequestParts, Path, Query, State};
+use axum::http::{header, request::Parts, StatusCode};
+use axum::response::{IntoResponse, Response};
+use axum::{routing, Json, Router};
+
+use crate::db::{
+    self, goal_for_date, ConsumptionFilter, ConsumptionParams, EntryFlags, FoodParams, Macros,
+    UserId,
+};
+use crate::handler::{
+    authz, barcode, barcode_lookup, check_amount, check_food_unit, consumption_consumable,
+    daily_weights, ingredient_consumable, meal_slot_id, merge_food, serving_id, visible,
+};
+use crate::nutrients::Nutrients;
+use crate::{AppError, AppState};
+
+pub fn router() -> Router<AppState> {
+    Router::new()
+        .route("/days/{date}", routing::get(days_read))
+        .route("/weights", routing::get(weights_index).post(weights_create))
+        .route(
+            "/weights/{id}",
+            routing::get(weights_read)
+                .put(weights_update)
+                .delete(weights_delete),
+        )
+        .route("/foods", routing::get(foods_index).post(foods_create))
+        .route("/foods/barcode/{code}", routing::get(foods_barcode_read))
+        .route(
+            "/foods/{id}",
+            routing::get(foods_read)
+                .put(foods_update)
+                .delete(foods_delete),
+        )
+        .route("/foods/{id}/clone", routing::post(foods_clone))
+        .route("/foods/{id}/merge", routing::post(foods_merge))
+        .route(
+            "/foods/{id}/servings",
+            routing::get(servings_index).post(servings_create),
+        )
+        .route(
+            "/servings/{id}",
+            routing::get(servings_read).delete(servings_delete),
+        )
+        .route("/recipes", routing::get(recipes_index).post(recipes_create))
+        .route(
+            "/recipes/{id}",
+            routing::get(recipes_read)
+                .put(recipes_update)
+                .delete(recipes_delete),
+        )
+        .route("/recipes/{id}/clone", routing::post(recipes_clone))
+        .route(
+            "/recipes/{id}/ingredients",
+            routing::get(ingredients_index).post(ingredients_create),
+        )
+        .route(
+            "/ingredients/{id}",
+            routing::get(ingredients_read)
+                .put(ingredients_update)
+                .delete(ingredients_delete),
+        )
+        .route(
+            "/consumptions",
+            routing::get(consumptions_index).post(consumptions_create),
+        )
+        .route(
+            "/consumptions/{id}",
+            routing::get(consumptions_read)
+                .put(consumptions_update)
+                .delete(consumptions_delete),
+        )
+        .route(
+            "/consumptions/{id}/recalculate",
+            routing::post(consumptions_recalculate),
+        )
+        .route("/meal-slots", routing::get(meal_slots_index))
+        .fallback(not_found)
+}
+
+async fn not_found() -> ApiError {
+    AppError::NotFound.into()
+}
+
+pub struct ApiSession {
+    user_id: UserId,
+}
+
+impl FromRequestParts<AppState> for ApiSession {
+    type Rejection = ApiError;
+
+    async fn from_request_parts(
+        parts: &mut Parts,
+        state: &AppState,
+    ) -> Result<Self, Self::Rejection> {
+        let token = parts
+            .headers
+            .get(header::AUTHORIZATION)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.strip_prefix("Bearer "))
+            .ok_or(AppError::InvalidApiToken)?;
+
+        match state.db.get_api_token_user_id(token.trim()).await? {
+            Some(user_id) => Ok(ApiSession {
+                user_id: UserId(user_id),
+            }),
+            None => Err(AppError::InvalidApiToken.into()),
+        }
+    }
+}
+
+/// Wraps [`AppError`] so that API clients get a JSON error with a matching
+/// status code instead of an HTML page or a redirect to the login form.
+pub struct ApiError(AppError);
+
+impl<E: Into<AppError>> From<E> for ApiError {
+    fn from(e: E) -> Self {
+        ApiError(e.into())
+    }
+}
+
+impl IntoResponse for ApiError {
+    fn into_response(self) -> Response {
+        let status = self.0.status();
+        let message = match self.0 {
+            AppError::NoSessionCookie | AppError::UnknownSessionId => {
+                AppError::InvalidApiToken.message()
+            }
+            e => e.message(),
+        };
+
+        let mut response = (status, Json(serde_json::json!({ "error": message }))).into_response();
+        if status == StatusCode::UNAUTHORIZED {
+            response
+                .headers_mut()
+                .insert(header::WWW_AUTHENTICATE, "Bearer".parse().unwrap());
+        }
+        response
+    }
+}
+
+/// Bodies that can't be read are the API's version of an invalid form.
+impl From<JsonRejection> for AppError {
+    fn from(_: JsonRejection) -> Self {
+        AppError::InvalidForm
+    }
+}
+
+impl From<QueryRejection> for AppError {
+    fn from(_: QueryRejection) -> Self {
+        AppError::InvalidForm
+    }
+}
+
+/// A path segment that doesn't parse, e.g. an invalid date, names nothing.
+impl From<PathRejection> for AppError {
+    fn from(_: PathRejection) -> Self {
+        AppError::NotFound
+    }
+}
+
+/// [`Json`] that is rejected with an [`ApiError`] instead of plain text.
+#[derive(FromRequest)]
+#[from_request(via(Json), rejection(ApiError))]
+struct ApiJson<T>(T);
+
+/// [`Path`] that is rejected with an [`ApiError`] instead of plain text.
+#[derive(FromRequestParts)]
+#[from_request(via(Path), rejection(ApiError))]
+struct ApiPath<T>(T);
+
+/// [`Query`] that is rejected with an [`ApiError`] instead of plain text.
+#[derive(FromRequestParts)]
+#[from_request(via(Query), rejection(ApiError))]
+struct ApiQuery<T>(T);
+
+type ApiResult = Result<Response, ApiError>;
+
+fn created(body: impl serde::Serialize) -> ApiResult {
+    Ok((StatusCode::CREATED, Json(body)).into_response())
+}
+
+fn json(body: impl serde::Serialize) -> ApiResult {
+    Ok(Json(body).into_response())
+}
+
+fn no_content() -> ApiResult {
+    Ok(StatusCode::NO_CONTENT.into_response())
+}
+
+#[derive(serde::Serialize)]
+struct DaySummary {
+    date: chrono::NaiveDate,
+    /// Weight of the day by the user's rule, see [`crate::trend::WeightRule`].
+    weight: Option<f64>,
+    /// All weigh-ins of the day.
+    weights: Vec<db::WeightWithDate>,
+    total: Macros,
+    total_nutrients: Nutrients,
+    goal: Option<db::Goal>,
+    consumptions: Vec<db::Consumption>,
+}
+
+async fn days_read(
+    State(state): State<AppState>,
+    session: ApiSession,
+    ApiPath(date): ApiPath<chrono::NaiveDate>,
+) -> ApiResult {
+    let weights = state
+        .db
+        .get_weights_in_range(&session.user_id, date..=date)
+        .await?;
+    let rule = state.db.get_weight_rule(&session.user_id).await?;
+    let consumptions = state
+        .db
+        .get_consumptions(&session.user_id, ConsumptionFilter::ConsumedAt(date))
+        .await?;
+    let goals = state.db.get_goals(&session.user_id).await?;
+    let goal = goal_for_date(&goals, date).cloned();
+
+    let total = state
+        .db
+        .get_daily_totals(&session.user_id, date..=date)
+        .await?
+        .pop();
+
+    json(DaySummary {
+        date,
+        weight: daily_weights(&weights, rule)
+            .pop()
+            .map(|(_, weight)| weight),
+        weights,
+        total: total.as_ref().map(|t| t.macros()).unwrap_or_default(),
+        total_nutrients: total.map(|t| t.nutrients).unwrap_or_default(),
+        goal,
+        consumptions,
+    })
+}
+
+#[derive(serde::Deserialize)]
+struct WeightBody {
+    weight: f64,
+    measured_at: chrono::NaiveDate,
+    measured_time: Option<chrono::NaiveTime>,
+}
+
+async fn weights_index(State(state): State<AppState>, session: ApiSession) -> ApiResult {
+    json(state.db.get_weights(&session.user_id).await?)
+}
+
+async fn weights_create(
+    State(state): State<AppState>,
+    session: ApiSession,
+    ApiJson(body): ApiJson<WeightBody>,
+) -> ApiResult {
+    let id = state
+        .db
+        .add_weight(
+            &session.user_id,
+            body.weight,
+            &body.measured_at,
+            body.measured_time,
+        )
+        .await?;
+
+    created(state.db.get_weight(&id).await?)
+}
+
+async fn weights_read(
+    State(state): State<AppState>,
+    session: ApiSession,
+    ApiPath(id): ApiPath<String>,
+) -> ApiResult {
+    let weight = state.db.get_weight(&id).await?;
+
+    if !authz(&session.user_id, &weight.user_id) {
+        return Err(AppError::Forbidden.into());
+    }
+
+    json(weight)
+}
+
+async fn weights_update(
+    State(state): State<AppState>,
+    session: ApiSession,
+    ApiPath(id): ApiPath<String>,
+    ApiJson(body): ApiJson<WeightBody>,
+) -> ApiResult {
+    let weight = state.db.get_weight(&id).await?;
+
+    if !authz(&session.user_id, &weight.user_id) {
+        return Err(AppError::Forbidden.into());
+    }
+
+    state
+        .db
+        .update_weight(&id, body.weight, &body.measured_at, body.measured_time)
+        .await?;
+
+    json(state.db.get_weight(&id).await?)
+}
+
+async fn weights_delete(
+    State(state): State<AppState>,
+    session: ApiSession,
+    ApiPath(id): ApiPath<String>,
+) -> ApiResult {
+    let weight = state.db.get_weight(&id).await?;
+
+    if !authz(&session.user_id, &weight.user_id) {
+        return Err(AppError::Forbidden.into());
+    }
+
+    state.db.delete_weight(&id).await?;
+
+    no_content()
+}
+
+#[derive(serde::Deserialize)]
+struct FoodBody {
+    name: String,
+    kcal: f64,
+    fat: f64,
+    carbs: f64,
+    protein: f64,
+    #[serde(flatten)]
+    nutrients: Nutrients,
+    #[serde(default = "default_unit")]
+    unit: String,
+    #[serde(default = "default_basis")]
+    basis: f64,
+    #[serde(default)]
+    barcode: Option<String>,
+    #[serde(default)]
+    hidden: bool,
+    #[serde(default)]
+    starred: bool,
+    #[serde(default)]
+    shared: bool,
+}
+
+impl FoodBody {
+    fn macros(&self) -> Macros {
+        Macros {
+            kcal: self.kcal,
+            fat: self.fat,
+            carbs: self.carbs,
+            protein: self.protein,
+        }
+    }
+}
+
+fn default_unit() -> String {
+    "g".to_string()
+}
+
+fn default_basis() -> f64 {
+    1.0
+}
+
+async fn foods_index(State(state): State<AppState>, session: ApiSession) -> ApiResult {
+    json(state.db.get_foods(&session.user_id).await?)
+}
+
+async fn foods_create(
+    State(state): State<AppState>,
+    session: ApiSession,
+    ApiJson(body): ApiJson<FoodBody>,
+) -> ApiResult {
+    check_food_unit(&body.unit)?;
+    check_amount(body.basis)?;
+    let barcode = barcode(body.barcode.as_deref())?;
+
+    let id = state
+        .db
+        .add_food(
+            &session.user_id,
+            FoodParams {
+                name: &body.name,
+                macros: body.macros(),
+                nutrients: body.nutrients,
+                unit: &body.unit,
+                basis: body.basis,
+                barcode: barcode.as_deref(),
+                flags: EntryFlags {
+                    hidden: body.hidden,
+                    starred: body.starred,
+                    shared: body.shared,
+                },
+            },
+        )
+        .await?;
+
+    created(state.db.get_food(&id).await?)
+}
+
+async fn foods_barcode_read(
+    State(state): State<AppState>,
+    session: ApiSession,
+    ApiPath(code): ApiPath<String>,
+) -> ApiResult {
+    json(barcode_lookup(&state, &session.user_id, &code).await?)
+}
+
+async fn foods_read(
+    State(state): State<AppState>,
+    session: ApiSession,
+    ApiPath(id): ApiPath<String>,
+) -> ApiResult {
+    let food = state.db.get_food(&id).await?;
+
+    if !visible(&session.user_id, &food.user_id, &food.shared_at) {
+        return Err(AppError::Forbidden.into());
+    }
+
+    json(food)
+}
+
+async fn foods_update(
+    State(state): State<AppState>,
+    session: ApiSession,
+    ApiPath(id): ApiPath<String>,
+    ApiJson(body): ApiJson<FoodBody>,
+) -> ApiResult {
+    let food = state.db.get_food(&id).await?;
+
+    if !authz(&session.user_id, &food.user_id) {
+        return Err(AppError::Forbidden.into());
+    }
+
+    check_food_unit(&body.unit)?;
+    check_amount(body.basis)?;
+    let barcode = barcode(body.barcode.as_deref())?;
+
+    state
+        .db
+        .update_food(
+            &id,
+            FoodParams {
+                name: &body.name,
+                macros: body.macros(),
+                nutrients: body.nutrients,
+                unit: &body.unit,
+                basis: body.basis,
+                barcode: barcode.as_deref(),
+                flags: EntryFlags {
+                    hidden: body.hidden,
+                    starred: body.starred,
+                    shared: body.shared,
+                },
+            },
+        )
+        .await?;
+
+    json(state.db.get_food(&id).await?)
+}
+
+async fn foods_delete(
+    State(state): State<AppState>,
+    session: ApiSession,
+    ApiPath(id): ApiPath<String>,
+) -> ApiResult {
+    let food = state.db.get_food(&id).await?;
+
+    if !authz(&session.user_id, &food.user_id) {
+        return Err(AppError::Forbidden.into());
+    }
+
+    if !state
+        .db
+        .get_food_references(&session.user_id, &id)
+        .await?
+        .is_empty()
+    {
+        return Err(AppError::InUse.into());
+    }
+
+    state.db.delete_food(&id).await?;
+
+    no_content()
+}
+
+async fn foods_clone(
+    State(state): State<AppState>,
+    session: ApiSession,
+    ApiPath(id): ApiPath<String>,
+) -> ApiResult {
+    let food = state.db.get_food(&id).await?;
+
+    if !visible(&session.user_id, &food.user_id, &food.shared_at) {
+        return Err(AppError::Forbidden.into());
+    }
+
+    let id = state.db.clone_food(&session.user_id, &id).await?;
+
+    created(state.db.get_food(&id).await?)
+}
+
+#[derive(serde::Deserialize)]
+struct MergeFoodBody {
+    into_id: String,
+    #[serde(default)]
+    keep_alias: bool,
+}
+
+/// Merges the food into `into_id` and returns the merged food.
+async fn foods_merge(
+    State(state): State<AppState>,
+    session: ApiSession,
+    ApiPath(id): ApiPath<String>,
+    ApiJson(body): ApiJson<MergeFoodBody>,
+) -> ApiResult {
+    merge_food(
+        &state,
+        &session.user_id,
+        &id,
+        &body.into_id,
+        body.keep_alias,
+    )
+    .await?;
+
+    json(state.db.get_food(&body.into_id).await?)
+}
+
+#[derive(serde::Deserialize)]
+struct ServingBody {
+    name: String,
+    amount: f64,
+}
+
+async fn servings_index(
+    State(state): State<AppState>,
+    session: ApiSession,
+    ApiPath(food_id): ApiPath<String>,
+) -> ApiResult {
+    let food = state.db.get_food(&food_id).await?;
+
+    if !visible(&session.user_id, &food.user_id, &food.shared_at) {
+        return Err(AppError::Forbidden.into());
+    }
+
+    json(state.db.get_servings(&food.id).await?)
+}
+
+async fn servings_create(
+    State(state): State<AppState>,
+    session: ApiSession,
+    ApiPath(food_id): ApiPath<String>,
+    ApiJson(body): ApiJson<ServingBody>,
+) -> ApiResult {
+    let food = state.db.get_food(&food_id).await?;
+
+    if !authz(&session.user_id, &food.user_id) {
+        return Err(AppError::Forbidden.into());
+    }
+
+    check_amount(body.amount)?;
+
+    let id = state
+        .db
+        .add_serving(&session.user_id, &food.id, &body.name, body.amount)
+        .await?;
+
+    created(state.db.get_serving(&id).await?)
+}
+
+async fn servings_read(
+    State(state): State<AppState>,
+    session: ApiSession,
+    ApiPath(id): ApiPath<String>,
+) -> ApiResult {
+    let serving = state.db.get_serving(&id).await?;
+
+    if !authz(&session.user_id, &serving.user_id) {
+        return Err(AppError::Forbidden.into());
+    }
+
+    json(serving)
+}
+
+async fn servings_delete(
+    State(state): State<AppState>,
+    session: ApiSession,
+    ApiPath(id): ApiPath<String>,
+) -> ApiResult {
+    let serving = state.db.get_serving(&id).await?;
+
+    if !authz(&session.user_id, &serving.user_id) {
+        return Err(AppError::Forbidden.into());
+    }
+
+    state.db.delete_serving(&id).await?;
+
+    no_content()
+}
+
+#[derive(serde::Deserialize)]
+struct RecipeBody {
+    name: String,
+    quantity: f64,
+    #[serde(default)]
+    hidden: bool,
+    #[serde(default)]
+    starred: bool,
+    #[serde(default)]
+    shared: bool,
+}
+
+async fn recipes_index(State(state): State<AppState>, session: ApiSession) -> ApiResult {
+    json(state.db.get_recipes(&session.user_id).await?)
+}
+
+async fn recipes_create(
+    State(state): State<AppState>,
+    session: ApiSession,
+    ApiJson(body): ApiJson<RecipeBody>,
+) -> ApiResult {
+    let id = state
+        .db
+        .add_recipe(
+            &session.user_id,
+            &body.name,
+            body.quantity,
+            EntryFlags {
+                hidden: body.hidden,
+                starred: body.starred,
+                shared: body.shared,
+            },
+        )
+        .await?;
+
+    created(state.db.get_recipe(&id).await?)
+}
+
+async fn recipes_read(
+    State(state): State<AppState>,
+    session: ApiSession,
+    ApiPath(id): ApiPath<String>,
+) -> ApiResult {
+    let recipe = state.db.get_recipe(&id).await?;
+
+    if !visible(&session.user_id, &recipe.user_id, &recipe.shared_at) {
+        return Err(AppError::Forbidden.into());
+    }
+
+    json(recipe)
+}
+
+async fn recipes_update(
+    State(state): State<AppState>,
+    session: ApiSession,
+    ApiPath(id): ApiPath<String>,
+    ApiJson(body): ApiJson<RecipeBody>,
+) -> ApiResult {
+    let recipe = state.db.get_recipe(&id).await?;
+
+    if !authz(&session.user_id, &recipe.user_id) {
+        return Err(AppError::Forbidden.into());
+    }
+
+    state
+        .db
+        .update_recipe(
+            &id,
+            &body.name,
+            body.quantity,
+            EntryFlags {
+                hidden: body.hidden,
+                starred: body.starred,
+                shared: body.shared,
+            },
+        )
+        .await?;
+
+    json(state.db.get_recipe(&id).await?)
+}
+
+async fn recipes_delete(
+    State(state): State<AppState>,
+    session: ApiSession,
+    ApiPath(id): ApiPath<String>,
+) -> ApiResult {
+    let recipe = state.db.get_recipe(&id).await?;
+
+    if !authz(&session.user_id, &recipe.user_id) {
+        return Err(AppError::Forbidden.into());
+    }
+
+    if !state
+        .db
+        .get_recipe_references(&session.user_id, &id)
+        .await?
+        .is_empty()
+    {
+        return Err(AppError::InUse.into());
+    }
+
+    state.db.delete_recipe(&id).await?;
+
+    no_content()
+}
+
+async fn recipes_clone(
+    State(state): State<AppState>,
+    session: ApiSession,
+    ApiPath(id): ApiPath<String>,
+) -> ApiResult {
+    let recipe = state.db.get_recipe(&id).await?;
+
+    if !visible(&session.user_id, &recipe.user_id, &recipe.shared_at) {
+        return Err(AppError::Forbidden.into());
+    }
+
+    let id = state.db.clone_recipe(&session.user_id, &id).await?;
+
+    created(state.db.get_recipe(&id).await?)
+}
+
+#[derive(serde::Deserialize)]
+struct IngredientBody {
+    consumable_id: String,
+    consumable_type: String,
+    quantity: f64,
+    serving_id: Option<String>,
+}
+
+async fn ingredients_index(
+    State(state): State<AppState>,
+    session: ApiSession,
+    ApiPath(recipe_id): ApiPath<String>,
+) -> ApiResult {
+    let recipe = state.db.get_recipe(&recipe_id).await?;
+
+    if !visible(&session.user_id, &recipe.user_id, &recipe.shared_at) {
+        return Err(AppError::Forbidden.into());
+    }
+
+    json(state.db.get_ingredients(&recipe_id).await?)
+}
+
+async fn ingredients_create(
+    State(state): State<AppState>,
+    session: ApiSession,
+    ApiPath(recipe_id): ApiPath<String>,
+    ApiJson(body): ApiJson<IngredientBody>,
+) -> ApiResult {
+    let recipe = state.db.get_recipe(&recipe_id).await?;
+
+    if !authz(&session.user_id, &recipe.user_id) {
+        return Err(AppError::Forbidden.into());
+    }
+
+    let (food_id, sub_recipe_id) = ingredient_consumable(
+        &state,
+        &session.user_id,
+        &recipe.id,
+        &body.consumable_id,
+        &body.consumable_type,
+    )
+    .await?;
+    let serving_id = serving_id(&state, food_id.as_deref(), body.serving_id).await?;
+
+    let id = state
+        .db
+        .add_ingredient(
+            &session.user_id,
+            &recipe.id,
+            food_id.as_deref(),
+            sub_recipe_id.as_deref(),
+            body.quantity,
+            serving_id.as_deref(),
+        )
+        .await?;
+
+    created(state.db.get_ingredient(&id).await?)
+}
+
+async fn ingredients_read(
+    State(state): State<AppState>,
+    session: ApiSession,
+    ApiPath(id): ApiPath<String>,
+) -> ApiResult {
+    let ingredient = state.db.get_ingredient(&id).await?;
+
+    if !authz(&session.user_id, &ingredient.user_id) {
+        return Err(AppError::Forbidden.into());
+    }
+
+    json(ingredient)
+}
+
+async fn ingredients_update(
+    State(state): State<AppState>,
+    session: ApiSession,
+    ApiPath(id): ApiPath<String>,
+    ApiJson(body): ApiJson<IngredientBody>,
+) -> ApiResult {
+    let ingredient = state.db.get_ingredient(&id).await?;
+
+    if !authz(&session.user_id, &ingredient.user_id) {
+        return Err(AppError::Forbidden.into());
+    }
+
+    let (food_id, sub_recipe_id) = ingredient_consumable(
+        &state,
+        &session.user_id,
+        &ingredient.recipe_id,
+        &body.consumable_id,
+        &body.consumable_type,
+    )
+    .await?;
+    let serving_id = serving_id(&state, food_id.as_deref(), body.serving_id).await?;
+
+    state
+        .db
+        .update_ingredient(
+            &id,
+            food_id.as_deref(),
+            sub_recipe_id.as_deref(),
+            body.quantity,
+            serving_id.as_deref(),
+        )
+        .await?;
+
+    json(state.db.get_ingredient(&id).await?)
+}
+
+async fn ingredients_delete(
+    State(state): State<AppState>,
+    session: ApiSession,
+    ApiPath(id): ApiPath<String>,
+) -> ApiResult {
+    let ingredient = state.db.get_ingredient(&id).await?;
+
+    if !authz(&session.user_id, &ingredient.user_id) {
+        return Err(AppError::Forbidden.into());
+    }
+
+    state.db.delete_ingredient(&id).await?;
+
+    no_content()
+}
+
+#[derive(serde::Deserialize)]
+struct ConsumptionQuery {
+    date: Option<chrono::NaiveDate>,
+}
+
+#[derive(serde::Deserialize)]
+struct ConsumptionBody {
+    consumable_id: String,
+    consumable_type: String,
+    quantity: f64,
+    serving_id: Option<String>,
+    consumed_at: chrono::NaiveDate,
+    consumed_time: Option<chrono::NaiveTime>,
+    meal_slot_id: Option<String>,
+}
+
+async fn consumptions_index(
+    State(state): State<AppState>,
+    session: ApiSession,
+    ApiQuery(query): ApiQuery<ConsumptionQuery>,
+) -> ApiResult {
+    let filter = match query.date {
+        Some(date) => ConsumptionFilter::ConsumedAt(date),
+        None => ConsumptionFilter::None,
+    };
+
+    json(state.db.get_consumptions(&session.user_id, filter).await?)
+}
+
+async fn consumptions_create(
+    State(state): State<AppState>,
+    session: ApiSession,
+    ApiJson(body): ApiJson<ConsumptionBody>,
+) -> ApiResult {
+    let (food_id, recipe_id) = consumption_consumable(
+        &state,
+        &session.user_id,
+        &body.consumable_id,
+        &body.consumable_type,
+    )
+    .await?;
+    let serving_id = serving_id(&state, food_id.as_deref(), body.serving_id).await?;
+    let meal_slot_id = meal_slot_id(&state, &session.user_id, body.meal_slot_id).await?;
+
+    let id = state
+        .db
+        .add_consumption(
+            &session.user_id,
+            ConsumptionParams {
+                food_id: food_id.as_deref(),
+                recipe_id: recipe_id.as_deref(),
+                quantity: body.quantity,
+                serving_id: serving_id.as_deref(),
+                consumed_at: body.consumed_at,
+                consumed_time: body.consumed_time,
+                meal_slot_id: meal_slot_id.as_deref(),
+            },
+        )
+        .await?;
+
+    created(state.db.get_consumption(&id).await?)
+}
+
+async fn consumptions_read(
+    State(state): State<AppState>,
+    session: ApiSession,
+    ApiPath(id): ApiPath<String>,
+) -> ApiResult {
+    let consumption = state.db.get_consumption(&id).await?;
+
+    if !authz(&session.user_id, &consumption.user_id) {
+        return Err(AppError::Forbidden.into());
+    }
+
+    json(consumption)
+}
+
+async fn consumptions_update(
+    State(state): State<AppState>,
+    session: ApiSession,
+    ApiPath(id): ApiPath<String>,
+    ApiJson(body): ApiJson<ConsumptionBody>,
+) -> ApiResult {
+    let consumption = state.db.get_consumption(&id).await?;
+
+    if !authz(&session.user_id, &consumption.user_id) {
+        return Err(AppError::Forbidden.into());
+    }
+
+    let (food_id, recipe_id) = consumption_consumable(
+        &state,
+        &session.user_id,
+        &body.consumable_id,
+        &body.consumable_type,
+    )
+    .await?;
+    let serving_id = serving_id(&state, food_id.as_deref(), body.serving_id).await?;
+    let meal_slot_id = meal_slot_id(&state, &session.user_id, body.meal_slot_id).await?;
+
+    state
+        .db
+        .update_consumption(
+            &id,
+            ConsumptionParams {
+                food_id: food_id.as_deref(),
+                recipe_id: recipe_id.as_deref(),
+                quantity: body.quantity,
+                serving_id: serving_id.as_deref(),
+                consumed_at: body.consumed_at,
+                consumed_time: body.consumed_time,
+                meal_slot_id: meal_slot_id.as_deref(),
+            },
+        )
+        .await?;
+
+    json(state.db.get_consumption(&id).await?)
+}
+
+async fn consumptions_delete(
+    State(state): State<AppState>,
+    session: ApiSession,
+    ApiPath(id): ApiPath<String>,
+) -> ApiResult {
+    let consumption = state.db.get_consumption(&id).await?;
+
+    if !authz(&session.user_id, &consumption.user_id) {
+        return Err(AppError::Forbidden.into());
+    }
+
+    state.db.delete_consumption(&id).await?;
+
+    no_content()
+}
+
+async fn consumptions_recalculate(
+    State(state): State<AppState>,
+    session: ApiSession,
+    ApiPath(id): ApiPath<String>,
+) -> ApiResult {
+    let consumption = state.db.get_consumption(&id).await?;
+
+    if !authz(&session.user_id, &consumption.user_id) {
+        return Err(AppError::Forbidden.into());
+    }
+
+    state.db.recalculate_consumption(&id).await?;
+
+    json(state.db.get_consumption(&id).await?)
+}
+
+async fn meal_slots_index(State(state): State<AppState>, session: ApiSession) -> ApiResult {
+    json(state.db.get_meal_slots(&session.user_id).await?)
+}
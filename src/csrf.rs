@@ -0,0 +1,121 @@
+//! Protection against cross-site request forgery. Every form that is posted
+//! with a session cookie has to contain a token derived from a secret stored
+//! with the session. Other sites can't read it, so they can't compute the
+//! token. The secret is kept when the session id is rotated, so forms that
+//! were rendered before still work.
+//!
+//! [`verify`] checks the token of state-changing requests and makes it
+//! available to the `html` module while the request is handled, which embeds
+//! it in every form.
+
+use axum::body::{to_bytes, Body};
+use axum::extract::{FromRequest, Multipart, Request, State};
+use axum::http::{header, Method, StatusCode};
+use axum::middleware::Next;
+use axum::response::{IntoResponse, Response};
+use axum::Form;
+use axum_extra::extract::cookie::CookieJar;
+use sha2::{Digest, Sha256};
+use tracing::warn;
+
+use crate::{AppError, AppState, IMPORT_LIMIT};
+
+/// Name of the form field with the token.
+pub const FIELD: &str = "csrf_token";
+
+/// Largest URL-encoded form that is read to find the token.
+const FORM_LIMIT: usize = 2 * 1024 * 1024;
+
+tokio::task_local! {
+    static TOKEN: Option<String>;
+}
+
+pub fn session_token(csrf_secret: &str) -> String {
+    base16ct::lower::encode_string(&Sha256::digest(format!("csrf:{}", csrf_secret)))
+}
+
+/// Token of the request that is currently handled, if it has a session.
+pub fn token() -> Option<String> {
+    TOKEN.try_with(Clone::clone).ok().flatten()
+}
+
+#[derive(serde::Deserialize)]
+struct TokenForm {
+    csrf_token: Option<String>,
+}
+
+pub async fn verify(
+    State(state): State<AppState>,
+    request: Request,
+    next: Next,
+) -> Result<Response, AppError> {
+    let session_id = CookieJar::from_headers(request.headers())
+        .get("MATRAFL_SESSION")
+        .map(|cookie| cookie.value().to_string());
+    let token = match session_id {
+        Some(session_id) => state
+            .db
+            .get_csrf_secret(&session_id)
+            .await?
+            .map(|secret| session_token(&secret)),
+        None => None,
+    };
+
+    // Requests without a session aren't authenticated, so there's nothing to
+    // forge. This is the case for the login form.
+    let request = match &token {
+        Some(token) if !matches!(*request.method(), Method::GET | Method::HEAD) => {
+            let path = request.uri().path().to_string();
+            match sent_token(request).await {
+                Ok((request, sent)) if sent.as_deref().is_some_and(|sent| sent == token) => request,
+                Ok(_) => {
+                    warn!(path, "invalid CSRF token");
+                    return Err(AppError::InvalidCsrfToken);
+                }
+                Err(response) => return Ok(response),
+            }
+        }
+        _ => request,
+    };
+
+    Ok(TOKEN.scope(token, next.run(request)).await)
+}
+
+/// Reads the token from the body and returns a request with the same body.
+async fn sent_token(request: Request) -> Result<(Request, Option<String>), Response> {
+    let (parts, body) = request.into_parts();
+    let content_type = parts
+        .headers
+        .get(header::CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .unwrap_or_default();
+    let multipart = content_type.starts_with("multipart/form-data");
+    let limit = if multipart { IMPORT_LIMIT } else { FORM_LIMIT };
+    let bytes = to_bytes(body, limit)
+        .await
+        .map_err(|_| StatusCode::PAYLOAD_TOO_LARGE.into_response())?;
+    let copy = || Request::from_parts(parts.clone(), Body::from(bytes.clone()));
+
+    let sent = if multipart {
+        multipart_token(copy()).await
+    } else {
+        Form::<TokenForm>::from_request(copy(), &())
+            .await
+            .ok()
+            .and_then(|Form(form)| form.csrf_token)
+    };
+
+    Ok((copy(), sent))
+}
+
+/// Looks for the token in the fields of a multipart form, which puts it
+/// before the file.
+async fn multipart_token(request: Request) -> Option<String> {
+    let mut multipart = Multipart::from_request(request, &()).await.ok()?;
+    while let Some(field) = multipart.next_field().await.ok()? {
+        if field.name() == Some(FIELD) {
+            return field.text().await.ok();
+        }
+    }
+    None
+}
@@ -1,16 +1,222 @@
-use std::str;
+use std::{collections::HashMap, ops::RangeInclusive, str};
 
 use argon2::{
     password_hash::{rand_core::OsRng, PasswordHasher, SaltString},
     Argon2,
 };
+use chrono_tz::Tz;
 use rand::Rng;
-use serde_json::json;
 use sha2::{Digest, Sha256};
 use sqlx::SqlitePool;
 use uuid::Uuid;
 
-pub const SESSION_DAYS: i64 = 7;
+use crate::export::{self, ExportData, ImportError, ImportMode};
+use crate::nutrients::{self, Nutrient, Nutrients};
+use crate::products::Product;
+use crate::throttle;
+use crate::trend::WeightRule;
+
+/// Sessions expire after this long without being used, unless the user
+/// asked to be remembered on login.
+pub const SESSION_HOURS: i64 = 24;
+
+pub const REMEMBERED_SESSION_DAYS: i64 = 30;
+
+/// Age after which a session gets a new id.
+pub const SESSION_ROTATION_HOURS: i64 = 24;
+
+/// How long the previous id of a rotated session stays valid, for requests
+/// that were sent before the new cookie arrived.
+pub const SESSION_ROTATION_GRACE_SECONDS: i64 = 60;
+
+/// How long a session stays valid without being used.
+pub fn session_lifetime(remember: bool) -> chrono::Duration {
+    if remember {
+        chrono::Duration::days(REMEMBERED_SESSION_DAYS)
+    } else {
+        chrono::Duration::hours(SESSION_HOURS)
+    }
+}
+
+/// Total macros of every recipe, including nested recipes. The depth limit
+/// only guards against cycles, which `Db::recipe_contains` rejects.
+fn recipe_macros_cte() -> String {
+    format!(
+        "
+    recipe_tree (root_id, recipe_id, factor, depth) AS (
+        SELECT id, id, 1.0, 0 FROM recipes
+        UNION ALL
+        SELECT
+            t.root_id,
+            i.sub_recipe_id,
+            t.factor * i.quantity / sr.quantity,
+            t.depth + 1
+        FROM
+            recipe_tree t
+        JOIN
+            ingredients i
+                ON
+                    t.recipe_id = i.recipe_id
+        JOIN
+            recipes sr
+                ON
+                    i.sub_recipe_id = sr.id
+        WHERE
+            t.depth < 16
+    ),
+    recipe_macros (recipe_id, kcal, fat, carbs, protein, {nutrient_keys}) AS (
+        SELECT
+            t.root_id,
+            sum(f.kcal / f.basis * i.quantity * coalesce(s.amount, 1.0) * t.factor),
+            sum(f.fat / f.basis * i.quantity * coalesce(s.amount, 1.0) * t.factor),
+            sum(f.carbs / f.basis * i.quantity * coalesce(s.amount, 1.0) * t.factor),
+            sum(f.protein / f.basis * i.quantity * coalesce(s.amount, 1.0) * t.factor),
+            {nutrient_sums}
+        FROM
+            recipe_tree t
+        JOIN
+            ingredients i
+                ON
+                    t.recipe_id = i.recipe_id
+        JOIN
+            foods f
+                ON
+                    i.food_id = f.id
+        LEFT JOIN
+            servings s
+                ON
+                    i.serving_id = s.id
+        GROUP BY
+            t.root_id
+    )
+",
+        nutrient_keys = nutrients::sql_columns("{n}"),
+        nutrient_sums = nutrients::sql_columns(
+            "sum(f.{n} / f.basis * i.quantity * coalesce(s.amount, 1.0) * t.factor)"
+        ),
+    )
+}
+
+/// Foods and recipes for the consumable picker, with their usage from the
+/// start of the date `?2` on, i.e. during the last seven days, for sorting.
+/// Shared entries of other users are included, but their owner's stars
+/// aren't.
+const CONSUMABLE_FOODS_SQL: &str = r#"
+    SELECT
+        f.id,
+        f.name,
+        f.unit,
+        CASE WHEN f.user_id = ?1 THEN f.starred_at END as starred_at,
+        f.user_id != ?1 as is_shared,
+        f.created_at,
+        MAX(DATE(c.consumed_at)) as last_consumed_at,
+        COUNT(c.id) as consumed_count,
+        (SELECT group_concat(a.name, ', ') FROM food_aliases a WHERE a.food_id = f.id) as aliases
+    FROM
+        foods f
+    LEFT JOIN
+        consumptions c
+            ON
+                f.id = c.food_id
+                AND c.user_id = ?1
+                AND c.consumed_at >= ?2
+    WHERE
+        f.id IN (
+            SELECT id FROM foods WHERE user_id = ?1
+            UNION ALL
+            SELECT id FROM foods WHERE shared_at IS NOT NULL
+        )
+        AND f.hidden_at IS NULL
+    GROUP BY
+        f.id
+"#;
+
+const CONSUMABLE_RECIPES_SQL: &str = r#"
+    SELECT
+        r.id,
+        r.name,
+        NULL as unit,
+        CASE WHEN r.user_id = ?1 THEN r.starred_at END as starred_at,
+        r.user_id != ?1 as is_shared,
+        r.created_at,
+        MAX(DATE(c.consumed_at)) as last_consumed_at,
+        COUNT(c.id) as consumed_count,
+        NULL as aliases
+    FROM
+        recipes r
+    LEFT JOIN
+        consumptions c
+            ON
+                r.id = c.recipe_id
+                AND c.user_id = ?1
+                AND c.consumed_at >= ?2
+    WHERE
+        r.id IN (
+            SELECT id FROM recipes WHERE user_id = ?1
+            UNION ALL
+            SELECT id FROM recipes WHERE shared_at IS NOT NULL
+        )
+        AND r.hidden_at IS NULL
+    GROUP BY
+        r.id
+"#;
+
+/// Shared entries of other users that the data of user `?1` references,
+/// directly or through shared recipes.
+const EXPORT_SHARED_CTE: &str = r#"
+    used_recipes (id) AS (
+        SELECT recipe_id FROM consumptions WHERE user_id = ?1 AND recipe_id IS NOT NULL
+        UNION
+        SELECT sub_recipe_id FROM ingredients WHERE user_id = ?1 AND sub_recipe_id IS NOT NULL
+        UNION
+        SELECT
+            i.sub_recipe_id
+        FROM
+            used_recipes u
+        JOIN
+            ingredients i
+                ON
+                    u.id = i.recipe_id
+        WHERE
+            i.sub_recipe_id IS NOT NULL
+    ),
+    foreign_recipes (id) AS (
+        SELECT id FROM recipes WHERE id IN (SELECT id FROM used_recipes) AND user_id != ?1
+    ),
+    foreign_ingredients (id, food_id, serving_id) AS (
+        SELECT id, food_id, serving_id FROM ingredients WHERE recipe_id IN (SELECT id FROM foreign_recipes)
+    ),
+    used_rows (food_id, serving_id) AS (
+        SELECT food_id, serving_id FROM consumptions WHERE user_id = ?1
+        UNION ALL
+        SELECT food_id, serving_id FROM ingredients WHERE user_id = ?1
+        UNION ALL
+        SELECT food_id, serving_id FROM foreign_ingredients
+    ),
+    foreign_foods (id) AS (
+        SELECT id FROM foods WHERE id IN (SELECT food_id FROM used_rows) AND user_id != ?1
+    ),
+    foreign_servings (id) AS (
+        SELECT id FROM servings WHERE id IN (SELECT serving_id FROM used_rows) AND user_id != ?1
+    )
+"#;
+
+pub const FOOD_UNITS: [&str; 3] = ["g", "ml", "piece"];
+
+/// Tables with the data of a user, in an order in which they can be emptied.
+const USER_DATA_TABLES: [&str; 9] = [
+    "ingredients",
+    "consumptions",
+    "servings",
+    "food_aliases",
+    "recipes",
+    "foods",
+    "weights",
+    "goals",
+    "meal_slots",
+];
+
+pub const DEFAULT_MEAL_SLOTS: [&str; 4] = ["Breakfast", "Lunch", "Dinner", "Snacks"];
 
 #[derive(Debug, Clone)]
 pub struct Db {
@@ -21,9 +227,19 @@ pub struct Db {
 pub struct UserWithPasswordHash {
     pub id: UserId,
     pub password_hash: String,
+    pub disabled_at: Option<chrono::DateTime<chrono::Utc>>,
+}
+
+#[derive(sqlx::FromRow)]
+pub struct User {
+    pub id: UserId,
+    pub username: String,
+    pub is_admin: bool,
+    pub disabled_at: Option<chrono::DateTime<chrono::Utc>>,
+    pub created_at: chrono::DateTime<chrono::Utc>,
 }
 
-#[derive(Debug, PartialEq, Eq, Clone, sqlx::Decode, sqlx::Encode)]
+#[derive(Debug, PartialEq, Eq, Clone, sqlx::Decode, sqlx::Encode, serde::Serialize)]
 pub struct UserId(pub String);
 
 impl sqlx::Type<sqlx::Sqlite> for UserId {
@@ -36,15 +252,17 @@ impl sqlx::Type<sqlx::Sqlite> for UserId {
     }
 }
 
-#[derive(sqlx::FromRow)]
+#[derive(sqlx::FromRow, serde::Serialize)]
 pub struct WeightWithDate {
     pub id: String,
     pub user_id: UserId,
     pub weight: f64,
     pub measured_at: chrono::NaiveDate,
+    /// Time of the weigh-in, if it was given.
+    pub measured_time: Option<chrono::NaiveTime>,
 }
 
-#[derive(sqlx::FromRow)]
+#[derive(sqlx::FromRow, serde::Serialize)]
 pub struct Food {
     pub id: String,
     pub user_id: UserId,
@@ -53,11 +271,92 @@ pub struct Food {
     pub fat: f64,
     pub carbs: f64,
     pub protein: f64,
+    /// Unit the food is measured in, one of [`FOOD_UNITS`].
+    pub unit: String,
+    /// Amount of `unit` that the macros refer to, e.g. 100 for "per 100 g".
+    pub basis: f64,
+    /// Normalized EAN/UPC code, see [`crate::products::normalize_barcode`].
+    pub barcode: Option<String>,
+    #[sqlx(flatten)]
+    #[serde(flatten)]
+    pub nutrients: Nutrients,
     pub hidden_at: Option<chrono::NaiveDateTime>,
     pub starred_at: Option<chrono::NaiveDateTime>,
+    pub shared_at: Option<chrono::NaiveDateTime>,
 }
 
-#[derive(sqlx::FromRow)]
+pub struct FoodParams<'a> {
+    pub name: &'a str,
+    pub macros: Macros,
+    pub nutrients: Nutrients,
+    pub unit: &'a str,
+    pub basis: f64,
+    pub barcode: Option<&'a str>,
+    pub flags: EntryFlags,
+}
+
+/// Each flag is saved as the time it was first set.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct EntryFlags {
+    pub hidden: bool,
+    pub starred: bool,
+    pub shared: bool,
+}
+
+/// Most recent consumptions listed in [`References`].
+pub const REFERENCES_LIMIT: i64 = 10;
+
+/// Entries that use a food or recipe, which can't be deleted while there are
+/// any.
+#[derive(Debug)]
+pub struct References {
+    pub consumption_count: i64,
+    /// Most recent consumptions of the user, at most [`REFERENCES_LIMIT`].
+    pub consumptions: Vec<ConsumptionReference>,
+    pub recipes: Vec<RecipeReference>,
+    /// Consumptions and ingredients of other users, which are only counted.
+    pub other_users: i64,
+}
+
+impl References {
+    pub fn is_empty(&self) -> bool {
+        self.consumption_count == 0 && self.recipes.is_empty() && self.other_users == 0
+    }
+}
+
+#[derive(Debug, sqlx::FromRow)]
+pub struct ConsumptionReference {
+    pub id: String,
+    pub consumed_at: chrono::NaiveDate,
+}
+
+#[derive(Debug, sqlx::FromRow)]
+pub struct RecipeReference {
+    pub id: String,
+    pub name: String,
+}
+
+/// Another name of a food, e.g. of a duplicate that was merged into it.
+#[derive(sqlx::FromRow, serde::Serialize)]
+pub struct FoodAlias {
+    pub id: String,
+    pub user_id: UserId,
+    pub food_id: String,
+    pub name: String,
+}
+
+/// Named portion of a food, e.g. "1 slice" = 35 g. `amount` is given in the
+/// unit of the food.
+#[derive(sqlx::FromRow, serde::Serialize)]
+pub struct Serving {
+    pub id: String,
+    pub user_id: UserId,
+    pub food_id: String,
+    pub name: String,
+    pub amount: f64,
+}
+
+#[derive(sqlx::FromRow, serde::Serialize)]
 pub struct Recipe {
     pub id: String,
     pub user_id: UserId,
@@ -67,8 +366,23 @@ pub struct Recipe {
     pub fat: f64,
     pub carbs: f64,
     pub protein: f64,
+    #[sqlx(flatten)]
+    #[serde(flatten)]
+    pub nutrients: Nutrients,
     pub hidden_at: Option<chrono::NaiveDateTime>,
     pub starred_at: Option<chrono::NaiveDateTime>,
+    pub shared_at: Option<chrono::NaiveDateTime>,
+}
+
+/// Either `food_id` or `recipe_id` is set.
+pub struct ConsumptionParams<'a> {
+    pub food_id: Option<&'a str>,
+    pub recipe_id: Option<&'a str>,
+    pub quantity: f64,
+    pub serving_id: Option<&'a str>,
+    pub consumed_at: chrono::NaiveDate,
+    pub consumed_time: Option<chrono::NaiveTime>,
+    pub meal_slot_id: Option<&'a str>,
 }
 
 pub enum ConsumptionFilter {
@@ -78,34 +392,68 @@ pub enum ConsumptionFilter {
     RecipeId(String),
 }
 
-#[derive(sqlx::FromRow)]
+impl ConsumptionFilter {
+    /// Condition on the consumptions `c` and the values to bind.
+    fn sql(self) -> (&'static str, Vec<String>) {
+        // Dates are compared as a half-open range on the raw column, so that
+        // the (user_id, consumed_at) index can be used.
+        match self {
+            ConsumptionFilter::None => ("1", vec![]),
+            ConsumptionFilter::ConsumedAt(date) => (
+                "c.consumed_at >= ? AND c.consumed_at < ?",
+                vec![date.to_string(), date.succ_opt().unwrap().to_string()],
+            ),
+            ConsumptionFilter::FoodId(food_id) => ("c.food_id = ?", vec![food_id]),
+            ConsumptionFilter::RecipeId(recipe_id) => ("c.recipe_id = ?", vec![recipe_id]),
+        }
+    }
+}
+
+#[derive(sqlx::FromRow, serde::Serialize)]
 pub struct Consumption {
     pub id: String,
     pub user_id: UserId,
     pub consumable_id: String,
     pub consumable_name: String,
+    /// Quantity in the selected serving, or in the unit of the food if there
+    /// is none. Recipes are measured in their own quantity.
     pub quantity: f64,
+    pub serving_id: Option<String>,
+    /// Name of the serving or unit of the food. `None` for recipes.
+    pub unit: Option<String>,
     pub kcal: f64,
     pub fat: f64,
     pub carbs: f64,
     pub protein: f64,
+    #[sqlx(flatten)]
+    #[serde(flatten)]
+    pub nutrients: Nutrients,
     pub consumed_at: chrono::NaiveDate,
+    /// Time of the meal, if it was given.
+    pub consumed_time: Option<chrono::NaiveTime>,
+    pub meal_slot_id: Option<String>,
 }
 
-#[derive(sqlx::FromRow)]
+#[derive(sqlx::FromRow, serde::Serialize)]
 pub struct Ingredient {
     pub id: String,
     pub user_id: UserId,
     pub recipe_id: String,
-    pub food_id: String,
-    pub food_name: String,
+    pub consumable_id: String,
+    pub consumable_name: String,
     pub quantity: f64,
+    pub serving_id: Option<String>,
+    pub unit: Option<String>,
     pub kcal: f64,
     pub fat: f64,
     pub carbs: f64,
     pub protein: f64,
+    #[sqlx(flatten)]
+    #[serde(flatten)]
+    pub nutrients: Nutrients,
 }
 
+#[derive(Default, serde::Serialize)]
 pub struct Macros {
     pub kcal: f64,
     pub fat: f64,
@@ -113,6 +461,78 @@ pub struct Macros {
     pub protein: f64,
 }
 
+#[derive(sqlx::FromRow, serde::Serialize)]
+pub struct DailyTotal {
+    pub date: chrono::NaiveDate,
+    pub kcal: f64,
+    pub fat: f64,
+    pub carbs: f64,
+    pub protein: f64,
+    #[sqlx(flatten)]
+    #[serde(flatten)]
+    pub nutrients: Nutrients,
+}
+
+impl DailyTotal {
+    pub fn macros(&self) -> Macros {
+        Macros {
+            kcal: self.kcal,
+            fat: self.fat,
+            carbs: self.carbs,
+            protein: self.protein,
+        }
+    }
+}
+
+/// Daily target. A goal without a weekday applies to every day that has no
+/// weekday-specific goal. Weekdays are numbered from Monday (0) to Sunday (6).
+#[derive(Clone, sqlx::FromRow, serde::Serialize)]
+pub struct Goal {
+    pub weekday: Option<u32>,
+    pub kcal: f64,
+    pub fat: f64,
+    pub carbs: f64,
+    pub protein: f64,
+}
+
+pub fn goal_for_date(goals: &[Goal], date: chrono::NaiveDate) -> Option<&Goal> {
+    use chrono::Datelike;
+
+    let weekday = date.weekday().num_days_from_monday();
+    goals
+        .iter()
+        .find(|g| g.weekday == Some(weekday))
+        .or_else(|| goals.iter().find(|g| g.weekday.is_none()))
+}
+
+#[derive(sqlx::FromRow, serde::Serialize)]
+pub struct MealSlot {
+    pub id: String,
+    pub user_id: UserId,
+    pub name: String,
+    pub position: i64,
+}
+
+/// Login session. The id is the hash of the session cookie, so it can be
+/// shown without giving access.
+#[derive(sqlx::FromRow)]
+pub struct UserSession {
+    pub id: String,
+    pub user_id: UserId,
+    pub user_agent: Option<String>,
+    pub last_seen_at: Option<chrono::DateTime<chrono::Utc>>,
+    pub created_at: chrono::DateTime<chrono::Utc>,
+}
+
+#[derive(sqlx::FromRow)]
+pub struct ApiToken {
+    pub id: String,
+    pub user_id: UserId,
+    pub name: String,
+    pub last_used_at: Option<chrono::DateTime<chrono::Utc>>,
+    pub created_at: chrono::DateTime<chrono::Utc>,
+}
+
 #[derive(PartialEq, Eq)]
 pub enum ConsumableType {
     Food,
@@ -132,9 +552,15 @@ pub struct Consumable {
     pub ctype: ConsumableType,
     pub id: String,
     pub name: String,
+    /// Unit of a food, `None` for recipes.
+    pub unit: Option<String>,
     pub is_starred: bool,
+    pub is_shared: bool,
+    /// Other names of a food, separated by commas, which the search matches.
+    pub aliases: Option<String>,
     created_at: chrono::NaiveDateTime,
-    last_consumed_at: Option<chrono::NaiveDate>,
+    /// Days between the last consumption and today.
+    days_since_consumed: Option<i64>,
     consumed_count: Option<i64>,
 }
 
@@ -143,6 +569,8 @@ impl Db {
         Self { db_pool }
     }
 
+    /// Weigh-ins of the user, newest first. The ordering uses the column
+    /// and not the alias of the same name, which only has the date.
     pub async fn get_weights(&self, user_id: &UserId) -> Result<Vec<WeightWithDate>, sqlx::Error> {
         sqlx::query_as::<_, WeightWithDate>(
             r#"
@@ -150,13 +578,15 @@ impl Db {
                 id,
                 user_id,
                 weight,
-                date(measured_at) as measured_at
+                date(measured_at) as measured_at,
+                CASE WHEN length(measured_at) > 10 THEN time(measured_at) END as measured_time
             FROM
                 weights
             WHERE
                 user_id = ?
             ORDER BY
-                measured_at DESC
+                weights.measured_at DESC,
+                created_at DESC
             "#,
         )
         .bind(user_id)
@@ -164,47 +594,54 @@ impl Db {
         .await
     }
 
-    pub async fn get_weight(&self, id: &str) -> Result<WeightWithDate, sqlx::Error> {
+    pub async fn get_weights_in_range(
+        &self,
+        user_id: &UserId,
+        range: RangeInclusive<chrono::NaiveDate>,
+    ) -> Result<Vec<WeightWithDate>, sqlx::Error> {
         sqlx::query_as::<_, WeightWithDate>(
             r#"
             SELECT
                 id,
                 user_id,
                 weight,
-                date(measured_at) as measured_at
+                date(measured_at) as measured_at,
+                CASE WHEN length(measured_at) > 10 THEN time(measured_at) END as measured_time
             FROM
                 weights
             WHERE
-                id = ?
+                user_id = ?
+                AND weights.measured_at >= ?
+                AND weights.measured_at < ?
+            ORDER BY
+                weights.measured_at DESC,
+                created_at DESC
             "#,
         )
-        .bind(id)
-        .fetch_one(&self.db_pool)
+        .bind(user_id)
+        .bind(range.start())
+        .bind(range.end().succ_opt())
+        .fetch_all(&self.db_pool)
         .await
     }
 
-    pub async fn get_weight_by_date(
-        &self,
-        user_id: &UserId,
-        measured_at: &chrono::NaiveDate,
-    ) -> Result<Option<WeightWithDate>, sqlx::Error> {
+    pub async fn get_weight(&self, id: &str) -> Result<WeightWithDate, sqlx::Error> {
         sqlx::query_as::<_, WeightWithDate>(
             r#"
             SELECT
                 id,
                 user_id,
                 weight,
-                date(measured_at) as measured_at
+                date(measured_at) as measured_at,
+                CASE WHEN length(measured_at) > 10 THEN time(measured_at) END as measured_time
             FROM
                 weights
             WHERE
-                user_id = ?
-                AND date(measured_at) = date(?)
+                id = ?
             "#,
         )
-        .bind(user_id)
-        .bind(measured_at)
-        .fetch_optional(&self.db_pool)
+        .bind(id)
+        .fetch_one(&self.db_pool)
         .await
     }
 
@@ -213,18 +650,20 @@ impl Db {
         user_id: &UserId,
         weight: f64,
         measured_at: &chrono::NaiveDate,
-    ) -> Result<(), sqlx::Error> {
+        measured_time: Option<chrono::NaiveTime>,
+    ) -> Result<String, sqlx::Error> {
+        let id = Uuid::new_v4().to_string();
         let now = chrono::Utc::now();
         sqlx::query("INSERT INTO weights (id, user_id, weight, measured_at, created_at, updated_at) VALUES (?, ?, ?, ?, ?, ?)")
-            .bind(Uuid::new_v4().to_string())
+            .bind(&id)
             .bind(user_id)
             .bind(weight)
-            .bind(measured_at)
+            .bind(timestamp(measured_at, measured_time))
             .bind(now)
             .bind(now)
             .execute(&self.db_pool)
             .await?;
-        Ok(())
+        Ok(id)
     }
 
     pub async fn update_weight(
@@ -232,11 +671,12 @@ impl Db {
         id: &str,
         weight: f64,
         measured_at: &chrono::NaiveDate,
+        measured_time: Option<chrono::NaiveTime>,
     ) -> Result<(), sqlx::Error> {
         let now = chrono::Utc::now();
         sqlx::query("UPDATE weights SET weight = ?, measured_at = ?, updated_at = ? WHERE id = ?")
             .bind(weight)
-            .bind(measured_at)
+            .bind(timestamp(measured_at, measured_time))
             .bind(now)
             .bind(id)
             .execute(&self.db_pool)
@@ -254,7 +694,11 @@ impl Db {
 
     pub async fn get_foods(&self, user_id: &UserId) -> Result<Vec<Food>, sqlx::Error> {
         sqlx::query_as::<_, Food>(
-            "SELECT id, user_id, name, kcal, fat, carbs, protein, hidden_at, starred_at FROM foods WHERE user_id = ? ORDER BY updated_at DESC",
+            format!(
+                "SELECT id, user_id, name, kcal, fat, carbs, protein, unit, basis, barcode, {}, hidden_at, starred_at, shared_at FROM foods WHERE user_id = ? ORDER BY updated_at DESC",
+                nutrients::sql_columns("{n}")
+            )
+            .as_str(),
         )
         .bind(user_id)
         .fetch_all(&self.db_pool)
@@ -263,670 +707,2054 @@ impl Db {
 
     pub async fn get_food(&self, id: &str) -> Result<Food, sqlx::Error> {
         sqlx::query_as::<_, Food>(
-            "SELECT id, user_id, name, kcal, fat, carbs, protein, hidden_at, starred_at FROM foods WHERE id = ?",
+            format!(
+                "SELECT id, user_id, name, kcal, fat, carbs, protein, unit, basis, barcode, {}, hidden_at, starred_at, shared_at FROM foods WHERE id = ?",
+                nutrients::sql_columns("{n}")
+            )
+            .as_str(),
         )
         .bind(id)
         .fetch_one(&self.db_pool)
         .await
     }
 
+    /// Most recently updated food of the user with this barcode.
+    pub async fn get_food_by_barcode(
+        &self,
+        user_id: &UserId,
+        barcode: &str,
+    ) -> Result<Option<Food>, sqlx::Error> {
+        sqlx::query_as::<_, Food>(
+            format!(
+                "SELECT id, user_id, name, kcal, fat, carbs, protein, unit, basis, barcode, {}, hidden_at, starred_at, shared_at FROM foods WHERE user_id = ? AND barcode = ? ORDER BY updated_at DESC LIMIT 1",
+                nutrients::sql_columns("{n}")
+            )
+            .as_str(),
+        )
+        .bind(user_id)
+        .bind(barcode)
+        .fetch_optional(&self.db_pool)
+        .await
+    }
+
     pub async fn add_food(
         &self,
         user_id: &UserId,
-        name: &str,
-        macros: Macros,
-        hidden: bool,
-        starred: bool,
-    ) -> Result<(), sqlx::Error> {
+        food: FoodParams<'_>,
+    ) -> Result<String, sqlx::Error> {
+        let FoodParams {
+            name,
+            macros,
+            nutrients,
+            unit,
+            basis,
+            barcode,
+            flags,
+        } = food;
+        let id = Uuid::new_v4().to_string();
         let now = chrono::Utc::now();
-        sqlx::query("INSERT INTO foods (id, user_id, name, kcal, fat, carbs, protein, hidden_at, starred_at, created_at, updated_at) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)")
-            .bind(Uuid::new_v4().to_string())
+        let sql = format!(
+            "INSERT INTO foods ({}, id, user_id, name, kcal, fat, carbs, protein, unit, basis, barcode, hidden_at, starred_at, shared_at, created_at, updated_at) VALUES ({}, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)",
+            nutrients::sql_columns("{n}"),
+            nutrients::sql_columns("?")
+        );
+        let mut query = sqlx::query(&sql);
+        for value in nutrients.values() {
+            query = query.bind(value);
+        }
+        query
+            .bind(&id)
             .bind(user_id)
             .bind(name)
             .bind(macros.kcal)
             .bind(macros.fat)
             .bind(macros.carbs)
             .bind(macros.protein)
-            .bind(hidden.then_some(now))
-            .bind(starred.then_some(now))
+            .bind(unit)
+            .bind(basis)
+            .bind(barcode)
+            .bind(flags.hidden.then_some(now))
+            .bind(flags.starred.then_some(now))
+            .bind(flags.shared.then_some(now))
+            .bind(now)
             .bind(now)
+            .execute(&self.db_pool)
+            .await?;
+        Ok(id)
+    }
+
+    pub async fn update_food(&self, id: &str, food: FoodParams<'_>) -> Result<(), sqlx::Error> {
+        let FoodParams {
+            name,
+            macros,
+            nutrients,
+            unit,
+            basis,
+            barcode,
+            flags,
+        } = food;
+        let now = chrono::Utc::now();
+        let sql = format!(
+            "UPDATE foods SET {}, name = ?, kcal = ?, fat = ?, carbs = ?, protein = ?, unit = ?, basis = ?, barcode = ?, hidden_at = min(?, coalesce(hidden_at, datetime())), starred_at = min(?, coalesce(starred_at, datetime())), shared_at = min(?, coalesce(shared_at, datetime())), updated_at = ? WHERE id = ?",
+            nutrients::sql_columns("{n} = ?")
+        );
+        let mut query = sqlx::query(&sql);
+        for value in nutrients.values() {
+            query = query.bind(value);
+        }
+        query
+            .bind(name)
+            .bind(macros.kcal)
+            .bind(macros.fat)
+            .bind(macros.carbs)
+            .bind(macros.protein)
+            .bind(unit)
+            .bind(basis)
+            .bind(barcode)
+            .bind(flags.hidden.then_some(now))
+            .bind(flags.starred.then_some(now))
+            .bind(flags.shared.then_some(now))
             .bind(now)
+            .bind(id)
             .execute(&self.db_pool)
             .await?;
         Ok(())
     }
 
-    pub async fn update_food(
+    /// Fails if the food is used, see [`Db::get_food_references`].
+    pub async fn delete_food(&self, id: &str) -> Result<(), sqlx::Error> {
+        let mut tx = self.db_pool.begin().await?;
+        for table in ["servings", "food_aliases"] {
+            sqlx::query(format!("DELETE FROM {table} WHERE food_id = ?").as_str())
+                .bind(id)
+                .execute(&mut *tx)
+                .await?;
+        }
+        sqlx::query("DELETE FROM foods WHERE id = ?")
+            .bind(id)
+            .execute(&mut *tx)
+            .await?;
+        tx.commit().await
+    }
+
+    /// Merges the food `id` into `into_id`: its consumptions, ingredients,
+    /// servings and aliases are moved to `into_id` and it's deleted. The name
+    /// becomes an alias if `keep_alias` is set and it differs from the name
+    /// of `into_id`. Both foods need to have the same unit.
+    pub async fn merge_food(
         &self,
         id: &str,
-        name: &str,
-        macros: Macros,
-        hidden: bool,
-        starred: bool,
+        into_id: &str,
+        keep_alias: bool,
     ) -> Result<(), sqlx::Error> {
         let now = chrono::Utc::now();
-        sqlx::query("UPDATE foods SET name = ?, kcal = ?, fat = ?, carbs = ?, protein = ?, hidden_at = min(?, coalesce(hidden_at, datetime())), starred_at = min(?, coalesce(starred_at, datetime())), updated_at = ? WHERE id = ?")
-        .bind(name)
-        .bind(macros.kcal)
-        .bind(macros.fat)
-        .bind(macros.carbs)
-        .bind(macros.protein)
-        .bind(hidden.then_some(now))
-        .bind(starred.then_some(now))
-        .bind(now)
-        .bind(id)
-        .execute(&self.db_pool)
-        .await?;
-        Ok(())
-    }
+        let mut tx = self.db_pool.begin().await?;
+        let (user_id, name, barcode, shared_at) =
+            sqlx::query_as::<
+                _,
+                (
+                    UserId,
+                    String,
+                    Option<String>,
+                    Option<chrono::NaiveDateTime>,
+                ),
+            >("SELECT user_id, name, barcode, shared_at FROM foods WHERE id = ?")
+            .bind(id)
+            .fetch_one(&mut *tx)
+            .await?;
 
-    pub async fn delete_food(&self, id: &str) -> Result<(), sqlx::Error> {
+        for table in ["consumptions", "ingredients", "servings", "food_aliases"] {
+            sqlx::query(format!("UPDATE {table} SET food_id = ? WHERE food_id = ?").as_str())
+                .bind(into_id)
+                .bind(id)
+                .execute(&mut *tx)
+                .await?;
+        }
+        if keep_alias {
+            sqlx::query(
+                "INSERT INTO food_aliases (id, user_id, food_id, name, created_at, updated_at) SELECT ?1, ?2, ?3, ?4, ?5, ?5 WHERE NOT EXISTS (SELECT 1 FROM foods WHERE id = ?3 AND lower(name) = lower(?4)) AND NOT EXISTS (SELECT 1 FROM food_aliases WHERE food_id = ?3 AND lower(name) = lower(?4))",
+            )
+            .bind(Uuid::new_v4().to_string())
+            .bind(&user_id)
+            .bind(into_id)
+            .bind(&name)
+            .bind(now)
+            .execute(&mut *tx)
+            .await?;
+        }
         sqlx::query("DELETE FROM foods WHERE id = ?")
             .bind(id)
-            .execute(&self.db_pool)
+            .execute(&mut *tx)
             .await?;
-        Ok(())
+        // Barcode lookups and other users keep finding the food.
+        sqlx::query("UPDATE foods SET barcode = coalesce(barcode, ?), shared_at = coalesce(shared_at, ?), updated_at = ? WHERE id = ?")
+            .bind(barcode)
+            .bind(shared_at)
+            .bind(now)
+            .bind(into_id)
+            .execute(&mut *tx)
+            .await?;
+        tx.commit().await
     }
 
-    pub async fn get_recipes(&self, user_id: &UserId) -> Result<Vec<Recipe>, sqlx::Error> {
-        sqlx::query_as::<_, Recipe>(
-            "
-            SELECT
-                r.id,
-                r.user_id,
-                r.name,
-                r.quantity,
-                sum(f.kcal * i.quantity) as kcal,
-                sum(f.fat * i.quantity) as fat,
-                sum(f.carbs * i.quantity) as carbs,
-                sum(f.protein * i.quantity) as protein,
-                r.hidden_at,
-                r.starred_at
-            FROM
-                recipes r
-            LEFT JOIN
-                ingredients i
-                    ON
-                        r.id = i.recipe_id
-            LEFT JOIN
-                foods f
-                    ON
-                        i.food_id = f.id
-            WHERE
-                r.user_id = ?
-            GROUP BY
-                r.id
-            ORDER BY
-                r.updated_at DESC
-            ",
+    pub async fn get_food_aliases(&self, food_id: &str) -> Result<Vec<FoodAlias>, sqlx::Error> {
+        sqlx::query_as::<_, FoodAlias>(
+            "SELECT id, user_id, food_id, name FROM food_aliases WHERE food_id = ? ORDER BY name",
         )
-        .bind(user_id)
+        .bind(food_id)
         .fetch_all(&self.db_pool)
         .await
     }
 
-    pub async fn get_recipe(&self, id: &str) -> Result<Recipe, sqlx::Error> {
-        sqlx::query_as::<_, Recipe>(
-            "
-            SELECT
-                r.id,
-                r.user_id,
-                r.name,
-                r.quantity,
-                sum(f.kcal * i.quantity) as kcal,
-                sum(f.fat * i.quantity) as fat,
-                sum(f.carbs * i.quantity) as carbs,
-                sum(f.protein * i.quantity) as protein,
-                r.hidden_at,
-                r.starred_at
-            FROM
-                recipes r
-            LEFT JOIN
-                ingredients i
-                    ON
-                        r.id = i.recipe_id
-            LEFT JOIN
-                foods f
-                    ON
-                        i.food_id = f.id
-            WHERE
-                r.id = ?
-            GROUP BY
-                r.id
-            ORDER BY
-                r.updated_at DESC
-            ",
+    pub async fn get_food_alias(&self, id: &str) -> Result<FoodAlias, sqlx::Error> {
+        sqlx::query_as::<_, FoodAlias>(
+            "SELECT id, user_id, food_id, name FROM food_aliases WHERE id = ?",
         )
         .bind(id)
         .fetch_one(&self.db_pool)
         .await
     }
 
-    pub async fn add_recipe(
-        &self,
-        user_id: &UserId,
-        name: &str,
-        quantity: f64,
-        hidden: bool,
-        starred: bool,
-    ) -> Result<(), sqlx::Error> {
-        let now = chrono::Utc::now();
-        sqlx::query("
-            INSERT INTO recipes (id, user_id, name, quantity, hidden_at, starred_at, created_at, updated_at)
-            VALUES (?, ?, ?, ?, ?, ?, ?, ?)
-        ")
-            .bind(Uuid::new_v4().to_string())
-            .bind(user_id)
-            .bind(name)
-            .bind(quantity)
-            .bind(hidden.then_some(now))
-            .bind(starred.then_some(now))
-            .bind(now)
-            .bind(now)
+    pub async fn delete_food_alias(&self, id: &str) -> Result<(), sqlx::Error> {
+        sqlx::query("DELETE FROM food_aliases WHERE id = ?")
+            .bind(id)
             .execute(&self.db_pool)
             .await?;
         Ok(())
     }
 
-    pub async fn update_recipe(
-        &self,
-        id: &str,
-        name: &str,
-        quantity: f64,
-        hidden: bool,
-        starred: bool,
-    ) -> Result<(), sqlx::Error> {
-        let now = chrono::Utc::now();
-        sqlx::query("UPDATE recipes SET name = ?, quantity = ?, hidden_at = min(?, coalesce(hidden_at, datetime())), starred_at = min(?, coalesce(starred_at, datetime())), updated_at = ? WHERE id = ?")
-        .bind(name)
-        .bind(quantity)
-        .bind(hidden.then_some(now))
-        .bind(starred.then_some(now))
-        .bind(now)
+    pub async fn hide_food(&self, id: &str) -> Result<(), sqlx::Error> {
+        sqlx::query(
+            "UPDATE foods SET hidden_at = coalesce(hidden_at, datetime()), updated_at = ? WHERE id = ?",
+        )
+        .bind(chrono::Utc::now())
         .bind(id)
         .execute(&self.db_pool)
         .await?;
         Ok(())
     }
 
-    pub async fn delete_recipe(&self, id: &str) -> Result<(), sqlx::Error> {
-        sqlx::query("DELETE FROM recipes WHERE id = ?")
-            .bind(id)
-            .execute(&self.db_pool)
-            .await?;
-        Ok(())
+    pub async fn get_food_references(
+        &self,
+        user_id: &UserId,
+        id: &str,
+    ) -> Result<References, sqlx::Error> {
+        self.get_references(user_id, "food_id", "food_id", id).await
     }
 
-    pub async fn get_consumptions(
+    pub async fn get_recipe_references(
         &self,
         user_id: &UserId,
-        filter: ConsumptionFilter,
-    ) -> Result<Vec<Consumption>, sqlx::Error> {
-        let filter_sql = match &filter {
-            ConsumptionFilter::None => "?",
-            ConsumptionFilter::ConsumedAt(_) => "date(c.consumed_at) = date(?)",
-            ConsumptionFilter::FoodId(_) => "c.food_id = ?",
-            ConsumptionFilter::RecipeId(_) => "c.recipe_id = ?",
-        };
-        let filter_value = match filter {
-            ConsumptionFilter::None => Some("1".to_string()),
-            ConsumptionFilter::ConsumedAt(date) => Some(date.to_string()),
-            ConsumptionFilter::FoodId(food_id) => Some(food_id.clone()),
-            ConsumptionFilter::RecipeId(recipe_id) => Some(recipe_id.clone()),
-        };
+        id: &str,
+    ) -> Result<References, sqlx::Error> {
+        self.get_references(user_id, "recipe_id", "sub_recipe_id", id)
+            .await
+    }
+
+    async fn get_references(
+        &self,
+        user_id: &UserId,
+        consumption_column: &str,
+        ingredient_column: &str,
+        id: &str,
+    ) -> Result<References, sqlx::Error> {
+        let consumption_count: i64 = sqlx::query_scalar(&format!(
+            "SELECT count(*) FROM consumptions WHERE {consumption_column} = ? AND user_id = ?"
+        ))
+        .bind(id)
+        .bind(user_id)
+        .fetch_one(&self.db_pool)
+        .await?;
+        let consumptions = sqlx::query_as::<_, ConsumptionReference>(&format!(
+            "SELECT id, date(consumed_at) AS consumed_at FROM consumptions WHERE {consumption_column} = ? AND user_id = ? ORDER BY consumed_at DESC LIMIT ?"
+        ))
+        .bind(id)
+        .bind(user_id)
+        .bind(REFERENCES_LIMIT)
+        .fetch_all(&self.db_pool)
+        .await?;
+        let recipes = sqlx::query_as::<_, RecipeReference>(&format!(
+            "SELECT DISTINCT r.id, r.name FROM ingredients i JOIN recipes r ON r.id = i.recipe_id WHERE i.{ingredient_column} = ? AND r.user_id = ? ORDER BY r.name"
+        ))
+        .bind(id)
+        .bind(user_id)
+        .fetch_all(&self.db_pool)
+        .await?;
+        let other_users: i64 = sqlx::query_scalar(&format!(
+            "SELECT
+                (SELECT count(*) FROM consumptions WHERE {consumption_column} = ?1 AND user_id != ?2)
+                + (SELECT count(*) FROM ingredients WHERE {ingredient_column} = ?1 AND user_id != ?2)"
+        ))
+        .bind(id)
+        .bind(user_id)
+        .fetch_one(&self.db_pool)
+        .await?;
+
+        Ok(References {
+            consumption_count,
+            consumptions,
+            recipes,
+            other_users,
+        })
+    }
+
+    pub async fn get_shared_foods(&self, user_id: &UserId) -> Result<Vec<Food>, sqlx::Error> {
+        sqlx::query_as::<_, Food>(
+            format!(
+                "SELECT id, user_id, name, kcal, fat, carbs, protein, unit, basis, barcode, {}, hidden_at, starred_at, shared_at FROM foods WHERE shared_at IS NOT NULL AND user_id != ? AND hidden_at IS NULL ORDER BY name",
+                nutrients::sql_columns("{n}")
+            )
+            .as_str(),
+        )
+        .bind(user_id)
+        .fetch_all(&self.db_pool)
+        .await
+    }
+
+    pub async fn clone_food(&self, user_id: &UserId, id: &str) -> Result<String, sqlx::Error> {
+        let mut tx = self.db_pool.begin().await?;
+        let (new_id, _) = clone_food(&mut tx, user_id, id).await?;
+        tx.commit().await?;
+        Ok(new_id)
+    }
+
+    pub async fn get_product(&self, barcode: &str) -> Result<Option<Product>, sqlx::Error> {
+        sqlx::query_as::<_, Product>(
+            format!(
+                "SELECT barcode, name, kcal, fat, carbs, protein, {} FROM products WHERE barcode = ?",
+                nutrients::sql_columns("{n}")
+            )
+            .as_str(),
+        )
+        .bind(barcode)
+        .fetch_optional(&self.db_pool)
+        .await
+    }
+
+    /// Inserts or replaces products. Runs in one transaction, so callers
+    /// should pass batches instead of a whole dump.
+    pub async fn import_products(&self, products: &[Product]) -> Result<(), sqlx::Error> {
+        let sql = format!(
+            "INSERT OR REPLACE INTO products (barcode, name, kcal, fat, carbs, protein, {}) VALUES (?, ?, ?, ?, ?, ?, {})",
+            nutrients::sql_columns("{n}"),
+            nutrients::sql_columns("?")
+        );
+        let mut tx = self.db_pool.begin().await?;
+        for product in products {
+            let mut query = sqlx::query(&sql)
+                .bind(&product.barcode)
+                .bind(&product.name)
+                .bind(product.kcal)
+                .bind(product.fat)
+                .bind(product.carbs)
+                .bind(product.protein);
+            for value in product.nutrients.values() {
+                query = query.bind(value);
+            }
+            query.execute(&mut *tx).await?;
+        }
+        tx.commit().await
+    }
+
+    pub async fn get_servings(&self, food_id: &str) -> Result<Vec<Serving>, sqlx::Error> {
+        sqlx::query_as::<_, Serving>(
+            "SELECT id, user_id, food_id, name, amount FROM servings WHERE food_id = ? ORDER BY amount",
+        )
+        .bind(food_id)
+        .fetch_all(&self.db_pool)
+        .await
+    }
+
+    /// Servings of the user's foods and of shared foods.
+    pub async fn get_user_servings(&self, user_id: &UserId) -> Result<Vec<Serving>, sqlx::Error> {
+        sqlx::query_as::<_, Serving>(
+            "SELECT id, user_id, food_id, name, amount FROM servings WHERE user_id = ? OR food_id IN (SELECT id FROM foods WHERE shared_at IS NOT NULL) ORDER BY amount",
+        )
+        .bind(user_id)
+        .fetch_all(&self.db_pool)
+        .await
+    }
+
+    pub async fn get_serving(&self, id: &str) -> Result<Serving, sqlx::Error> {
+        sqlx::query_as::<_, Serving>(
+            "SELECT id, user_id, food_id, name, amount FROM servings WHERE id = ?",
+        )
+        .bind(id)
+        .fetch_one(&self.db_pool)
+        .await
+    }
+
+    pub async fn add_serving(
+        &self,
+        user_id: &UserId,
+        food_id: &str,
+        name: &str,
+        amount: f64,
+    ) -> Result<String, sqlx::Error> {
+        let id = Uuid::new_v4().to_string();
+        let now = chrono::Utc::now();
+        sqlx::query("INSERT INTO servings (id, user_id, food_id, name, amount, created_at, updated_at) VALUES (?, ?, ?, ?, ?, ?, ?)")
+            .bind(&id)
+            .bind(user_id)
+            .bind(food_id)
+            .bind(name)
+            .bind(amount)
+            .bind(now)
+            .bind(now)
+            .execute(&self.db_pool)
+            .await?;
+        Ok(id)
+    }
+
+    /// Deletes a serving. Consumptions and ingredients that were measured in
+    /// it are converted to the unit of the food, so their macros don't change.
+    pub async fn delete_serving(&self, id: &str) -> Result<(), sqlx::Error> {
+        let mut tx = self.db_pool.begin().await?;
+        for table in ["consumptions", "ingredients"] {
+            sqlx::query(
+                format!(
+                    "UPDATE {table} SET quantity = quantity * (SELECT amount FROM servings WHERE id = ?), serving_id = NULL WHERE serving_id = ?"
+                )
+                .as_str(),
+            )
+            .bind(id)
+            .bind(id)
+            .execute(&mut *tx)
+            .await?;
+        }
+        sqlx::query("DELETE FROM servings WHERE id = ?")
+            .bind(id)
+            .execute(&mut *tx)
+            .await?;
+        tx.commit().await
+    }
 
-        sqlx::query_as::<_, Consumption>(
-            format!("
+    pub async fn get_recipes(&self, user_id: &UserId) -> Result<Vec<Recipe>, sqlx::Error> {
+        sqlx::query_as::<_, Recipe>(
+            format!(
+                "
+            WITH RECURSIVE {cte}
             SELECT
-                c.id,
-                c.user_id,
-                coalesce(c.food_id, c.recipe_id) as consumable_id,
-                c.quantity,
-                date(c.consumed_at) as consumed_at,
-                coalesce(f.name, r.name) as consumable_name,
-                sum(coalesce(f.kcal * c.quantity, fi.kcal * i.quantity / r.quantity * c.quantity)) as kcal,
-                sum(coalesce(f.fat * c.quantity, fi.fat * i.quantity / r.quantity * c.quantity)) as fat,
-                sum(coalesce(f.carbs * c.quantity, fi.carbs * i.quantity / r.quantity * c.quantity)) as carbs,
-                sum(coalesce(f.protein * c.quantity, fi.protein * i.quantity / r.quantity * c.quantity)) as protein
+                r.id,
+                r.user_id,
+                r.name,
+                r.quantity,
+                coalesce(m.kcal, 0.0) as kcal,
+                coalesce(m.fat, 0.0) as fat,
+                coalesce(m.carbs, 0.0) as carbs,
+                coalesce(m.protein, 0.0) as protein,
+                {nutrients},
+                r.hidden_at,
+                r.starred_at,
+                r.shared_at
             FROM
-                consumptions c
-            LEFT JOIN
-                foods f
-                    ON
-                        c.food_id = f.id
-            LEFT JOIN
                 recipes r
-                    ON
-                        c.recipe_id = r.id
-            LEFT JOIN
-                ingredients i
-                    ON
-                        r.id = i.recipe_id
             LEFT JOIN
-                foods fi
+                recipe_macros m
                     ON
-                        i.food_id = fi.id
+                        r.id = m.recipe_id
             WHERE
-                c.user_id = ?
-                AND {filter_sql}
-            GROUP BY
-                c.id
+                r.user_id = ?
             ORDER BY
-                c.updated_at DESC
-        ").as_str(),
+                r.updated_at DESC
+            ",
+                cte = recipe_macros_cte(),
+                nutrients = nutrients::sql_columns("m.{n} as {n}"),
+            )
+            .as_str(),
         )
         .bind(user_id)
-        .bind(filter_value)
         .fetch_all(&self.db_pool)
         .await
     }
 
-    pub async fn get_consumption(&self, id: &str) -> Result<Consumption, sqlx::Error> {
-        sqlx::query_as::<_, Consumption>(
-            "
+    pub async fn get_recipe(&self, id: &str) -> Result<Recipe, sqlx::Error> {
+        sqlx::query_as::<_, Recipe>(
+            format!(
+                "
+            WITH RECURSIVE {cte}
             SELECT
-                c.id,
-                c.user_id,
-                coalesce(c.food_id, c.recipe_id) as consumable_id,
-                c.quantity,
-                date(c.consumed_at) as consumed_at,
-                coalesce(f.name, r.name) as consumable_name,
-                sum(coalesce(f.kcal * c.quantity, fi.kcal * i.quantity / r.quantity * c.quantity)) as kcal,
-                sum(coalesce(f.fat * c.quantity, fi.fat * i.quantity / r.quantity * c.quantity)) as fat,
-                sum(coalesce(f.carbs * c.quantity, fi.carbs * i.quantity / r.quantity * c.quantity)) as carbs,
-                sum(coalesce(f.protein * c.quantity, fi.protein * i.quantity / r.quantity * c.quantity)) as protein
+                r.id,
+                r.user_id,
+                r.name,
+                r.quantity,
+                coalesce(m.kcal, 0.0) as kcal,
+                coalesce(m.fat, 0.0) as fat,
+                coalesce(m.carbs, 0.0) as carbs,
+                coalesce(m.protein, 0.0) as protein,
+                {nutrients},
+                r.hidden_at,
+                r.starred_at,
+                r.shared_at
             FROM
-                consumptions c
-            LEFT JOIN
-                foods f
-                    ON
-                        c.food_id = f.id
-            LEFT JOIN
                 recipes r
-                    ON
-                        c.recipe_id = r.id
             LEFT JOIN
-                ingredients i
+                recipe_macros m
                     ON
-                        r.id = i.recipe_id
+                        r.id = m.recipe_id
+            WHERE
+                r.id = ?
+            ",
+                cte = recipe_macros_cte(),
+                nutrients = nutrients::sql_columns("m.{n} as {n}"),
+            )
+            .as_str(),
+        )
+        .bind(id)
+        .fetch_one(&self.db_pool)
+        .await
+    }
+
+    pub async fn get_shared_recipes(&self, user_id: &UserId) -> Result<Vec<Recipe>, sqlx::Error> {
+        sqlx::query_as::<_, Recipe>(
+            format!(
+                "
+            WITH RECURSIVE {cte}
+            SELECT
+                r.id,
+                r.user_id,
+                r.name,
+                r.quantity,
+                coalesce(m.kcal, 0.0) as kcal,
+                coalesce(m.fat, 0.0) as fat,
+                coalesce(m.carbs, 0.0) as carbs,
+                coalesce(m.protein, 0.0) as protein,
+                {nutrients},
+                r.hidden_at,
+                r.starred_at,
+                r.shared_at
+            FROM
+                recipes r
             LEFT JOIN
-                foods fi
+                recipe_macros m
                     ON
-                        i.food_id = fi.id
+                        r.id = m.recipe_id
             WHERE
-                c.id = ?
-            GROUP BY
-                c.id
+                r.shared_at IS NOT NULL
+                AND r.user_id != ?
+                AND r.hidden_at IS NULL
             ORDER BY
-                c.updated_at DESC
-        ",
+                r.name
+            ",
+                cte = recipe_macros_cte(),
+                nutrients = nutrients::sql_columns("m.{n} as {n}"),
+            )
+            .as_str(),
         )
-        .bind(id)
+        .bind(user_id)
+        .fetch_all(&self.db_pool)
+        .await
+    }
+
+    /// Foods and nested recipes that the user can't see are copied as well.
+    pub async fn clone_recipe(&self, user_id: &UserId, id: &str) -> Result<String, sqlx::Error> {
+        let mut tx = self.db_pool.begin().await?;
+        let now = chrono::Utc::now();
+        let new_id = Uuid::new_v4().to_string();
+        let mut recipe_ids = HashMap::from([(id.to_string(), new_id.clone())]);
+        let mut food_ids = HashMap::new();
+        let mut serving_ids = HashMap::new();
+        let mut queue = vec![id.to_string()];
+
+        while let Some(old_id) = queue.pop() {
+            sqlx::query("INSERT INTO recipes (id, user_id, name, quantity, created_at, updated_at) SELECT ?, ?, name, quantity, ?, ? FROM recipes WHERE id = ?")
+                .bind(&recipe_ids[&old_id])
+                .bind(user_id)
+                .bind(now)
+                .bind(now)
+                .bind(&old_id)
+                .execute(&mut *tx)
+                .await?;
+
+            let ingredients = sqlx::query_as::<
+                _,
+                (Option<String>, Option<String>, f64, Option<String>, bool),
+            >(
+                "
+                SELECT
+                    i.food_id,
+                    i.sub_recipe_id,
+                    i.quantity,
+                    i.serving_id,
+                    coalesce(f.user_id = ?1 OR f.shared_at IS NOT NULL, sr.user_id = ?1 OR sr.shared_at IS NOT NULL)
+                FROM
+                    ingredients i
+                LEFT JOIN
+                    foods f
+                        ON
+                            i.food_id = f.id
+                LEFT JOIN
+                    recipes sr
+                        ON
+                            i.sub_recipe_id = sr.id
+                WHERE
+                    i.recipe_id = ?2
+                ",
+            )
+            .bind(user_id)
+            .bind(&old_id)
+            .fetch_all(&mut *tx)
+            .await?;
+
+            for (mut food_id, mut sub_recipe_id, quantity, mut serving_id, visible) in ingredients {
+                if let (Some(id), false) = (&food_id, visible) {
+                    if !food_ids.contains_key(id) {
+                        let (new_food_id, new_serving_ids) =
+                            clone_food(&mut tx, user_id, id).await?;
+                        food_ids.insert(id.clone(), new_food_id);
+                        serving_ids.extend(new_serving_ids);
+                    }
+                    food_id = food_ids.get(id).cloned();
+                    serving_id = serving_id.and_then(|id| serving_ids.get(&id).cloned());
+                }
+                if let (Some(id), false) = (&sub_recipe_id, visible) {
+                    if !recipe_ids.contains_key(id) {
+                        recipe_ids.insert(id.clone(), Uuid::new_v4().to_string());
+                        queue.push(id.clone());
+                    }
+                    sub_recipe_id = recipe_ids.get(id).cloned();
+                }
+
+                sqlx::query("INSERT INTO ingredients (id, user_id, recipe_id, food_id, sub_recipe_id, quantity, serving_id, created_at, updated_at) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?)")
+                    .bind(Uuid::new_v4().to_string())
+                    .bind(user_id)
+                    .bind(&recipe_ids[&old_id])
+                    .bind(food_id)
+                    .bind(sub_recipe_id)
+                    .bind(quantity)
+                    .bind(serving_id)
+                    .bind(now)
+                    .bind(now)
+                    .execute(&mut *tx)
+                    .await?;
+            }
+        }
+
+        tx.commit().await?;
+        Ok(new_id)
+    }
+
+    /// Whether `recipe_id` is `needle_id` or contains it as a (nested)
+    /// ingredient.
+    pub async fn recipe_contains(
+        &self,
+        recipe_id: &str,
+        needle_id: &str,
+    ) -> Result<bool, sqlx::Error> {
+        sqlx::query_scalar(
+            "
+            WITH RECURSIVE contained (id) AS (
+                SELECT ?
+                UNION
+                SELECT
+                    i.sub_recipe_id
+                FROM
+                    contained c
+                JOIN
+                    ingredients i
+                        ON
+                            c.id = i.recipe_id
+                WHERE
+                    i.sub_recipe_id IS NOT NULL
+            )
+            SELECT EXISTS (SELECT 1 FROM contained WHERE id = ?)
+            ",
+        )
+        .bind(recipe_id)
+        .bind(needle_id)
         .fetch_one(&self.db_pool)
         .await
     }
 
-    pub async fn add_consumption(
+    pub async fn add_recipe(
         &self,
         user_id: &UserId,
-        food_id: Option<&str>,
-        recipe_id: Option<&str>,
+        name: &str,
         quantity: f64,
-        consumed_at: &chrono::NaiveDate,
-    ) -> Result<(), sqlx::Error> {
+        flags: EntryFlags,
+    ) -> Result<String, sqlx::Error> {
+        let id = Uuid::new_v4().to_string();
         let now = chrono::Utc::now();
-        sqlx::query("INSERT INTO consumptions (id, user_id, food_id, recipe_id, quantity, consumed_at, created_at, updated_at) VALUES (?, ?, ?, ?, ?, ?, ?, ?)")
-            .bind(Uuid::new_v4().to_string())
+        sqlx::query("
+            INSERT INTO recipes (id, user_id, name, quantity, hidden_at, starred_at, shared_at, created_at, updated_at)
+            VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?)
+        ")
+            .bind(&id)
             .bind(user_id)
-            .bind(food_id)
-            .bind(recipe_id)
+            .bind(name)
             .bind(quantity)
-            .bind(consumed_at)
+            .bind(flags.hidden.then_some(now))
+            .bind(flags.starred.then_some(now))
+            .bind(flags.shared.then_some(now))
             .bind(now)
             .bind(now)
             .execute(&self.db_pool)
             .await?;
-        Ok(())
+        Ok(id)
     }
 
-    pub async fn update_consumption(
+    pub async fn update_recipe(
         &self,
         id: &str,
-        food_id: Option<&str>,
-        recipe_id: Option<&str>,
+        name: &str,
         quantity: f64,
-        consumed_at: &chrono::NaiveDate,
+        flags: EntryFlags,
     ) -> Result<(), sqlx::Error> {
         let now = chrono::Utc::now();
-        sqlx::query("UPDATE consumptions SET food_id = ?, recipe_id = ?, quantity = ?, consumed_at = ?, updated_at = ? WHERE id = ?")
-        .bind(food_id)
-        .bind(recipe_id)
+        sqlx::query("UPDATE recipes SET name = ?, quantity = ?, hidden_at = min(?, coalesce(hidden_at, datetime())), starred_at = min(?, coalesce(starred_at, datetime())), shared_at = min(?, coalesce(shared_at, datetime())), updated_at = ? WHERE id = ?")
+        .bind(name)
         .bind(quantity)
-            .bind(consumed_at)
-            .bind(now)
-            .bind(id)
-            .execute(&self.db_pool)
-            .await?;
+        .bind(flags.hidden.then_some(now))
+        .bind(flags.starred.then_some(now))
+        .bind(flags.shared.then_some(now))
+        .bind(now)
+        .bind(id)
+        .execute(&self.db_pool)
+        .await?;
         Ok(())
     }
 
-    pub async fn delete_consumption(&self, id: &str) -> Result<(), sqlx::Error> {
-        sqlx::query("DELETE FROM consumptions WHERE id = ?")
+    /// Fails if the recipe is used, see [`Db::get_recipe_references`].
+    pub async fn delete_recipe(&self, id: &str) -> Result<(), sqlx::Error> {
+        let mut tx = self.db_pool.begin().await?;
+        sqlx::query("DELETE FROM ingredients WHERE recipe_id = ?")
             .bind(id)
-            .execute(&self.db_pool)
+            .execute(&mut *tx)
+            .await?;
+        sqlx::query("DELETE FROM recipes WHERE id = ?")
+            .bind(id)
+            .execute(&mut *tx)
             .await?;
+        tx.commit().await
+    }
+
+    pub async fn hide_recipe(&self, id: &str) -> Result<(), sqlx::Error> {
+        sqlx::query(
+            "UPDATE recipes SET hidden_at = coalesce(hidden_at, datetime()), updated_at = ? WHERE id = ?",
+        )
+        .bind(chrono::Utc::now())
+        .bind(id)
+        .execute(&self.db_pool)
+        .await?;
         Ok(())
     }
 
-    pub async fn get_ingredients(&self, recipe_id: &str) -> Result<Vec<Ingredient>, sqlx::Error> {
-        sqlx::query_as::<_, Ingredient>(
-            r#"
+    pub async fn get_consumptions(
+        &self,
+        user_id: &UserId,
+        filter: ConsumptionFilter,
+    ) -> Result<Vec<Consumption>, sqlx::Error> {
+        let (filter_sql, filter_values) = filter.sql();
+        let sql = consumptions_sql(filter_sql);
+        let mut query = sqlx::query_as::<_, Consumption>(&sql).bind(user_id);
+        for value in filter_values {
+            query = query.bind(value);
+        }
+        query.fetch_all(&self.db_pool).await
+    }
+
+    /// Sums up the consumptions of every day in `range` that has any, newest
+    /// day first. Days without consumptions are left out.
+    pub async fn get_daily_totals(
+        &self,
+        user_id: &UserId,
+        range: RangeInclusive<chrono::NaiveDate>,
+    ) -> Result<Vec<DailyTotal>, sqlx::Error> {
+        sqlx::query_as::<_, DailyTotal>(
+            format!(
+                "
             SELECT
-                i.id,
-                i.user_id,
-                i.recipe_id,
-                i.food_id,
-                f.name as food_name,
-                i.quantity,
-                sum(f.kcal * i.quantity) as kcal,
-                sum(f.fat * i.quantity) as fat,
-                sum(f.carbs * i.quantity) as carbs,
-                sum(f.protein * i.quantity) as protein
+                date(c.consumed_at) as date,
+                sum(coalesce({kcal}, 0.0)) as kcal,
+                sum(coalesce({fat}, 0.0)) as fat,
+                sum(coalesce({carbs}, 0.0)) as carbs,
+                sum(coalesce({protein}, 0.0)) as protein,
+                {nutrients}
             FROM
-                ingredients i
+                consumptions c
             LEFT JOIN
-                foods f
+                servings s
                     ON
-                        i.food_id = f.id
+                        c.serving_id = s.id
             WHERE
-                i.recipe_id = ?
+                c.user_id = ?
+                AND c.consumed_at >= ?
+                AND c.consumed_at < ?
             GROUP BY
-                i.id
-            "#,
+                date(c.consumed_at)
+            ORDER BY
+                date DESC
+        ",
+                kcal = consumption_value("kcal"),
+                fat = consumption_value("fat"),
+                carbs = consumption_value("carbs"),
+                protein = consumption_value("protein"),
+                nutrients =
+                    nutrients::sql_columns(&format!("sum({}) as {{n}}", consumption_value("{n}"))),
+            )
+            .as_str(),
         )
-        .bind(recipe_id)
+        .bind(user_id)
+        .bind(range.start())
+        .bind(range.end().succ_opt())
         .fetch_all(&self.db_pool)
         .await
     }
 
-    pub async fn get_ingredient(&self, id: &str) -> Result<Ingredient, sqlx::Error> {
-        sqlx::query_as::<_, Ingredient>(
-            r#"
-            SELECT
-                i.id,
+    pub async fn get_consumption(&self, id: &str) -> Result<Consumption, sqlx::Error> {
+        sqlx::query_as::<_, Consumption>(&consumption_sql("c.id = ?"))
+            .bind(id)
+            .fetch_one(&self.db_pool)
+            .await
+    }
+
+    pub async fn add_consumption(
+        &self,
+        user_id: &UserId,
+        consumption: ConsumptionParams<'_>,
+    ) -> Result<String, sqlx::Error> {
+        let ConsumptionParams {
+            food_id,
+            recipe_id,
+            quantity,
+            serving_id,
+            consumed_at,
+            consumed_time,
+            meal_slot_id,
+        } = consumption;
+        let id = Uuid::new_v4().to_string();
+        let now = chrono::Utc::now();
+        let mut tx = self.db_pool.begin().await?;
+        sqlx::query("INSERT INTO consumptions (id, user_id, food_id, recipe_id, quantity, serving_id, consumed_at, meal_slot_id, created_at, updated_at) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?)")
+            .bind(&id)
+            .bind(user_id)
+            .bind(food_id)
+            .bind(recipe_id)
+            .bind(quantity)
+            .bind(serving_id)
+            .bind(timestamp(&consumed_at, consumed_time))
+            .bind(meal_slot_id)
+            .bind(now)
+            .bind(now)
+            .execute(&mut *tx)
+            .await?;
+        snapshot_consumptions(&mut tx, "c.id = ?", &[&id]).await?;
+        tx.commit().await?;
+        Ok(id)
+    }
+
+    pub async fn update_consumption(
+        &self,
+        id: &str,
+        consumption: ConsumptionParams<'_>,
+    ) -> Result<(), sqlx::Error> {
+        let ConsumptionParams {
+            food_id,
+            recipe_id,
+            quantity,
+            serving_id,
+            consumed_at,
+            consumed_time,
+            meal_slot_id,
+        } = consumption;
+        let now = chrono::Utc::now();
+        let mut tx = self.db_pool.begin().await?;
+        let previous = sqlx::query_as::<_, (Option<String>, Option<String>)>(
+            "SELECT food_id, recipe_id FROM consumptions WHERE id = ?",
+        )
+        .bind(id)
+        .fetch_one(&mut *tx)
+        .await?;
+        sqlx::query("UPDATE consumptions SET food_id = ?, recipe_id = ?, quantity = ?, serving_id = ?, consumed_at = ?, meal_slot_id = ?, updated_at = ? WHERE id = ?")
+            .bind(food_id)
+            .bind(recipe_id)
+            .bind(quantity)
+            .bind(serving_id)
+            .bind(timestamp(&consumed_at, consumed_time))
+            .bind(meal_slot_id)
+            .bind(now)
+            .bind(id)
+            .execute(&mut *tx)
+            .await?;
+        // The saved values only change with the food or recipe.
+        if (previous.0.as_deref(), previous.1.as_deref()) != (food_id, recipe_id) {
+            snapshot_consumptions(&mut tx, "c.id = ?", &[id]).await?;
+        }
+        tx.commit().await
+    }
+
+    /// Replaces the saved values of a consumption with the current ones of
+    /// its food or recipe.
+    pub async fn recalculate_consumption(&self, id: &str) -> Result<(), sqlx::Error> {
+        let mut tx = self.db_pool.begin().await?;
+        snapshot_consumptions(&mut tx, "c.id = ?", &[id]).await?;
+        tx.commit().await
+    }
+
+    /// Like [`Db::recalculate_consumption`] for all consumptions of the user
+    /// that match `filter`. Returns how many there were.
+    pub async fn recalculate_consumptions(
+        &self,
+        user_id: &UserId,
+        filter: ConsumptionFilter,
+    ) -> Result<u64, sqlx::Error> {
+        let (filter_sql, filter_values) = filter.sql();
+        let values = std::iter::once(user_id.0.as_str())
+            .chain(filter_values.iter().map(String::as_str))
+            .collect::<Vec<_>>();
+        let mut tx = self.db_pool.begin().await?;
+        let count =
+            snapshot_consumptions(&mut tx, &format!("c.user_id = ? AND {filter_sql}"), &values)
+                .await?;
+        tx.commit().await?;
+        Ok(count)
+    }
+
+    pub async fn delete_consumption(&self, id: &str) -> Result<(), sqlx::Error> {
+        sqlx::query("DELETE FROM consumptions WHERE id = ?")
+            .bind(id)
+            .execute(&self.db_pool)
+            .await?;
+        Ok(())
+    }
+
+    pub async fn get_ingredients(&self, recipe_id: &str) -> Result<Vec<Ingredient>, sqlx::Error> {
+        sqlx::query_as::<_, Ingredient>(
+            format!(
+                "
+            WITH RECURSIVE {cte}
+            SELECT
+                i.id,
                 i.user_id,
                 i.recipe_id,
-                i.food_id,
-                f.name as food_name,
+                coalesce(i.food_id, i.sub_recipe_id) as consumable_id,
+                coalesce(f.name, sr.name) as consumable_name,
                 i.quantity,
-                sum(f.kcal * i.quantity) as kcal,
-                sum(f.fat * i.quantity) as fat,
-                sum(f.carbs * i.quantity) as carbs,
-                sum(f.protein * i.quantity) as protein
+                i.serving_id,
+                coalesce(s.name, f.unit) as unit,
+                coalesce(f.kcal / f.basis * i.quantity * coalesce(s.amount, 1.0), m.kcal / sr.quantity * i.quantity, 0.0) as kcal,
+                coalesce(f.fat / f.basis * i.quantity * coalesce(s.amount, 1.0), m.fat / sr.quantity * i.quantity, 0.0) as fat,
+                coalesce(f.carbs / f.basis * i.quantity * coalesce(s.amount, 1.0), m.carbs / sr.quantity * i.quantity, 0.0) as carbs,
+                coalesce(f.protein / f.basis * i.quantity * coalesce(s.amount, 1.0), m.protein / sr.quantity * i.quantity, 0.0) as protein,
+                {nutrients}
             FROM
                 ingredients i
             LEFT JOIN
                 foods f
                     ON
                         i.food_id = f.id
+            LEFT JOIN
+                servings s
+                    ON
+                        i.serving_id = s.id
+            LEFT JOIN
+                recipes sr
+                    ON
+                        i.sub_recipe_id = sr.id
+            LEFT JOIN
+                recipe_macros m
+                    ON
+                        sr.id = m.recipe_id
+            WHERE
+                i.recipe_id = ?
+            ",
+                cte = recipe_macros_cte(),
+                nutrients = nutrients::sql_columns(
+                    "coalesce(f.{n} / f.basis * i.quantity * coalesce(s.amount, 1.0), m.{n} / sr.quantity * i.quantity) as {n}"
+                ),
+            )
+            .as_str(),
+        )
+        .bind(recipe_id)
+        .fetch_all(&self.db_pool)
+        .await
+    }
+
+    pub async fn get_ingredient(&self, id: &str) -> Result<Ingredient, sqlx::Error> {
+        sqlx::query_as::<_, Ingredient>(
+            format!(
+                "
+            WITH RECURSIVE {cte}
+            SELECT
+                i.id,
+                i.user_id,
+                i.recipe_id,
+                coalesce(i.food_id, i.sub_recipe_id) as consumable_id,
+                coalesce(f.name, sr.name) as consumable_name,
+                i.quantity,
+                i.serving_id,
+                coalesce(s.name, f.unit) as unit,
+                coalesce(f.kcal / f.basis * i.quantity * coalesce(s.amount, 1.0), m.kcal / sr.quantity * i.quantity, 0.0) as kcal,
+                coalesce(f.fat / f.basis * i.quantity * coalesce(s.amount, 1.0), m.fat / sr.quantity * i.quantity, 0.0) as fat,
+                coalesce(f.carbs / f.basis * i.quantity * coalesce(s.amount, 1.0), m.carbs / sr.quantity * i.quantity, 0.0) as carbs,
+                coalesce(f.protein / f.basis * i.quantity * coalesce(s.amount, 1.0), m.protein / sr.quantity * i.quantity, 0.0) as protein,
+                {nutrients}
+            FROM
+                ingredients i
+            LEFT JOIN
+                foods f
+                    ON
+                        i.food_id = f.id
+            LEFT JOIN
+                servings s
+                    ON
+                        i.serving_id = s.id
+            LEFT JOIN
+                recipes sr
+                    ON
+                        i.sub_recipe_id = sr.id
+            LEFT JOIN
+                recipe_macros m
+                    ON
+                        sr.id = m.recipe_id
             WHERE
                 i.id = ?
-            GROUP BY
-                i.id
-            "#,
+            ",
+                cte = recipe_macros_cte(),
+                nutrients = nutrients::sql_columns(
+                    "coalesce(f.{n} / f.basis * i.quantity * coalesce(s.amount, 1.0), m.{n} / sr.quantity * i.quantity) as {n}"
+                ),
+            )
+            .as_str(),
+        )
+        .bind(id)
+        .fetch_one(&self.db_pool)
+        .await
+    }
+
+    pub async fn add_ingredient(
+        &self,
+        user_id: &UserId,
+        recipe_id: &str,
+        food_id: Option<&str>,
+        sub_recipe_id: Option<&str>,
+        quantity: f64,
+        serving_id: Option<&str>,
+    ) -> Result<String, sqlx::Error> {
+        let id = Uuid::new_v4().to_string();
+        let now = chrono::Utc::now();
+        sqlx::query("INSERT INTO ingredients (id, user_id, recipe_id, food_id, sub_recipe_id, quantity, serving_id, created_at, updated_at) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?)")
+            .bind(&id)
+            .bind(user_id)
+            .bind(recipe_id)
+            .bind(food_id)
+            .bind(sub_recipe_id)
+            .bind(quantity)
+            .bind(serving_id)
+            .bind(now)
+            .bind(now)
+            .execute(&self.db_pool)
+            .await?;
+        Ok(id)
+    }
+
+    pub async fn update_ingredient(
+        &self,
+        id: &str,
+        food_id: Option<&str>,
+        sub_recipe_id: Option<&str>,
+        quantity: f64,
+        serving_id: Option<&str>,
+    ) -> Result<(), sqlx::Error> {
+        let now = chrono::Utc::now();
+        sqlx::query(
+            "UPDATE ingredients SET food_id = ?, sub_recipe_id = ?, quantity = ?, serving_id = ?, updated_at = ? WHERE id = ?",
+        )
+        .bind(food_id)
+        .bind(sub_recipe_id)
+        .bind(quantity)
+        .bind(serving_id)
+        .bind(now)
+        .bind(id)
+        .execute(&self.db_pool)
+        .await?;
+        Ok(())
+    }
+
+    pub async fn delete_ingredient(&self, id: &str) -> Result<(), sqlx::Error> {
+        sqlx::query("DELETE FROM ingredients WHERE id = ?")
+            .bind(id)
+            .execute(&self.db_pool)
+            .await?;
+        Ok(())
+    }
+
+    pub async fn get_meal_slots(&self, user_id: &UserId) -> Result<Vec<MealSlot>, sqlx::Error> {
+        sqlx::query_as::<_, MealSlot>(
+            "SELECT id, user_id, name, position FROM meal_slots WHERE user_id = ? ORDER BY position, name",
+        )
+        .bind(user_id)
+        .fetch_all(&self.db_pool)
+        .await
+    }
+
+    pub async fn get_meal_slot(&self, id: &str) -> Result<MealSlot, sqlx::Error> {
+        sqlx::query_as::<_, MealSlot>(
+            "SELECT id, user_id, name, position FROM meal_slots WHERE id = ?",
         )
         .bind(id)
         .fetch_one(&self.db_pool)
         .await
     }
 
-    pub async fn add_ingredient(
+    pub async fn add_meal_slot(&self, user_id: &UserId, name: &str) -> Result<(), sqlx::Error> {
+        let now = chrono::Utc::now();
+        sqlx::query("
+            INSERT INTO meal_slots (id, user_id, name, position, created_at, updated_at)
+            VALUES (?, ?, ?, (SELECT coalesce(max(position), 0) + 1 FROM meal_slots WHERE user_id = ?), ?, ?)
+        ")
+            .bind(Uuid::new_v4().to_string())
+            .bind(user_id)
+            .bind(name)
+            .bind(user_id)
+            .bind(now)
+            .bind(now)
+            .execute(&self.db_pool)
+            .await?;
+        Ok(())
+    }
+
+    pub async fn update_meal_slot(
+        &self,
+        id: &str,
+        name: &str,
+        position: i64,
+    ) -> Result<(), sqlx::Error> {
+        let now = chrono::Utc::now();
+        sqlx::query("UPDATE meal_slots SET name = ?, position = ?, updated_at = ? WHERE id = ?")
+            .bind(name)
+            .bind(position)
+            .bind(now)
+            .bind(id)
+            .execute(&self.db_pool)
+            .await?;
+        Ok(())
+    }
+
+    pub async fn delete_meal_slot(&self, id: &str) -> Result<(), sqlx::Error> {
+        let mut tx = self.db_pool.begin().await?;
+        sqlx::query("UPDATE consumptions SET meal_slot_id = NULL WHERE meal_slot_id = ?")
+            .bind(id)
+            .execute(&mut *tx)
+            .await?;
+        sqlx::query("DELETE FROM meal_slots WHERE id = ?")
+            .bind(id)
+            .execute(&mut *tx)
+            .await?;
+        tx.commit().await
+    }
+
+    pub async fn get_goals(&self, user_id: &UserId) -> Result<Vec<Goal>, sqlx::Error> {
+        sqlx::query_as::<_, Goal>(
+            "SELECT weekday, kcal, fat, carbs, protein FROM goals WHERE user_id = ? ORDER BY weekday",
+        )
+        .bind(user_id)
+        .fetch_all(&self.db_pool)
+        .await
+    }
+
+    pub async fn set_goal(
+        &self,
+        user_id: &UserId,
+        weekday: Option<u32>,
+        macros: Macros,
+    ) -> Result<(), sqlx::Error> {
+        let now = chrono::Utc::now();
+        let mut tx = self.db_pool.begin().await?;
+        sqlx::query("DELETE FROM goals WHERE user_id = ? AND weekday IS ?")
+            .bind(user_id)
+            .bind(weekday)
+            .execute(&mut *tx)
+            .await?;
+        sqlx::query("INSERT INTO goals (id, user_id, weekday, kcal, fat, carbs, protein, created_at, updated_at) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?)")
+            .bind(Uuid::new_v4().to_string())
+            .bind(user_id)
+            .bind(weekday)
+            .bind(macros.kcal)
+            .bind(macros.fat)
+            .bind(macros.carbs)
+            .bind(macros.protein)
+            .bind(now)
+            .bind(now)
+            .execute(&mut *tx)
+            .await?;
+        tx.commit().await
+    }
+
+    pub async fn delete_goal(
+        &self,
+        user_id: &UserId,
+        weekday: Option<u32>,
+    ) -> Result<(), sqlx::Error> {
+        sqlx::query("DELETE FROM goals WHERE user_id = ? AND weekday IS ?")
+            .bind(user_id)
+            .bind(weekday)
+            .execute(&self.db_pool)
+            .await?;
+        Ok(())
+    }
+
+    pub async fn get_user(
+        &self,
+        username: &str,
+    ) -> Result<Option<UserWithPasswordHash>, sqlx::Error> {
+        sqlx::query_as::<_, UserWithPasswordHash>(
+            "SELECT id, password_hash, disabled_at FROM users WHERE username = ? LIMIT 1",
+        )
+        .bind(username)
+        .fetch_optional(&self.db_pool)
+        .await
+    }
+
+    pub async fn create_session(
+        &self,
+        user_id: &UserId,
+        user_agent: Option<&str>,
+        remember: bool,
+    ) -> Result<String, sqlx::Error> {
+        let session_id = new_session_id();
+        let now = chrono::Utc::now();
+        let csrf_secret =
+            base16ct::lower::encode_string(&rand::prelude::thread_rng().gen::<[u8; 32]>());
+        sqlx::query("INSERT INTO sessions (id, user_id, user_agent, remember, csrf_secret, last_seen_at, created_at) VALUES (?, ?, ?, ?, ?, ?, ?)")
+            .bind(hash_session_id(&session_id))
+            .bind(user_id)
+            .bind(user_agent)
+            .bind(remember)
+            .bind(csrf_secret)
+            .bind(now)
+            .bind(now)
+            .execute(&self.db_pool)
+            .await?;
+        Ok(session_id)
+    }
+
+    pub async fn delete_session(&self, session_id: &str) -> Result<(), sqlx::Error> {
+        self.delete_session_by_id(&hash_session_id(session_id))
+            .await
+    }
+
+    /// Sessions of the user, most recently used first.
+    pub async fn get_sessions(&self, user_id: &UserId) -> Result<Vec<UserSession>, sqlx::Error> {
+        sqlx::query_as::<_, UserSession>(
+            "SELECT id, user_id, user_agent, last_seen_at, created_at FROM sessions WHERE user_id = ? ORDER BY coalesce(last_seen_at, created_at) DESC",
+        )
+        .bind(user_id)
+        .fetch_all(&self.db_pool)
+        .await
+    }
+
+    /// Looks up a session by its id as stored, see [`UserSession`].
+    pub async fn get_session_by_id(&self, id: &str) -> Result<UserSession, sqlx::Error> {
+        sqlx::query_as::<_, UserSession>(
+            "SELECT id, user_id, user_agent, last_seen_at, created_at FROM sessions WHERE id = ?",
+        )
+        .bind(id)
+        .fetch_one(&self.db_pool)
+        .await
+    }
+
+    pub async fn delete_session_by_id(&self, id: &str) -> Result<(), sqlx::Error> {
+        sqlx::query("DELETE FROM sessions WHERE id = ?")
+            .bind(id)
+            .execute(&self.db_pool)
+            .await?;
+        Ok(())
+    }
+
+    /// Deletes all sessions of the user except the given one.
+    pub async fn delete_other_sessions(
+        &self,
+        user_id: &UserId,
+        session_id: &str,
+    ) -> Result<(), sqlx::Error> {
+        sqlx::query("DELETE FROM sessions WHERE user_id = ? AND id != ?")
+            .bind(user_id)
+            .bind(hash_session_id(session_id))
+            .execute(&self.db_pool)
+            .await?;
+        Ok(())
+    }
+
+    pub async fn delete_expired_sessions(&self) -> Result<(), sqlx::Error> {
+        let now = chrono::Utc::now();
+        sqlx::query(
+            "DELETE FROM sessions WHERE coalesce(last_seen_at, created_at) < CASE WHEN remember THEN ? ELSE ? END",
+        )
+        .bind(now - session_lifetime(true))
+        .bind(now - session_lifetime(false))
+        .execute(&self.db_pool)
+        .await?;
+        Ok(())
+    }
+
+    /// Gives the session a new id if it has had its current one for
+    /// [`SESSION_ROTATION_HOURS`]. Returns the new id and whether the session
+    /// is remembered. The previous id stays valid for
+    /// [`SESSION_ROTATION_GRACE_SECONDS`].
+    pub async fn rotate_session(
+        &self,
+        session_id: &str,
+    ) -> Result<Option<(String, bool)>, sqlx::Error> {
+        let new_session_id = new_session_id();
+        let now = chrono::Utc::now();
+        let remember: Option<bool> = sqlx::query_scalar(
+            "UPDATE sessions SET id = ?1, previous_id = id, rotated_at = ?2
+            WHERE id = ?3
+            AND coalesce(rotated_at, created_at) < ?4
+            AND coalesce(last_seen_at, created_at) > CASE WHEN remember THEN ?5 ELSE ?6 END
+            AND user_id IN (SELECT id FROM users WHERE disabled_at IS NULL)
+            RETURNING remember",
+        )
+        .bind(hash_session_id(&new_session_id))
+        .bind(now)
+        .bind(hash_session_id(session_id))
+        .bind(now - chrono::Duration::hours(SESSION_ROTATION_HOURS))
+        .bind(now - session_lifetime(true))
+        .bind(now - session_lifetime(false))
+        .fetch_optional(&self.db_pool)
+        .await?;
+        Ok(remember.map(|remember| (new_session_id, remember)))
+    }
+
+    /// Latest time until which any of the keys is locked for logins, if
+    /// one of them is locked now.
+    pub async fn get_login_locked_until(
+        &self,
+        keys: &[String],
+    ) -> Result<Option<chrono::DateTime<chrono::Utc>>, sqlx::Error> {
+        let now = chrono::Utc::now();
+        let mut locked_until = None;
+        for key in keys {
+            let key_locked_until: Option<chrono::DateTime<chrono::Utc>> = sqlx::query_scalar(
+                "SELECT locked_until FROM login_attempts WHERE key = ? AND locked_until > ?",
+            )
+            .bind(key)
+            .bind(now)
+            .fetch_optional(&self.db_pool)
+            .await?;
+            locked_until = locked_until.max(key_locked_until);
+        }
+        Ok(locked_until)
+    }
+
+    /// Counts a failed login for the key and locks it if it has used up its
+    /// free failures. Returns the number of failures in a row.
+    pub async fn record_login_failure(
+        &self,
+        key: &str,
+        free_failures: i64,
+    ) -> Result<i64, sqlx::Error> {
+        let now = chrono::Utc::now();
+        let mut tx = self.db_pool.begin().await?;
+        let failures: i64 = sqlx::query_scalar(
+            "INSERT INTO login_attempts (key, failures, last_failed_at) VALUES (?1, 1, ?2)
+            ON CONFLICT (key) DO UPDATE SET
+                failures = CASE WHEN last_failed_at < ?3 THEN 1 ELSE failures + 1 END,
+                last_failed_at = ?2
+            RETURNING failures",
+        )
+        .bind(key)
+        .bind(now)
+        .bind(now - chrono::Duration::hours(throttle::RESET_HOURS))
+        .fetch_one(&mut *tx)
+        .await?;
+        if let Some(duration) = throttle::lock_duration(failures, free_failures) {
+            sqlx::query("UPDATE login_attempts SET locked_until = ? WHERE key = ?")
+                .bind(now + duration)
+                .bind(key)
+                .execute(&mut *tx)
+                .await?;
+        }
+        tx.commit().await?;
+        Ok(failures)
+    }
+
+    pub async fn reset_login_failures(&self, key: &str) -> Result<(), sqlx::Error> {
+        sqlx::query("DELETE FROM login_attempts WHERE key = ?")
+            .bind(key)
+            .execute(&self.db_pool)
+            .await?;
+        Ok(())
+    }
+
+    pub async fn delete_stale_login_attempts(&self) -> Result<(), sqlx::Error> {
+        let now = chrono::Utc::now();
+        sqlx::query(
+            "DELETE FROM login_attempts WHERE last_failed_at < ? AND (locked_until IS NULL OR locked_until < ?)",
+        )
+        .bind(now - chrono::Duration::hours(throttle::RESET_HOURS))
+        .bind(now)
+        .execute(&self.db_pool)
+        .await?;
+        Ok(())
+    }
+
+    /// Looks up the user of a session that hasn't expired and records that
+    /// it was used, which extends its lifetime.
+    pub async fn get_session_user_id(
+        &self,
+        session_id: &str,
+    ) -> Result<Option<String>, sqlx::Error> {
+        let now = chrono::Utc::now();
+        sqlx::query_scalar(
+            "UPDATE sessions SET last_seen_at = ?1
+            WHERE (id = ?2 OR (previous_id = ?2 AND rotated_at > ?3))
+            AND coalesce(last_seen_at, created_at) > CASE WHEN remember THEN ?4 ELSE ?5 END
+            AND user_id IN (SELECT id FROM users WHERE disabled_at IS NULL)
+            RETURNING user_id",
+        )
+        .bind(now)
+        .bind(hash_session_id(session_id))
+        .bind(now - chrono::Duration::seconds(SESSION_ROTATION_GRACE_SECONDS))
+        .bind(now - session_lifetime(true))
+        .bind(now - session_lifetime(false))
+        .fetch_optional(&self.db_pool)
+        .await
+    }
+
+    /// Secret of the session for its CSRF tokens, which is kept when the
+    /// session is rotated.
+    pub async fn get_csrf_secret(&self, session_id: &str) -> Result<Option<String>, sqlx::Error> {
+        sqlx::query_scalar(
+            "SELECT csrf_secret FROM sessions WHERE id = ?1 OR (previous_id = ?1 AND rotated_at > ?2)",
+        )
+        .bind(hash_session_id(session_id))
+        .bind(chrono::Utc::now() - chrono::Duration::seconds(SESSION_ROTATION_GRACE_SECONDS))
+        .fetch_optional(&self.db_pool)
+        .await
+    }
+
+    /// Creates a token for the JSON API and returns it. Only its hash is
+    /// stored, so the token can't be shown again later.
+    pub async fn create_api_token(
+        &self,
+        user_id: &UserId,
+        name: &str,
+    ) -> Result<String, sqlx::Error> {
+        let token = base16ct::lower::encode_string(&rand::prelude::thread_rng().gen::<[u8; 32]>());
+        let hashed_token = base16ct::lower::encode_string(&Sha256::digest(&token));
+        sqlx::query("INSERT INTO api_tokens (id, user_id, name, token_hash, created_at) VALUES (?, ?, ?, ?, ?)")
+            .bind(Uuid::new_v4().to_string())
+            .bind(user_id)
+            .bind(name)
+            .bind(hashed_token)
+            .bind(chrono::Utc::now())
+            .execute(&self.db_pool)
+            .await?;
+        Ok(token)
+    }
+
+    pub async fn get_api_tokens(&self, user_id: &UserId) -> Result<Vec<ApiToken>, sqlx::Error> {
+        sqlx::query_as::<_, ApiToken>("SELECT id, user_id, name, last_used_at, created_at FROM api_tokens WHERE user_id = ? ORDER BY created_at DESC")
+            .bind(user_id)
+            .fetch_all(&self.db_pool)
+            .await
+    }
+
+    pub async fn get_api_token(&self, id: &str) -> Result<ApiToken, sqlx::Error> {
+        sqlx::query_as::<_, ApiToken>(
+            "SELECT id, user_id, name, last_used_at, created_at FROM api_tokens WHERE id = ?",
+        )
+        .bind(id)
+        .fetch_one(&self.db_pool)
+        .await
+    }
+
+    pub async fn delete_api_token(&self, id: &str) -> Result<(), sqlx::Error> {
+        sqlx::query("DELETE FROM api_tokens WHERE id = ?")
+            .bind(id)
+            .execute(&self.db_pool)
+            .await?;
+        Ok(())
+    }
+
+    /// Looks up the owner of an API token and records that it was used.
+    pub async fn get_api_token_user_id(&self, token: &str) -> Result<Option<String>, sqlx::Error> {
+        let hashed_token = base16ct::lower::encode_string(&Sha256::digest(token));
+        sqlx::query_scalar(
+            "UPDATE api_tokens SET last_used_at = ? WHERE token_hash = ? AND user_id IN (SELECT id FROM users WHERE disabled_at IS NULL) RETURNING user_id",
+        )
+        .bind(chrono::Utc::now())
+        .bind(hashed_token)
+        .fetch_optional(&self.db_pool)
+        .await
+    }
+
+    /// Nutrients the user wants to see in addition to the macros.
+    pub async fn get_shown_nutrients(
+        &self,
+        user_id: &UserId,
+    ) -> Result<Vec<&'static Nutrient>, sqlx::Error> {
+        let keys: String = sqlx::query_scalar("SELECT nutrients FROM users WHERE id = ?")
+            .bind(user_id)
+            .fetch_one(&self.db_pool)
+            .await?;
+        Ok(nutrients::parse_keys(&keys))
+    }
+
+    pub async fn set_shown_nutrients(
+        &self,
+        user_id: &UserId,
+        nutrients: &[&Nutrient],
+    ) -> Result<(), sqlx::Error> {
+        let keys = nutrients.iter().map(|n| n.key).collect::<Vec<_>>();
+        sqlx::query("UPDATE users SET nutrients = ?, updated_at = ? WHERE id = ?")
+            .bind(keys.join(","))
+            .bind(chrono::Utc::now())
+            .bind(user_id)
+            .execute(&self.db_pool)
+            .await?;
+        Ok(())
+    }
+
+    pub async fn get_weight_rule(&self, user_id: &UserId) -> Result<WeightRule, sqlx::Error> {
+        sqlx::query_scalar("SELECT weight_rule FROM users WHERE id = ?")
+            .bind(user_id)
+            .fetch_one(&self.db_pool)
+            .await
+    }
+
+    pub async fn set_weight_rule(
+        &self,
+        user_id: &UserId,
+        rule: WeightRule,
+    ) -> Result<(), sqlx::Error> {
+        sqlx::query("UPDATE users SET weight_rule = ?, updated_at = ? WHERE id = ?")
+            .bind(rule)
+            .bind(chrono::Utc::now())
+            .bind(user_id)
+            .execute(&self.db_pool)
+            .await?;
+        Ok(())
+    }
+
+    /// Time zone of the user. Zones are checked when they are set, but one
+    /// may be missing from a later version of the time zone database.
+    pub async fn get_time_zone(&self, user_id: &UserId) -> Result<Tz, sqlx::Error> {
+        let name: String = sqlx::query_scalar("SELECT time_zone FROM users WHERE id = ?")
+            .bind(user_id)
+            .fetch_one(&self.db_pool)
+            .await?;
+        Ok(name.parse().unwrap_or(Tz::UTC))
+    }
+
+    pub async fn set_time_zone(&self, user_id: &UserId, time_zone: Tz) -> Result<(), sqlx::Error> {
+        sqlx::query("UPDATE users SET time_zone = ?, updated_at = ? WHERE id = ?")
+            .bind(time_zone.name())
+            .bind(chrono::Utc::now())
+            .bind(user_id)
+            .execute(&self.db_pool)
+            .await?;
+        Ok(())
+    }
+
+    /// Current date in the time zone of the user. Consumptions and weights
+    /// are stored with the user's dates, so this is also the day they are
+    /// logged for by default.
+    pub async fn today(&self, user_id: &UserId) -> Result<chrono::NaiveDate, sqlx::Error> {
+        let time_zone = self.get_time_zone(user_id).await?;
+        Ok(chrono::Utc::now().with_timezone(&time_zone).date_naive())
+    }
+
+    pub async fn create_user(&self, username: &str, password: &str) -> Result<UserId, sqlx::Error> {
+        let now = chrono::Utc::now();
+        let password_hash = hash_password(password);
+        let user_id = Uuid::new_v4().to_string();
+        let mut tx = self.db_pool.begin().await?;
+
+        sqlx::query(
+            "INSERT INTO users (id, username, password_hash, created_at, updated_at) VALUES (?, ?, ?, ?, ?)",
+        )
+        .bind(&user_id)
+        .bind(username)
+        .bind(password_hash)
+        .bind(now)
+        .bind(now)
+        .execute(&mut *tx)
+        .await?;
+
+        for (position, name) in (1..).zip(DEFAULT_MEAL_SLOTS) {
+            sqlx::query("INSERT INTO meal_slots (id, user_id, name, position, created_at, updated_at) VALUES (?, ?, ?, ?, ?, ?)")
+                .bind(Uuid::new_v4().to_string())
+                .bind(&user_id)
+                .bind(name)
+                .bind(position)
+                .bind(now)
+                .bind(now)
+                .execute(&mut *tx)
+                .await?;
+        }
+
+        tx.commit().await?;
+        Ok(UserId(user_id))
+    }
+
+    pub async fn get_users(&self) -> Result<Vec<User>, sqlx::Error> {
+        sqlx::query_as::<_, User>(
+            "SELECT id, username, is_admin, disabled_at, created_at FROM users ORDER BY username",
+        )
+        .fetch_all(&self.db_pool)
+        .await
+    }
+
+    pub async fn get_user_by_id(&self, user_id: &UserId) -> Result<User, sqlx::Error> {
+        sqlx::query_as::<_, User>(
+            "SELECT id, username, is_admin, disabled_at, created_at FROM users WHERE id = ?",
+        )
+        .bind(user_id)
+        .fetch_one(&self.db_pool)
+        .await
+    }
+
+    pub async fn set_user_admin(
         &self,
         user_id: &UserId,
-        recipe_id: &str,
-        food_id: &str,
-        quantity: f64,
+        is_admin: bool,
     ) -> Result<(), sqlx::Error> {
-        let now = chrono::Utc::now();
-        sqlx::query("INSERT INTO ingredients (id, user_id, recipe_id, food_id, quantity, created_at, updated_at) VALUES (?, ?, ?, ?, ?, ?, ?)")
-            .bind(Uuid::new_v4().to_string())
+        sqlx::query("UPDATE users SET is_admin = ?, updated_at = ? WHERE id = ?")
+            .bind(is_admin)
+            .bind(chrono::Utc::now())
             .bind(user_id)
-            .bind(recipe_id)
-            .bind(food_id)
-            .bind(quantity)
-            .bind(now)
-            .bind(now)
             .execute(&self.db_pool)
             .await?;
         Ok(())
     }
 
-    pub async fn update_ingredient(
+    /// Disabled users can't log in, and their sessions and API tokens stop
+    /// working. Their data is kept.
+    pub async fn set_user_disabled(
         &self,
-        id: &str,
-        food_id: &str,
-        quantity: f64,
+        user_id: &UserId,
+        disabled: bool,
     ) -> Result<(), sqlx::Error> {
         let now = chrono::Utc::now();
-        sqlx::query(
-            "UPDATE ingredients SET food_id = ?, quantity = ?, updated_at = ? WHERE id = ?",
-        )
-        .bind(food_id)
-        .bind(quantity)
-        .bind(now)
-        .bind(id)
-        .execute(&self.db_pool)
-        .await?;
-        Ok(())
-    }
-
-    pub async fn delete_ingredient(&self, id: &str) -> Result<(), sqlx::Error> {
-        sqlx::query("DELETE FROM ingredients WHERE id = ?")
-            .bind(id)
+        sqlx::query("UPDATE users SET disabled_at = min(?, coalesce(disabled_at, ?)), updated_at = ? WHERE id = ?")
+            .bind(disabled.then_some(now))
+            .bind(now)
+            .bind(now)
+            .bind(user_id)
             .execute(&self.db_pool)
             .await?;
+        if disabled {
+            self.delete_user_sessions(user_id).await?;
+        }
         Ok(())
     }
 
-    pub async fn get_user(
-        &self,
-        username: &str,
-    ) -> Result<Option<UserWithPasswordHash>, sqlx::Error> {
-        sqlx::query_as::<_, UserWithPasswordHash>(
-            "SELECT id, password_hash FROM users WHERE username = ? LIMIT 1",
-        )
-        .bind(username)
-        .fetch_optional(&self.db_pool)
-        .await
-    }
-
-    pub async fn create_session(&self, user_id: &UserId) -> Result<String, sqlx::Error> {
-        let session_id = rand::prelude::thread_rng().gen::<u128>().to_string();
-        let hashed_id = base16ct::lower::encode_string(&Sha256::digest(&session_id));
-        let now = chrono::Utc::now();
-        sqlx::query("INSERT INTO sessions (id, user_id, created_at) VALUES (?, ?, ?)")
-            .bind(&hashed_id)
+    pub async fn get_password_hash(&self, user_id: &UserId) -> Result<String, sqlx::Error> {
+        sqlx::query_scalar("SELECT password_hash FROM users WHERE id = ?")
             .bind(user_id)
-            .bind(now)
-            .execute(&self.db_pool)
-            .await?;
-        Ok(session_id)
+            .fetch_one(&self.db_pool)
+            .await
     }
 
-    pub async fn delete_session(&self, session_id: &str) -> Result<(), sqlx::Error> {
-        let hashed_id = base16ct::lower::encode_string(&Sha256::digest(session_id));
-        sqlx::query("DELETE FROM sessions WHERE id = ?")
-            .bind(hashed_id)
+    pub async fn set_password(&self, user_id: &UserId, password: &str) -> Result<(), sqlx::Error> {
+        sqlx::query("UPDATE users SET password_hash = ?, updated_at = ? WHERE id = ?")
+            .bind(hash_password(password))
+            .bind(chrono::Utc::now())
+            .bind(user_id)
             .execute(&self.db_pool)
             .await?;
         Ok(())
     }
 
-    pub async fn delete_expired_sessions(&self) -> Result<(), sqlx::Error> {
-        let created_at = chrono::Utc::now() - chrono::Duration::days(SESSION_DAYS);
-        sqlx::query("DELETE FROM sessions WHERE created_at < ?")
-            .bind(created_at)
+    pub async fn delete_user_sessions(&self, user_id: &UserId) -> Result<(), sqlx::Error> {
+        sqlx::query("DELETE FROM sessions WHERE user_id = ?")
+            .bind(user_id)
             .execute(&self.db_pool)
             .await?;
         Ok(())
     }
 
-    pub async fn get_session_user_id(
+    /// Number of consumptions and ingredients of other users that refer to
+    /// shared foods, recipes or servings of the user.
+    pub async fn count_user_entries_used_by_others(
         &self,
-        session_id: &str,
-    ) -> Result<Option<String>, sqlx::Error> {
-        let hashed_id = base16ct::lower::encode_string(&Sha256::digest(session_id));
-        sqlx::query_scalar("SELECT user_id FROM sessions WHERE id = ? LIMIT 1")
-            .bind(hashed_id)
-            .fetch_optional(&self.db_pool)
-            .await
+        user_id: &UserId,
+    ) -> Result<i64, sqlx::Error> {
+        let mut conn = self.db_pool.acquire().await?;
+        count_entries_used_by_others(&mut conn, user_id).await
     }
 
-    pub async fn create_user(&self, username: &str, password: &str) -> Result<(), sqlx::Error> {
-        let now = chrono::Utc::now();
+    /// Deletes the user with all their data, sessions and API tokens. Fails
+    /// if other users use shared entries of the user, see
+    /// [`Db::count_user_entries_used_by_others`].
+    pub async fn delete_user(&self, user_id: &UserId) -> Result<(), sqlx::Error> {
+        let mut tx = self.db_pool.begin().await?;
+        for table in USER_DATA_TABLES.iter().chain(&["sessions", "api_tokens"]) {
+            sqlx::query(format!("DELETE FROM {table} WHERE user_id = ?").as_str())
+                .bind(user_id)
+                .execute(&mut *tx)
+                .await?;
+        }
+        sqlx::query("DELETE FROM users WHERE id = ?")
+            .bind(user_id)
+            .execute(&mut *tx)
+            .await?;
+        tx.commit().await
+    }
 
-        let salt = SaltString::generate(&mut OsRng);
-        let password_hash = Argon2::default()
-            .hash_password(password.as_bytes(), &salt)
-            .unwrap()
-            .to_string();
+    pub async fn export_data(&self, user_id: &UserId) -> Result<ExportData, sqlx::Error> {
+        let weights = sqlx::query_as::<_, export::WeightRow>(
+            "SELECT id, weight, date(measured_at) as measured_at, CASE WHEN length(measured_at) > 10 THEN time(measured_at) END as measured_time, created_at, updated_at FROM weights WHERE user_id = ?",
+        ).bind(user_id).fetch_all(&self.db_pool).await?;
 
-        sqlx::query(
-            "INSERT INTO users (id, username, password_hash, created_at, updated_at) VALUES (?, ?, ?, ?, ?)",
+        // The owner's flags of shared entries of other users are left out.
+        let foods = sqlx::query_as::<_, export::FoodRow>(
+            format!(
+                "WITH RECURSIVE {EXPORT_SHARED_CTE} SELECT id, name, kcal, fat, carbs, protein, unit, basis, barcode, {}, CASE WHEN user_id = ?1 THEN hidden_at END as hidden_at, CASE WHEN user_id = ?1 THEN starred_at END as starred_at, CASE WHEN user_id = ?1 THEN shared_at END as shared_at, created_at, updated_at FROM foods WHERE user_id = ?1 OR id IN (SELECT id FROM foreign_foods)",
+                nutrients::sql_columns("{n}")
+            )
+            .as_str(),
+        ).bind(user_id).fetch_all(&self.db_pool).await?;
+
+        let consumptions = sqlx::query_as::<_, export::ConsumptionRow>(
+            format!(
+                "SELECT id, food_id, recipe_id, quantity, serving_id, date(consumed_at) as consumed_at, CASE WHEN length(consumed_at) > 10 THEN time(consumed_at) END as consumed_time, meal_slot_id, unit_kcal, unit_fat, unit_carbs, unit_protein, {}, created_at, updated_at FROM consumptions WHERE user_id = ?",
+                nutrients::sql_columns("unit_{n} as {n}")
+            )
+            .as_str(),
+        ).bind(user_id).fetch_all(&self.db_pool).await?;
+
+        let recipes = sqlx::query_as::<_, export::RecipeRow>(
+            format!(
+                "WITH RECURSIVE {EXPORT_SHARED_CTE} SELECT id, name, quantity, CASE WHEN user_id = ?1 THEN hidden_at END as hidden_at, CASE WHEN user_id = ?1 THEN starred_at END as starred_at, CASE WHEN user_id = ?1 THEN shared_at END as shared_at, created_at, updated_at FROM recipes WHERE user_id = ?1 OR id IN (SELECT id FROM foreign_recipes)",
+            )
+            .as_str(),
+        ).bind(user_id).fetch_all(&self.db_pool).await?;
+
+        let ingredients = sqlx::query_as::<_, export::IngredientRow>(
+            format!(
+                "WITH RECURSIVE {EXPORT_SHARED_CTE} SELECT id, recipe_id, food_id, sub_recipe_id, quantity, serving_id, created_at, updated_at FROM ingredients WHERE user_id = ?1 OR id IN (SELECT id FROM foreign_ingredients)",
+            )
+            .as_str(),
+        ).bind(user_id).fetch_all(&self.db_pool).await?;
+
+        let goals = sqlx::query_as::<_, export::GoalRow>(
+            "SELECT id, weekday, kcal, fat, carbs, protein, created_at, updated_at FROM goals WHERE user_id = ?",
+        ).bind(user_id).fetch_all(&self.db_pool).await?;
+
+        let meal_slots = sqlx::query_as::<_, export::MealSlotRow>(
+            "SELECT id, name, position, created_at, updated_at FROM meal_slots WHERE user_id = ?",
         )
-        .bind(Uuid::new_v4().to_string())
-        .bind(username)
-        .bind(password_hash)
-        .bind(now)
-        .bind(now)
-        .fetch_optional(&self.db_pool)
+        .bind(user_id)
+        .fetch_all(&self.db_pool)
         .await?;
 
-        Ok(())
+        let servings = sqlx::query_as::<_, export::ServingRow>(
+            format!(
+                "WITH RECURSIVE {EXPORT_SHARED_CTE} SELECT id, food_id, name, amount, created_at, updated_at FROM servings WHERE user_id = ?1 OR id IN (SELECT id FROM foreign_servings)",
+            )
+            .as_str(),
+        )
+        .bind(user_id)
+        .fetch_all(&self.db_pool)
+        .await?;
+
+        let food_aliases = sqlx::query_as::<_, export::FoodAliasRow>(
+            "SELECT id, food_id, name, created_at, updated_at FROM food_aliases WHERE user_id = ?",
+        )
+        .bind(user_id)
+        .fetch_all(&self.db_pool)
+        .await?;
+
+        Ok(ExportData {
+            user_id: user_id.0.clone(),
+            exported_at: chrono::Utc::now(),
+            weights,
+            foods,
+            consumptions,
+            recipes,
+            ingredients,
+            goals,
+            meal_slots,
+            servings,
+            food_aliases,
+        })
     }
 
-    pub async fn export_data(&self, user_id: &UserId) -> Result<serde_json::Value, sqlx::Error> {
-        #[derive(sqlx::FromRow, serde::Serialize)]
-        struct WeightRow {
-            id: String,
-            weight: f64,
-            measured_at: chrono::NaiveDate,
-            created_at: chrono::NaiveDateTime,
-            updated_at: chrono::NaiveDateTime,
+    /// Imports an export in a single transaction. Data exported by another
+    /// user gets fresh ids; entries that already exist are kept as they are.
+    /// Replacing fails if other users use shared entries of the user, and
+    /// keeps the meal slots if the export has none.
+    pub async fn import_data(
+        &self,
+        user_id: &UserId,
+        data: ExportData,
+        mode: ImportMode,
+    ) -> Result<(), ImportError> {
+        let data = if data.is_from(user_id) {
+            data
+        } else {
+            data.with_new_ids()
+        };
+
+        let mut tx = self.db_pool.begin().await?;
+
+        if mode == ImportMode::Replace {
+            let used_by_others = count_entries_used_by_others(&mut tx, user_id).await?;
+            if used_by_others > 0 {
+                return Err(ImportError::Invalid(format!(
+                    "Your shared entries are used {} times by other users, so your data can't be replaced. Merge the import instead.",
+                    used_by_others
+                )));
+            }
+            for table in USER_DATA_TABLES {
+                if table == "meal_slots" && data.meal_slots.is_empty() {
+                    continue;
+                }
+                sqlx::query(format!("DELETE FROM {table} WHERE user_id = ?").as_str())
+                    .bind(user_id)
+                    .execute(&mut *tx)
+                    .await?;
+            }
         }
 
-        #[derive(sqlx::FromRow, serde::Serialize)]
-        struct FoodRow {
-            id: String,
-            name: String,
-            kcal: f64,
-            fat: f64,
-            carbs: f64,
-            protein: f64,
-            hidden_at: Option<chrono::NaiveDateTime>,
-            starred_at: Option<chrono::NaiveDateTime>,
-            created_at: chrono::NaiveDateTime,
-            updated_at: chrono::NaiveDateTime,
+        // Meals are matched by name, so merging doesn't duplicate the defaults.
+        let mut meal_slot_ids = HashMap::new();
+        for row in &data.meal_slots {
+            let existing: Option<String> =
+                sqlx::query_scalar("SELECT id FROM meal_slots WHERE user_id = ? AND name = ?")
+                    .bind(user_id)
+                    .bind(&row.name)
+                    .fetch_optional(&mut *tx)
+                    .await?;
+            if let Some(existing) = existing {
+                meal_slot_ids.insert(row.id.as_str(), existing);
+                continue;
+            }
+
+            let result = sqlx::query("INSERT INTO meal_slots (id, user_id, name, position, created_at, updated_at) VALUES (?, ?, ?, ?, ?, ?) ON CONFLICT (id) DO NOTHING")
+                .bind(&row.id)
+                .bind(user_id)
+                .bind(&row.name)
+                .bind(row.position)
+                .bind(row.created_at)
+                .bind(row.updated_at)
+                .execute(&mut *tx)
+                .await?;
+            if result.rows_affected() == 0 {
+                check_owner(&mut tx, "meal_slots", &row.id, user_id).await?;
+            }
         }
 
-        #[derive(sqlx::FromRow, serde::Serialize)]
-        struct ConsumptionRow {
-            id: String,
-            food_id: Option<String>,
-            recipe_id: Option<String>,
-            quantity: f64,
-            consumed_at: chrono::NaiveDate,
-            created_at: chrono::NaiveDateTime,
-            updated_at: chrono::NaiveDateTime,
+        let insert_food_sql = format!(
+            "INSERT INTO foods ({}, id, user_id, name, kcal, fat, carbs, protein, unit, basis, barcode, hidden_at, starred_at, shared_at, created_at, updated_at) VALUES ({}, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?) ON CONFLICT (id) DO NOTHING",
+            nutrients::sql_columns("{n}"),
+            nutrients::sql_columns("?")
+        );
+        for row in &data.foods {
+            let mut query = sqlx::query(&insert_food_sql);
+            for value in row.nutrients.values() {
+                query = query.bind(value);
+            }
+            let result = query
+                .bind(&row.id)
+                .bind(user_id)
+                .bind(&row.name)
+                .bind(row.kcal)
+                .bind(row.fat)
+                .bind(row.carbs)
+                .bind(row.protein)
+                .bind(&row.unit)
+                .bind(row.basis)
+                .bind(&row.barcode)
+                .bind(row.hidden_at)
+                .bind(row.starred_at)
+                .bind(row.shared_at)
+                .bind(row.created_at)
+                .bind(row.updated_at)
+                .execute(&mut *tx)
+                .await?;
+            if result.rows_affected() == 0 {
+                check_owner(&mut tx, "foods", &row.id, user_id).await?;
+            }
         }
 
-        #[derive(sqlx::FromRow, serde::Serialize)]
-        struct RecipeRow {
-            id: String,
-            name: String,
-            quantity: f64,
-            hidden_at: Option<chrono::NaiveDateTime>,
-            starred_at: Option<chrono::NaiveDateTime>,
-            created_at: chrono::NaiveDateTime,
-            updated_at: chrono::NaiveDateTime,
+        for row in &data.servings {
+            let result = sqlx::query("INSERT INTO servings (id, user_id, food_id, name, amount, created_at, updated_at) VALUES (?, ?, ?, ?, ?, ?, ?) ON CONFLICT (id) DO NOTHING")
+                .bind(&row.id)
+                .bind(user_id)
+                .bind(&row.food_id)
+                .bind(&row.name)
+                .bind(row.amount)
+                .bind(row.created_at)
+                .bind(row.updated_at)
+                .execute(&mut *tx)
+                .await?;
+            if result.rows_affected() == 0 {
+                check_owner(&mut tx, "servings", &row.id, user_id).await?;
+            }
         }
 
-        #[derive(sqlx::FromRow, serde::Serialize)]
-        struct IngredientRow {
-            id: String,
-            recipe_id: String,
-            food_id: String,
-            quantity: f64,
-            created_at: chrono::NaiveDateTime,
-            updated_at: chrono::NaiveDateTime,
+        for row in &data.food_aliases {
+            // Shared foods of other users keep their own aliases.
+            let is_own: bool = sqlx::query_scalar(
+                "SELECT EXISTS (SELECT 1 FROM foods WHERE id = ? AND user_id = ?)",
+            )
+            .bind(&row.food_id)
+            .bind(user_id)
+            .fetch_one(&mut *tx)
+            .await?;
+            if !is_own {
+                continue;
+            }
+
+            let result = sqlx::query("INSERT INTO food_aliases (id, user_id, food_id, name, created_at, updated_at) VALUES (?, ?, ?, ?, ?, ?) ON CONFLICT (id) DO NOTHING")
+                .bind(&row.id)
+                .bind(user_id)
+                .bind(&row.food_id)
+                .bind(&row.name)
+                .bind(row.created_at)
+                .bind(row.updated_at)
+                .execute(&mut *tx)
+                .await?;
+            if result.rows_affected() == 0 {
+                check_owner(&mut tx, "food_aliases", &row.id, user_id).await?;
+            }
         }
 
-        let weights = sqlx::query_as::<_, WeightRow>(
-            "SELECT id, weight, date(measured_at) as measured_at, created_at, updated_at FROM weights WHERE user_id = ?",
-        ).bind(user_id).fetch_all(&self.db_pool).await?;
+        for row in &data.recipes {
+            let result = sqlx::query("INSERT INTO recipes (id, user_id, name, quantity, hidden_at, starred_at, shared_at, created_at, updated_at) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?) ON CONFLICT (id) DO NOTHING")
+                .bind(&row.id)
+                .bind(user_id)
+                .bind(&row.name)
+                .bind(row.quantity)
+                .bind(row.hidden_at)
+                .bind(row.starred_at)
+                .bind(row.shared_at)
+                .bind(row.created_at)
+                .bind(row.updated_at)
+                .execute(&mut *tx)
+                .await?;
+            if result.rows_affected() == 0 {
+                check_owner(&mut tx, "recipes", &row.id, user_id).await?;
+            }
+        }
 
-        let foods = sqlx::query_as::<_, FoodRow>(
-            "SELECT id, name, kcal, fat, carbs, protein,  hidden_at, starred_at, created_at, updated_at FROM foods WHERE user_id = ?",
-        ).bind(user_id).fetch_all(&self.db_pool).await?;
+        for row in &data.ingredients {
+            let result = sqlx::query("INSERT INTO ingredients (id, user_id, recipe_id, food_id, sub_recipe_id, quantity, serving_id, created_at, updated_at) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?) ON CONFLICT (id) DO NOTHING")
+                .bind(&row.id)
+                .bind(user_id)
+                .bind(&row.recipe_id)
+                .bind(&row.food_id)
+                .bind(&row.sub_recipe_id)
+                .bind(row.quantity)
+                .bind(&row.serving_id)
+                .bind(row.created_at)
+                .bind(row.updated_at)
+                .execute(&mut *tx)
+                .await?;
+            if result.rows_affected() == 0 {
+                check_owner(&mut tx, "ingredients", &row.id, user_id).await?;
+            }
+        }
 
-        let consumptions = sqlx::query_as::<_, ConsumptionRow>(
-            "SELECT id, food_id, recipe_id, quantity, date(consumed_at) as consumed_at, created_at, updated_at FROM consumptions WHERE user_id = ?",
-        ).bind(user_id).fetch_all(&self.db_pool).await?;
+        let insert_consumption_sql = format!(
+            "INSERT INTO consumptions ({}, id, user_id, food_id, recipe_id, quantity, serving_id, consumed_at, meal_slot_id, unit_kcal, unit_fat, unit_carbs, unit_protein, created_at, updated_at) VALUES ({}, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?) ON CONFLICT (id) DO NOTHING",
+            nutrients::sql_columns("unit_{n}"),
+            nutrients::sql_columns("?")
+        );
+        for row in &data.consumptions {
+            let mut query = sqlx::query(&insert_consumption_sql);
+            for value in row.unit_nutrients.values() {
+                query = query.bind(value);
+            }
+            let result = query
+                .bind(&row.id)
+                .bind(user_id)
+                .bind(&row.food_id)
+                .bind(&row.recipe_id)
+                .bind(row.quantity)
+                .bind(&row.serving_id)
+                .bind(timestamp(&row.consumed_at, row.consumed_time))
+                .bind(
+                    row.meal_slot_id
+                        .as_deref()
+                        .map(|id| meal_slot_ids.get(id).map_or(id, |id| id.as_str())),
+                )
+                .bind(row.unit_kcal)
+                .bind(row.unit_fat)
+                .bind(row.unit_carbs)
+                .bind(row.unit_protein)
+                .bind(row.created_at)
+                .bind(row.updated_at)
+                .execute(&mut *tx)
+                .await?;
+            if result.rows_affected() == 0 {
+                check_owner(&mut tx, "consumptions", &row.id, user_id).await?;
+            }
+        }
+        snapshot_consumptions(
+            &mut tx,
+            "c.user_id = ? AND c.unit_kcal IS NULL",
+            &[&user_id.0],
+        )
+        .await?;
 
-        let recipes = sqlx::query_as::<_, RecipeRow>(
-            "SELECT id, name, quantity, hidden_at, starred_at, created_at, updated_at FROM recipes WHERE user_id = ?",
-        ).bind(user_id).fetch_all(&self.db_pool).await?;
+        for row in &data.weights {
+            let result = sqlx::query("INSERT INTO weights (id, user_id, weight, measured_at, created_at, updated_at) VALUES (?, ?, ?, ?, ?, ?) ON CONFLICT (id) DO NOTHING")
+                .bind(&row.id)
+                .bind(user_id)
+                .bind(row.weight)
+                .bind(timestamp(&row.measured_at, row.measured_time))
+                .bind(row.created_at)
+                .bind(row.updated_at)
+                .execute(&mut *tx)
+                .await?;
+            if result.rows_affected() == 0 {
+                check_owner(&mut tx, "weights", &row.id, user_id).await?;
+            }
+        }
 
-        let ingredients = sqlx::query_as::<_, IngredientRow>(
-            "SELECT id, recipe_id, food_id, quantity, created_at, updated_at FROM ingredients WHERE user_id = ?",
-        ).bind(user_id).fetch_all(&self.db_pool).await?;
+        for row in &data.goals {
+            sqlx::query("
+                INSERT INTO goals (id, user_id, weekday, kcal, fat, carbs, protein, created_at, updated_at)
+                SELECT ?, ?, ?, ?, ?, ?, ?, ?, ?
+                WHERE NOT EXISTS (SELECT 1 FROM goals WHERE user_id = ? AND weekday IS ?)
+                ON CONFLICT (id) DO NOTHING
+            ")
+                .bind(&row.id)
+                .bind(user_id)
+                .bind(row.weekday)
+                .bind(row.kcal)
+                .bind(row.fat)
+                .bind(row.carbs)
+                .bind(row.protein)
+                .bind(row.created_at)
+                .bind(row.updated_at)
+                .bind(user_id)
+                .bind(row.weekday)
+                .execute(&mut *tx)
+                .await?;
+        }
 
-        Ok(json!({
-            "user_id": user_id.0,
-            "exported_at": chrono::Utc::now(),
-            "weights": weights,
-            "foods": foods,
-            "consumptions": consumptions,
-            "recipes": recipes,
-            "ingredients": ingredients,
-        }))
+        tx.commit().await?;
+
+        Ok(())
     }
 
     pub async fn get_consumables(&self, user_id: &UserId) -> Result<Vec<Consumable>, sqlx::Error> {
+        let today = self.today(user_id).await?;
+        // Timestamps on the first day compare greater than its bare date.
+        let week_start = today - chrono::Duration::days(6);
         let foods = sqlx::query_as::<
             _,
             (
                 String,
                 String,
+                Option<String>,
                 Option<chrono::NaiveDateTime>,
+                bool,
                 chrono::NaiveDateTime,
                 Option<chrono::NaiveDate>,
                 Option<i64>,
+                Option<String>,
             ),
-        >(
-            r#"
-            SELECT
-                f.id,
-                f.name,
-                f.starred_at,
-                f.created_at,
-                MAX(DATE(c.consumed_at)) as last_consumed_at,
-                COUNT(c.id) as consumed_count
-            FROM
-                foods f
-            LEFT JOIN
-                consumptions c
-                    ON
-                        f.id = c.food_id
-                        AND c.consumed_at > DATE('now', '-7 days')
-            WHERE
-                f.user_id = ?
-                AND f.hidden_at IS NULL
-            GROUP BY
-                f.id
-        "#,
-        )
+        >(CONSUMABLE_FOODS_SQL)
         .bind(user_id)
+        .bind(week_start)
         .fetch_all(&self.db_pool)
         .await?;
 
@@ -935,35 +2763,17 @@ impl Db {
             (
                 String,
                 String,
+                Option<String>,
                 Option<chrono::NaiveDateTime>,
+                bool,
                 chrono::NaiveDateTime,
                 Option<chrono::NaiveDate>,
                 Option<i64>,
+                Option<String>,
             ),
-        >(
-            r#"
-            SELECT
-                r.id,
-                r.name,
-                r.starred_at,
-                r.created_at,
-                MAX(DATE(c.consumed_at)) as last_consumed_at,
-                COUNT(c.id) as consumed_count
-            FROM
-                recipes r
-            LEFT JOIN
-                consumptions c
-                    ON
-                        r.id = c.recipe_id
-                        AND c.consumed_at > DATE('now', '-7 days')
-            WHERE
-                r.user_id = ?
-                AND r.hidden_at IS NULL
-            GROUP BY
-                r.id
-        "#,
-        )
+        >(CONSUMABLE_RECIPES_SQL)
         .bind(user_id)
+        .bind(week_start)
         .fetch_all(&self.db_pool)
         .await?;
 
@@ -973,19 +2783,25 @@ impl Db {
                 ctype: ConsumableType::Food,
                 id: f.0,
                 name: f.1,
-                is_starred: f.2.is_some(),
-                created_at: f.3,
-                last_consumed_at: f.4,
-                consumed_count: f.5,
+                unit: f.2,
+                is_starred: f.3.is_some(),
+                is_shared: f.4,
+                aliases: f.8,
+                created_at: f.5,
+                days_since_consumed: f.6.map(|date| (today - date).num_days()),
+                consumed_count: f.7,
             })
             .chain(recipes.into_iter().map(|r| Consumable {
                 ctype: ConsumableType::Recipe,
                 id: r.0,
                 name: r.1,
-                is_starred: r.2.is_some(),
-                created_at: r.3,
-                last_consumed_at: r.4,
-                consumed_count: r.5,
+                unit: r.2,
+                is_starred: r.3.is_some(),
+                is_shared: r.4,
+                aliases: r.8,
+                created_at: r.5,
+                days_since_consumed: r.6.map(|date| (today - date).num_days()),
+                consumed_count: r.7,
             }))
             .collect();
 
@@ -1022,11 +2838,7 @@ fn sorting_points(consumable: &Consumable) -> i64 {
     if consumable.is_starred {
         points += 100;
     }
-    if let Some(last_consumed_at) = consumable.last_consumed_at {
-        let days_ago = chrono::Utc::now()
-            .date_naive()
-            .signed_duration_since(last_consumed_at)
-            .num_days();
+    if let Some(days_ago) = consumable.days_since_consumed {
         points += 100 - days_ago;
     }
     if let Some(consumed_count) = consumable.consumed_count {
@@ -1042,3 +2854,603 @@ fn sorting_points(consumable: &Consumable) -> i64 {
     }
     points
 }
+
+/// Consumptions with their consumable and macros, filtered by `filter_sql`
+/// in addition to the user. Newest days come first, within a day they are
+/// sorted by time, where those without a time come first.
+fn consumptions_sql(filter_sql: &str) -> String {
+    format!(
+        "{}
+        ORDER BY
+            date(c.consumed_at) DESC,
+            c.consumed_at,
+            c.updated_at DESC
+    ",
+        consumption_sql(&format!("c.user_id = ? AND {filter_sql}"))
+    )
+}
+
+/// Consumptions with the macros saved when they were logged.
+fn consumption_sql(filter_sql: &str) -> String {
+    format!(
+        "
+        SELECT
+            c.id,
+            c.user_id,
+            coalesce(c.food_id, c.recipe_id) as consumable_id,
+            c.quantity,
+            date(c.consumed_at) as consumed_at,
+            CASE WHEN length(c.consumed_at) > 10 THEN time(c.consumed_at) END as consumed_time,
+            c.meal_slot_id,
+            c.serving_id,
+            coalesce(s.name, f.unit) as unit,
+            coalesce(f.name, r.name) as consumable_name,
+            coalesce({kcal}, 0.0) as kcal,
+            coalesce({fat}, 0.0) as fat,
+            coalesce({carbs}, 0.0) as carbs,
+            coalesce({protein}, 0.0) as protein,
+            {nutrients}
+        FROM
+            consumptions c
+        LEFT JOIN
+            foods f
+                ON
+                    c.food_id = f.id
+        LEFT JOIN
+            servings s
+                ON
+                    c.serving_id = s.id
+        LEFT JOIN
+            recipes r
+                ON
+                    c.recipe_id = r.id
+        WHERE
+            {filter_sql}
+    ",
+        kcal = consumption_value("kcal"),
+        fat = consumption_value("fat"),
+        carbs = consumption_value("carbs"),
+        protein = consumption_value("protein"),
+        nutrients = nutrients::sql_columns(&format!("{} as {{n}}", consumption_value("{n}"))),
+    )
+}
+
+/// Value of a `consumed_at` or `measured_at` column. The time of day is
+/// optional, without it only the date is stored, which sorts before all
+/// times of that day.
+fn timestamp(date: &chrono::NaiveDate, time: Option<chrono::NaiveTime>) -> String {
+    match time {
+        Some(time) => date.and_time(time).format("%F %T").to_string(),
+        None => date.to_string(),
+    }
+}
+
+fn consumption_value(column: &str) -> String {
+    format!("c.unit_{column} * c.quantity * coalesce(s.amount, 1.0)")
+}
+
+/// Saves the current values per unit of the food or recipe in the
+/// consumptions that match `filter_sql`. Returns how many there were.
+async fn snapshot_consumptions(
+    tx: &mut sqlx::Transaction<'_, sqlx::Sqlite>,
+    filter_sql: &str,
+    filter_values: &[&str],
+) -> Result<u64, sqlx::Error> {
+    let sql = format!(
+        "
+        WITH RECURSIVE {cte}
+        UPDATE
+            consumptions
+        SET
+            unit_kcal = v.kcal,
+            unit_fat = v.fat,
+            unit_carbs = v.carbs,
+            unit_protein = v.protein,
+            {nutrient_updates}
+        FROM (
+            SELECT
+                c.id,
+                coalesce(f.kcal / f.basis, m.kcal / r.quantity, 0.0) as kcal,
+                coalesce(f.fat / f.basis, m.fat / r.quantity, 0.0) as fat,
+                coalesce(f.carbs / f.basis, m.carbs / r.quantity, 0.0) as carbs,
+                coalesce(f.protein / f.basis, m.protein / r.quantity, 0.0) as protein,
+                {nutrients}
+            FROM
+                consumptions c
+            LEFT JOIN
+                foods f
+                    ON
+                        c.food_id = f.id
+            LEFT JOIN
+                recipes r
+                    ON
+                        c.recipe_id = r.id
+            LEFT JOIN
+                recipe_macros m
+                    ON
+                        r.id = m.recipe_id
+            WHERE
+                {filter_sql}
+        ) v
+        WHERE
+            consumptions.id = v.id
+    ",
+        cte = recipe_macros_cte(),
+        nutrient_updates = nutrients::sql_columns("unit_{n} = v.{n}"),
+        nutrients = nutrients::sql_columns("coalesce(f.{n} / f.basis, m.{n} / r.quantity) as {n}"),
+    );
+    let mut query = sqlx::query(&sql);
+    for value in filter_values {
+        query = query.bind(value);
+    }
+    Ok(query.execute(&mut **tx).await?.rows_affected())
+}
+
+/// Sessions are stored by the hash of the cookie value.
+fn new_session_id() -> String {
+    rand::prelude::thread_rng().gen::<u128>().to_string()
+}
+
+pub fn hash_session_id(session_id: &str) -> String {
+    base16ct::lower::encode_string(&Sha256::digest(session_id))
+}
+
+fn hash_password(password: &str) -> String {
+    let salt = SaltString::generate(&mut OsRng);
+    Argon2::default()
+        .hash_password(password.as_bytes(), &salt)
+        .unwrap()
+        .to_string()
+}
+
+/// Returns the new id and the new ids of the servings by their old ids.
+async fn clone_food(
+    tx: &mut sqlx::Transaction<'_, sqlx::Sqlite>,
+    user_id: &UserId,
+    id: &str,
+) -> Result<(String, HashMap<String, String>), sqlx::Error> {
+    let now = chrono::Utc::now();
+    let new_id = Uuid::new_v4().to_string();
+    sqlx::query(
+        format!(
+            "INSERT INTO foods (id, user_id, name, kcal, fat, carbs, protein, unit, basis, barcode, {nutrients}, created_at, updated_at) SELECT ?, ?, name, kcal, fat, carbs, protein, unit, basis, barcode, {nutrients}, ?, ? FROM foods WHERE id = ?",
+            nutrients = nutrients::sql_columns("{n}")
+        )
+        .as_str(),
+    )
+    .bind(&new_id)
+    .bind(user_id)
+    .bind(now)
+    .bind(now)
+    .bind(id)
+    .execute(&mut **tx)
+    .await?;
+
+    let servings = sqlx::query_as::<_, (String, String, f64)>(
+        "SELECT id, name, amount FROM servings WHERE food_id = ?",
+    )
+    .bind(id)
+    .fetch_all(&mut **tx)
+    .await?;
+    let mut serving_ids = HashMap::new();
+    for (serving_id, name, amount) in servings {
+        let new_serving_id = Uuid::new_v4().to_string();
+        sqlx::query("INSERT INTO servings (id, user_id, food_id, name, amount, created_at, updated_at) VALUES (?, ?, ?, ?, ?, ?, ?)")
+            .bind(&new_serving_id)
+            .bind(user_id)
+            .bind(&new_id)
+            .bind(name)
+            .bind(amount)
+            .bind(now)
+            .bind(now)
+            .execute(&mut **tx)
+            .await?;
+        serving_ids.insert(serving_id, new_serving_id);
+    }
+
+    Ok((new_id, serving_ids))
+}
+
+async fn count_entries_used_by_others(
+    conn: &mut sqlx::SqliteConnection,
+    user_id: &UserId,
+) -> Result<i64, sqlx::Error> {
+    sqlx::query_scalar(
+        r#"
+        SELECT
+            (
+                SELECT count(*) FROM consumptions
+                WHERE
+                    user_id != ?1
+                    AND (
+                        food_id IN (SELECT id FROM foods WHERE user_id = ?1)
+                        OR recipe_id IN (SELECT id FROM recipes WHERE user_id = ?1)
+                        OR serving_id IN (SELECT id FROM servings WHERE user_id = ?1)
+                    )
+            ) + (
+                SELECT count(*) FROM ingredients
+                WHERE
+                    user_id != ?1
+                    AND (
+                        food_id IN (SELECT id FROM foods WHERE user_id = ?1)
+                        OR sub_recipe_id IN (SELECT id FROM recipes WHERE user_id = ?1)
+                        OR serving_id IN (SELECT id FROM servings WHERE user_id = ?1)
+                    )
+            )
+        "#,
+    )
+    .bind(user_id)
+    .fetch_one(conn)
+    .await
+}
+
+/// Fails the import if an entry with the same id exists but belongs to
+/// another user and isn't shared, directly or through a shared recipe.
+async fn check_owner(
+    tx: &mut sqlx::Transaction<'_, sqlx::Sqlite>,
+    table: &str,
+    id: &str,
+    user_id: &UserId,
+) -> Result<(), ImportError> {
+    let shared = match table {
+        "foods" => "id IN (SELECT id FROM shared_foods)",
+        "recipes" => "id IN (SELECT id FROM shared_recipes)",
+        "servings" => "food_id IN (SELECT id FROM shared_foods)",
+        "ingredients" => "recipe_id IN (SELECT id FROM shared_recipes)",
+        _ => "0",
+    };
+    let (owner, is_shared): (UserId, bool) = sqlx::query_as(
+        format!(
+            "
+            WITH RECURSIVE shared_recipes (id) AS (
+                SELECT id FROM recipes WHERE shared_at IS NOT NULL
+                UNION
+                SELECT
+                    i.sub_recipe_id
+                FROM
+                    shared_recipes s
+                JOIN
+                    ingredients i
+                        ON
+                            s.id = i.recipe_id
+                WHERE
+                    i.sub_recipe_id IS NOT NULL
+            ),
+            shared_foods (id) AS (
+                SELECT id FROM foods WHERE shared_at IS NOT NULL
+                UNION
+                SELECT food_id FROM ingredients WHERE recipe_id IN (SELECT id FROM shared_recipes)
+            )
+            SELECT user_id, {shared} FROM {table} WHERE id = ?
+            "
+        )
+        .as_str(),
+    )
+    .bind(id)
+    .fetch_one(&mut **tx)
+    .await?;
+
+    if &owner != user_id && !is_shared {
+        return Err(ImportError::Invalid(format!(
+            "Entry {} belongs to another user",
+            id
+        )));
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use sqlx::Row;
+
+    use super::*;
+    use crate::tests::test_db;
+
+    async fn query_plan(db: &Db, sql: &str) -> Vec<String> {
+        sqlx::query(&format!("EXPLAIN QUERY PLAN {}", sql))
+            .fetch_all(&db.db_pool)
+            .await
+            .unwrap()
+            .iter()
+            .map(|row| row.get::<String, _>("detail"))
+            .collect()
+    }
+
+    fn assert_plan_contains(plan: &[String], expected: &str) {
+        assert!(
+            plan.iter().any(|detail| detail.contains(expected)),
+            "expected `{}` in query plan:\n{}",
+            expected,
+            plan.join("\n")
+        );
+    }
+
+    #[tokio::test]
+    async fn goals_are_unique_per_weekday() {
+        let db = test_db().await;
+        db.create_user("test", "test").await.unwrap();
+        let user = db.get_user("test").await.unwrap().unwrap();
+        let insert = |weekday: Option<u32>| {
+            sqlx::query("INSERT INTO goals (id, user_id, weekday, kcal, fat, carbs, protein, created_at, updated_at) VALUES (?, ?, ?, 2000, 70, 250, 120, datetime(), datetime())")
+                .bind(Uuid::new_v4().to_string())
+                .bind(&user.id)
+                .bind(weekday)
+                .execute(&db.db_pool)
+        };
+
+        insert(None).await.unwrap();
+        insert(Some(0)).await.unwrap();
+        assert!(insert(None).await.is_err());
+        assert!(insert(Some(0)).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn consumptions_by_date_use_index() {
+        let db = test_db().await;
+        let plan = query_plan(
+            &db,
+            &consumptions_sql("c.consumed_at >= ? AND c.consumed_at < ?"),
+        )
+        .await;
+
+        assert_plan_contains(
+            &plan,
+            "SEARCH c USING INDEX consumptions_user_id_consumed_at (user_id=? AND consumed_at>? AND consumed_at<?)",
+        );
+    }
+
+    #[tokio::test]
+    async fn consumptions_by_consumable_use_index() {
+        let db = test_db().await;
+
+        for (filter_sql, index) in [
+            ("c.food_id = ?", "consumptions_food_id_consumed_at"),
+            ("c.recipe_id = ?", "consumptions_recipe_id_consumed_at"),
+        ] {
+            let plan = query_plan(&db, &consumptions_sql(filter_sql)).await;
+            assert_plan_contains(&plan, &format!("SEARCH c USING INDEX {}", index));
+        }
+    }
+
+    #[tokio::test]
+    async fn consumables_use_indexes() {
+        let db = test_db().await;
+
+        let plan = query_plan(&db, CONSUMABLE_FOODS_SQL).await;
+        assert_plan_contains(&plan, "SEARCH foods USING INDEX foods_user_id (user_id=?)");
+        assert_plan_contains(
+            &plan,
+            "SEARCH foods USING COVERING INDEX foods_shared_at (shared_at>?)",
+        );
+        assert_plan_contains(
+            &plan,
+            "SEARCH c USING INDEX consumptions_food_id_consumed_at (food_id=? AND consumed_at>?)",
+        );
+
+        let plan = query_plan(&db, CONSUMABLE_RECIPES_SQL).await;
+        assert_plan_contains(
+            &plan,
+            "SEARCH recipes USING INDEX recipes_user_id (user_id=?)",
+        );
+        assert_plan_contains(
+            &plan,
+            "SEARCH recipes USING COVERING INDEX recipes_shared_at (shared_at>?)",
+        );
+        assert_plan_contains(
+            &plan,
+            "SEARCH c USING INDEX consumptions_recipe_id_consumed_at (recipe_id=? AND consumed_at>?)",
+        );
+    }
+
+    #[tokio::test]
+    async fn consumptions_by_date_match_datetimes() {
+        let db = test_db().await;
+        db.create_user("test", "test").await.unwrap();
+        let user = db.get_user("test").await.unwrap().unwrap();
+        let food_id = db
+            .add_food(
+                &user.id,
+                FoodParams {
+                    name: "Apple",
+                    macros: Macros {
+                        kcal: 0.5,
+                        fat: 0.0,
+                        carbs: 0.1,
+                        protein: 0.0,
+                    },
+                    nutrients: Nutrients::default(),
+                    unit: "g",
+                    basis: 1.0,
+                    barcode: None,
+                    flags: EntryFlags::default(),
+                },
+            )
+            .await
+            .unwrap();
+        let date = chrono::NaiveDate::from_ymd_opt(2024, 1, 2).unwrap();
+        for consumed_at in [
+            "2024-01-01",
+            "2024-01-02",
+            "2024-01-02 23:59:59",
+            "2024-01-03",
+        ] {
+            sqlx::query("INSERT INTO consumptions (id, user_id, food_id, quantity, unit_kcal, consumed_at, created_at, updated_at) VALUES (?, ?, ?, 100, 0.5, ?, datetime(), datetime())")
+                .bind(Uuid::new_v4().to_string())
+                .bind(&user.id)
+                .bind(&food_id)
+                .bind(consumed_at)
+                .execute(&db.db_pool)
+                .await
+                .unwrap();
+        }
+
+        let consumptions = db
+            .get_consumptions(&user.id, ConsumptionFilter::ConsumedAt(date))
+            .await
+            .unwrap();
+        assert_eq!(consumptions.len(), 2);
+
+        let totals = db.get_daily_totals(&user.id, date..=date).await.unwrap();
+        assert_eq!(totals.len(), 1);
+        assert_eq!(totals[0].date, date);
+        assert_eq!(totals[0].kcal, 100.0);
+    }
+
+    #[tokio::test]
+    async fn consumables_count_the_whole_first_day_of_the_week() {
+        let db = test_db().await;
+        db.create_user("test", "test").await.unwrap();
+        let user = db.get_user("test").await.unwrap().unwrap();
+        let today = db.today(&user.id).await.unwrap();
+        let oats = db
+            .add_food(
+                &user.id,
+                FoodParams {
+                    name: "Oats",
+                    macros: Macros::default(),
+                    nutrients: Nutrients::default(),
+                    unit: "g",
+                    basis: 100.0,
+                    barcode: None,
+                    flags: EntryFlags::default(),
+                },
+            )
+            .await
+            .unwrap();
+        let late = chrono::NaiveTime::from_hms_opt(23, 0, 0);
+        for (days_ago, time) in [(6, None), (6, late), (7, late)] {
+            db.add_consumption(
+                &user.id,
+                ConsumptionParams {
+                    food_id: Some(&oats),
+                    recipe_id: None,
+                    quantity: 50.0,
+                    serving_id: None,
+                    consumed_at: today - chrono::Duration::days(days_ago),
+                    consumed_time: time,
+                    meal_slot_id: None,
+                },
+            )
+            .await
+            .unwrap();
+        }
+
+        let consumables = db.get_consumables(&user.id).await.unwrap();
+        assert_eq!(consumables[0].consumed_count, Some(2));
+        assert_eq!(consumables[0].days_since_consumed, Some(6));
+    }
+
+    /// The backfill of the migration that added the saved values has to
+    /// calculate them like [`snapshot_consumptions`].
+    #[tokio::test]
+    async fn snapshot_migration_matches_recalculation() {
+        let db = test_db().await;
+        db.create_user("test", "test").await.unwrap();
+        let user = db.get_user("test").await.unwrap().unwrap();
+        let date = chrono::NaiveDate::from_ymd_opt(2024, 1, 1).unwrap();
+        let milk = db
+            .add_food(
+                &user.id,
+                FoodParams {
+                    name: "Milk",
+                    macros: Macros {
+                        kcal: 64.0,
+                        fat: 3.5,
+                        carbs: 4.8,
+                        protein: 3.4,
+                    },
+                    nutrients: Nutrients {
+                        calcium: Some(120.0),
+                        ..Nutrients::default()
+                    },
+                    unit: "ml",
+                    basis: 100.0,
+                    barcode: None,
+                    flags: EntryFlags::default(),
+                },
+            )
+            .await
+            .unwrap();
+        let glass = db
+            .add_serving(&user.id, &milk, "Glass", 200.0)
+            .await
+            .unwrap();
+        let cocoa = db
+            .add_recipe(&user.id, "Cocoa", 2.0, EntryFlags::default())
+            .await
+            .unwrap();
+        db.add_ingredient(&user.id, &cocoa, Some(&milk), None, 1.0, Some(&glass))
+            .await
+            .unwrap();
+        let breakfast = db
+            .add_recipe(&user.id, "Breakfast", 1.0, EntryFlags::default())
+            .await
+            .unwrap();
+        db.add_ingredient(&user.id, &breakfast, None, Some(&cocoa), 1.0, None)
+            .await
+            .unwrap();
+        for (food_id, recipe_id, serving_id) in [
+            (Some(&milk), None, None),
+            (Some(&milk), None, Some(&glass)),
+            (None, Some(&breakfast), None),
+        ] {
+            db.add_consumption(
+                &user.id,
+                ConsumptionParams {
+                    food_id: food_id.map(String::as_str),
+                    recipe_id: recipe_id.map(String::as_str),
+                    quantity: 2.0,
+                    serving_id: serving_id.map(String::as_str),
+                    consumed_at: date,
+                    consumed_time: None,
+                    meal_slot_id: None,
+                },
+            )
+            .await
+            .unwrap();
+        }
+        let expected = db
+            .get_consumptions(&user.id, ConsumptionFilter::None)
+            .await
+            .unwrap();
+
+        sqlx::query(&format!(
+            "UPDATE consumptions SET unit_kcal = NULL, unit_fat = NULL, unit_carbs = NULL, unit_protein = NULL, {}",
+            nutrients::sql_columns("unit_{n} = NULL")
+        ))
+        .execute(&db.db_pool)
+        .await
+        .unwrap();
+        let migration = include_str!("../migrations/017_consumption_snapshots.sql");
+        let backfill = &migration[migration.find("with recursive").unwrap()..];
+        sqlx::query(backfill).execute(&db.db_pool).await.unwrap();
+
+        let consumptions = db
+            .get_consumptions(&user.id, ConsumptionFilter::None)
+            .await
+            .unwrap();
+        assert_close_values(&consumptions, &expected);
+        assert!(
+            (consumptions.iter().map(|c| c.kcal).sum::<f64>() - 1.28 - 256.0 - 128.0).abs() < 1e-9
+        );
+        assert_eq!(consumptions[0].nutrients.fiber, None);
+    }
+
+    fn assert_close_values(actual: &[Consumption], expected: &[Consumption]) {
+        assert_eq!(actual.len(), expected.len());
+        for (actual, expected) in actual.iter().zip(expected) {
+            assert_eq!(actual.id, expected.id);
+            assert!((actual.kcal - expected.kcal).abs() < 1e-9);
+            assert!((actual.protein - expected.protein).abs() < 1e-9);
+            assert_eq!(
+                actual.nutrients.calcium.is_some(),
+                expected.nutrients.calcium.is_some()
+            );
+            assert!(
+                (actual.nutrients.calcium.unwrap() - expected.nutrients.calcium.unwrap()).abs()
+                    < 1e-9
+            );
+        }
+    }
+}
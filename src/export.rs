@@ -0,0 +1,440 @@
+use std::collections::{HashMap, HashSet};
+
+use uuid::Uuid;
+
+use crate::db::{UserId, FOOD_UNITS};
+use crate::nutrients::Nutrients;
+use crate::products::normalize_barcode;
+
+/// Full dump of a user's data, as written by `/account/export` and read back
+/// by `/account/import` and the `import` subcommand. Fields that were added
+/// after the first export format default to empty so that older files can
+/// still be imported.
+#[derive(serde::Serialize, serde::Deserialize)]
+pub struct ExportData {
+    pub user_id: String,
+    pub exported_at: chrono::DateTime<chrono::Utc>,
+    pub weights: Vec<WeightRow>,
+    pub foods: Vec<FoodRow>,
+    pub consumptions: Vec<ConsumptionRow>,
+    pub recipes: Vec<RecipeRow>,
+    pub ingredients: Vec<IngredientRow>,
+    #[serde(default)]
+    pub goals: Vec<GoalRow>,
+    #[serde(default)]
+    pub meal_slots: Vec<MealSlotRow>,
+    #[serde(default)]
+    pub servings: Vec<ServingRow>,
+    #[serde(default)]
+    pub food_aliases: Vec<FoodAliasRow>,
+}
+
+#[derive(sqlx::FromRow, serde::Serialize, serde::Deserialize)]
+pub struct WeightRow {
+    pub id: String,
+    pub weight: f64,
+    pub measured_at: chrono::NaiveDate,
+    #[serde(default)]
+    pub measured_time: Option<chrono::NaiveTime>,
+    pub created_at: chrono::NaiveDateTime,
+    pub updated_at: chrono::NaiveDateTime,
+}
+
+#[derive(sqlx::FromRow, serde::Serialize, serde::Deserialize)]
+pub struct FoodRow {
+    pub id: String,
+    pub name: String,
+    pub kcal: f64,
+    pub fat: f64,
+    pub carbs: f64,
+    pub protein: f64,
+    #[serde(default = "default_unit")]
+    pub unit: String,
+    #[serde(default = "default_basis")]
+    pub basis: f64,
+    #[serde(default)]
+    pub barcode: Option<String>,
+    #[sqlx(flatten)]
+    #[serde(flatten)]
+    pub nutrients: Nutrients,
+    pub hidden_at: Option<chrono::NaiveDateTime>,
+    pub starred_at: Option<chrono::NaiveDateTime>,
+    #[serde(default)]
+    pub shared_at: Option<chrono::NaiveDateTime>,
+    pub created_at: chrono::NaiveDateTime,
+    pub updated_at: chrono::NaiveDateTime,
+}
+
+#[derive(sqlx::FromRow, serde::Serialize, serde::Deserialize)]
+pub struct ConsumptionRow {
+    pub id: String,
+    pub food_id: Option<String>,
+    pub recipe_id: Option<String>,
+    pub quantity: f64,
+    #[serde(default)]
+    pub serving_id: Option<String>,
+    pub consumed_at: chrono::NaiveDate,
+    #[serde(default)]
+    pub consumed_time: Option<chrono::NaiveTime>,
+    #[serde(default)]
+    pub meal_slot_id: Option<String>,
+    /// Missing in older exports, which get the current values on import.
+    #[serde(default)]
+    pub unit_kcal: Option<f64>,
+    #[serde(default)]
+    pub unit_fat: Option<f64>,
+    #[serde(default)]
+    pub unit_carbs: Option<f64>,
+    #[serde(default)]
+    pub unit_protein: Option<f64>,
+    #[sqlx(flatten)]
+    #[serde(default)]
+    pub unit_nutrients: Nutrients,
+    pub created_at: chrono::NaiveDateTime,
+    pub updated_at: chrono::NaiveDateTime,
+}
+
+#[derive(sqlx::FromRow, serde::Serialize, serde::Deserialize)]
+pub struct RecipeRow {
+    pub id: String,
+    pub name: String,
+    pub quantity: f64,
+    pub hidden_at: Option<chrono::NaiveDateTime>,
+    pub starred_at: Option<chrono::NaiveDateTime>,
+    #[serde(default)]
+    pub shared_at: Option<chrono::NaiveDateTime>,
+    pub created_at: chrono::NaiveDateTime,
+    pub updated_at: chrono::NaiveDateTime,
+}
+
+#[derive(sqlx::FromRow, serde::Serialize, serde::Deserialize)]
+pub struct IngredientRow {
+    pub id: String,
+    pub recipe_id: String,
+    pub food_id: Option<String>,
+    #[serde(default)]
+    pub sub_recipe_id: Option<String>,
+    pub quantity: f64,
+    #[serde(default)]
+    pub serving_id: Option<String>,
+    pub created_at: chrono::NaiveDateTime,
+    pub updated_at: chrono::NaiveDateTime,
+}
+
+#[derive(sqlx::FromRow, serde::Serialize, serde::Deserialize)]
+pub struct GoalRow {
+    pub id: String,
+    pub weekday: Option<u32>,
+    pub kcal: f64,
+    pub fat: f64,
+    pub carbs: f64,
+    pub protein: f64,
+    pub created_at: chrono::NaiveDateTime,
+    pub updated_at: chrono::NaiveDateTime,
+}
+
+#[derive(sqlx::FromRow, serde::Serialize, serde::Deserialize)]
+pub struct MealSlotRow {
+    pub id: String,
+    pub name: String,
+    pub position: i64,
+    pub created_at: chrono::NaiveDateTime,
+    pub updated_at: chrono::NaiveDateTime,
+}
+
+#[derive(sqlx::FromRow, serde::Serialize, serde::Deserialize)]
+pub struct ServingRow {
+    pub id: String,
+    pub food_id: String,
+    pub name: String,
+    pub amount: f64,
+    pub created_at: chrono::NaiveDateTime,
+    pub updated_at: chrono::NaiveDateTime,
+}
+
+#[derive(sqlx::FromRow, serde::Serialize, serde::Deserialize)]
+pub struct FoodAliasRow {
+    pub id: String,
+    pub food_id: String,
+    pub name: String,
+    pub created_at: chrono::NaiveDateTime,
+    pub updated_at: chrono::NaiveDateTime,
+}
+
+fn default_unit() -> String {
+    "g".to_string()
+}
+
+fn default_basis() -> f64 {
+    1.0
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ImportMode {
+    /// Keep existing data and add the entries that don't exist yet.
+    Merge,
+    /// Delete all existing data of the user before importing.
+    Replace,
+}
+
+impl ImportMode {
+    pub fn parse(mode: &str) -> Option<Self> {
+        match mode {
+            "merge" => Some(ImportMode::Merge),
+            "replace" => Some(ImportMode::Replace),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum ImportError {
+    Invalid(String),
+    SQLError(sqlx::Error),
+}
+
+impl std::fmt::Display for ImportError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ImportError::Invalid(msg) => write!(f, "{}", msg),
+            ImportError::SQLError(e) => write!(f, "database error: {}", e),
+        }
+    }
+}
+
+impl From<sqlx::Error> for ImportError {
+    fn from(e: sqlx::Error) -> Self {
+        ImportError::SQLError(e)
+    }
+}
+
+impl ExportData {
+    pub fn parse(json: &[u8]) -> Result<Self, ImportError> {
+        let data: ExportData = serde_json::from_slice(json)
+            .map_err(|e| ImportError::Invalid(format!("Not a valid export: {}", e)))?;
+        data.validate()?;
+        Ok(data)
+    }
+
+    /// Checks that every reference points to an entry of this export, so the
+    /// import never depends on (or leaks into) data outside of it.
+    fn validate(&self) -> Result<(), ImportError> {
+        let mut ids = HashSet::new();
+        let all_ids = self
+            .weights
+            .iter()
+            .map(|r| &r.id)
+            .chain(self.foods.iter().map(|r| &r.id))
+            .chain(self.consumptions.iter().map(|r| &r.id))
+            .chain(self.recipes.iter().map(|r| &r.id))
+            .chain(self.ingredients.iter().map(|r| &r.id))
+            .chain(self.goals.iter().map(|r| &r.id))
+            .chain(self.meal_slots.iter().map(|r| &r.id))
+            .chain(self.servings.iter().map(|r| &r.id))
+            .chain(self.food_aliases.iter().map(|r| &r.id));
+        for id in all_ids {
+            if !ids.insert(id) {
+                return Err(invalid(format!("Duplicate id {}", id)));
+            }
+        }
+
+        let food_ids = self.foods.iter().map(|r| &r.id).collect::<HashSet<_>>();
+        let recipe_ids = self.recipes.iter().map(|r| &r.id).collect::<HashSet<_>>();
+        let meal_slot_ids = self
+            .meal_slots
+            .iter()
+            .map(|r| &r.id)
+            .collect::<HashSet<_>>();
+        let serving_food_ids = self
+            .servings
+            .iter()
+            .map(|r| (&r.id, &r.food_id))
+            .collect::<HashMap<_, _>>();
+
+        for row in &self.foods {
+            if !FOOD_UNITS.contains(&row.unit.as_str()) {
+                return Err(invalid(format!("Food {} has an invalid unit", row.id)));
+            }
+            if row
+                .barcode
+                .as_ref()
+                .is_some_and(|code| normalize_barcode(code).as_ref() != Some(code))
+            {
+                return Err(invalid(format!("Food {} has an invalid barcode", row.id)));
+            }
+        }
+
+        for row in &self.servings {
+            if !food_ids.contains(&row.food_id) {
+                return Err(invalid(format!("Serving {} has an unknown food", row.id)));
+            }
+        }
+
+        for row in &self.food_aliases {
+            if !food_ids.contains(&row.food_id) {
+                return Err(invalid(format!("Alias {} has an unknown food", row.id)));
+            }
+        }
+
+        for row in &self.consumptions {
+            match (&row.food_id, &row.recipe_id) {
+                (Some(food_id), None) if food_ids.contains(food_id) => {}
+                (None, Some(recipe_id)) if recipe_ids.contains(recipe_id) => {}
+                _ => {
+                    return Err(invalid(format!(
+                        "Consumption {} has an invalid food or recipe",
+                        row.id
+                    )))
+                }
+            }
+            if !serving_matches(&serving_food_ids, &row.serving_id, &row.food_id) {
+                return Err(invalid(format!(
+                    "Consumption {} has an invalid serving",
+                    row.id
+                )));
+            }
+            if let Some(meal_slot_id) = &row.meal_slot_id {
+                if !meal_slot_ids.contains(meal_slot_id) {
+                    return Err(invalid(format!(
+                        "Consumption {} has an unknown meal",
+                        row.id
+                    )));
+                }
+            }
+        }
+
+        for row in &self.ingredients {
+            if !recipe_ids.contains(&row.recipe_id) {
+                return Err(invalid(format!(
+                    "Ingredient {} has an unknown recipe",
+                    row.id
+                )));
+            }
+            match (&row.food_id, &row.sub_recipe_id) {
+                (Some(food_id), None) if food_ids.contains(food_id) => {}
+                (None, Some(recipe_id)) if recipe_ids.contains(recipe_id) => {}
+                _ => {
+                    return Err(invalid(format!(
+                        "Ingredient {} has an invalid food or recipe",
+                        row.id
+                    )))
+                }
+            }
+            if !serving_matches(&serving_food_ids, &row.serving_id, &row.food_id) {
+                return Err(invalid(format!(
+                    "Ingredient {} has an invalid serving",
+                    row.id
+                )));
+            }
+        }
+
+        if self.goals.iter().any(|r| r.weekday.is_some_and(|w| w > 6)) {
+            return Err(invalid("Goal with an invalid weekday".to_string()));
+        }
+
+        if self.has_recipe_cycle() {
+            return Err(invalid("Recipes contain each other".to_string()));
+        }
+
+        Ok(())
+    }
+
+    fn has_recipe_cycle(&self) -> bool {
+        let mut children: HashMap<&str, Vec<&str>> = HashMap::new();
+        for row in &self.ingredients {
+            if let Some(sub_recipe_id) = &row.sub_recipe_id {
+                children
+                    .entry(&row.recipe_id)
+                    .or_default()
+                    .push(sub_recipe_id);
+            }
+        }
+
+        fn visit<'a>(
+            id: &'a str,
+            children: &HashMap<&'a str, Vec<&'a str>>,
+            path: &mut Vec<&'a str>,
+            done: &mut HashSet<&'a str>,
+        ) -> bool {
+            if path.contains(&id) {
+                return true;
+            }
+            if !done.insert(id) {
+                return false;
+            }
+            path.push(id);
+            let cycle = children
+                .get(id)
+                .is_some_and(|c| c.iter().any(|child| visit(child, children, path, done)));
+            path.pop();
+            cycle
+        }
+
+        let mut done = HashSet::new();
+        self.recipes
+            .iter()
+            .any(|r| visit(&r.id, &children, &mut Vec::new(), &mut done))
+    }
+
+    /// Replaces every id with a fresh one and updates all references, so the
+    /// data can be imported next to the original rows.
+    pub fn with_new_ids(mut self) -> Self {
+        let mut ids = HashMap::new();
+        let mut new_id = |id: &mut String| {
+            *id = ids
+                .entry(id.clone())
+                .or_insert_with(|| Uuid::new_v4().to_string())
+                .clone();
+        };
+
+        self.weights.iter_mut().for_each(|r| new_id(&mut r.id));
+        self.foods.iter_mut().for_each(|r| new_id(&mut r.id));
+        self.recipes.iter_mut().for_each(|r| new_id(&mut r.id));
+        self.goals.iter_mut().for_each(|r| new_id(&mut r.id));
+        self.meal_slots.iter_mut().for_each(|r| new_id(&mut r.id));
+        for row in self.servings.iter_mut() {
+            new_id(&mut row.id);
+            new_id(&mut row.food_id);
+        }
+        for row in self.food_aliases.iter_mut() {
+            new_id(&mut row.id);
+            new_id(&mut row.food_id);
+        }
+        for row in self.consumptions.iter_mut() {
+            new_id(&mut row.id);
+            row.food_id.iter_mut().for_each(&mut new_id);
+            row.recipe_id.iter_mut().for_each(&mut new_id);
+            row.serving_id.iter_mut().for_each(&mut new_id);
+            row.meal_slot_id.iter_mut().for_each(&mut new_id);
+        }
+        for row in self.ingredients.iter_mut() {
+            new_id(&mut row.id);
+            new_id(&mut row.recipe_id);
+            row.food_id.iter_mut().for_each(&mut new_id);
+            row.sub_recipe_id.iter_mut().for_each(&mut new_id);
+            row.serving_id.iter_mut().for_each(&mut new_id);
+        }
+
+        self
+    }
+
+    pub fn is_from(&self, user_id: &UserId) -> bool {
+        self.user_id == user_id.0
+    }
+}
+
+/// Whether `serving_id` is unset or a serving of `food_id`.
+fn serving_matches(
+    serving_food_ids: &HashMap<&String, &String>,
+    serving_id: &Option<String>,
+    food_id: &Option<String>,
+) -> bool {
+    match serving_id {
+        None => true,
+        Some(serving_id) => serving_food_ids.get(serving_id).copied() == food_id.as_ref(),
+    }
+}
+
+fn invalid(msg: String) -> ImportError {
+    ImportError::Invalid(msg)
+}
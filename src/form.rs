@@ -0,0 +1,120 @@
+//! Forms that are shown again with the submitted values when they can't be
+//! saved, instead of ending on an error page.
+
+use axum::body::Bytes;
+use axum::extract::{FromRequest, Request};
+use axum::http::StatusCode;
+use axum::response::{IntoResponse, Response};
+use serde::de::DeserializeOwned;
+
+use crate::AppError;
+
+/// Submitted values of a form by field name. Every field is a string, as
+/// the values may not have been valid.
+#[derive(Debug, Default, Clone)]
+pub struct FormValues(Vec<(String, String)>);
+
+impl FormValues {
+    pub fn new<'a>(values: impl IntoIterator<Item = (&'a str, String)>) -> Self {
+        FormValues(
+            values
+                .into_iter()
+                .map(|(name, value)| (name.to_string(), value))
+                .collect(),
+        )
+    }
+
+    /// Value of the field, empty if it wasn't sent.
+    pub fn get(&self, name: &str) -> &str {
+        self.0
+            .iter()
+            .find(|(n, _)| n == name)
+            .map(|(_, value)| value.as_str())
+            .unwrap_or_default()
+    }
+
+    /// Whether the checkbox was checked, i.e. sent at all.
+    pub fn checked(&self, name: &str) -> bool {
+        self.0.iter().any(|(n, _)| n == name)
+    }
+}
+
+/// A form that couldn't be saved.
+#[derive(Debug, Clone)]
+pub struct InvalidForm {
+    /// Path the form was posted to, which tells the forms of a page apart.
+    pub action: String,
+    pub values: FormValues,
+    pub message: String,
+}
+
+/// URL-encoded form that keeps the submitted values next to the parsed ones.
+/// `parsed` is `None` if a field is missing or has an invalid value.
+pub struct FormInput<T> {
+    pub action: String,
+    pub values: FormValues,
+    pub parsed: Option<T>,
+}
+
+impl<T> FormInput<T> {
+    /// The parsed form and the result of `check`, or the form to show again
+    /// if it couldn't be parsed or `check` fails.
+    pub fn check<R>(
+        self,
+        check: impl FnOnce(&T) -> Result<R, AppError>,
+    ) -> Result<(T, R), InvalidForm> {
+        let result = match self.parsed {
+            Some(form) => check(&form).map(|checked| (form, checked)),
+            None => Err(AppError::InvalidForm),
+        };
+        result.map_err(|e| InvalidForm {
+            action: self.action,
+            values: self.values,
+            message: e.message().to_string(),
+        })
+    }
+
+    /// The parsed form, or the form to show again if it couldn't be parsed.
+    pub fn parse(self) -> Result<T, InvalidForm> {
+        self.check(|_| Ok(())).map(|(form, ())| form)
+    }
+
+    /// Like [`FormInput::check`], for checks that need the database. Only
+    /// errors about the submitted values show the form again, others are
+    /// returned as they are.
+    pub async fn check_async<R>(
+        self,
+        check: impl AsyncFnOnce(&T) -> Result<R, AppError>,
+    ) -> Result<Result<(T, R), InvalidForm>, AppError> {
+        let result = match self.parsed {
+            Some(form) => check(&form).await.map(|checked| (form, checked)),
+            None => Err(AppError::InvalidForm),
+        };
+        match result {
+            Ok(checked) => Ok(Ok(checked)),
+            Err(e) if e.status() == StatusCode::UNPROCESSABLE_ENTITY => Ok(Err(InvalidForm {
+                action: self.action,
+                values: self.values,
+                message: e.message().to_string(),
+            })),
+            Err(e) => Err(e),
+        }
+    }
+}
+
+impl<S: Send + Sync, T: DeserializeOwned> FromRequest<S> for FormInput<T> {
+    type Rejection = Response;
+
+    async fn from_request(request: Request, state: &S) -> Result<Self, Self::Rejection> {
+        let action = request.uri().path().to_string();
+        let bytes = Bytes::from_request(request, state)
+            .await
+            .map_err(IntoResponse::into_response)?;
+
+        Ok(FormInput {
+            action,
+            values: FormValues(serde_urlencoded::from_bytes(&bytes).unwrap_or_default()),
+            parsed: serde_urlencoded::from_bytes(&bytes).ok(),
+        })
+    }
+}
@@ -1,24 +1,73 @@
 use std::collections::HashMap;
 
-use crate::db::{ConsumptionFilter, Macros, UserId, SESSION_DAYS};
+use crate::db::{
+    goal_for_date, hash_session_id, ConsumptionFilter, ConsumptionParams, EntryFlags, Food,
+    FoodParams, Macros, Recipe, UserId, WeightWithDate, FOOD_UNITS, REMEMBERED_SESSION_DAYS,
+};
+use crate::export::{ExportData, ImportError, ImportMode};
+use crate::form::{FormInput, InvalidForm};
 use crate::html::AccountSummaryRow;
+use crate::nutrients::{Nutrients, NUTRIENTS};
+use crate::products::{self, PRODUCT_BASIS, PRODUCT_UNIT};
+use crate::tdee;
+use crate::throttle;
+use crate::trend::{self, TrendRange, WeightRule};
 use crate::{html, redirect_to, AppError, AppState, AppUrl, Session};
 use argon2::{Argon2, PasswordHash, PasswordVerifier};
 use axum::body::Body;
-use axum::extract::{Path, State};
-use axum::http::{header, HeaderValue, StatusCode};
+use axum::extract::{Multipart, Path, Query, State};
+use axum::http::{header, HeaderMap, HeaderValue, StatusCode};
 use axum::response::{Html, IntoResponse, Response};
-use axum::Form;
+use axum::Json;
 use cookie::{Cookie, SameSite};
 use maud::Markup;
 use time::{Duration, OffsetDateTime};
+use tracing::{info, warn};
 
-pub fn authz(session: &Session, user_id: &UserId) -> bool {
-    &session.user_id == user_id
+pub fn authz(user_id: &UserId, owner_id: &UserId) -> bool {
+    user_id == owner_id
+}
+
+/// Changing an entry still requires [`authz`].
+pub fn visible(
+    user_id: &UserId,
+    owner_id: &UserId,
+    shared_at: &Option<chrono::NaiveDateTime>,
+) -> bool {
+    authz(user_id, owner_id) || shared_at.is_some()
+}
+
+/// One weight per day by `rule`, oldest first, from weigh-ins sorted newest
+/// first.
+pub fn daily_weights(
+    weights: &[WeightWithDate],
+    rule: WeightRule,
+) -> Vec<(chrono::NaiveDate, f64)> {
+    let weigh_ins = weights
+        .iter()
+        .rev()
+        .map(|w| (w.measured_at, w.weight))
+        .collect::<Vec<_>>();
+    trend::daily_weights(&weigh_ins, rule)
+}
+
+/// Accepts an empty time field, meaning that no time was given.
+pub fn optional_time<'de, D: serde::Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Option<chrono::NaiveTime>, D::Error> {
+    use serde::de::Error;
+    use serde::Deserialize;
+
+    match Option::<String>::deserialize(deserializer)? {
+        Some(text) if !text.trim().is_empty() => {
+            text.trim().parse().map(Some).map_err(D::Error::custom)
+        }
+        _ => Ok(None),
+    }
 }
 
 pub async fn index(state: State<AppState>, session: Session) -> Result<Response, AppError> {
-    let date = chrono::Utc::now().date_naive();
+    let date = state.db.today(&session.user_id).await?;
 
     days_read(state, session, Path(date)).await
 }
@@ -28,42 +77,178 @@ pub async fn days_read(
     session: Session,
     Path(date): Path<chrono::NaiveDate>,
 ) -> Result<Response, AppError> {
-    let weight = state.db.get_weight_by_date(&session.user_id, &date).await?;
+    Ok(render_html(
+        day_summary_page(&state, &session.user_id, date, None).await?,
+    ))
+}
+
+/// The day summary, with `invalid` being its weight or consumption form if
+/// that couldn't be saved.
+async fn day_summary_page(
+    state: &AppState,
+    user_id: &UserId,
+    date: chrono::NaiveDate,
+    invalid: Option<&InvalidForm>,
+) -> Result<Markup, AppError> {
+    let weights = state.db.get_weights_in_range(user_id, date..=date).await?;
+    let rule = state.db.get_weight_rule(user_id).await?;
+    let weight = daily_weights(&weights, rule)
+        .pop()
+        .map(|(_, weight)| weight);
     let consumptions = state
         .db
-        .get_consumptions(&session.user_id, ConsumptionFilter::ConsumedAt(date))
+        .get_consumptions(user_id, ConsumptionFilter::ConsumedAt(date))
         .await?;
-    let consumables = state.db.get_consumables(&session.user_id).await?;
-
-    Ok(render_html(html::day_summary_page(
+    let total = state.db.get_daily_totals(user_id, date..=date).await?.pop();
+    let consumables = state.db.get_consumables(user_id).await?;
+    let goals = state.db.get_goals(user_id).await?;
+    let meal_slots = state.db.get_meal_slots(user_id).await?;
+    let servings = state.db.get_user_servings(user_id).await?;
+    let shown = state.db.get_shown_nutrients(user_id).await?;
+
+    Ok(html::day_summary_page(
         date,
+        weights,
         weight,
+        total.as_ref().map(|t| t.macros()).unwrap_or_default(),
+        total.map(|t| t.nutrients).unwrap_or_default(),
         consumptions,
         consumables,
-    )))
+        goal_for_date(&goals, date),
+        meal_slots,
+        servings,
+        &shown,
+        invalid,
+    ))
 }
 
-pub async fn weights_index(state: State<AppState>, session: Session) -> Result<Response, AppError> {
-    let weights = state.db.get_weights(&session.user_id).await?;
+#[derive(Debug, serde::Deserialize)]
+pub struct WeightsQuery {
+    range: Option<TrendRange>,
+}
+
+pub async fn weights_index(
+    state: State<AppState>,
+    session: Session,
+    Query(query): Query<WeightsQuery>,
+) -> Result<Response, AppError> {
+    Ok(render_html(
+        weights_page(
+            &state,
+            &session.user_id,
+            query.range.unwrap_or_default(),
+            None,
+        )
+        .await?,
+    ))
+}
+
+/// The weights page, with `invalid` being its weight or rule form if that
+/// couldn't be saved.
+async fn weights_page(
+    state: &AppState,
+    user_id: &UserId,
+    range: TrendRange,
+    invalid: Option<&InvalidForm>,
+) -> Result<Markup, AppError> {
+    let weights = state.db.get_weights(user_id).await?;
+    let rule = state.db.get_weight_rule(user_id).await?;
+
+    // The trend is computed over the whole history so that it is already
+    // settled at the start of the selected range.
+    let series = daily_weights(&weights, rule);
+    let today = state.db.today(user_id).await?;
+    let start = range.start(today);
+    let points = trend::exponential_moving_average(&series, trend::TREND_DAYS)
+        .into_iter()
+        .filter(|p| start.is_none_or(|start| p.date >= start))
+        .collect::<Vec<_>>();
+
+    Ok(html::weights_page(
+        weights, &points, range, rule, today, invalid,
+    ))
+}
+
+#[derive(Debug, serde::Deserialize)]
+pub struct WeightRuleForm {
+    weight_rule: WeightRule,
+}
+
+pub async fn weights_rule_update(
+    state: State<AppState>,
+    session: Session,
+    input: FormInput<WeightRuleForm>,
+) -> Result<Response, AppError> {
+    let form = match input.parse() {
+        Ok(form) => form,
+        Err(invalid) => {
+            let page = weights_page(
+                &state,
+                &session.user_id,
+                TrendRange::default(),
+                Some(&invalid),
+            )
+            .await?;
+            return Ok(render_invalid_form(page));
+        }
+    };
+
+    state
+        .db
+        .set_weight_rule(&session.user_id, form.weight_rule)
+        .await?;
 
-    Ok(render_html(html::weights_page(weights)))
+    Ok(redirect_to(AppUrl::Weights))
 }
 
 #[derive(Debug, serde::Deserialize)]
 pub struct CreateWeightForm {
     weight: f64,
     measured_at: chrono::NaiveDate,
+    #[serde(default, deserialize_with = "optional_time")]
+    measured_time: Option<chrono::NaiveTime>,
     redirect_to: Option<String>,
 }
 
 pub async fn weights_create(
     state: State<AppState>,
     session: Session,
-    Form(form): Form<CreateWeightForm>,
+    input: FormInput<CreateWeightForm>,
 ) -> Result<Response, AppError> {
+    let form = match input.parse() {
+        Ok(form) => form,
+        Err(invalid) => {
+            // The form of the day summary only has the weight.
+            let day = match invalid.values.get("redirect_to") {
+                "DaySummary" => invalid.values.get("measured_at").parse().ok(),
+                _ => None,
+            };
+            let page = match day {
+                Some(date) => {
+                    day_summary_page(&state, &session.user_id, date, Some(&invalid)).await?
+                }
+                None => {
+                    weights_page(
+                        &state,
+                        &session.user_id,
+                        TrendRange::default(),
+                        Some(&invalid),
+                    )
+                    .await?
+                }
+            };
+            return Ok(render_invalid_form(page));
+        }
+    };
+
     state
         .db
-        .add_weight(&session.user_id, form.weight, &form.measured_at)
+        .add_weight(
+            &session.user_id,
+            form.weight,
+            &form.measured_at,
+            form.measured_time,
+        )
         .await?;
 
     match form.redirect_to.map(|s| s.to_owned()).as_deref() {
@@ -79,34 +264,46 @@ pub async fn weights_read(
 ) -> Result<Response, AppError> {
     let weight = state.db.get_weight(&id).await?;
 
-    if !authz(&session, &weight.user_id) {
+    if !authz(&session.user_id, &weight.user_id) {
         return Err(AppError::Forbidden);
     }
 
-    Ok(render_html(html::weights_update_page(&weight)))
+    Ok(render_html(html::weights_update_page(&weight, None)))
 }
 
 #[derive(Debug, serde::Deserialize)]
 pub struct UpdateWeightForm {
     weight: f64,
     measured_at: chrono::NaiveDate,
+    #[serde(default, deserialize_with = "optional_time")]
+    measured_time: Option<chrono::NaiveTime>,
 }
 
 pub async fn weights_update(
     state: State<AppState>,
     session: Session,
     Path(id): Path<String>,
-    Form(form): Form<UpdateWeightForm>,
+    input: FormInput<UpdateWeightForm>,
 ) -> Result<Response, AppError> {
     let weight = state.db.get_weight(&id).await?;
 
-    if !authz(&session, &weight.user_id) {
+    if !authz(&session.user_id, &weight.user_id) {
         return Err(AppError::Forbidden);
     }
 
+    let form = match input.parse() {
+        Ok(form) => form,
+        Err(invalid) => {
+            return Ok(render_invalid_form(html::weights_update_page(
+                &weight,
+                Some(&invalid),
+            )));
+        }
+    };
+
     state
         .db
-        .update_weight(&id, form.weight, &form.measured_at)
+        .update_weight(&id, form.weight, &form.measured_at, form.measured_time)
         .await?;
 
     Ok(redirect_to(AppUrl::Weights))
@@ -119,7 +316,7 @@ pub async fn weights_delete(
 ) -> Result<Response, AppError> {
     let weight = state.db.get_weight(&id).await?;
 
-    if !authz(&session, &weight.user_id) {
+    if !authz(&session.user_id, &weight.user_id) {
         return Err(AppError::Forbidden);
     }
 
@@ -130,8 +327,9 @@ pub async fn weights_delete(
 
 pub async fn foods_index(state: State<AppState>, session: Session) -> Result<Response, AppError> {
     let foods = state.db.get_foods(&session.user_id).await?;
+    let shown = state.db.get_shown_nutrients(&session.user_id).await?;
 
-    Ok(render_html(html::foods_page(foods)))
+    Ok(render_html(html::foods_page(foods, &shown, None)))
 }
 
 #[derive(Debug, serde::Deserialize)]
@@ -141,28 +339,60 @@ pub struct CreateFoodForm {
     fat: f64,
     carbs: f64,
     protein: f64,
+    #[serde(flatten)]
+    nutrients: Nutrients,
+    unit: String,
+    basis: f64,
+    barcode: Option<String>,
     hidden: Option<bool>,
     starred: Option<bool>,
+    shared: Option<bool>,
 }
 
 pub async fn foods_create(
     state: State<AppState>,
     session: Session,
-    Form(form): Form<CreateFoodForm>,
+    input: FormInput<CreateFoodForm>,
 ) -> Result<Response, AppError> {
+    let (form, barcode) = match input.check(|form| {
+        check_food_unit(&form.unit)?;
+        check_amount(form.basis)?;
+        barcode(form.barcode.as_deref())
+    }) {
+        Ok(checked) => checked,
+        Err(invalid) => {
+            let foods = state.db.get_foods(&session.user_id).await?;
+            let shown = state.db.get_shown_nutrients(&session.user_id).await?;
+            return Ok(render_invalid_form(html::foods_page(
+                foods,
+                &shown,
+                Some(&invalid),
+            )));
+        }
+    };
+
     state
         .db
         .add_food(
             &session.user_id,
-            &form.name,
-            Macros {
-                kcal: form.kcal,
-                fat: form.fat,
-                carbs: form.carbs,
-                protein: form.protein,
+            FoodParams {
+                name: &form.name,
+                macros: Macros {
+                    kcal: form.kcal,
+                    fat: form.fat,
+                    carbs: form.carbs,
+                    protein: form.protein,
+                },
+                nutrients: form.nutrients,
+                unit: &form.unit,
+                basis: form.basis,
+                barcode: barcode.as_deref(),
+                flags: EntryFlags {
+                    hidden: form.hidden.is_some(),
+                    starred: form.starred.is_some(),
+                    shared: form.shared.is_some(),
+                },
             },
-            form.hidden.is_some(),
-            form.starred.is_some(),
         )
         .await?;
 
@@ -176,16 +406,57 @@ pub async fn foods_read(
 ) -> Result<Response, AppError> {
     let food = state.db.get_food(&id).await?;
 
-    if !authz(&session, &food.user_id) {
+    if !visible(&session.user_id, &food.user_id, &food.shared_at) {
         return Err(AppError::Forbidden);
     }
 
     let consumptions = state
         .db
-        .get_consumptions(&session.user_id, ConsumptionFilter::FoodId(id))
+        .get_consumptions(&session.user_id, ConsumptionFilter::FoodId(id.clone()))
         .await?;
+    let servings = state.db.get_servings(&id).await?;
+    let shown = state.db.get_shown_nutrients(&session.user_id).await?;
+
+    if !authz(&session.user_id, &food.user_id) {
+        return Ok(render_html(html::foods_shared_page(
+            food,
+            servings,
+            consumptions,
+            &shown,
+        )));
+    }
+
+    Ok(render_html(
+        foods_update_page(&state, &session.user_id, food, None).await?,
+    ))
+}
 
-    Ok(render_html(html::foods_update_page(food, consumptions)))
+/// The page of the user's own food, with `invalid` being one of its forms if
+/// that couldn't be saved.
+async fn foods_update_page(
+    state: &AppState,
+    user_id: &UserId,
+    food: Food,
+    invalid: Option<&InvalidForm>,
+) -> Result<Markup, AppError> {
+    let consumptions = state
+        .db
+        .get_consumptions(user_id, ConsumptionFilter::FoodId(food.id.clone()))
+        .await?;
+    let servings = state.db.get_servings(&food.id).await?;
+    let aliases = state.db.get_food_aliases(&food.id).await?;
+    let merge_targets = merge_targets(state, user_id, &food).await?;
+    let shown = state.db.get_shown_nutrients(user_id).await?;
+
+    Ok(html::foods_update_page(
+        food,
+        servings,
+        aliases,
+        merge_targets,
+        consumptions,
+        &shown,
+        invalid,
+    ))
 }
 
 #[derive(Debug, serde::Deserialize)]
@@ -195,35 +466,62 @@ pub struct UpdateFoodForm {
     fat: f64,
     carbs: f64,
     protein: f64,
+    #[serde(flatten)]
+    nutrients: Nutrients,
+    unit: String,
+    basis: f64,
+    barcode: Option<String>,
     hidden: Option<bool>,
     starred: Option<bool>,
+    shared: Option<bool>,
 }
 
 pub async fn foods_update(
     state: State<AppState>,
     session: Session,
     Path(id): Path<String>,
-    Form(form): Form<UpdateFoodForm>,
+    input: FormInput<UpdateFoodForm>,
 ) -> Result<Response, AppError> {
     let food = state.db.get_food(&id).await?;
 
-    if !authz(&session, &food.user_id) {
+    if !authz(&session.user_id, &food.user_id) {
         return Err(AppError::Forbidden);
     }
 
+    let (form, barcode) = match input.check(|form| {
+        check_food_unit(&form.unit)?;
+        check_amount(form.basis)?;
+        barcode(form.barcode.as_deref())
+    }) {
+        Ok(checked) => checked,
+        Err(invalid) => {
+            let page = foods_update_page(&state, &session.user_id, food, Some(&invalid)).await?;
+            return Ok(render_invalid_form(page));
+        }
+    };
+
     state
         .db
         .update_food(
             &id,
-            &form.name,
-            Macros {
-                kcal: form.kcal,
-                fat: form.fat,
-                carbs: form.carbs,
-                protein: form.protein,
+            FoodParams {
+                name: &form.name,
+                macros: Macros {
+                    kcal: form.kcal,
+                    fat: form.fat,
+                    carbs: form.carbs,
+                    protein: form.protein,
+                },
+                nutrients: form.nutrients,
+                unit: &form.unit,
+                basis: form.basis,
+                barcode: barcode.as_deref(),
+                flags: EntryFlags {
+                    hidden: form.hidden.is_some(),
+                    starred: form.starred.is_some(),
+                    shared: form.shared.is_some(),
+                },
             },
-            form.hidden.is_some(),
-            form.starred.is_some(),
         )
         .await?;
 
@@ -237,409 +535,1689 @@ pub async fn foods_delete(
 ) -> Result<Response, AppError> {
     let food = state.db.get_food(&id).await?;
 
-    if !authz(&session, &food.user_id) {
+    if !authz(&session.user_id, &food.user_id) {
         return Err(AppError::Forbidden);
     }
 
+    let references = state.db.get_food_references(&session.user_id, &id).await?;
+    if !references.is_empty() {
+        return Ok((
+            StatusCode::CONFLICT,
+            render_html(html::foods_delete_blocked_page(&food, &references)),
+        )
+            .into_response());
+    }
+
     state.db.delete_food(&id).await?;
 
     Ok(redirect_to(AppUrl::Foods))
 }
 
+/// Foods of the user that `food` can be merged into, sorted by name.
+async fn merge_targets(
+    state: &AppState,
+    user_id: &UserId,
+    food: &Food,
+) -> Result<Vec<Food>, AppError> {
+    let mut foods = state
+        .db
+        .get_foods(user_id)
+        .await?
+        .into_iter()
+        .filter(|f| f.id != food.id && f.unit == food.unit)
+        .collect::<Vec<_>>();
+    foods.sort_by_key(|f| f.name.to_lowercase());
+    Ok(foods)
+}
+
 #[derive(Debug, serde::Deserialize)]
-pub struct CreateConsumptionForm {
-    consumable_id: String,
-    consumable_type: String,
-    quantity: f64,
-    consumed_at: chrono::NaiveDate,
+pub struct MergeFoodForm {
+    into_id: String,
+    keep_alias: Option<bool>,
 }
 
-pub async fn consumptions_create(
+pub async fn foods_merge(
     state: State<AppState>,
     session: Session,
-    Form(form): Form<CreateConsumptionForm>,
+    Path(id): Path<String>,
+    input: FormInput<MergeFoodForm>,
 ) -> Result<Response, AppError> {
-    let mut food_id = None::<String>;
-    let mut recipe_id = None::<String>;
+    let food = state.db.get_food(&id).await?;
 
-    if form.consumable_type == "food" {
-        let food = state.db.get_food(&form.consumable_id).await?;
+    if !authz(&session.user_id, &food.user_id) {
+        return Err(AppError::Forbidden);
+    }
 
-        if !authz(&session, &food.user_id) {
-            return Err(AppError::Forbidden);
+    let form = match input.parse() {
+        Ok(form) => form,
+        Err(invalid) => {
+            let page = foods_update_page(&state, &session.user_id, food, Some(&invalid)).await?;
+            return Ok(render_invalid_form(page));
         }
+    };
+
+    merge_food(
+        &state,
+        &session.user_id,
+        &id,
+        &form.into_id,
+        form.keep_alias.is_some(),
+    )
+    .await?;
 
-        food_id = Some(food.id.clone());
-    } else if form.consumable_type == "recipe" {
-        let recipe = state.db.get_recipe(&form.consumable_id).await?;
-
-        if !authz(&session, &recipe.user_id) {
-            return Err(AppError::Forbidden);
-        }
+    Ok(redirect_to(AppUrl::FoodsId(form.into_id)))
+}
 
-        recipe_id = Some(recipe.id.clone());
-    } else {
-        return Err(AppError::InvalidConsumableType);
+/// Checks and performs a merge for the HTML and the JSON API.
+pub async fn merge_food(
+    state: &AppState,
+    user_id: &UserId,
+    id: &str,
+    into_id: &str,
+    keep_alias: bool,
+) -> Result<(), AppError> {
+    let food = state.db.get_food(id).await?;
+    if !authz(user_id, &food.user_id) {
+        return Err(AppError::Forbidden);
+    }
+    let into = match state.db.get_food(into_id).await {
+        Err(sqlx::Error::RowNotFound) => return Err(AppError::InvalidMerge),
+        into => into?,
+    };
+    if !authz(user_id, &into.user_id) || into.id == food.id || into.unit != food.unit {
+        return Err(AppError::InvalidMerge);
     }
 
-    state
-        .db
-        .add_consumption(
-            &session.user_id,
-            food_id.as_deref(),
-            recipe_id.as_deref(),
-            form.quantity,
-            &form.consumed_at,
-        )
-        .await?;
+    state.db.merge_food(id, into_id, keep_alias).await?;
+    info!(user_id = user_id.0, id, into_id, "merged food");
 
-    Ok(redirect_to(AppUrl::DaySummary(form.consumed_at)))
+    Ok(())
 }
 
-pub async fn consumptions_read(
+pub async fn food_aliases_delete(
     state: State<AppState>,
     session: Session,
     Path(id): Path<String>,
 ) -> Result<Response, AppError> {
-    let consumption = state.db.get_consumption(&id).await?;
-    let consumables = state.db.get_consumables(&session.user_id).await?;
+    let alias = state.db.get_food_alias(&id).await?;
 
-    if !authz(&session, &consumption.user_id) {
+    if !authz(&session.user_id, &alias.user_id) {
         return Err(AppError::Forbidden);
     }
 
-    Ok(render_html(html::consumptions_update_page(
-        consumption,
-        consumables,
-    )))
-}
+    state.db.delete_food_alias(&id).await?;
 
-#[derive(Debug, serde::Deserialize)]
-pub struct UpdateConsumptionForm {
-    consumable_id: String,
-    consumable_type: String,
-    quantity: f64,
-    consumed_at: chrono::NaiveDate,
+    Ok(redirect_to(AppUrl::FoodsId(alias.food_id)))
 }
 
-pub async fn consumptions_update(
+/// The food may also be one that another user shares.
+pub async fn foods_recalculate(
     state: State<AppState>,
     session: Session,
     Path(id): Path<String>,
-    Form(form): Form<UpdateConsumptionForm>,
 ) -> Result<Response, AppError> {
-    let consumption = state.db.get_consumption(&id).await?;
+    let food = state.db.get_food(&id).await?;
 
-    if !authz(&session, &consumption.user_id) {
+    if !visible(&session.user_id, &food.user_id, &food.shared_at) {
         return Err(AppError::Forbidden);
     }
 
-    let mut food_id = None::<String>;
-    let mut recipe_id = None::<String>;
-
-    if form.consumable_type == "food" {
-        let food = state.db.get_food(&form.consumable_id).await?;
-
-        if !authz(&session, &food.user_id) {
-            return Err(AppError::Forbidden);
-        }
+    let count = state
+        .db
+        .recalculate_consumptions(&session.user_id, ConsumptionFilter::FoodId(id.clone()))
+        .await?;
+    info!(
+        user_id = session.user_id.0,
+        id, count, "recalculated consumptions"
+    );
 
-        food_id = Some(food.id.clone());
-    } else if form.consumable_type == "recipe" {
-        let recipe = state.db.get_recipe(&form.consumable_id).await?;
+    Ok(redirect_to(AppUrl::FoodsId(id)))
+}
 
-        if !authz(&session, &recipe.user_id) {
-            return Err(AppError::Forbidden);
-        }
+pub async fn foods_hide(
+    state: State<AppState>,
+    session: Session,
+    Path(id): Path<String>,
+) -> Result<Response, AppError> {
+    let food = state.db.get_food(&id).await?;
 
-        recipe_id = Some(recipe.id.clone());
-    } else {
-        return Err(AppError::InvalidConsumableType);
+    if !authz(&session.user_id, &food.user_id) {
+        return Err(AppError::Forbidden);
     }
 
-    state
-        .db
-        .update_consumption(
-            &id,
-            food_id.as_deref(),
-            recipe_id.as_deref(),
-            form.quantity,
-            &form.consumed_at,
-        )
-        .await?;
+    state.db.hide_food(&id).await?;
 
-    Ok(redirect_to(AppUrl::DaySummary(consumption.consumed_at)))
+    Ok(redirect_to(AppUrl::Foods))
 }
 
-pub async fn consumptions_delete(
+pub async fn foods_clone(
     state: State<AppState>,
     session: Session,
     Path(id): Path<String>,
 ) -> Result<Response, AppError> {
-    let consumption = state.db.get_consumption(&id).await?;
+    let food = state.db.get_food(&id).await?;
 
-    if !authz(&session, &consumption.user_id) {
+    if !visible(&session.user_id, &food.user_id, &food.shared_at) {
         return Err(AppError::Forbidden);
     }
 
-    state.db.delete_consumption(&id).await?;
+    let id = state.db.clone_food(&session.user_id, &id).await?;
 
-    Ok(redirect_to(AppUrl::DaySummary(consumption.consumed_at)))
+    Ok(redirect_to(AppUrl::FoodsId(id)))
 }
 
-pub async fn recipes_index(state: State<AppState>, session: Session) -> Result<Response, AppError> {
-    let recipes = state.db.get_recipes(&session.user_id).await?;
+pub fn check_food_unit(unit: &str) -> Result<(), AppError> {
+    if FOOD_UNITS.contains(&unit) {
+        Ok(())
+    } else {
+        Err(AppError::InvalidUnit)
+    }
+}
 
-    Ok(render_html(html::recipes_page(recipes)))
+pub fn check_amount(amount: f64) -> Result<(), AppError> {
+    if amount > 0.0 {
+        Ok(())
+    } else {
+        Err(AppError::InvalidAmount)
+    }
 }
 
-#[derive(Debug, serde::Deserialize)]
-pub struct CreateRecipeForm {
+/// Normalizes an optional barcode. An empty field means no barcode.
+pub fn barcode(code: Option<&str>) -> Result<Option<String>, AppError> {
+    match code.map(str::trim).filter(|code| !code.is_empty()) {
+        None => Ok(None),
+        Some(code) => products::normalize_barcode(code)
+            .map(Some)
+            .ok_or(AppError::InvalidBarcode),
+    }
+}
+
+/// Values to pre-fill the food form with after scanning a barcode. If the
+/// user already has a food with the code, `food_id` is set.
+#[derive(serde::Serialize)]
+pub struct BarcodeMatch {
+    food_id: Option<String>,
+    barcode: String,
     name: String,
-    quantity: f64,
-    hidden: Option<bool>,
-    starred: Option<bool>,
+    kcal: f64,
+    fat: f64,
+    carbs: f64,
+    protein: f64,
+    unit: String,
+    basis: f64,
+    #[serde(flatten)]
+    nutrients: Nutrients,
 }
 
-pub async fn recipes_create(
-    state: State<AppState>,
-    session: Session,
-    Form(form): Form<CreateRecipeForm>,
-) -> Result<Response, AppError> {
-    state
-        .db
-        .add_recipe(
-            &session.user_id,
-            &form.name,
-            form.quantity,
-            form.hidden.is_some(),
-            form.starred.is_some(),
-        )
-        .await?;
+/// Looks up a barcode in the user's foods and then in the local product
+/// database.
+pub async fn barcode_lookup(
+    state: &AppState,
+    user_id: &UserId,
+    code: &str,
+) -> Result<BarcodeMatch, AppError> {
+    let barcode = products::normalize_barcode(code).ok_or(AppError::InvalidBarcode)?;
+
+    if let Some(food) = state.db.get_food_by_barcode(user_id, &barcode).await? {
+        return Ok(BarcodeMatch {
+            food_id: Some(food.id),
+            barcode,
+            name: food.name,
+            kcal: food.kcal,
+            fat: food.fat,
+            carbs: food.carbs,
+            protein: food.protein,
+            unit: food.unit,
+            basis: food.basis,
+            nutrients: food.nutrients,
+        });
+    }
 
-    Ok(redirect_to(AppUrl::Recipes))
+    let product = state
+        .db
+        .get_product(&barcode)
+        .await?
+        .ok_or(AppError::NotFound)?;
+
+    Ok(BarcodeMatch {
+        food_id: None,
+        barcode,
+        name: product.name,
+        kcal: product.kcal,
+        fat: product.fat,
+        carbs: product.carbs,
+        protein: product.protein,
+        unit: PRODUCT_UNIT.to_string(),
+        basis: PRODUCT_BASIS,
+        nutrients: product.nutrients,
+    })
 }
 
-pub async fn recipes_read(
+pub async fn foods_barcode_read(
     state: State<AppState>,
     session: Session,
-    Path(id): Path<String>,
+    Path(code): Path<String>,
 ) -> Result<Response, AppError> {
-    let recipe = state.db.get_recipe(&id).await?;
-
-    if !authz(&session, &recipe.user_id) {
-        return Err(AppError::Forbidden);
-    }
-
-    let consumptions = state
-        .db
-        .get_consumptions(&session.user_id, ConsumptionFilter::RecipeId(id.clone()))
-        .await?;
-    let ingredients = state.db.get_ingredients(&id).await?;
-    let consumables = state.db.get_consumables(&session.user_id).await?;
+    let result = barcode_lookup(&state, &session.user_id, &code).await?;
 
-    Ok(render_html(html::recipes_update_page(
-        recipe,
-        consumptions,
-        ingredients,
-        consumables,
-    )))
+    Ok(Json(result).into_response())
 }
 
 #[derive(Debug, serde::Deserialize)]
-pub struct UpdateRecipeForm {
+pub struct CreateServingForm {
     name: String,
-    quantity: f64,
-    hidden: Option<bool>,
-    starred: Option<bool>,
+    amount: f64,
 }
 
-pub async fn recipes_update(
+pub async fn servings_create(
     state: State<AppState>,
     session: Session,
-    Path(id): Path<String>,
-    Form(form): Form<UpdateRecipeForm>,
+    Path(food_id): Path<String>,
+    input: FormInput<CreateServingForm>,
 ) -> Result<Response, AppError> {
-    let recipe = state.db.get_recipe(&id).await?;
+    let food = state.db.get_food(&food_id).await?;
 
-    if !authz(&session, &recipe.user_id) {
+    if !authz(&session.user_id, &food.user_id) {
         return Err(AppError::Forbidden);
     }
 
+    let (form, _) = match input.check(|form| check_amount(form.amount)) {
+        Ok(checked) => checked,
+        Err(invalid) => {
+            let page = foods_update_page(&state, &session.user_id, food, Some(&invalid)).await?;
+            return Ok(render_invalid_form(page));
+        }
+    };
+
     state
         .db
-        .update_recipe(
-            &id,
-            &form.name,
-            form.quantity,
-            form.hidden.is_some(),
-            form.starred.is_some(),
-        )
+        .add_serving(&session.user_id, &food.id, &form.name, form.amount)
         .await?;
 
-    Ok(redirect_to(AppUrl::RecipesId(id)))
+    Ok(redirect_to(AppUrl::FoodsId(food.id)))
 }
 
-pub async fn recipes_delete(
+pub async fn servings_delete(
     state: State<AppState>,
     session: Session,
     Path(id): Path<String>,
 ) -> Result<Response, AppError> {
-    let recipe = state.db.get_recipe(&id).await?;
+    let serving = state.db.get_serving(&id).await?;
 
-    if !authz(&session, &recipe.user_id) {
+    if !authz(&session.user_id, &serving.user_id) {
         return Err(AppError::Forbidden);
     }
 
-    state.db.delete_recipe(&id).await?;
+    state.db.delete_serving(&id).await?;
 
-    Ok(redirect_to(AppUrl::Recipes))
+    Ok(redirect_to(AppUrl::FoodsId(serving.food_id)))
 }
 
 #[derive(Debug, serde::Deserialize)]
-pub struct CreateIngredientForm {
-    recipe_id: String,
+pub struct CreateConsumptionForm {
     consumable_id: String,
+    consumable_type: String,
     quantity: f64,
+    serving_id: Option<String>,
+    consumed_at: chrono::NaiveDate,
+    #[serde(default, deserialize_with = "optional_time")]
+    consumed_time: Option<chrono::NaiveTime>,
+    meal_slot_id: Option<String>,
 }
 
-pub async fn ingredients_create(
+pub async fn consumptions_create(
     state: State<AppState>,
     session: Session,
-    Form(form): Form<CreateIngredientForm>,
+    input: FormInput<CreateConsumptionForm>,
 ) -> Result<Response, AppError> {
-    let recipe = state.db.get_recipe(&form.recipe_id).await?;
-
-    if !authz(&session, &recipe.user_id) {
-        return Err(AppError::Forbidden);
-    }
-
-    let food = state.db.get_food(&form.consumable_id).await?;
-
-    if !authz(&session, &food.user_id) {
-        return Err(AppError::Forbidden);
-    }
+    let checked = input
+        .check_async(async |form| {
+            consumption_ids(
+                &state,
+                &session.user_id,
+                &form.consumable_id,
+                &form.consumable_type,
+                form.serving_id.clone(),
+                form.meal_slot_id.clone(),
+            )
+            .await
+        })
+        .await?;
+    let (form, (food_id, recipe_id, serving_id, meal_slot_id)) = match checked {
+        Ok(checked) => checked,
+        Err(invalid) => {
+            let date = match invalid.values.get("consumed_at").parse() {
+                Ok(date) => date,
+                Err(_) => state.db.today(&session.user_id).await?,
+            };
+            let page = day_summary_page(&state, &session.user_id, date, Some(&invalid)).await?;
+            return Ok(render_invalid_form(page));
+        }
+    };
 
     state
         .db
-        .add_ingredient(
+        .add_consumption(
             &session.user_id,
-            &form.recipe_id,
-            &form.consumable_id,
-            form.quantity,
+            ConsumptionParams {
+                food_id: food_id.as_deref(),
+                recipe_id: recipe_id.as_deref(),
+                quantity: form.quantity,
+                serving_id: serving_id.as_deref(),
+                consumed_at: form.consumed_at,
+                consumed_time: form.consumed_time,
+                meal_slot_id: meal_slot_id.as_deref(),
+            },
         )
         .await?;
-    Ok(redirect_to(AppUrl::RecipesId(form.recipe_id)))
+
+    Ok(redirect_to(AppUrl::DaySummary(form.consumed_at)))
 }
 
-pub async fn ingredients_read(
+pub async fn consumptions_read(
     state: State<AppState>,
     session: Session,
     Path(id): Path<String>,
 ) -> Result<Response, AppError> {
-    let ingredient = state.db.get_ingredient(&id).await?;
+    let consumption = state.db.get_consumption(&id).await?;
     let consumables = state.db.get_consumables(&session.user_id).await?;
+    let meal_slots = state.db.get_meal_slots(&session.user_id).await?;
+    let servings = state.db.get_user_servings(&session.user_id).await?;
 
-    if !authz(&session, &ingredient.user_id) {
+    if !authz(&session.user_id, &consumption.user_id) {
         return Err(AppError::Forbidden);
     }
 
-    Ok(render_html(html::ingredients_update_page(
-        &ingredient,
+    Ok(render_html(html::consumptions_update_page(
+        consumption,
         consumables,
+        meal_slots,
+        servings,
+        None,
     )))
 }
 
 #[derive(Debug, serde::Deserialize)]
-pub struct UpdateIngredientForm {
+pub struct UpdateConsumptionForm {
     consumable_id: String,
+    consumable_type: String,
     quantity: f64,
+    serving_id: Option<String>,
+    consumed_at: chrono::NaiveDate,
+    #[serde(default, deserialize_with = "optional_time")]
+    consumed_time: Option<chrono::NaiveTime>,
+    meal_slot_id: Option<String>,
 }
 
-pub async fn ingredients_update(
+pub async fn consumptions_update(
+    state: State<AppState>,
+    session: Session,
+    Path(id): Path<String>,
+    input: FormInput<UpdateConsumptionForm>,
+) -> Result<Response, AppError> {
+    let consumption = state.db.get_consumption(&id).await?;
+
+    if !authz(&session.user_id, &consumption.user_id) {
+        return Err(AppError::Forbidden);
+    }
+
+    let checked = input
+        .check_async(async |form| {
+            consumption_ids(
+                &state,
+                &session.user_id,
+                &form.consumable_id,
+                &form.consumable_type,
+                form.serving_id.clone(),
+                form.meal_slot_id.clone(),
+            )
+            .await
+        })
+        .await?;
+    let (form, (food_id, recipe_id, serving_id, meal_slot_id)) = match checked {
+        Ok(checked) => checked,
+        Err(invalid) => {
+            let consumables = state.db.get_consumables(&session.user_id).await?;
+            let meal_slots = state.db.get_meal_slots(&session.user_id).await?;
+            let servings = state.db.get_user_servings(&session.user_id).await?;
+            return Ok(render_invalid_form(html::consumptions_update_page(
+                consumption,
+                consumables,
+                meal_slots,
+                servings,
+                Some(&invalid),
+            )));
+        }
+    };
+
+    state
+        .db
+        .update_consumption(
+            &id,
+            ConsumptionParams {
+                food_id: food_id.as_deref(),
+                recipe_id: recipe_id.as_deref(),
+                quantity: form.quantity,
+                serving_id: serving_id.as_deref(),
+                consumed_at: form.consumed_at,
+                consumed_time: form.consumed_time,
+                meal_slot_id: meal_slot_id.as_deref(),
+            },
+        )
+        .await?;
+
+    Ok(redirect_to(AppUrl::DaySummary(consumption.consumed_at)))
+}
+
+pub async fn consumptions_delete(
+    state: State<AppState>,
+    session: Session,
+    Path(id): Path<String>,
+) -> Result<Response, AppError> {
+    let consumption = state.db.get_consumption(&id).await?;
+
+    if !authz(&session.user_id, &consumption.user_id) {
+        return Err(AppError::Forbidden);
+    }
+
+    state.db.delete_consumption(&id).await?;
+
+    Ok(redirect_to(AppUrl::DaySummary(consumption.consumed_at)))
+}
+
+pub async fn consumptions_recalculate(
+    state: State<AppState>,
+    session: Session,
+    Path(id): Path<String>,
+) -> Result<Response, AppError> {
+    let consumption = state.db.get_consumption(&id).await?;
+
+    if !authz(&session.user_id, &consumption.user_id) {
+        return Err(AppError::Forbidden);
+    }
+
+    state.db.recalculate_consumption(&id).await?;
+
+    Ok(redirect_to(AppUrl::DaySummary(consumption.consumed_at)))
+}
+
+/// Food, recipe, serving and meal slot ids of a consumption form, checked
+/// like the single values below.
+async fn consumption_ids(
+    state: &AppState,
+    user_id: &UserId,
+    consumable_id: &str,
+    consumable_type: &str,
+    serving_id: Option<String>,
+    meal_slot_id: Option<String>,
+) -> Result<ConsumptionIds, AppError> {
+    let (food_id, recipe_id) =
+        consumption_consumable(state, user_id, consumable_id, consumable_type).await?;
+    let serving_id = self::serving_id(state, food_id.as_deref(), serving_id).await?;
+    let meal_slot_id = self::meal_slot_id(state, user_id, meal_slot_id).await?;
+    Ok((food_id, recipe_id, serving_id, meal_slot_id))
+}
+
+/// Food, recipe, serving and meal slot id of a consumption.
+type ConsumptionIds = (
+    Option<String>,
+    Option<String>,
+    Option<String>,
+    Option<String>,
+);
+
+/// Resolves the selected consumable of a consumption form into a food id or
+/// a recipe id.
+pub async fn consumption_consumable(
+    state: &AppState,
+    user_id: &UserId,
+    consumable_id: &str,
+    consumable_type: &str,
+) -> Result<(Option<String>, Option<String>), AppError> {
+    if consumable_type == "food" {
+        let food = state.db.get_food(consumable_id).await?;
+
+        if !visible(user_id, &food.user_id, &food.shared_at) {
+            return Err(AppError::Forbidden);
+        }
+
+        Ok((Some(food.id), None))
+    } else if consumable_type == "recipe" {
+        let recipe = state.db.get_recipe(consumable_id).await?;
+
+        if !visible(user_id, &recipe.user_id, &recipe.shared_at) {
+            return Err(AppError::Forbidden);
+        }
+
+        Ok((None, Some(recipe.id)))
+    } else {
+        Err(AppError::InvalidConsumableType)
+    }
+}
+
+/// Resolves the selected serving of a consumption or ingredient form. The
+/// serving has to belong to the selected food.
+pub async fn serving_id(
+    state: &AppState,
+    food_id: Option<&str>,
+    serving_id: Option<String>,
+) -> Result<Option<String>, AppError> {
+    match serving_id.filter(|id| !id.is_empty()) {
+        None => Ok(None),
+        Some(id) => {
+            let serving = state.db.get_serving(&id).await?;
+
+            if food_id != Some(serving.food_id.as_str()) {
+                return Err(AppError::InvalidServing);
+            }
+
+            Ok(Some(serving.id))
+        }
+    }
+}
+
+pub async fn meal_slot_id(
+    state: &AppState,
+    user_id: &UserId,
+    meal_slot_id: Option<String>,
+) -> Result<Option<String>, AppError> {
+    match meal_slot_id.filter(|id| !id.is_empty()) {
+        None => Ok(None),
+        Some(id) => {
+            let meal_slot = state.db.get_meal_slot(&id).await?;
+
+            if !authz(user_id, &meal_slot.user_id) {
+                return Err(AppError::Forbidden);
+            }
+
+            Ok(Some(meal_slot.id))
+        }
+    }
+}
+
+pub async fn recipes_index(state: State<AppState>, session: Session) -> Result<Response, AppError> {
+    let recipes = state.db.get_recipes(&session.user_id).await?;
+    let shown = state.db.get_shown_nutrients(&session.user_id).await?;
+
+    Ok(render_html(html::recipes_page(recipes, &shown, None)))
+}
+
+#[derive(Debug, serde::Deserialize)]
+pub struct CreateRecipeForm {
+    name: String,
+    quantity: f64,
+    hidden: Option<bool>,
+    starred: Option<bool>,
+    shared: Option<bool>,
+}
+
+pub async fn recipes_create(
+    state: State<AppState>,
+    session: Session,
+    input: FormInput<CreateRecipeForm>,
+) -> Result<Response, AppError> {
+    let form = match input.parse() {
+        Ok(form) => form,
+        Err(invalid) => {
+            let recipes = state.db.get_recipes(&session.user_id).await?;
+            let shown = state.db.get_shown_nutrients(&session.user_id).await?;
+            return Ok(render_invalid_form(html::recipes_page(
+                recipes,
+                &shown,
+                Some(&invalid),
+            )));
+        }
+    };
+
+    state
+        .db
+        .add_recipe(
+            &session.user_id,
+            &form.name,
+            form.quantity,
+            EntryFlags {
+                hidden: form.hidden.is_some(),
+                starred: form.starred.is_some(),
+                shared: form.shared.is_some(),
+            },
+        )
+        .await?;
+
+    Ok(redirect_to(AppUrl::Recipes))
+}
+
+pub async fn recipes_read(
+    state: State<AppState>,
+    session: Session,
+    Path(id): Path<String>,
+) -> Result<Response, AppError> {
+    let recipe = state.db.get_recipe(&id).await?;
+
+    if !visible(&session.user_id, &recipe.user_id, &recipe.shared_at) {
+        return Err(AppError::Forbidden);
+    }
+
+    let consumptions = state
+        .db
+        .get_consumptions(&session.user_id, ConsumptionFilter::RecipeId(id.clone()))
+        .await?;
+    let ingredients = state.db.get_ingredients(&id).await?;
+
+    if !authz(&session.user_id, &recipe.user_id) {
+        let shown = state.db.get_shown_nutrients(&session.user_id).await?;
+        return Ok(render_html(html::recipes_shared_page(
+            recipe,
+            consumptions,
+            ingredients,
+            &shown,
+        )));
+    }
+
+    Ok(render_html(
+        recipes_update_page(&state, &session.user_id, recipe, None).await?,
+    ))
+}
+
+/// The page of the user's own recipe, with `invalid` being its recipe or
+/// ingredient form if that couldn't be saved.
+async fn recipes_update_page(
+    state: &AppState,
+    user_id: &UserId,
+    recipe: Recipe,
+    invalid: Option<&InvalidForm>,
+) -> Result<Markup, AppError> {
+    let consumptions = state
+        .db
+        .get_consumptions(user_id, ConsumptionFilter::RecipeId(recipe.id.clone()))
+        .await?;
+    let ingredients = state.db.get_ingredients(&recipe.id).await?;
+    let consumables = state.db.get_consumables(user_id).await?;
+    let servings = state.db.get_user_servings(user_id).await?;
+    let shown = state.db.get_shown_nutrients(user_id).await?;
+
+    Ok(html::recipes_update_page(
+        recipe,
+        consumptions,
+        ingredients,
+        consumables,
+        servings,
+        &shown,
+        invalid,
+    ))
+}
+
+#[derive(Debug, serde::Deserialize)]
+pub struct UpdateRecipeForm {
+    name: String,
+    quantity: f64,
+    hidden: Option<bool>,
+    starred: Option<bool>,
+    shared: Option<bool>,
+}
+
+pub async fn recipes_update(
+    state: State<AppState>,
+    session: Session,
+    Path(id): Path<String>,
+    input: FormInput<UpdateRecipeForm>,
+) -> Result<Response, AppError> {
+    let recipe = state.db.get_recipe(&id).await?;
+
+    if !authz(&session.user_id, &recipe.user_id) {
+        return Err(AppError::Forbidden);
+    }
+
+    let form = match input.parse() {
+        Ok(form) => form,
+        Err(invalid) => {
+            let page =
+                recipes_update_page(&state, &session.user_id, recipe, Some(&invalid)).await?;
+            return Ok(render_invalid_form(page));
+        }
+    };
+
+    state
+        .db
+        .update_recipe(
+            &id,
+            &form.name,
+            form.quantity,
+            EntryFlags {
+                hidden: form.hidden.is_some(),
+                starred: form.starred.is_some(),
+                shared: form.shared.is_some(),
+            },
+        )
+        .await?;
+
+    Ok(redirect_to(AppUrl::RecipesId(id)))
+}
+
+pub async fn recipes_delete(
+    state: State<AppState>,
+    session: Session,
+    Path(id): Path<String>,
+) -> Result<Response, AppError> {
+    let recipe = state.db.get_recipe(&id).await?;
+
+    if !authz(&session.user_id, &recipe.user_id) {
+        return Err(AppError::Forbidden);
+    }
+
+    let references = state
+        .db
+        .get_recipe_references(&session.user_id, &id)
+        .await?;
+    if !references.is_empty() {
+        return Ok((
+            StatusCode::CONFLICT,
+            render_html(html::recipes_delete_blocked_page(&recipe, &references)),
+        )
+            .into_response());
+    }
+
+    state.db.delete_recipe(&id).await?;
+
+    Ok(redirect_to(AppUrl::Recipes))
+}
+
+pub async fn recipes_recalculate(
+    state: State<AppState>,
+    session: Session,
+    Path(id): Path<String>,
+) -> Result<Response, AppError> {
+    let recipe = state.db.get_recipe(&id).await?;
+
+    if !visible(&session.user_id, &recipe.user_id, &recipe.shared_at) {
+        return Err(AppError::Forbidden);
+    }
+
+    let count = state
+        .db
+        .recalculate_consumptions(&session.user_id, ConsumptionFilter::RecipeId(id.clone()))
+        .await?;
+    info!(
+        user_id = session.user_id.0,
+        id, count, "recalculated consumptions"
+    );
+
+    Ok(redirect_to(AppUrl::RecipesId(id)))
+}
+
+pub async fn recipes_hide(
+    state: State<AppState>,
+    session: Session,
+    Path(id): Path<String>,
+) -> Result<Response, AppError> {
+    let recipe = state.db.get_recipe(&id).await?;
+
+    if !authz(&session.user_id, &recipe.user_id) {
+        return Err(AppError::Forbidden);
+    }
+
+    state.db.hide_recipe(&id).await?;
+
+    Ok(redirect_to(AppUrl::Recipes))
+}
+
+pub async fn recipes_clone(
+    state: State<AppState>,
+    session: Session,
+    Path(id): Path<String>,
+) -> Result<Response, AppError> {
+    let recipe = state.db.get_recipe(&id).await?;
+
+    if !visible(&session.user_id, &recipe.user_id, &recipe.shared_at) {
+        return Err(AppError::Forbidden);
+    }
+
+    let id = state.db.clone_recipe(&session.user_id, &id).await?;
+
+    Ok(redirect_to(AppUrl::RecipesId(id)))
+}
+
+pub async fn catalog_index(state: State<AppState>, session: Session) -> Result<Response, AppError> {
+    let foods = state.db.get_shared_foods(&session.user_id).await?;
+    let recipes = state.db.get_shared_recipes(&session.user_id).await?;
+    let shown = state.db.get_shown_nutrients(&session.user_id).await?;
+
+    Ok(render_html(html::catalog_page(foods, recipes, &shown)))
+}
+
+/// The recipe comes from the `recipe_id` field, which is read before the
+/// rest of the form so that the form can be shown again on its page.
+#[derive(Debug, serde::Deserialize)]
+pub struct CreateIngredientForm {
+    consumable_id: String,
+    consumable_type: String,
+    quantity: f64,
+    serving_id: Option<String>,
+}
+
+pub async fn ingredients_create(
+    state: State<AppState>,
+    session: Session,
+    input: FormInput<CreateIngredientForm>,
+) -> Result<Response, AppError> {
+    let recipe = state.db.get_recipe(input.values.get("recipe_id")).await?;
+
+    if !authz(&session.user_id, &recipe.user_id) {
+        return Err(AppError::Forbidden);
+    }
+
+    let checked = input
+        .check_async(async |form| {
+            ingredient_ids(
+                &state,
+                &session.user_id,
+                &recipe.id,
+                &form.consumable_id,
+                &form.consumable_type,
+                form.serving_id.clone(),
+            )
+            .await
+        })
+        .await?;
+    let (form, (food_id, sub_recipe_id, serving_id)) = match checked {
+        Ok(checked) => checked,
+        Err(invalid) => {
+            let page =
+                recipes_update_page(&state, &session.user_id, recipe, Some(&invalid)).await?;
+            return Ok(render_invalid_form(page));
+        }
+    };
+
+    state
+        .db
+        .add_ingredient(
+            &session.user_id,
+            &recipe.id,
+            food_id.as_deref(),
+            sub_recipe_id.as_deref(),
+            form.quantity,
+            serving_id.as_deref(),
+        )
+        .await?;
+    Ok(redirect_to(AppUrl::RecipesId(recipe.id)))
+}
+
+/// Food, sub-recipe and serving ids of an ingredient form, checked like the
+/// single values.
+async fn ingredient_ids(
+    state: &AppState,
+    user_id: &UserId,
+    recipe_id: &str,
+    consumable_id: &str,
+    consumable_type: &str,
+    serving_id: Option<String>,
+) -> Result<(Option<String>, Option<String>, Option<String>), AppError> {
+    let (food_id, sub_recipe_id) =
+        ingredient_consumable(state, user_id, recipe_id, consumable_id, consumable_type).await?;
+    let serving_id = self::serving_id(state, food_id.as_deref(), serving_id).await?;
+    Ok((food_id, sub_recipe_id, serving_id))
+}
+
+/// Resolves the selected consumable of an ingredient form into a food id or
+/// a sub-recipe id, rejecting recipes that already contain `recipe_id`.
+pub async fn ingredient_consumable(
+    state: &AppState,
+    user_id: &UserId,
+    recipe_id: &str,
+    consumable_id: &str,
+    consumable_type: &str,
+) -> Result<(Option<String>, Option<String>), AppError> {
+    if consumable_type == "food" {
+        let food = state.db.get_food(consumable_id).await?;
+
+        if !visible(user_id, &food.user_id, &food.shared_at) {
+            return Err(AppError::Forbidden);
+        }
+
+        Ok((Some(food.id), None))
+    } else if consumable_type == "recipe" {
+        let sub_recipe = state.db.get_recipe(consumable_id).await?;
+
+        if !visible(user_id, &sub_recipe.user_id, &sub_recipe.shared_at) {
+            return Err(AppError::Forbidden);
+        }
+
+        if state.db.recipe_contains(&sub_recipe.id, recipe_id).await? {
+            return Err(AppError::RecipeCycle);
+        }
+
+        Ok((None, Some(sub_recipe.id)))
+    } else {
+        Err(AppError::InvalidConsumableType)
+    }
+}
+
+pub async fn ingredients_read(
+    state: State<AppState>,
+    session: Session,
+    Path(id): Path<String>,
+) -> Result<Response, AppError> {
+    let ingredient = state.db.get_ingredient(&id).await?;
+    let consumables = state.db.get_consumables(&session.user_id).await?;
+    let servings = state.db.get_user_servings(&session.user_id).await?;
+
+    if !authz(&session.user_id, &ingredient.user_id) {
+        return Err(AppError::Forbidden);
+    }
+
+    Ok(render_html(html::ingredients_update_page(
+        &ingredient,
+        consumables,
+        servings,
+        None,
+    )))
+}
+
+#[derive(Debug, serde::Deserialize)]
+pub struct UpdateIngredientForm {
+    consumable_id: String,
+    consumable_type: String,
+    quantity: f64,
+    serving_id: Option<String>,
+}
+
+pub async fn ingredients_update(
+    state: State<AppState>,
+    session: Session,
+    Path(id): Path<String>,
+    input: FormInput<UpdateIngredientForm>,
+) -> Result<Response, AppError> {
+    let ingredient = state.db.get_ingredient(&id).await?;
+
+    if !authz(&session.user_id, &ingredient.user_id) {
+        return Err(AppError::Forbidden);
+    }
+
+    let checked = input
+        .check_async(async |form| {
+            ingredient_ids(
+                &state,
+                &session.user_id,
+                &ingredient.recipe_id,
+                &form.consumable_id,
+                &form.consumable_type,
+                form.serving_id.clone(),
+            )
+            .await
+        })
+        .await?;
+    let (form, (food_id, sub_recipe_id, serving_id)) = match checked {
+        Ok(checked) => checked,
+        Err(invalid) => {
+            let consumables = state.db.get_consumables(&session.user_id).await?;
+            let servings = state.db.get_user_servings(&session.user_id).await?;
+            return Ok(render_invalid_form(html::ingredients_update_page(
+                &ingredient,
+                consumables,
+                servings,
+                Some(&invalid),
+            )));
+        }
+    };
+
+    state
+        .db
+        .update_ingredient(
+            &id,
+            food_id.as_deref(),
+            sub_recipe_id.as_deref(),
+            form.quantity,
+            serving_id.as_deref(),
+        )
+        .await?;
+
+    Ok(redirect_to(AppUrl::RecipesId(ingredient.recipe_id)))
+}
+
+pub async fn ingredients_delete(
     state: State<AppState>,
     session: Session,
     Path(id): Path<String>,
-    Form(form): Form<UpdateIngredientForm>,
 ) -> Result<Response, AppError> {
     let ingredient = state.db.get_ingredient(&id).await?;
 
-    if !authz(&session, &ingredient.user_id) {
-        return Err(AppError::Forbidden);
-    }
+    if !authz(&session.user_id, &ingredient.user_id) {
+        return Err(AppError::Forbidden);
+    }
+
+    state.db.delete_ingredient(&id).await?;
+
+    Ok(redirect_to(AppUrl::RecipesId(ingredient.recipe_id)))
+}
+
+/// Number of days shown per page of the account summary table.
+const ACCOUNT_PAGE_DAYS: i64 = 30;
+
+#[derive(Debug, serde::Deserialize)]
+pub struct AccountQuery {
+    until: Option<chrono::NaiveDate>,
+}
+
+pub async fn account_read(
+    state: State<AppState>,
+    session: Session,
+    Query(query): Query<AccountQuery>,
+) -> Result<Response, AppError> {
+    let today = state.db.today(&session.user_id).await?;
+    let until = query.until.unwrap_or(today);
+    let from = until - chrono::Duration::days(ACCOUNT_PAGE_DAYS - 1);
+
+    let mut entries = HashMap::new();
+
+    let weights = state
+        .db
+        .get_weights_in_range(&session.user_id, from..=until)
+        .await?;
+    let rule = state.db.get_weight_rule(&session.user_id).await?;
+    for (date, weight) in daily_weights(&weights, rule) {
+        let mut weigh_ins = weights.iter().filter(|w| w.measured_at == date);
+        let weight_id = match (weigh_ins.next(), weigh_ins.next()) {
+            (Some(weigh_in), None) => Some(weigh_in.id.clone()),
+            _ => None,
+        };
+        entries.entry(date).or_insert(html::AccountSummaryRow {
+            date,
+            weight: Some(weight),
+            weight_id,
+            kcal: None,
+            protein: None,
+        });
+    }
+
+    let totals = state
+        .db
+        .get_daily_totals(&session.user_id, from..=until)
+        .await?;
+    for total in totals {
+        entries
+            .entry(total.date)
+            .and_modify(|r| {
+                r.kcal = Some(total.kcal);
+                r.protein = Some(total.protein);
+            })
+            .or_insert(html::AccountSummaryRow {
+                date: total.date,
+                weight: None,
+                weight_id: None,
+                kcal: Some(total.kcal),
+                protein: Some(total.protein),
+            });
+    }
+
+    let mut rows = entries
+        .values()
+        .cloned()
+        .collect::<Vec<AccountSummaryRow>>();
+
+    rows.sort_by_key(|r| std::cmp::Reverse(r.date));
+
+    let goals = state.db.get_goals(&session.user_id).await?;
+
+    let older = from.pred_opt();
+    let newer = (until < today).then(|| until + chrono::Duration::days(ACCOUNT_PAGE_DAYS));
+
+    let user = state.db.get_user_by_id(&session.user_id).await?;
+
+    Ok(render_html(html::account_page(
+        rows,
+        goals,
+        older,
+        newer,
+        user.is_admin,
+    )))
+}
+
+#[derive(Debug, serde::Deserialize)]
+pub struct StatsQuery {
+    weekly_change: Option<f64>,
+}
+
+pub async fn stats_read(
+    state: State<AppState>,
+    session: Session,
+    Query(query): Query<StatsQuery>,
+) -> Result<Response, AppError> {
+    // Today is still incomplete, so the window ends yesterday.
+    let end = state.db.today(&session.user_id).await?.pred_opt().unwrap();
+    let start = end - chrono::Duration::days(tdee::WINDOW_DAYS - 1);
+
+    let intake = state
+        .db
+        .get_daily_totals(&session.user_id, start..=end)
+        .await?
+        .into_iter()
+        .map(|t| (t.date, t.kcal))
+        .collect::<Vec<_>>();
+    let weights = state.db.get_weights(&session.user_id).await?;
+    let rule = state.db.get_weight_rule(&session.user_id).await?;
+    let weights = daily_weights(&weights, rule);
+    let points = trend::exponential_moving_average(&weights, trend::TREND_DAYS);
+
+    let estimate = tdee::estimate(&intake, &points, end);
+
+    Ok(render_html(html::stats_page(
+        estimate,
+        query.weekly_change.unwrap_or(0.0),
+    )))
+}
+
+pub async fn account_goals_read(
+    state: State<AppState>,
+    session: Session,
+) -> Result<Response, AppError> {
+    let goals = state.db.get_goals(&session.user_id).await?;
+
+    Ok(render_html(html::goals_page(goals, None)))
+}
+
+#[derive(Debug, serde::Deserialize)]
+pub struct UpdateGoalForm {
+    weekday: Option<u32>,
+    kcal: f64,
+    fat: f64,
+    carbs: f64,
+    protein: f64,
+}
+
+pub async fn account_goals_update(
+    state: State<AppState>,
+    session: Session,
+    input: FormInput<UpdateGoalForm>,
+) -> Result<Response, AppError> {
+    let form = match input.check(|form| {
+        if form.weekday.is_some_and(|w| w > 6) {
+            return Err(AppError::InvalidWeekday);
+        }
+        Ok(())
+    }) {
+        Ok((form, ())) => form,
+        Err(invalid) => {
+            let goals = state.db.get_goals(&session.user_id).await?;
+            return Ok(render_invalid_form(html::goals_page(goals, Some(&invalid))));
+        }
+    };
+
+    state
+        .db
+        .set_goal(
+            &session.user_id,
+            form.weekday,
+            Macros {
+                kcal: form.kcal,
+                fat: form.fat,
+                carbs: form.carbs,
+                protein: form.protein,
+            },
+        )
+        .await?;
+
+    Ok(redirect_to(AppUrl::AccountGoals))
+}
+
+pub async fn account_nutrients_read(
+    state: State<AppState>,
+    session: Session,
+) -> Result<Response, AppError> {
+    let shown = state.db.get_shown_nutrients(&session.user_id).await?;
+
+    Ok(render_html(html::nutrients_page(shown, None)))
+}
+
+pub async fn account_time_zone_read(
+    state: State<AppState>,
+    session: Session,
+) -> Result<Response, AppError> {
+    let time_zone = state.db.get_time_zone(&session.user_id).await?;
+
+    Ok(render_html(html::time_zone_page(time_zone, None)))
+}
+
+#[derive(Debug, serde::Deserialize)]
+pub struct TimeZoneForm {
+    time_zone: String,
+}
+
+pub async fn account_time_zone_update(
+    state: State<AppState>,
+    session: Session,
+    input: FormInput<TimeZoneForm>,
+) -> Result<Response, AppError> {
+    let time_zone = match input.check(|form| {
+        form.time_zone
+            .parse()
+            .map_err(|_| AppError::InvalidTimeZone)
+    }) {
+        Ok((_, time_zone)) => time_zone,
+        Err(invalid) => {
+            let time_zone = state.db.get_time_zone(&session.user_id).await?;
+            return Ok(render_invalid_form(html::time_zone_page(
+                time_zone,
+                Some(&invalid),
+            )));
+        }
+    };
+
+    state.db.set_time_zone(&session.user_id, time_zone).await?;
+
+    Ok(redirect_to(AppUrl::AccountTimeZone))
+}
+
+/// The form has a checkbox per nutrient, only the checked ones are sent.
+pub async fn account_nutrients_update(
+    state: State<AppState>,
+    session: Session,
+    input: FormInput<HashMap<String, String>>,
+) -> Result<Response, AppError> {
+    let form = match input.parse() {
+        Ok(form) => form,
+        Err(invalid) => {
+            let shown = state.db.get_shown_nutrients(&session.user_id).await?;
+            return Ok(render_invalid_form(html::nutrients_page(
+                shown,
+                Some(&invalid),
+            )));
+        }
+    };
+
+    let shown = NUTRIENTS
+        .iter()
+        .filter(|n| form.contains_key(n.key))
+        .collect::<Vec<_>>();
+
+    state
+        .db
+        .set_shown_nutrients(&session.user_id, &shown)
+        .await?;
+
+    Ok(redirect_to(AppUrl::AccountNutrients))
+}
+
+#[derive(Debug, serde::Deserialize)]
+pub struct DeleteGoalForm {
+    weekday: Option<u32>,
+}
+
+pub async fn account_goals_delete(
+    state: State<AppState>,
+    session: Session,
+    input: FormInput<DeleteGoalForm>,
+) -> Result<Response, AppError> {
+    let form = match input.parse() {
+        Ok(form) => form,
+        Err(invalid) => {
+            let goals = state.db.get_goals(&session.user_id).await?;
+            return Ok(render_invalid_form(html::goals_page(goals, Some(&invalid))));
+        }
+    };
+
+    state.db.delete_goal(&session.user_id, form.weekday).await?;
+
+    Ok(redirect_to(AppUrl::AccountGoals))
+}
+
+pub async fn account_meal_slots_read(
+    state: State<AppState>,
+    session: Session,
+) -> Result<Response, AppError> {
+    let meal_slots = state.db.get_meal_slots(&session.user_id).await?;
+
+    Ok(render_html(html::meal_slots_page(meal_slots, None)))
+}
+
+#[derive(Debug, serde::Deserialize)]
+pub struct CreateMealSlotForm {
+    name: String,
+}
+
+pub async fn account_meal_slots_create(
+    state: State<AppState>,
+    session: Session,
+    input: FormInput<CreateMealSlotForm>,
+) -> Result<Response, AppError> {
+    let form = match input.parse() {
+        Ok(form) => form,
+        Err(invalid) => {
+            let meal_slots = state.db.get_meal_slots(&session.user_id).await?;
+            return Ok(render_invalid_form(html::meal_slots_page(
+                meal_slots,
+                Some(&invalid),
+            )));
+        }
+    };
+
+    state.db.add_meal_slot(&session.user_id, &form.name).await?;
 
-    let food = state.db.get_food(&form.consumable_id).await?;
+    Ok(redirect_to(AppUrl::AccountMealSlots))
+}
+
+#[derive(Debug, serde::Deserialize)]
+pub struct UpdateMealSlotForm {
+    name: String,
+    position: i64,
+}
+
+pub async fn account_meal_slots_update(
+    state: State<AppState>,
+    session: Session,
+    Path(id): Path<String>,
+    input: FormInput<UpdateMealSlotForm>,
+) -> Result<Response, AppError> {
+    let meal_slot = state.db.get_meal_slot(&id).await?;
 
-    if !authz(&session, &food.user_id) {
+    if !authz(&session.user_id, &meal_slot.user_id) {
         return Err(AppError::Forbidden);
     }
 
+    let form = match input.parse() {
+        Ok(form) => form,
+        Err(invalid) => {
+            let meal_slots = state.db.get_meal_slots(&session.user_id).await?;
+            return Ok(render_invalid_form(html::meal_slots_page(
+                meal_slots,
+                Some(&invalid),
+            )));
+        }
+    };
+
     state
         .db
-        .update_ingredient(&id, &form.consumable_id, form.quantity)
+        .update_meal_slot(&id, &form.name, form.position)
         .await?;
 
-    Ok(redirect_to(AppUrl::RecipesId(ingredient.recipe_id)))
+    Ok(redirect_to(AppUrl::AccountMealSlots))
 }
 
-pub async fn ingredients_delete(
+pub async fn account_meal_slots_delete(
     state: State<AppState>,
     session: Session,
     Path(id): Path<String>,
 ) -> Result<Response, AppError> {
-    let ingredient = state.db.get_ingredient(&id).await?;
+    let meal_slot = state.db.get_meal_slot(&id).await?;
 
-    if !authz(&session, &ingredient.user_id) {
+    if !authz(&session.user_id, &meal_slot.user_id) {
         return Err(AppError::Forbidden);
     }
 
-    state.db.delete_ingredient(&id).await?;
+    state.db.delete_meal_slot(&id).await?;
 
-    Ok(redirect_to(AppUrl::RecipesId(ingredient.recipe_id)))
+    Ok(redirect_to(AppUrl::AccountMealSlots))
 }
 
-pub async fn account_read(state: State<AppState>, session: Session) -> Result<Response, AppError> {
-    let mut entries = HashMap::new();
+pub async fn account_api_tokens_read(
+    state: State<AppState>,
+    session: Session,
+) -> Result<Response, AppError> {
+    let api_tokens = state.db.get_api_tokens(&session.user_id).await?;
 
-    let weights = state.db.get_weights(&session.user_id).await?;
-    for weight in weights {
-        entries
-            .entry(weight.measured_at)
-            .or_insert(html::AccountSummaryRow {
-                date: weight.measured_at,
-                weight: Some((weight.id, weight.weight)),
-                kcal: None,
-                protein: None,
-            });
+    let time_zone = state.db.get_time_zone(&session.user_id).await?;
+    Ok(render_html(html::api_tokens_page(
+        api_tokens, time_zone, None, None,
+    )))
+}
+
+#[derive(Debug, serde::Deserialize)]
+pub struct CreateApiTokenForm {
+    name: String,
+}
+
+pub async fn account_api_tokens_create(
+    state: State<AppState>,
+    session: Session,
+    input: FormInput<CreateApiTokenForm>,
+) -> Result<Response, AppError> {
+    let form = match input.parse() {
+        Ok(form) => form,
+        Err(invalid) => {
+            let api_tokens = state.db.get_api_tokens(&session.user_id).await?;
+            let time_zone = state.db.get_time_zone(&session.user_id).await?;
+            return Ok(render_invalid_form(html::api_tokens_page(
+                api_tokens,
+                time_zone,
+                None,
+                Some(&invalid),
+            )));
+        }
+    };
+
+    let token = state
+        .db
+        .create_api_token(&session.user_id, &form.name)
+        .await?;
+    let api_tokens = state.db.get_api_tokens(&session.user_id).await?;
+
+    // The token is only shown once, so render it instead of redirecting.
+    let time_zone = state.db.get_time_zone(&session.user_id).await?;
+    Ok(render_html(html::api_tokens_page(
+        api_tokens,
+        time_zone,
+        Some(token),
+        None,
+    )))
+}
+
+pub async fn account_api_tokens_delete(
+    state: State<AppState>,
+    session: Session,
+    Path(id): Path<String>,
+) -> Result<Response, AppError> {
+    let api_token = state.db.get_api_token(&id).await?;
+
+    if !authz(&session.user_id, &api_token.user_id) {
+        return Err(AppError::Forbidden);
     }
 
-    let consumptions = state
+    state.db.delete_api_token(&id).await?;
+
+    Ok(redirect_to(AppUrl::AccountApiTokens))
+}
+
+/// Fails unless the user of the session is an admin.
+async fn require_admin(state: &AppState, session: &Session) -> Result<(), AppError> {
+    if state.db.get_user_by_id(&session.user_id).await?.is_admin {
+        Ok(())
+    } else {
+        Err(AppError::Forbidden)
+    }
+}
+
+pub async fn admin_users_index(
+    state: State<AppState>,
+    session: Session,
+) -> Result<Response, AppError> {
+    require_admin(&state, &session).await?;
+
+    let users = state.db.get_users().await?;
+
+    let time_zone = state.db.get_time_zone(&session.user_id).await?;
+    Ok(render_html(html::admin_users_page(users, time_zone, None)))
+}
+
+#[derive(Debug, serde::Deserialize)]
+pub struct CreateUserForm {
+    username: String,
+    password: String,
+    is_admin: Option<bool>,
+}
+
+pub async fn admin_users_create(
+    state: State<AppState>,
+    session: Session,
+    input: FormInput<CreateUserForm>,
+) -> Result<Response, AppError> {
+    require_admin(&state, &session).await?;
+
+    let taken = match &input.parsed {
+        Some(form) => state.db.get_user(form.username.trim()).await?.is_some(),
+        None => false,
+    };
+    let (form, ()) = match input.check(|form| {
+        if form.username.trim().is_empty() || taken {
+            return Err(AppError::InvalidUsername);
+        }
+        if form.password.is_empty() {
+            return Err(AppError::EmptyPassword);
+        }
+        Ok(())
+    }) {
+        Ok(checked) => checked,
+        Err(invalid) => {
+            let users = state.db.get_users().await?;
+            let time_zone = state.db.get_time_zone(&session.user_id).await?;
+            return Ok(render_invalid_form(html::admin_users_page(
+                users,
+                time_zone,
+                Some(&invalid),
+            )));
+        }
+    };
+
+    let user_id = state
         .db
-        .get_consumptions(&session.user_id, ConsumptionFilter::None)
+        .create_user(form.username.trim(), &form.password)
         .await?;
-    for consumption in consumptions {
-        entries
-            .entry(consumption.consumed_at)
-            .and_modify(|r| {
-                r.kcal = Some(r.kcal.unwrap_or(0.0) + consumption.kcal);
-                r.protein = Some(r.protein.unwrap_or(0.0) + consumption.protein);
-            })
-            .or_insert(html::AccountSummaryRow {
-                date: consumption.consumed_at,
-                weight: None,
-                kcal: Some(consumption.kcal),
-                protein: Some(consumption.protein),
-            });
+    if form.is_admin.is_some() {
+        state.db.set_user_admin(&user_id, true).await?;
     }
 
-    let mut rows = entries
-        .values()
-        .cloned()
-        .collect::<Vec<AccountSummaryRow>>();
+    Ok(redirect_to(AppUrl::AdminUsers))
+}
+
+pub async fn admin_users_read(
+    state: State<AppState>,
+    session: Session,
+    Path(id): Path<String>,
+) -> Result<Response, AppError> {
+    require_admin(&state, &session).await?;
+
+    let user = state.db.get_user_by_id(&UserId(id)).await?;
+    let used_by_others = state.db.count_user_entries_used_by_others(&user.id).await?;
+    let is_self = user.id == session.user_id;
+
+    let time_zone = state.db.get_time_zone(&session.user_id).await?;
+    Ok(render_html(html::admin_users_update_page(
+        user,
+        time_zone,
+        used_by_others,
+        is_self,
+        None,
+    )))
+}
+
+#[derive(Debug, serde::Deserialize)]
+pub struct UpdateUserForm {
+    is_admin: Option<bool>,
+    disabled: Option<bool>,
+}
 
-    rows.sort_by(|a, b| b.date.cmp(&a.date));
+pub async fn admin_users_update(
+    state: State<AppState>,
+    session: Session,
+    Path(id): Path<String>,
+    input: FormInput<UpdateUserForm>,
+) -> Result<Response, AppError> {
+    require_admin(&state, &session).await?;
+
+    let user = state.db.get_user_by_id(&UserId(id)).await?;
+
+    // Admins can't lock themselves out.
+    if user.id == session.user_id {
+        return Err(AppError::Forbidden);
+    }
+
+    let form = match input.parse() {
+        Ok(form) => form,
+        Err(invalid) => {
+            let used_by_others = state.db.count_user_entries_used_by_others(&user.id).await?;
+            let time_zone = state.db.get_time_zone(&session.user_id).await?;
+            return Ok(render_invalid_form(html::admin_users_update_page(
+                user,
+                time_zone,
+                used_by_others,
+                false,
+                Some(&invalid),
+            )));
+        }
+    };
+
+    state
+        .db
+        .set_user_admin(&user.id, form.is_admin.is_some())
+        .await?;
+    state
+        .db
+        .set_user_disabled(&user.id, form.disabled.is_some())
+        .await?;
+
+    Ok(redirect_to(AppUrl::AdminUsersId(user.id.0)))
+}
+
+#[derive(Debug, serde::Deserialize)]
+pub struct ResetPasswordForm {
+    password: String,
+}
+
+pub async fn admin_users_password(
+    state: State<AppState>,
+    session: Session,
+    Path(id): Path<String>,
+    input: FormInput<ResetPasswordForm>,
+) -> Result<Response, AppError> {
+    require_admin(&state, &session).await?;
+
+    let user = state.db.get_user_by_id(&UserId(id)).await?;
+    let form = match input.check(|form| {
+        if form.password.is_empty() {
+            return Err(AppError::EmptyPassword);
+        }
+        Ok(())
+    }) {
+        Ok((form, ())) => form,
+        Err(invalid) => {
+            let used_by_others = state.db.count_user_entries_used_by_others(&user.id).await?;
+            let is_self = user.id == session.user_id;
+            let time_zone = state.db.get_time_zone(&session.user_id).await?;
+            return Ok(render_invalid_form(html::admin_users_update_page(
+                user,
+                time_zone,
+                used_by_others,
+                is_self,
+                Some(&invalid),
+            )));
+        }
+    };
+
+    state.db.set_password(&user.id, &form.password).await?;
+    if user.id != session.user_id {
+        state.db.delete_user_sessions(&user.id).await?;
+    }
+
+    Ok(redirect_to(AppUrl::AdminUsersId(user.id.0)))
+}
+
+pub async fn admin_users_delete(
+    state: State<AppState>,
+    session: Session,
+    Path(id): Path<String>,
+) -> Result<Response, AppError> {
+    require_admin(&state, &session).await?;
 
-    Ok(render_html(html::account_page(rows)))
+    let user = state.db.get_user_by_id(&UserId(id)).await?;
+
+    if user.id == session.user_id {
+        return Err(AppError::Forbidden);
+    }
+
+    if state.db.count_user_entries_used_by_others(&user.id).await? > 0 {
+        return Err(AppError::UserInUse);
+    }
+
+    state.db.delete_user(&user.id).await?;
+
+    Ok(redirect_to(AppUrl::AdminUsers))
 }
 
 pub async fn account_login_form(session: Option<Session>) -> Result<Response, AppError> {
@@ -654,34 +2232,112 @@ pub async fn account_login_form(session: Option<Session>) -> Result<Response, Ap
 pub struct LoginForm {
     username: String,
     password: String,
+    remember: Option<bool>,
+}
+
+/// Longest user agent that is stored with a session.
+const USER_AGENT_MAX_CHARS: usize = 255;
+
+/// Client IP of a request. The app only listens on localhost behind a
+/// reverse proxy, which appends the IP it received the request from to
+/// `X-Forwarded-For`. Earlier entries are set by the client and not trusted.
+fn client_ip(headers: &HeaderMap) -> Option<String> {
+    headers
+        .get_all("x-forwarded-for")
+        .iter()
+        .next_back()
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.rsplit(',').next())
+        .map(str::trim)
+        .filter(|ip| !ip.is_empty())
+        .map(str::to_string)
 }
 
 pub async fn account_login(
     state: State<AppState>,
     session: Option<Session>,
-    Form(form): Form<LoginForm>,
+    headers: HeaderMap,
+    input: FormInput<LoginForm>,
 ) -> Result<Response, AppError> {
     if session.is_some() {
         return Ok(redirect_to(AppUrl::Home));
     }
 
-    let error_response = Ok(render_html(html::login_page(
-        Some(form.username.clone()),
-        Some("Invalid username or password.".to_string()),
-    )));
-
-    match state.db.get_user(&form.username).await? {
-        None => error_response,
-        Some(user) => match verify_password(&form.password, &user.password_hash) {
-            Err(_) => error_response,
-            Ok(_) => {
-                let session_id = state.db.create_session(&user.id).await?;
-                Ok(redirect_with_session_cookie_response(
-                    AppUrl::Home,
-                    Some(session_id),
-                ))
+    let form = match input.parse() {
+        Ok(form) => form,
+        Err(invalid) => {
+            return Ok(render_invalid_form(html::login_page(
+                Some(invalid.values.get("username").to_string()),
+                Some(invalid.message),
+            )));
+        }
+    };
+
+    let ip = client_ip(&headers);
+    let username_key = throttle::username_key(&form.username);
+    let ip_key = ip.as_deref().map(throttle::ip_key);
+    let keys = std::iter::once(username_key.clone())
+        .chain(ip_key.clone())
+        .collect::<Vec<_>>();
+
+    // Checked before the password so that a locked key can't be used to
+    // guess it.
+    if let Some(locked_until) = state.db.get_login_locked_until(&keys).await? {
+        warn!(username = form.username, ip, %locked_until, "login throttled");
+        let minutes = (locked_until - chrono::Utc::now()).num_seconds() / 60 + 1;
+        return Ok((
+            StatusCode::TOO_MANY_REQUESTS,
+            render_html(html::login_page(
+                Some(form.username),
+                Some(format!(
+                    "Too many failed logins. Please try again in {} minute{}.",
+                    minutes,
+                    if minutes == 1 { "" } else { "s" }
+                )),
+            )),
+        )
+            .into_response());
+    }
+
+    let user = match state.db.get_user(&form.username).await? {
+        Some(user) if user.disabled_at.is_none() => Some(user),
+        _ => None,
+    };
+    match user {
+        Some(user) if verify_password(&form.password, &user.password_hash).is_ok() => {
+            state.db.reset_login_failures(&username_key).await?;
+            info!(username = form.username, ip, "login succeeded");
+            let user_agent = headers
+                .get(header::USER_AGENT)
+                .and_then(|value| value.to_str().ok())
+                .map(|value| value.chars().take(USER_AGENT_MAX_CHARS).collect::<String>());
+            let remember = form.remember.is_some();
+            let session_id = state
+                .db
+                .create_session(&user.id, user_agent.as_deref(), remember)
+                .await?;
+            Ok(redirect_with_session_cookie_response(
+                AppUrl::Home,
+                session_cookie(Some(&session_id), remember),
+            ))
+        }
+        _ => {
+            let failures = state
+                .db
+                .record_login_failure(&username_key, throttle::USERNAME_FREE_FAILURES)
+                .await?;
+            if let Some(ip_key) = ip_key {
+                state
+                    .db
+                    .record_login_failure(&ip_key, throttle::IP_FREE_FAILURES)
+                    .await?;
             }
-        },
+            warn!(username = form.username, ip, failures, "login failed");
+            Ok(render_html(html::login_page(
+                Some(form.username),
+                Some("Invalid username or password.".to_string()),
+            )))
+        }
     }
 }
 
@@ -690,14 +2346,121 @@ pub async fn account_logout(
     session: Session,
 ) -> Result<Response, AppError> {
     state.db.delete_session(&session.session_id).await?;
-    Ok(redirect_with_session_cookie_response(AppUrl::Home, None))
+    Ok(redirect_with_session_cookie_response(
+        AppUrl::Home,
+        session_cookie(None, false),
+    ))
+}
+
+pub async fn account_password_read(_session: Session) -> Result<Response, AppError> {
+    Ok(render_html(html::password_page(None)))
+}
+
+#[derive(Debug, serde::Deserialize)]
+pub struct ChangePasswordForm {
+    current_password: String,
+    new_password: String,
+    new_password_confirmation: String,
+}
+
+/// Changes the password and logs out all other sessions.
+pub async fn account_password_update(
+    state: State<AppState>,
+    session: Session,
+    input: FormInput<ChangePasswordForm>,
+) -> Result<Response, AppError> {
+    let form = match input.parse() {
+        Ok(form) => form,
+        Err(invalid) => {
+            return Ok(render_invalid_form(html::password_page(Some(
+                invalid.message,
+            ))));
+        }
+    };
+
+    let password_hash = state.db.get_password_hash(&session.user_id).await?;
+
+    let error = if verify_password(&form.current_password, &password_hash).is_err() {
+        Some("The current password is wrong.")
+    } else if form.new_password.is_empty() {
+        Some("The new password must not be empty.")
+    } else if form.new_password != form.new_password_confirmation {
+        Some("The new passwords don't match.")
+    } else {
+        None
+    };
+    if let Some(error) = error {
+        return Ok(render_invalid_form(html::password_page(Some(
+            error.to_string(),
+        ))));
+    }
+
+    state
+        .db
+        .set_password(&session.user_id, &form.new_password)
+        .await?;
+    state
+        .db
+        .delete_other_sessions(&session.user_id, &session.session_id)
+        .await?;
+
+    Ok(redirect_to(AppUrl::Account))
+}
+
+pub async fn account_sessions_read(
+    state: State<AppState>,
+    session: Session,
+) -> Result<Response, AppError> {
+    let sessions = state.db.get_sessions(&session.user_id).await?;
+
+    let time_zone = state.db.get_time_zone(&session.user_id).await?;
+    Ok(render_html(html::sessions_page(
+        sessions,
+        &hash_session_id(&session.session_id),
+        time_zone,
+    )))
+}
+
+pub async fn account_sessions_delete(
+    state: State<AppState>,
+    session: Session,
+    Path(id): Path<String>,
+) -> Result<Response, AppError> {
+    let user_session = state.db.get_session_by_id(&id).await?;
+
+    if !authz(&session.user_id, &user_session.user_id) {
+        return Err(AppError::Forbidden);
+    }
+
+    state.db.delete_session_by_id(&id).await?;
+
+    if id == hash_session_id(&session.session_id) {
+        return Ok(redirect_with_session_cookie_response(
+            AppUrl::Home,
+            session_cookie(None, false),
+        ));
+    }
+
+    Ok(redirect_to(AppUrl::AccountSessions))
+}
+
+pub async fn account_sessions_delete_others(
+    state: State<AppState>,
+    session: Session,
+) -> Result<Response, AppError> {
+    state
+        .db
+        .delete_other_sessions(&session.user_id, &session.session_id)
+        .await?;
+
+    Ok(redirect_to(AppUrl::AccountSessions))
 }
 
 pub async fn account_export(
     state: State<AppState>,
     session: Session,
 ) -> Result<Response, AppError> {
-    let filename = format!("{}-matrafl.json", chrono::Utc::now().date_naive());
+    let filename = format!("{}-matrafl.json", state.db.today(&session.user_id).await?);
     let data = state.db.export_data(&session.user_id).await?;
     Ok(Response::builder()
         .header("Content-Type", "application/json")
@@ -708,17 +2471,64 @@ pub async fn account_export(
         .body(Body::from(serde_json::to_string(&data).unwrap()))?)
 }
 
-fn redirect_with_session_cookie_response(url: AppUrl, session_id: Option<String>) -> Response {
-    let mut cookie = Cookie::new("MATRAFL_SESSION", session_id.clone().unwrap_or_default());
-    cookie.set_expires(match session_id {
-        Some(_) => OffsetDateTime::now_utc() + Duration::days(SESSION_DAYS),
-        None => OffsetDateTime::now_utc() - Duration::days(1),
-    });
+pub async fn account_import_form(_session: Session) -> Result<Response, AppError> {
+    Ok(render_html(html::import_page(None)))
+}
+
+pub async fn account_import(
+    state: State<AppState>,
+    session: Session,
+    mut multipart: Multipart,
+) -> Result<Response, AppError> {
+    let mut file = None;
+    let mut mode = None;
+
+    while let Some(field) = multipart.next_field().await? {
+        match field.name() {
+            Some("file") => file = Some(field.bytes().await?),
+            Some("mode") => mode = ImportMode::parse(&field.text().await?),
+            _ => {}
+        }
+    }
+
+    let (Some(file), Some(mode)) = (file, mode) else {
+        return Ok(render_html(html::import_page(Some(
+            "Please choose a file and an import mode.".to_string(),
+        ))));
+    };
+
+    let result = match ExportData::parse(&file) {
+        Ok(data) => state.db.import_data(&session.user_id, data, mode).await,
+        Err(e) => Err(e),
+    };
+
+    match result {
+        Ok(()) => Ok(redirect_to(AppUrl::Account)),
+        Err(ImportError::Invalid(msg)) => Ok(render_html(html::import_page(Some(msg)))),
+        Err(ImportError::SQLError(e)) => Err(e.into()),
+    }
+}
+
+/// `Set-Cookie` header for a session, or one that removes the cookie. Only
+/// remembered sessions outlive the browser session.
+pub fn session_cookie(session_id: Option<&str>, remember: bool) -> HeaderValue {
+    let mut cookie = Cookie::new("MATRAFL_SESSION", session_id.unwrap_or_default());
+    match session_id {
+        Some(_) if remember => {
+            cookie.set_expires(OffsetDateTime::now_utc() + Duration::days(REMEMBERED_SESSION_DAYS))
+        }
+        Some(_) => {}
+        None => cookie.set_expires(OffsetDateTime::now_utc() - Duration::days(1)),
+    }
     cookie.set_http_only(true);
     // With SameSite=Strict, the cookie will not be sent when opening the PWA.
     cookie.set_same_site(SameSite::Lax);
     cookie.set_path("/");
 
+    HeaderValue::try_from(cookie.encoded().to_string().as_str()).unwrap()
+}
+
+fn redirect_with_session_cookie_response(url: AppUrl, cookie: HeaderValue) -> Response {
     (
         StatusCode::SEE_OTHER,
         [
@@ -726,10 +2536,7 @@ fn redirect_with_session_cookie_response(url: AppUrl, session_id: Option<String>
                 header::LOCATION,
                 HeaderValue::try_from(url.to_string().as_str()).unwrap(),
             ),
-            (
-                header::SET_COOKIE,
-                HeaderValue::try_from(cookie.encoded().to_string().as_str()).unwrap(),
-            ),
+            (header::SET_COOKIE, cookie),
         ],
     )
         .into_response()
@@ -743,3 +2550,12 @@ fn verify_password(password: &str, hash: &str) -> Result<(), AppError> {
 fn render_html(markup: Markup) -> Response {
     Html(markup.into_string()).into_response()
 }
+
+/// Page with a form that is shown again, see [`crate::form`].
+fn render_invalid_form(markup: Markup) -> Response {
+    (StatusCode::UNPROCESSABLE_ENTITY, render_html(markup)).into_response()
+}
+
+pub async fn not_found() -> AppError {
+    AppError::NotFound
+}
@@ -1,7 +1,14 @@
 use std::env;
 
+use axum::http::StatusCode;
+use chrono_tz::{Tz, TZ_VARIANTS};
 use maud::{html, Markup, PreEscaped, Render, DOCTYPE};
 
+use crate::csrf;
+use crate::form::{FormValues, InvalidForm};
+use crate::nutrients::{Nutrient, Nutrients, NUTRIENTS};
+use crate::tdee;
+use crate::trend::{self, TrendPoint, TrendRange, WeightRule};
 use crate::{db, AppUrl};
 
 #[derive(Debug, PartialEq, Eq)]
@@ -14,26 +21,32 @@ pub enum NavItem {
     Account,
 }
 
+#[allow(clippy::too_many_arguments)]
 pub fn day_summary_page(
     date: chrono::NaiveDate,
-    weight: Option<db::WeightWithDate>,
+    weights: Vec<db::WeightWithDate>,
+    weight: Option<f64>,
+    total: db::Macros,
+    total_nutrients: Nutrients,
     consumptions: Vec<db::Consumption>,
     consumables: Vec<db::Consumable>,
+    goal: Option<&db::Goal>,
+    meal_slots: Vec<db::MealSlot>,
+    servings: Vec<db::Serving>,
+    shown: &[&Nutrient],
+    invalid: Option<&InvalidForm>,
 ) -> Markup {
     let prev_day = date.pred_opt().unwrap();
     let next_day = date.succ_opt().unwrap();
-
-    let mut total_kcal = 0.0;
-    let mut total_fat = 0.0;
-    let mut total_carbs = 0.0;
-    let mut total_protein = 0.0;
-
-    for row in &consumptions {
-        total_kcal += row.kcal;
-        total_fat += row.fat;
-        total_carbs += row.carbs;
-        total_protein += row.protein;
-    }
+    let invalid_weight = invalid_at(invalid, &AppUrl::Weights);
+    let weight_values = form_values(invalid_weight, FormValues::default);
+    let invalid_consumption = invalid_at(invalid, &AppUrl::Consumptions);
+    let values = form_values(invalid_consumption, || {
+        FormValues::new([("consumed_at", date.to_string())])
+    });
+    let consumable = consumables
+        .iter()
+        .find(|c| c.id == values.get("consumable_id"));
 
     page_with_layout(
         &NavItem::Home,
@@ -43,17 +56,35 @@ pub fn day_summary_page(
                 a href=(AppUrl::DaySummary(prev_day)) class="button gray" { (PhosphorIcon::CaretLeft) }
                 div {
                     span {(date)}
-                    @if let Some(weight) = &weight {
-                        a href=(AppUrl::WeightsId(weight.id.clone())) { (weight.weight) " kg" }
+                    @if let [weigh_in] = weights.as_slice() {
+                        a href=(AppUrl::WeightsId(weigh_in.id.clone())) { (weigh_in.weight) " kg" }
+                    } @else if let Some(weight) = weight {
+                        span { (fmt_macro(weight)) " kg" }
                     } @else {
                         span { "\u{00a0}" }
                     }
                 }
                 a href=(AppUrl::DaySummary(next_day)) class="button gray" { (PhosphorIcon::CaretRight) }
             }
-            @if weight.is_none() {
+            @if weights.len() > 1 {
+                ul class="weigh-ins" {
+                    @for weigh_in in weights.iter().rev() {
+                        li {
+                            a href=(AppUrl::WeightsId(weigh_in.id.clone())) {
+                                @if weigh_in.measured_time.is_some() {
+                                    (fmt_time(weigh_in.measured_time)) " "
+                                }
+                                (weigh_in.weight) " kg"
+                            }
+                        }
+                    }
+                }
+            }
+            @if weights.is_empty() {
                 form method="post" action=(AppUrl::Weights) {
-                    (input_group_number("weight", "Weight", "weight", ""))
+                    (csrf_input())
+                    (form_error(invalid_weight))
+                    (input_group_number("weight", "Weight", "weight", weight_values.get("weight")))
                     input type="hidden" name="measured_at" value=(date);
                     input type="hidden" name="redirect_to" value="DaySummary";
                     button type="submit" { "Save" };
@@ -61,39 +92,97 @@ pub fn day_summary_page(
             }
             div class="home-summary" {
                 div class="summary" {
-                    div { span { (format!("{:.0}", total_kcal)) } span { "kcal" } }
-                    div { span { (format!("{:.0}", total_fat)) } span { "fat" } }
-                    div { span { (format!("{:.0}", total_carbs)) } span { "carbs" } }
-                    div { span { (format!("{:.0}", total_protein)) } span { "protein" } }
+                    div { span { (format!("{:.0}", total.kcal)) } span { "kcal" } }
+                    div { span { (format!("{:.0}", total.fat)) } span { "fat" } }
+                    div { span { (format!("{:.0}", total.carbs)) } span { "carbs" } }
+                    div { span { (format!("{:.0}", total.protein)) } span { "protein" } }
                 }
                 button type="button" class="green" data-add-consumption-toggler { (PhosphorIcon::Plus) };
             }
-            form method="post" action=(AppUrl::Consumptions) data-add-consumption-form hidden[true] {
-                (input_group_date("input-date", "Date", "consumed_at", &date.to_string()));
-                (food_select_trigger(None));
-                (input_group_number("input-quantity", "Quantity", "quantity", ""));
+            (nutrient_summary(shown, &total_nutrients))
+            @if let Some(goal) = goal {
+                div class="goal-progress" {
+                    (goal_progress_row("kcal", total.kcal, goal.kcal))
+                    (goal_progress_row("fat", total.fat, goal.fat))
+                    (goal_progress_row("carbs", total.carbs, goal.carbs))
+                    (goal_progress_row("protein", total.protein, goal.protein))
+                }
+            }
+            form method="post" action=(AppUrl::Consumptions) data-add-consumption-form hidden[invalid_consumption.is_none()] {
+                (csrf_input())
+                (form_error(invalid_consumption))
+                (input_group_date("input-date", "Date", "consumed_at", values.get("consumed_at")));
+                (input_group_time("input-time", "Time", "consumed_time", values.get("consumed_time")));
+                (food_select_trigger(consumable));
+                (input_group_number("input-quantity", "Quantity", "quantity", values.get("quantity")));
+                (serving_select(consumable, &servings, Some(values.get("serving_id"))));
+                (meal_slot_select(&meal_slots, Some(values.get("meal_slot_id"))));
                 button type="submit" { "Save" };
             }
             (food_select_dialog(consumables))
-            div class="macro-cards" {
-                @for consumption in consumptions {
-                    (consumption_card(&consumption, false))
-                }
+            @for (name, consumptions) in group_by_meal_slot(consumptions, &meal_slots) {
+                (meal_slot_group(&name, &consumptions, shown))
             }
         },
     )
 }
 
-pub fn weights_page(weights: Vec<db::WeightWithDate>) -> Markup {
+pub fn weights_page(
+    weights: Vec<db::WeightWithDate>,
+    points: &[TrendPoint],
+    range: TrendRange,
+    rule: WeightRule,
+    today: chrono::NaiveDate,
+    invalid: Option<&InvalidForm>,
+) -> Markup {
+    let invalid_weight = invalid_at(invalid, &AppUrl::Weights);
+    let values = form_values(invalid_weight, || {
+        FormValues::new([("measured_at", today.to_string())])
+    });
+    let invalid_rule = invalid_at(invalid, &AppUrl::WeightsRule);
+    let rule_values = form_values(invalid_rule, || {
+        FormValues::new([("weight_rule", rule.as_str().to_string())])
+    });
     page_with_layout(
         &NavItem::Weights,
         "Weights",
         html! {
             form method="post" action=(AppUrl::Weights) {
-                (input_group_date("measured_at", "Date", "measured_at", &chrono::Utc::now().date_naive().to_string()));
-                (input_group_number("weight", "Weight", "weight", ""))
+                (csrf_input())
+                (form_error(invalid_weight))
+                (input_group_date("measured_at", "Date", "measured_at", values.get("measured_at")));
+                (input_group_time("measured_time", "Time", "measured_time", values.get("measured_time")));
+                (input_group_number("weight", "Weight", "weight", values.get("weight")))
                 button type="submit" { "Save" };
             }
+            div class="weight-trend" {
+                div class="grid-col-4" {
+                    @for option in TrendRange::ALL {
+                        a href=(AppUrl::WeightsRange(option)) class=(if option == range { "button" } else { "button gray" }) { (option.label()) }
+                    }
+                }
+                @if let Some(last) = points.last() {
+                    div class="trend-summary" {
+                        span { "Trend " (format!("{:.1}", last.trend)) " kg" }
+                        @if let Some(rate) = trend::weekly_rate(points) {
+                            span { (format!("{:+.2}", rate)) " kg/week" }
+                        }
+                    }
+                }
+                (weight_chart(points))
+            }
+            form method="post" action=(AppUrl::WeightsRule) class="weight-rule" {
+                (csrf_input())
+                (form_error(invalid_rule))
+                (input_group_select(
+                    "weight_rule",
+                    "Weight of days with several weigh-ins",
+                    "weight_rule",
+                    &WeightRule::ALL.map(|r| (r.as_str(), r.label())),
+                    Some(rule_values.get("weight_rule")),
+                ))
+                button type="submit" class="gray" { "Save" };
+            }
             div class="table-container" {
                 table {
                     thead {
@@ -110,7 +199,12 @@ pub fn weights_page(weights: Vec<db::WeightWithDate>) -> Markup {
                         }
                         @for row in weights {
                             tr {
-                                td { a href=(AppUrl::DaySummary(row.measured_at)) { (row.measured_at) } }
+                                td {
+                                    a href=(AppUrl::DaySummary(row.measured_at)) { (row.measured_at) }
+                                    @if row.measured_time.is_some() {
+                                        " " (fmt_time(row.measured_time))
+                                    }
+                                }
                                 td { a href=(AppUrl::WeightsId(row.id.clone())) { (row.weight) } }
                             }
                         }
@@ -121,24 +215,42 @@ pub fn weights_page(weights: Vec<db::WeightWithDate>) -> Markup {
     )
 }
 
-pub fn weights_update_page(weight: &db::WeightWithDate) -> Markup {
+pub fn weights_update_page(weight: &db::WeightWithDate, invalid: Option<&InvalidForm>) -> Markup {
+    let values = form_values(invalid, || {
+        FormValues::new([
+            ("weight", weight.weight.to_string()),
+            ("measured_at", weight.measured_at.to_string()),
+            ("measured_time", fmt_time(weight.measured_time)),
+        ])
+    });
     page_with_layout(
         &NavItem::Weights,
         "Update Weight",
         html! {
             form method="post" action=(AppUrl::WeightsId(weight.id.clone())) {
-                (input_group_number("weight", "Weight", "weight", &weight.weight.to_string()))
-                (input_group_date("measured_at", "Date", "measured_at", &weight.measured_at.to_string()));
+                (csrf_input())
+                (form_error(invalid))
+                (input_group_number("weight", "Weight", "weight", values.get("weight")))
+                (input_group_date("measured_at", "Date", "measured_at", values.get("measured_at")));
+                (input_group_time("measured_time", "Time", "measured_time", values.get("measured_time")));
                 button type="submit" { "Save" };
             }
             form method="post" action=(AppUrl::WeightsIdDelete(weight.id.clone())) data-confirm-delete {
+                (csrf_input())
                 button type="submit" class="red" { "Delete" };
             }
         },
     )
 }
 
-pub fn foods_page(foods: Vec<db::Food>) -> Markup {
+pub fn foods_page(
+    foods: Vec<db::Food>,
+    shown: &[&Nutrient],
+    invalid: Option<&InvalidForm>,
+) -> Markup {
+    let values = form_values(invalid, || {
+        FormValues::new([("unit", "g".to_string()), ("basis", "100".to_string())])
+    });
     page_with_layout(
         &NavItem::Foods,
         "Foods",
@@ -150,29 +262,28 @@ pub fn foods_page(foods: Vec<db::Food>) -> Markup {
                 }
                 button type="button" class="green" data-toggler data-toggler-target="form.food" { (PhosphorIcon::Plus) };
             }
-            form method="post" action=(AppUrl::Foods) class="food" hidden[true] autocomplete="off" {
-                (input_group_text("input-name", "Name", "name", ""));
-                (input_group_number("input-kcal", "Calories", "kcal", ""));
-                (input_group_number("input-fat", "Fat", "fat", ""));
-                (input_group_number("input-carbs", "Carbs", "carbs", ""));
-                (input_group_number("input-protein", "Protein", "protein", ""));
-                (input_group_checkbox("input-hidden", "Hidden", "hidden", false));
-                (input_group_checkbox("input-starred", "Starred", "starred", false));
+            form method="post" action=(AppUrl::Foods) class="food" hidden[invalid.is_none()] autocomplete="off" {
+                (csrf_input())
+                (form_error(invalid))
+                (food_inputs(&values, true))
                 button type="submit" { "Save" };
             }
+            a href=(AppUrl::Catalog) class="button gray" { "Shared catalog" }
             div class="macro-cards" {
                 @for food in foods {
                     (macro_card(
                         food.name.as_str(),
                         AppUrl::FoodsId(food.id.clone()),
-                        None,
+                        Some(fmt_quantity(food.basis, Some(&food.unit))),
                         None,
                         db::Macros {
                             kcal: food.kcal,
                             fat: food.fat,
                             carbs: food.carbs,
                             protein: food.protein
-                        }
+                        },
+                        shown,
+                        &food.nutrients
                     ))
                 }
             }
@@ -180,28 +291,170 @@ pub fn foods_page(foods: Vec<db::Food>) -> Markup {
     )
 }
 
-pub fn foods_update_page(food: db::Food, consumptions: Vec<db::Consumption>) -> Markup {
+pub fn foods_update_page(
+    food: db::Food,
+    servings: Vec<db::Serving>,
+    aliases: Vec<db::FoodAlias>,
+    merge_targets: Vec<db::Food>,
+    consumptions: Vec<db::Consumption>,
+    shown: &[&Nutrient],
+    invalid: Option<&InvalidForm>,
+) -> Markup {
+    let invalid_food = invalid_at(invalid, &AppUrl::FoodsId(food.id.clone()));
+    let values = form_values(invalid_food, || food_values(&food));
+    let invalid_serving = invalid_at(invalid, &AppUrl::FoodsIdServings(food.id.clone()));
+    let serving_values = form_values(invalid_serving, FormValues::default);
+    let invalid_merge = invalid_at(invalid, &AppUrl::FoodsIdMerge(food.id.clone()));
+    let merge_values = form_values(invalid_merge, || {
+        FormValues::new([("keep_alias", "true".to_string())])
+    });
     page_with_layout(
         &NavItem::Foods,
         "Update Food",
         html! {
             form method="post" action=(AppUrl::FoodsId(food.id.clone())) class="food" {
-                (input_group_text("input-name", "Name", "name", &food.name));
-                (input_group_number("input-kcal", "Calories", "kcal", &food.kcal.to_string()));
-                (input_group_number("input-fat", "Fat", "fat", &food.fat.to_string()));
-                (input_group_number("input-carbs", "Carbs", "carbs", &food.carbs.to_string()));
-                (input_group_number("input-protein", "Protein", "protein", &food.protein.to_string()));
-                (input_group_checkbox("input-hidden", "Hidden", "hidden", food.hidden_at.is_some()));
-                (input_group_checkbox("input-starred", "Starred", "starred", food.starred_at.is_some()));
+                (csrf_input())
+                (form_error(invalid_food))
+                (food_inputs(&values, false))
                 button type="submit" { "Save" };
             }
             form method="post" action=(AppUrl::FoodsIdDelete(food.id.clone())) data-confirm-delete {
+                (csrf_input())
                 button type="submit" class="red" { "Delete" };
             }
+            h2 { "Servings" }
+            form method="post" action=(AppUrl::FoodsIdServings(food.id.clone())) class="serving" {
+                (csrf_input())
+                (form_error(invalid_serving))
+                (input_group_text("input-serving-name", "Name", "name", serving_values.get("name")));
+                (input_group_number("input-serving-amount", &format!("Amount ({})", food.unit), "amount", serving_values.get("amount")));
+                button type="submit" { "Add" };
+            }
+            div.table-container {
+                table {
+                    tbody {
+                        @if servings.is_empty() {
+                            tr {
+                                td colspan="3" class="text-center" { "No servings." }
+                            }
+                        }
+                        @for serving in servings {
+                            tr {
+                                td { (serving.name) }
+                                td { (fmt_quantity(serving.amount, Some(&food.unit))) }
+                                td {
+                                    form method="post" action=(AppUrl::ServingsIdDelete(serving.id)) data-confirm-delete {
+                                        (csrf_input())
+                                        button type="submit" class="red" { "Delete" };
+                                    }
+                                }
+                            }
+                        }
+                    }
+                }
+            }
+            @if !aliases.is_empty() {
+                h2 { "Aliases" }
+                div.table-container {
+                    table {
+                        tbody {
+                            @for alias in aliases {
+                                tr {
+                                    td { (alias.name) }
+                                    td {
+                                        form method="post" action=(AppUrl::FoodAliasesIdDelete(alias.id)) data-confirm-delete {
+                                            (csrf_input())
+                                            button type="submit" class="red" { "Delete" };
+                                        }
+                                    }
+                                }
+                            }
+                        }
+                    }
+                }
+            }
+            h2 id="merge" { "Merge" }
+            p { "Moves the consumptions, ingredients and servings of this food to another food with the same unit and deletes it. Recipes then use the values of the other food, consumptions keep the values they were logged with." }
+            @if merge_targets.is_empty() {
+                p { "There are no other foods in " (food.unit) "." }
+            } @else {
+                @let options = merge_targets
+                    .iter()
+                    .map(|f| (f.id.as_str(), f.name.as_str()))
+                    .collect::<Vec<_>>();
+                form method="post" action=(AppUrl::FoodsIdMerge(food.id.clone())) data-confirm-delete {
+                    (csrf_input())
+                    (form_error(invalid_merge))
+                    (input_group_select("input-merge-into", "Into", "into_id", &options, Some(merge_values.get("into_id"))))
+                    (input_group_checkbox("input-merge-keep-alias", &format!("Keep \"{}\" as alias", food.name), "keep_alias", merge_values.checked("keep_alias")))
+                    button type="submit" { "Merge" };
+                }
+            }
+            h2 { "Consumptions" }
+            (recalculate_form(AppUrl::FoodsIdRecalculate(food.id.clone()), &consumptions))
+            div class="macro-cards" {
+                @for consumption in consumptions {
+                    (consumption_card(&consumption, true, shown))
+                }
+            }
+        },
+    )
+}
+
+/// Read-only page for a food that another user shares.
+pub fn foods_shared_page(
+    food: db::Food,
+    servings: Vec<db::Serving>,
+    consumptions: Vec<db::Consumption>,
+    shown: &[&Nutrient],
+) -> Markup {
+    page_with_layout(
+        &NavItem::Foods,
+        &food.name,
+        html! {
+            div class="macro-cards" {
+                (macro_card(
+                    food.name.as_str(),
+                    AppUrl::FoodsId(food.id.clone()),
+                    Some(fmt_quantity(food.basis, Some(&food.unit))),
+                    None,
+                    db::Macros {
+                        kcal: food.kcal,
+                        fat: food.fat,
+                        carbs: food.carbs,
+                        protein: food.protein
+                    },
+                    shown,
+                    &food.nutrients
+                ))
+            }
+            form method="post" action=(AppUrl::FoodsIdClone(food.id.clone())) {
+                (csrf_input())
+                button type="submit" class="gray" { "Clone" };
+            }
+            h2 { "Servings" }
+            div.table-container {
+                table {
+                    tbody {
+                        @if servings.is_empty() {
+                            tr {
+                                td colspan="2" class="text-center" { "No servings." }
+                            }
+                        }
+                        @for serving in servings {
+                            tr {
+                                td { (serving.name) }
+                                td { (fmt_quantity(serving.amount, Some(&food.unit))) }
+                            }
+                        }
+                    }
+                }
+            }
             h2 { "Consumptions" }
+            (recalculate_form(AppUrl::FoodsIdRecalculate(food.id.clone()), &consumptions))
             div class="macro-cards" {
                 @for consumption in consumptions {
-                    (consumption_card(&consumption, true))
+                    (consumption_card(&consumption, true, shown))
                 }
             }
         },
@@ -211,26 +464,65 @@ pub fn foods_update_page(food: db::Food, consumptions: Vec<db::Consumption>) ->
 pub fn consumptions_update_page(
     consumption: db::Consumption,
     consumables: Vec<db::Consumable>,
+    meal_slots: Vec<db::MealSlot>,
+    servings: Vec<db::Serving>,
+    invalid: Option<&InvalidForm>,
 ) -> Markup {
+    let values = form_values(invalid, || {
+        FormValues::new([
+            ("consumed_at", consumption.consumed_at.to_string()),
+            ("consumed_time", fmt_time(consumption.consumed_time)),
+            ("consumable_id", consumption.consumable_id.clone()),
+            ("quantity", consumption.quantity.to_string()),
+            (
+                "serving_id",
+                consumption.serving_id.clone().unwrap_or_default(),
+            ),
+            (
+                "meal_slot_id",
+                consumption.meal_slot_id.clone().unwrap_or_default(),
+            ),
+        ])
+    });
+    let consumable = consumables
+        .iter()
+        .find(|c| c.id == values.get("consumable_id"));
+
     page_with_layout(
         &NavItem::Home,
         "Update Consumption",
         html! {
             form method="post" action=(AppUrl::ConsumptionsId(consumption.id.clone())) {
-                (input_group_date("input-date", "Date", "consumed_at", &consumption.consumed_at.to_string()));
-                (food_select_trigger(consumables.iter().find(|c| c.id == consumption.consumable_id)));
-                (input_group_number("input-quantity", "Quantity", "quantity", &consumption.quantity.to_string()));
+                (csrf_input())
+                (form_error(invalid))
+                (input_group_date("input-date", "Date", "consumed_at", values.get("consumed_at")));
+                (input_group_time("input-time", "Time", "consumed_time", values.get("consumed_time")));
+                (food_select_trigger(consumable));
+                (input_group_number("input-quantity", "Quantity", "quantity", values.get("quantity")));
+                (serving_select(consumable, &servings, Some(values.get("serving_id"))));
+                (meal_slot_select(&meal_slots, Some(values.get("meal_slot_id"))));
                 button type="submit" { "Save" };
             }
             (food_select_dialog(consumables))
+            form method="post" action=(AppUrl::ConsumptionsIdRecalculate(consumption.id.clone())) class="recalculate" {
+                (csrf_input())
+                p { "The values of the food or recipe are saved when it's logged, so later changes don't affect it." }
+                button type="submit" class="gray" { "Recalculate from current values" };
+            }
             form method="post" action=(AppUrl::ConsumptionsIdDelete(consumption.id.clone())) data-confirm-delete {
+                (csrf_input())
                 button type="submit" class="red" { "Delete" };
             }
         },
     )
 }
 
-pub fn recipes_page(recipes: Vec<db::Recipe>) -> Markup {
+pub fn recipes_page(
+    recipes: Vec<db::Recipe>,
+    shown: &[&Nutrient],
+    invalid: Option<&InvalidForm>,
+) -> Markup {
+    let values = form_values(invalid, || FormValues::new([("quantity", "1".to_string())]));
     page_with_layout(
         &NavItem::Recipes,
         "Recipes",
@@ -242,24 +534,29 @@ pub fn recipes_page(recipes: Vec<db::Recipe>) -> Markup {
                 }
                 button type="button" class="green" data-toggler data-toggler-target="form.recipe" { (PhosphorIcon::Plus) };
             }
-            form method="post" action=(AppUrl::Recipes) class="recipe" hidden[true] {
-                (input_group_text("input-name", "Name", "name", ""));
-                (input_group_number("input-quantity", "Quantity", "quantity", "1"));
+            form method="post" action=(AppUrl::Recipes) class="recipe" hidden[invalid.is_none()] {
+                (csrf_input())
+                (form_error(invalid))
+                (input_group_text("input-name", "Name", "name", values.get("name")));
+                (input_group_number("input-quantity", "Quantity", "quantity", values.get("quantity")));
                 button type="submit" { "Save" };
             }
+            a href=(AppUrl::Catalog) class="button gray" { "Shared catalog" }
             div class="macro-cards" {
                 @for row in recipes {
                     (macro_card(
                         row.name.as_str(),
                         AppUrl::RecipesId(row.id.clone()),
-                        Some(row.quantity),
+                        Some(fmt_quantity(row.quantity, None)),
                         None,
                         db::Macros {
                             kcal: row.kcal,
                             fat: row.fat,
                             carbs: row.carbs,
                             protein: row.protein
-                        }
+                        },
+                        shown,
+                        &row.nutrients
                     ))
                 }
             }
@@ -272,17 +569,46 @@ pub fn recipes_update_page(
     consumptions: Vec<db::Consumption>,
     ingredients: Vec<db::Ingredient>,
     consumables: Vec<db::Consumable>,
+    servings: Vec<db::Serving>,
+    shown: &[&Nutrient],
+    invalid: Option<&InvalidForm>,
 ) -> Markup {
+    let invalid_recipe = invalid_at(invalid, &AppUrl::RecipesId(recipe.id.clone()));
+    let values = form_values(invalid_recipe, || {
+        let checkboxes = [
+            ("hidden", recipe.hidden_at),
+            ("starred", recipe.starred_at),
+            ("shared", recipe.shared_at),
+        ]
+        .into_iter()
+        .filter(|(_, at)| at.is_some())
+        .map(|(name, _)| (name, "true".to_string()));
+        FormValues::new(
+            [
+                ("name", recipe.name.clone()),
+                ("quantity", recipe.quantity.to_string()),
+            ]
+            .into_iter()
+            .chain(checkboxes),
+        )
+    });
+    let invalid_ingredient = invalid_at(invalid, &AppUrl::Ingredients);
+    let ingredient_values = form_values(invalid_ingredient, FormValues::default);
+    let consumable = consumables
+        .iter()
+        .find(|c| c.id == ingredient_values.get("consumable_id"));
     let mut total_kcal = 0.0;
     let mut total_fat = 0.0;
     let mut total_carbs = 0.0;
     let mut total_protein = 0.0;
+    let mut total_nutrients = Nutrients::default();
 
     for row in &ingredients {
         total_kcal += row.kcal;
         total_fat += row.fat;
         total_carbs += row.carbs;
         total_protein += row.protein;
+        total_nutrients = total_nutrients.add(&row.nutrients);
     }
 
     page_with_layout(
@@ -290,10 +616,13 @@ pub fn recipes_update_page(
         "Update Recipe",
         html! {
             form method="post" action=(AppUrl::RecipesId(recipe.id.clone())) class="recipe" {
-                (input_group_text("input-name", "Name", "name", &recipe.name));
-                (input_group_number("input-kcal", "Quantity", "quantity", &recipe.quantity.to_string()));
-                (input_group_checkbox("input-hidden", "Hidden", "hidden", recipe.hidden_at.is_some()));
-                (input_group_checkbox("input-starred", "Starred", "starred", recipe.starred_at.is_some()));
+                (csrf_input())
+                (form_error(invalid_recipe))
+                (input_group_text("input-name", "Name", "name", values.get("name")));
+                (input_group_number("input-kcal", "Quantity", "quantity", values.get("quantity")));
+                (input_group_checkbox("input-hidden", "Hidden", "hidden", values.checked("hidden")));
+                (input_group_checkbox("input-starred", "Starred", "starred", values.checked("starred")));
+                (input_group_checkbox("input-shared", "Shared", "shared", values.checked("shared")));
                 button type="submit" { "Save" };
             }
             div class="home-summary" {
@@ -305,28 +634,148 @@ pub fn recipes_update_page(
                 }
                 button type="button" class="green" data-toggler data-toggler-target="form.ingredient" { (PhosphorIcon::Plus) };
             }
-            form method="post" action=(AppUrl::Ingredients) class="ingredient" hidden[true] {
+            (nutrient_summary(shown, &total_nutrients))
+            form method="post" action=(AppUrl::Ingredients) class="ingredient" hidden[invalid_ingredient.is_none()] {
+                (csrf_input())
+                (form_error(invalid_ingredient))
                 input type="hidden" name="recipe_id" value=(recipe.id);
-                (food_select_trigger(None));
-                (input_group_number("input-quantity", "Quantity", "quantity", ""));
+                (food_select_trigger(consumable));
+                (input_group_number("input-quantity", "Quantity", "quantity", ingredient_values.get("quantity")));
+                (serving_select(consumable, &servings, Some(ingredient_values.get("serving_id"))));
                 button type="submit" { "Save" };
             }
             h2 { "Ingredients" }
             div class="macro-cards" {
                 @for ingredient in ingredients {
-                    (ingredient_card(&ingredient))
+                    (ingredient_card(&ingredient, shown))
                 }
             }
             h2 { "Consumptions" }
+            (recalculate_form(AppUrl::RecipesIdRecalculate(recipe.id.clone()), &consumptions))
             div class="macro-cards" {
                 @for consumption in consumptions {
-                    (consumption_card(&consumption, true))
+                    (consumption_card(&consumption, true, shown))
                 }
             }
             form method="post" action=(AppUrl::RecipesIdDelete(recipe.id.clone())) data-confirm-delete {
+                (csrf_input())
                 button type="submit" class="red" { "Delete" };
             }
-            (food_select_dialog(consumables))
+            (food_select_dialog(consumables.into_iter().filter(|c| c.id != recipe.id).collect()))
+        },
+    )
+}
+
+/// Read-only page for a recipe that another user shares.
+pub fn recipes_shared_page(
+    recipe: db::Recipe,
+    consumptions: Vec<db::Consumption>,
+    ingredients: Vec<db::Ingredient>,
+    shown: &[&Nutrient],
+) -> Markup {
+    page_with_layout(
+        &NavItem::Recipes,
+        &recipe.name,
+        html! {
+            div class="macro-cards" {
+                (macro_card(
+                    recipe.name.as_str(),
+                    AppUrl::RecipesId(recipe.id.clone()),
+                    Some(fmt_quantity(recipe.quantity, None)),
+                    None,
+                    db::Macros {
+                        kcal: recipe.kcal,
+                        fat: recipe.fat,
+                        carbs: recipe.carbs,
+                        protein: recipe.protein
+                    },
+                    shown,
+                    &recipe.nutrients
+                ))
+            }
+            form method="post" action=(AppUrl::RecipesIdClone(recipe.id.clone())) {
+                (csrf_input())
+                button type="submit" class="gray" { "Clone" };
+            }
+            h2 { "Ingredients" }
+            div.table-container {
+                table {
+                    tbody {
+                        @if ingredients.is_empty() {
+                            tr {
+                                td colspan="3" class="text-center" { "No ingredients." }
+                            }
+                        }
+                        @for ingredient in ingredients {
+                            tr {
+                                td { (ingredient.consumable_name) }
+                                td { (fmt_quantity(ingredient.quantity, ingredient.unit.as_deref())) }
+                                td { (fmt_macro(ingredient.kcal)) " kcal" }
+                            }
+                        }
+                    }
+                }
+            }
+            h2 { "Consumptions" }
+            (recalculate_form(AppUrl::RecipesIdRecalculate(recipe.id.clone()), &consumptions))
+            div class="macro-cards" {
+                @for consumption in consumptions {
+                    (consumption_card(&consumption, true, shown))
+                }
+            }
+        },
+    )
+}
+
+pub fn catalog_page(foods: Vec<db::Food>, recipes: Vec<db::Recipe>, shown: &[&Nutrient]) -> Markup {
+    page_with_layout(
+        &NavItem::Foods,
+        "Shared catalog",
+        html! {
+            h2 { "Foods" }
+            div class="macro-cards" {
+                @if foods.is_empty() {
+                    p class="text-center" { "No shared foods." }
+                }
+                @for food in foods {
+                    (macro_card(
+                        food.name.as_str(),
+                        AppUrl::FoodsId(food.id.clone()),
+                        Some(fmt_quantity(food.basis, Some(&food.unit))),
+                        None,
+                        db::Macros {
+                            kcal: food.kcal,
+                            fat: food.fat,
+                            carbs: food.carbs,
+                            protein: food.protein
+                        },
+                        shown,
+                        &food.nutrients
+                    ))
+                }
+            }
+            h2 { "Recipes" }
+            div class="macro-cards" {
+                @if recipes.is_empty() {
+                    p class="text-center" { "No shared recipes." }
+                }
+                @for recipe in recipes {
+                    (macro_card(
+                        recipe.name.as_str(),
+                        AppUrl::RecipesId(recipe.id.clone()),
+                        Some(fmt_quantity(recipe.quantity, None)),
+                        None,
+                        db::Macros {
+                            kcal: recipe.kcal,
+                            fat: recipe.fat,
+                            carbs: recipe.carbs,
+                            protein: recipe.protein
+                        },
+                        shown,
+                        &recipe.nutrients
+                    ))
+                }
+            }
         },
     )
 }
@@ -334,20 +783,40 @@ pub fn recipes_update_page(
 pub fn ingredients_update_page(
     ingredient: &db::Ingredient,
     consumables: Vec<db::Consumable>,
+    servings: Vec<db::Serving>,
+    invalid: Option<&InvalidForm>,
 ) -> Markup {
+    let values = form_values(invalid, || {
+        FormValues::new([
+            ("consumable_id", ingredient.consumable_id.clone()),
+            ("quantity", ingredient.quantity.to_string()),
+            (
+                "serving_id",
+                ingredient.serving_id.clone().unwrap_or_default(),
+            ),
+        ])
+    });
+    let consumable = consumables
+        .iter()
+        .find(|c| c.id == values.get("consumable_id"));
+
     page_with_layout(
         &NavItem::Recipes,
         "Update Ingredient",
         html! {
             form method="post" action=(AppUrl::IngredientsId(ingredient.id.clone())) class="ingredient" {
-                (food_select_trigger(consumables.iter().find(|c| c.id == ingredient.food_id)));
-                (input_group_number("input-kcal", "Quantity", "quantity", &ingredient.quantity.to_string()));
+                (csrf_input())
+                (form_error(invalid))
+                (food_select_trigger(consumable));
+                (input_group_number("input-kcal", "Quantity", "quantity", values.get("quantity")));
+                (serving_select(consumable, &servings, Some(values.get("serving_id"))));
                 button type="submit" { "Save" };
             }
             form method="post" action=(AppUrl::IngredientsIdDelete(ingredient.id.clone())) data-confirm-delete {
+                (csrf_input())
                 button type="submit" class="red" { "Delete" };
             }
-            (food_select_dialog(consumables))
+            (food_select_dialog(consumables.into_iter().filter(|c| c.id != ingredient.recipe_id).collect()))
         },
     )
 }
@@ -355,21 +824,45 @@ pub fn ingredients_update_page(
 #[derive(Clone)]
 pub struct AccountSummaryRow {
     pub date: chrono::NaiveDate,
-    pub weight: Option<(String, f64)>,
+    pub weight: Option<f64>,
+    /// Set if the day has a single weigh-in.
+    pub weight_id: Option<String>,
     pub kcal: Option<f64>,
     pub protein: Option<f64>,
 }
 
-pub fn account_page(rows: Vec<AccountSummaryRow>) -> Markup {
+pub fn account_page(
+    rows: Vec<AccountSummaryRow>,
+    goals: Vec<db::Goal>,
+    older: Option<chrono::NaiveDate>,
+    newer: Option<chrono::NaiveDate>,
+    is_admin: bool,
+) -> Markup {
     page_with_layout(
         &NavItem::Account,
         "Account",
         html! {
+            div.grid-col-2 {
+                a href=(AppUrl::AccountGoals) class="button gray" { "Goals" }
+                a href=(AppUrl::AccountMealSlots) class="button gray" { "Meals" }
+                a href=(AppUrl::AccountNutrients) class="button gray" { "Nutrients" }
+                a href=(AppUrl::AccountTimeZone) class="button gray" { "Time zone" }
+                a href=(AppUrl::AccountApiTokens) class="button gray" { "API tokens" }
+                a href=(AppUrl::Stats) class="button gray" { "Statistics" }
+                a href=(AppUrl::AccountPassword) class="button gray" { "Password" }
+                a href=(AppUrl::AccountSessions) class="button gray" { "Sessions" }
+                @if is_admin {
+                    a href=(AppUrl::AdminUsers) class="button gray" { "Users" }
+                }
+            }
             div.grid-col-2 {
                 form method="post" action=(AppUrl::AccountExport) {
+                    (csrf_input())
                     button type="submit" class="gray" { "Export"};
                 }
+                a href=(AppUrl::AccountImport) class="button gray" { "Import" }
                 form method="post" action=(AppUrl::AccountLogout) {
+                    (csrf_input())
                     button type="submit" class="gray" { "Logout"};
                 }
             }
@@ -390,18 +883,35 @@ pub fn account_page(rows: Vec<AccountSummaryRow>) -> Markup {
                             }
                         }
                         @for row in rows {
+                            @let goal = db::goal_for_date(&goals, row.date);
                             tr {
                                 td { a href=(AppUrl::DaySummary(row.date)) { (row.date) } }
-                                td { @if let Some((weight_id, weight_value)) = row.weight {
-                                    a href=(AppUrl::WeightsId(weight_id)) { (weight_value) }
-                                } }
-                                td { (row.kcal.map(fmt_macro).unwrap_or_default()) }
-                                td { (row.protein.map(fmt_macro).unwrap_or_default()) }
+                                td {
+                                    @if let Some(weight) = row.weight {
+                                        @if let Some(weight_id) = &row.weight_id {
+                                            a href=(AppUrl::WeightsId(weight_id.clone())) { (fmt_macro(weight)) }
+                                        } @else {
+                                            a href=(AppUrl::DaySummary(row.date)) { (fmt_macro(weight)) }
+                                        }
+                                    }
+                                }
+                                (goal_cell(row.kcal, goal.map(|g| g.kcal)))
+                                (goal_cell(row.protein, goal.map(|g| g.protein)))
                             }
                         }
                     }
                 }
             }
+            div.grid-col-2 {
+                @if let Some(newer) = newer {
+                    a href=(AppUrl::AccountUntil(newer)) class="button gray" { "Newer" }
+                } @else {
+                    span {}
+                }
+                @if let Some(older) = older {
+                    a href=(AppUrl::AccountUntil(older)) class="button gray" { "Older" }
+                }
+            }
             div.build-info {
                 "Build " (env!("BUILD_TIMESTAMP"))
             }
@@ -409,77 +919,683 @@ pub fn account_page(rows: Vec<AccountSummaryRow>) -> Markup {
     )
 }
 
-pub fn login_page(username: Option<String>, error_msg: Option<String>) -> Markup {
-    html!(
-        (DOCTYPE)
-        html {
-            (html_head("Login"))
-            body {
-                form method="post" action=(AppUrl::AccountLogin) class="login" {
-                    h1 { "Login" }
-                    @if let Some(error_msg) = error_msg {
-                        div class="error" { (error_msg) }
+pub fn stats_page(estimate: Option<tdee::TdeeEstimate>, weekly_change: f64) -> Markup {
+    page_with_layout(
+        &NavItem::Account,
+        "Statistics",
+        html! {
+            h2 { "Energy expenditure" }
+            @if let Some(estimate) = estimate {
+                div class="table-container" {
+                    table {
+                        tbody {
+                            tr {
+                                td { "Estimated TDEE" }
+                                td { (format!("{:.0}", estimate.tdee)) " kcal" }
+                            }
+                            tr {
+                                td { "Average intake" }
+                                td { (format!("{:.0}", estimate.average_intake)) " kcal" }
+                            }
+                            tr {
+                                td { "Trend weight change" }
+                                td { (format!("{:+.2}", estimate.weekly_change)) " kg/week" }
+                            }
+                            tr {
+                                td { "Days with logged intake" }
+                                td { (estimate.logged_days) " of " (tdee::WINDOW_DAYS) }
+                            }
+                        }
                     }
-                    (input_group_text("input-username", "Username", "username", &username.unwrap_or_default()))
-                    (input_group_password("input-password", "Password", "password"))
-                    button type="submit" { "Login" };
+                }
+                h2 { "Suggested target" }
+                form method="get" action=(AppUrl::Stats) {
+                    div.input-group {
+                        label for="input-weekly-change" { "Weight change per week (kg)" }
+                        input type="number" id="input-weekly-change" name="weekly_change" value=(weekly_change) step="0.05" required;
+                    }
+                    button type="submit" { "Calculate" };
+                }
+                div class="table-container" {
+                    table {
+                        tbody {
+                            tr {
+                                td { "Daily target" }
+                                td { (format!("{:.0}", tdee::suggested_target(estimate.tdee, weekly_change))) " kcal" }
+                            }
+                        }
+                    }
+                }
+            } @else {
+                div class="table-container text-center" {
+                    "Not enough data. Log your intake on at least "
+                    (tdee::MIN_LOGGED_DAYS) " of the last " (tdee::WINDOW_DAYS)
+                    " days and your weight over at least " (tdee::MIN_WEIGHT_DAYS) " days."
                 }
             }
-        }
+        },
     )
 }
 
-pub fn error_page() -> Markup {
+pub fn goals_page(goals: Vec<db::Goal>, invalid: Option<&InvalidForm>) -> Markup {
+    let default_goal = goals.iter().find(|g| g.weekday.is_none());
+    let weekdays = [
+        "Monday",
+        "Tuesday",
+        "Wednesday",
+        "Thursday",
+        "Friday",
+        "Saturday",
+        "Sunday",
+    ];
+
     page_with_layout(
-        &NavItem::None,
-        "Error",
+        &NavItem::Account,
+        "Goals",
         html! {
-            div class="error" {
-                "An error occurred."
-            }
-        },
-    )
-}
-
-fn page_with_layout(active_nav_item: &NavItem, page_title: &str, main_content: Markup) -> Markup {
-    html!(
-        (DOCTYPE)
-        html {
-            (html_head(page_title))
-            body {
-                div class="container" {
-                    nav {
-                        ul {
-                            li.active[active_nav_item.eq(&NavItem::Home)] { a href=(AppUrl::Home) { (PhosphorIcon::House) } }
-                            li.active[active_nav_item.eq(&NavItem::Weights)] { a href=(AppUrl::Weights) { (PhosphorIcon::Scales) } }
-                            li.active[active_nav_item.eq(&NavItem::Foods)] { a href=(AppUrl::Foods) { (PhosphorIcon::Orange) } }
-                            li.active[active_nav_item.eq(&NavItem::Recipes)] { a href=(AppUrl::Recipes) { (PhosphorIcon::CookingPot) } }
-                            li.active[active_nav_item.eq(&NavItem::Account)] { a href=(AppUrl::Account) { (PhosphorIcon::User) } }
-                        }
+            (form_error(invalid_at(invalid, &AppUrl::AccountGoalsDelete)))
+            h2 { "Every day" }
+            (goal_form(None, default_goal, invalid))
+            @for (weekday, weekday_name) in (0u32..).zip(weekdays) {
+                @let goal = goals.iter().find(|g| g.weekday == Some(weekday));
+                h2 { (weekday_name) }
+                (goal_form(Some(weekday), goal, invalid))
+                @if goal.is_some() {
+                    form method="post" action=(AppUrl::AccountGoalsDelete) data-confirm-delete {
+                        (csrf_input())
+                        input type="hidden" name="weekday" value=(weekday);
+                        button type="submit" class="red" { "Use every day goal" };
                     }
-                    (main_content)
                 }
             }
-        }
+        },
     )
 }
 
-fn input_group_date(id: &str, label: &str, name: &str, value: &str) -> Markup {
+/// `invalid` is shown at the form of the weekday it was posted for.
+fn goal_form(
+    weekday: Option<u32>,
+    goal: Option<&db::Goal>,
+    invalid: Option<&InvalidForm>,
+) -> Markup {
+    let suffix = weekday.map(|w| w.to_string()).unwrap_or("all".to_string());
+    let weekday_value = weekday.map(|w| w.to_string()).unwrap_or_default();
+    let invalid = invalid_at(invalid, &AppUrl::AccountGoals)
+        .filter(|invalid| invalid.values.get("weekday") == weekday_value);
+    let value = |f: fn(&db::Goal) -> f64| goal.map(|g| f(g).to_string()).unwrap_or_default();
+    let values = form_values(invalid, || {
+        FormValues::new([
+            ("kcal", value(|g| g.kcal)),
+            ("fat", value(|g| g.fat)),
+            ("carbs", value(|g| g.carbs)),
+            ("protein", value(|g| g.protein)),
+        ])
+    });
     html! {
-        div.input-group {
-            label for=(id) { (label) }
-            input type="date" id=(id) name=(name) value=(value) required;
+        form method="post" action=(AppUrl::AccountGoals) class="food" {
+            (csrf_input())
+            (form_error(invalid))
+            @if let Some(weekday) = weekday {
+                input type="hidden" name="weekday" value=(weekday);
+            }
+            (input_group_number(&format!("input-kcal-{}", suffix), "Calories", "kcal", values.get("kcal")));
+            (input_group_number(&format!("input-fat-{}", suffix), "Fat", "fat", values.get("fat")));
+            (input_group_number(&format!("input-carbs-{}", suffix), "Carbs", "carbs", values.get("carbs")));
+            (input_group_number(&format!("input-protein-{}", suffix), "Protein", "protein", values.get("protein")));
+            button type="submit" { "Save" };
         }
     }
 }
 
-fn input_group_text(id: &str, label: &str, name: &str, value: &str) -> Markup {
-    html! {
-        div.input-group {
-            label for=(id) { (label) }
-            input type="text" id=(id) name=(name) value=(value) required autocomplete="new-text";
-        }
-    }
+pub fn nutrients_page(shown: Vec<&Nutrient>, invalid: Option<&InvalidForm>) -> Markup {
+    page_with_layout(
+        &NavItem::Account,
+        "Nutrients",
+        html! {
+            p { "Nutrients to show in addition to the macros." }
+            form method="post" action=(AppUrl::AccountNutrients) {
+                (csrf_input())
+                (form_error(invalid))
+                @for nutrient in &NUTRIENTS {
+                    (input_group_checkbox(
+                        &format!("input-{}", nutrient.key),
+                        nutrient.label,
+                        nutrient.key,
+                        match invalid {
+                            Some(invalid) => invalid.values.checked(nutrient.key),
+                            None => shown.iter().any(|n| n.key == nutrient.key),
+                        }
+                    ))
+                }
+                button type="submit" { "Save" };
+            }
+        },
+    )
+}
+
+pub fn time_zone_page(time_zone: Tz, invalid: Option<&InvalidForm>) -> Markup {
+    let values = form_values(invalid, || {
+        FormValues::new([("time_zone", time_zone.name().to_string())])
+    });
+    let options = TZ_VARIANTS
+        .iter()
+        .map(|tz| (tz.name(), tz.name()))
+        .collect::<Vec<_>>();
+    page_with_layout(
+        &NavItem::Account,
+        "Time zone",
+        html! {
+            p { "Decides when a new day starts, e.g. which day is shown first and which one is filled in when adding weights." }
+            form method="post" action=(AppUrl::AccountTimeZone) {
+                (csrf_input())
+                (form_error(invalid))
+                (input_group_select(
+                    "input-time-zone",
+                    "Time zone",
+                    "time_zone",
+                    &options,
+                    Some(values.get("time_zone")),
+                ))
+                button type="submit" { "Save" };
+            }
+        },
+    )
+}
+
+pub fn meal_slots_page(meal_slots: Vec<db::MealSlot>, invalid: Option<&InvalidForm>) -> Markup {
+    let invalid_create = invalid_at(invalid, &AppUrl::AccountMealSlots);
+    let values = form_values(invalid_create, FormValues::default);
+    page_with_layout(
+        &NavItem::Account,
+        "Meals",
+        html! {
+            form method="post" action=(AppUrl::AccountMealSlots) {
+                (csrf_input())
+                (form_error(invalid_create))
+                (input_group_text("input-name", "Name", "name", values.get("name")));
+                button type="submit" { "Add" };
+            }
+            @for meal_slot in meal_slots {
+                @let invalid_update = invalid_at(invalid, &AppUrl::AccountMealSlotsId(meal_slot.id.clone()));
+                @let values = form_values(invalid_update, || {
+                    FormValues::new([
+                        ("name", meal_slot.name.clone()),
+                        ("position", meal_slot.position.to_string()),
+                    ])
+                });
+                form method="post" action=(AppUrl::AccountMealSlotsId(meal_slot.id.clone())) class="meal-slot" {
+                    (csrf_input())
+                    (form_error(invalid_update))
+                    (input_group_text(&format!("input-name-{}", meal_slot.id), "Name", "name", values.get("name")));
+                    div.input-group {
+                        label for=(format!("input-position-{}", meal_slot.id)) { "Position" }
+                        input type="number" id=(format!("input-position-{}", meal_slot.id)) name="position" value=(values.get("position")) step="1" required;
+                    }
+                    button type="submit" { "Save" };
+                }
+                form method="post" action=(AppUrl::AccountMealSlotsIdDelete(meal_slot.id.clone())) data-confirm-delete {
+                    (csrf_input())
+                    button type="submit" class="red" { "Delete " (meal_slot.name) };
+                }
+            }
+        },
+    )
+}
+
+pub fn password_page(error_msg: Option<String>) -> Markup {
+    page_with_layout(
+        &NavItem::Account,
+        "Password",
+        html! {
+            form method="post" action=(AppUrl::AccountPassword) {
+                (csrf_input())
+                @if let Some(error_msg) = error_msg {
+                    div class="error" { (error_msg) }
+                }
+                (input_group_password("input-current-password", "Current password", "current_password"));
+                (input_group_password("input-new-password", "New password", "new_password"));
+                (input_group_password("input-new-password-confirmation", "Repeat new password", "new_password_confirmation"));
+                button type="submit" { "Change password" };
+            }
+            p { "Changing the password logs out all other sessions." }
+        },
+    )
+}
+
+pub fn sessions_page(sessions: Vec<db::UserSession>, current_id: &str, time_zone: Tz) -> Markup {
+    page_with_layout(
+        &NavItem::Account,
+        "Sessions",
+        html! {
+            form method="post" action=(AppUrl::AccountSessionsDeleteOthers) data-confirm-delete {
+                (csrf_input())
+                button type="submit" class="red" { "Log out everywhere else" };
+            }
+            div.table-container {
+                table {
+                    thead {
+                        tr {
+                            th { "Device" }
+                            th { "Created" }
+                            th { "Last seen" }
+                            th {}
+                        }
+                    }
+                    tbody {
+                        @for session in sessions {
+                            tr {
+                                td { (session.user_agent.as_deref().unwrap_or("Unknown")) }
+                                td { (local_date(session.created_at, time_zone)) }
+                                td { @if let Some(last_seen_at) = session.last_seen_at {
+                                    (local_date(last_seen_at, time_zone))
+                                } @else {
+                                    "Never"
+                                } }
+                                td {
+                                    form method="post" action=(AppUrl::AccountSessionsIdDelete(session.id.clone())) {
+                                        (csrf_input())
+                                        @if session.id == current_id {
+                                            button type="submit" class="gray" { "Log out" };
+                                        } @else {
+                                            button type="submit" class="red" { "Log out" };
+                                        }
+                                    }
+                                }
+                            }
+                        }
+                    }
+                }
+            }
+        },
+    )
+}
+
+pub fn api_tokens_page(
+    api_tokens: Vec<db::ApiToken>,
+    time_zone: Tz,
+    new_token: Option<String>,
+    invalid: Option<&InvalidForm>,
+) -> Markup {
+    let values = form_values(invalid, FormValues::default);
+    page_with_layout(
+        &NavItem::Account,
+        "API tokens",
+        html! {
+            @if let Some(new_token) = new_token {
+                div.new-token {
+                    p { "Copy the new token now, it won't be shown again:" }
+                    code { (new_token) }
+                }
+            }
+            form method="post" action=(AppUrl::AccountApiTokens) {
+                (csrf_input())
+                (form_error(invalid))
+                (input_group_text("input-name", "Name", "name", values.get("name")));
+                button type="submit" { "Create token" };
+            }
+            div.table-container {
+                table {
+                    thead {
+                        tr {
+                            th { "Name" }
+                            th { "Created" }
+                            th { "Last used" }
+                            th {}
+                        }
+                    }
+                    tbody {
+                        @if api_tokens.is_empty() {
+                            tr {
+                                td colspan="4" class="text-center" { "No tokens." }
+                            }
+                        }
+                        @for api_token in api_tokens {
+                            tr {
+                                td { (api_token.name) }
+                                td { (local_date(api_token.created_at, time_zone)) }
+                                td { @if let Some(last_used_at) = api_token.last_used_at {
+                                    (local_date(last_used_at, time_zone))
+                                } @else {
+                                    "Never"
+                                } }
+                                td {
+                                    form method="post" action=(AppUrl::AccountApiTokensIdDelete(api_token.id)) data-confirm-delete {
+                                        (csrf_input())
+                                        button type="submit" class="red" { "Revoke" };
+                                    }
+                                }
+                            }
+                        }
+                    }
+                }
+            }
+        },
+    )
+}
+
+pub fn admin_users_page(
+    users: Vec<db::User>,
+    time_zone: Tz,
+    invalid: Option<&InvalidForm>,
+) -> Markup {
+    let values = form_values(invalid, FormValues::default);
+    page_with_layout(
+        &NavItem::Account,
+        "Users",
+        html! {
+            form method="post" action=(AppUrl::AdminUsers) {
+                (csrf_input())
+                (form_error(invalid))
+                (input_group_text("input-username", "Username", "username", values.get("username")));
+                (input_group_password("input-password", "Password", "password"));
+                (input_group_checkbox("input-is-admin", "Admin", "is_admin", values.checked("is_admin")));
+                button type="submit" { "Create user" };
+            }
+            div.table-container {
+                table {
+                    thead {
+                        tr {
+                            th { "Username" }
+                            th { "Created" }
+                            th { "Status" }
+                        }
+                    }
+                    tbody {
+                        @for user in users {
+                            tr {
+                                td { a href=(AppUrl::AdminUsersId(user.id.0.clone())) { (user.username) } }
+                                td { (local_date(user.created_at, time_zone)) }
+                                td { (user_status(&user)) }
+                            }
+                        }
+                    }
+                }
+            }
+        },
+    )
+}
+
+pub fn admin_users_update_page(
+    user: db::User,
+    time_zone: Tz,
+    used_by_others: i64,
+    is_self: bool,
+    invalid: Option<&InvalidForm>,
+) -> Markup {
+    let invalid_user = invalid_at(invalid, &AppUrl::AdminUsersId(user.id.0.clone()));
+    let values = form_values(invalid_user, || {
+        let checkboxes = [
+            ("is_admin", user.is_admin),
+            ("disabled", user.disabled_at.is_some()),
+        ];
+        FormValues::new(
+            checkboxes
+                .into_iter()
+                .filter(|(_, checked)| *checked)
+                .map(|(name, _)| (name, "true".to_string())),
+        )
+    });
+    let invalid_password = invalid_at(invalid, &AppUrl::AdminUsersIdPassword(user.id.0.clone()));
+    page_with_layout(
+        &NavItem::Account,
+        &user.username,
+        html! {
+            p { (user_status(&user)) ", created on " (local_date(user.created_at, time_zone)) "." }
+            @if !is_self {
+                form method="post" action=(AppUrl::AdminUsersId(user.id.0.clone())) {
+                    (csrf_input())
+                    (form_error(invalid_user))
+                    (input_group_checkbox("input-is-admin", "Admin", "is_admin", values.checked("is_admin")));
+                    (input_group_checkbox("input-disabled", "Disabled", "disabled", values.checked("disabled")));
+                    button type="submit" { "Save" };
+                }
+            }
+            h2 { "Password" }
+            form method="post" action=(AppUrl::AdminUsersIdPassword(user.id.0.clone())) {
+                (csrf_input())
+                (form_error(invalid_password))
+                (input_group_password("input-password", "New password", "password"));
+                button type="submit" { "Reset password" };
+            }
+            @if !is_self {
+                h2 { "Delete" }
+                @if used_by_others > 0 {
+                    p { "Shared entries of this user are used " (used_by_others) " times by other users, so it can't be deleted. Disable it instead." }
+                } @else {
+                    p { "Deletes the user and all their data." }
+                    form method="post" action=(AppUrl::AdminUsersIdDelete(user.id.0.clone())) data-confirm-delete {
+                        (csrf_input())
+                        button type="submit" class="red" { "Delete" };
+                    }
+                }
+            }
+        },
+    )
+}
+
+fn user_status(user: &db::User) -> &'static str {
+    match (user.is_admin, user.disabled_at.is_some()) {
+        (_, true) => "Disabled",
+        (true, false) => "Admin",
+        (false, false) => "Active",
+    }
+}
+
+pub fn import_page(error_msg: Option<String>) -> Markup {
+    page_with_layout(
+        &NavItem::Account,
+        "Import",
+        html! {
+            form method="post" action=(AppUrl::AccountImport) enctype="multipart/form-data" {
+                (csrf_input())
+                @if let Some(error_msg) = error_msg {
+                    div class="error" { (error_msg) }
+                }
+                div.input-group {
+                    label for="input-file" { "Export file" }
+                    input type="file" id="input-file" name="file" accept="application/json,.json" required;
+                }
+                (input_group_select(
+                    "input-mode",
+                    "Mode",
+                    "mode",
+                    &[
+                        ("merge", "Merge: keep existing data, add missing entries"),
+                        ("replace", "Replace: delete existing data first"),
+                    ],
+                    Some("merge"),
+                ))
+                button type="submit" { "Import" };
+            }
+        },
+    )
+}
+
+pub fn login_page(username: Option<String>, error_msg: Option<String>) -> Markup {
+    html!(
+        (DOCTYPE)
+        html {
+            (html_head("Login"))
+            body {
+                form method="post" action=(AppUrl::AccountLogin) class="login" {
+                    (csrf_input())
+                    h1 { "Login" }
+                    @if let Some(error_msg) = error_msg {
+                        div class="error" { (error_msg) }
+                    }
+                    (input_group_text("input-username", "Username", "username", &username.unwrap_or_default()))
+                    (input_group_password("input-password", "Password", "password"))
+                    (input_group_checkbox("input-remember", "Remember me", "remember", false))
+                    button type="submit" { "Login" };
+                }
+            }
+        }
+    )
+}
+
+pub fn foods_delete_blocked_page(food: &db::Food, references: &db::References) -> Markup {
+    delete_blocked_page(
+        &NavItem::Foods,
+        &food.name,
+        AppUrl::FoodsId(food.id.clone()),
+        food.hidden_at
+            .is_none()
+            .then(|| AppUrl::FoodsIdHide(food.id.clone())),
+        Some(format!("{}#merge", AppUrl::FoodsId(food.id.clone()))),
+        references,
+    )
+}
+
+pub fn recipes_delete_blocked_page(recipe: &db::Recipe, references: &db::References) -> Markup {
+    delete_blocked_page(
+        &NavItem::Recipes,
+        &recipe.name,
+        AppUrl::RecipesId(recipe.id.clone()),
+        recipe
+            .hidden_at
+            .is_none()
+            .then(|| AppUrl::RecipesIdHide(recipe.id.clone())),
+        None,
+        references,
+    )
+}
+
+/// Explains why a food or recipe can't be deleted. `hide_url` is `None` if
+/// it's hidden already, `merge_url` is only set for foods.
+fn delete_blocked_page(
+    active_nav_item: &NavItem,
+    name: &str,
+    back_url: AppUrl,
+    hide_url: Option<AppUrl>,
+    merge_url: Option<String>,
+    references: &db::References,
+) -> Markup {
+    page_with_layout(
+        active_nav_item,
+        "Can't delete",
+        html! {
+            h1 { "Can't delete " (name) }
+            div class="error" {
+                "It's still used by the entries below, so it can't be deleted."
+            }
+            @if references.consumption_count > 0 {
+                h2 { "Consumptions" }
+                ul {
+                    @for consumption in &references.consumptions {
+                        li { a href=(AppUrl::ConsumptionsId(consumption.id.clone())) { (consumption.consumed_at) } }
+                    }
+                    @let more = references.consumption_count - references.consumptions.len() as i64;
+                    @if more > 0 {
+                        li { "and " (more) " more" }
+                    }
+                }
+            }
+            @if !references.recipes.is_empty() {
+                h2 { "Recipes" }
+                ul {
+                    @for recipe in &references.recipes {
+                        li { a href=(AppUrl::RecipesId(recipe.id.clone())) { (recipe.name) } }
+                    }
+                }
+            }
+            @if references.other_users > 0 {
+                p { "It's shared and also used by " (references.other_users) " entries of other users." }
+            }
+            @if let Some(hide_url) = hide_url {
+                p { "Hidden entries keep their values but aren't offered for new consumptions." }
+                form method="post" action=(hide_url) {
+                    (csrf_input())
+                    button type="submit" { "Hide instead" };
+                }
+            }
+            @if let Some(merge_url) = merge_url {
+                p { "If it's a duplicate, " a href=(merge_url) { "merge it into another food" } " instead." }
+            }
+            p { a href=(back_url) { "Back" } }
+        },
+    )
+}
+
+pub fn error_page(status: StatusCode, message: &str, request_id: Option<&str>) -> Markup {
+    let title = match status {
+        StatusCode::FORBIDDEN => "Access denied",
+        StatusCode::NOT_FOUND => "Not found",
+        StatusCode::UNPROCESSABLE_ENTITY => "Invalid input",
+        _ => "Error",
+    };
+    page_with_layout(
+        &NavItem::None,
+        title,
+        html! {
+            h1 { (title) }
+            div class="error" { (message) }
+            p { a href=(AppUrl::Home) { "Back to today" } }
+            @if let Some(request_id) = request_id {
+                p class="request-id" { "Request ID: " code { (request_id) } }
+            }
+        },
+    )
+}
+
+fn page_with_layout(active_nav_item: &NavItem, page_title: &str, main_content: Markup) -> Markup {
+    html!(
+        (DOCTYPE)
+        html {
+            (html_head(page_title))
+            body {
+                div class="container" {
+                    nav {
+                        ul {
+                            li.active[active_nav_item.eq(&NavItem::Home)] { a href=(AppUrl::Home) { (PhosphorIcon::House) } }
+                            li.active[active_nav_item.eq(&NavItem::Weights)] { a href=(AppUrl::Weights) { (PhosphorIcon::Scales) } }
+                            li.active[active_nav_item.eq(&NavItem::Foods)] { a href=(AppUrl::Foods) { (PhosphorIcon::Orange) } }
+                            li.active[active_nav_item.eq(&NavItem::Recipes)] { a href=(AppUrl::Recipes) { (PhosphorIcon::CookingPot) } }
+                            li.active[active_nav_item.eq(&NavItem::Account)] { a href=(AppUrl::Account) { (PhosphorIcon::User) } }
+                        }
+                    }
+                    (main_content)
+                }
+            }
+        }
+    )
+}
+
+fn input_group_date(id: &str, label: &str, name: &str, value: &str) -> Markup {
+    html! {
+        div.input-group {
+            label for=(id) { (label) }
+            input type="date" id=(id) name=(name) value=(value) required;
+        }
+    }
+}
+
+/// Optional time of day, empty if there is none.
+fn input_group_time(id: &str, label: &str, name: &str, value: &str) -> Markup {
+    html! {
+        div.input-group {
+            label for=(id) { (label) }
+            input type="time" id=(id) name=(name) value=(value);
+        }
+    }
+}
+
+fn input_group_text(id: &str, label: &str, name: &str, value: &str) -> Markup {
+    html! {
+        div.input-group {
+            label for=(id) { (label) }
+            input type="text" id=(id) name=(name) value=(value) required autocomplete="new-text";
+        }
+    }
+}
+
+/// Hidden field with the CSRF token of the session, needed by every form
+/// that is posted.
+fn csrf_input() -> Markup {
+    html! {
+        @if let Some(token) = csrf::token() {
+            input type="hidden" name=(csrf::FIELD) value=(token);
+        }
+    }
 }
 
 fn input_group_password(id: &str, label: &str, name: &str) -> Markup {
@@ -509,6 +1625,380 @@ fn input_group_checkbox(id: &str, label: &str, name: &str, checked: bool) -> Mar
     }
 }
 
+fn input_group_select(
+    id: &str,
+    label: &str,
+    name: &str,
+    options: &[(&str, &str)],
+    selected: Option<&str>,
+) -> Markup {
+    html! {
+        div.input-group {
+            label for=(id) { (label) }
+            select id=(id) name=(name) {
+                @for (value, text) in options {
+                    option value=(value) selected[selected == Some(*value)] { (text) }
+                }
+            }
+        }
+    }
+}
+
+fn meal_slot_select(meal_slots: &[db::MealSlot], selected: Option<&str>) -> Markup {
+    let options = std::iter::once(("", "None"))
+        .chain(meal_slots.iter().map(|s| (s.id.as_str(), s.name.as_str())))
+        .collect::<Vec<_>>();
+    input_group_select(
+        "input-meal-slot",
+        "Meal",
+        "meal_slot_id",
+        &options,
+        selected,
+    )
+}
+
+/// Values of the food form for a saved food.
+fn food_values(food: &db::Food) -> FormValues {
+    let nutrients = NUTRIENTS
+        .iter()
+        .zip(food.nutrients.values())
+        .filter_map(|(nutrient, value)| Some((nutrient.key, value?.to_string())));
+    let checkboxes = [
+        ("hidden", food.hidden_at),
+        ("starred", food.starred_at),
+        ("shared", food.shared_at),
+    ]
+    .into_iter()
+    .filter(|(_, at)| at.is_some())
+    .map(|(name, _)| (name, "true".to_string()));
+    FormValues::new(
+        [
+            ("barcode", food.barcode.clone().unwrap_or_default()),
+            ("name", food.name.clone()),
+            ("kcal", food.kcal.to_string()),
+            ("fat", food.fat.to_string()),
+            ("carbs", food.carbs.to_string()),
+            ("protein", food.protein.to_string()),
+            ("unit", food.unit.clone()),
+            ("basis", food.basis.to_string()),
+        ]
+        .into_iter()
+        .chain(nutrients)
+        .chain(checkboxes),
+    )
+}
+
+/// Fields of the food form. With `lookup`, the barcode can be looked up.
+fn food_inputs(values: &FormValues, lookup: bool) -> Markup {
+    html! {
+        (barcode_input(values.get("barcode"), lookup));
+        (input_group_text("input-name", "Name", "name", values.get("name")));
+        (input_group_number("input-kcal", "Calories", "kcal", values.get("kcal")));
+        (input_group_number("input-fat", "Fat", "fat", values.get("fat")));
+        (input_group_number("input-carbs", "Carbs", "carbs", values.get("carbs")));
+        (input_group_number("input-protein", "Protein", "protein", values.get("protein")));
+        (food_unit_inputs(values.get("unit"), values.get("basis")));
+        (nutrient_inputs(values));
+        (input_group_checkbox("input-hidden", "Hidden", "hidden", values.checked("hidden")));
+        (input_group_checkbox("input-starred", "Starred", "starred", values.checked("starred")));
+        (input_group_checkbox("input-shared", "Shared", "shared", values.checked("shared")));
+    }
+}
+
+/// Message of a form that is shown again because it couldn't be saved.
+fn form_error(invalid: Option<&InvalidForm>) -> Markup {
+    html! {
+        @if let Some(invalid) = invalid {
+            div class="error" { (invalid.message) }
+        }
+    }
+}
+
+/// `invalid` if it was posted to `action`, for pages with several forms.
+fn invalid_at<'a>(invalid: Option<&'a InvalidForm>, action: &AppUrl) -> Option<&'a InvalidForm> {
+    invalid.filter(|invalid| invalid.action == action.to_string())
+}
+
+/// Values to show in a form: the submitted ones if it couldn't be saved,
+/// else `saved`.
+fn form_values(invalid: Option<&InvalidForm>, saved: impl FnOnce() -> FormValues) -> FormValues {
+    invalid.map_or_else(saved, |invalid| invalid.values.clone())
+}
+
+fn food_unit_inputs(unit: &str, basis: &str) -> Markup {
+    let options = db::FOOD_UNITS.map(|unit| (unit, unit));
+    html! {
+        (input_group_select("input-unit", "Unit", "unit", &options, Some(unit)))
+        (input_group_number("input-basis", "Macros per", "basis", basis))
+    }
+}
+
+/// Optional barcode field. With `lookup`, a button fills the rest of the form
+/// from the barcode, see `main.js`.
+fn barcode_input(value: &str, lookup: bool) -> Markup {
+    html! {
+        div.input-group.barcode-group {
+            label for="input-barcode" { "Barcode" }
+            input type="text" id="input-barcode" name="barcode" value=(value) inputmode="numeric" pattern="[0-9]*" autocomplete="off";
+            @if lookup {
+                button type="button" class="gray" data-barcode-lookup { "Look up" }
+                span class="barcode-status" data-barcode-status {}
+            }
+        }
+    }
+}
+
+/// Optional nutrient fields, folded away as most foods only have the macros.
+fn nutrient_inputs(values: &FormValues) -> Markup {
+    html! {
+        details class="nutrient-inputs" open[NUTRIENTS.iter().any(|n| !values.get(n.key).is_empty())] {
+            summary { "Nutrients" }
+            @for nutrient in &NUTRIENTS {
+                div.input-group {
+                    label for=(format!("input-{}", nutrient.key)) { (nutrient.label) " (" (nutrient.unit) ")" }
+                    input type="number" id=(format!("input-{}", nutrient.key)) name=(nutrient.key) value=(values.get(nutrient.key)) min="0" step="0.001" autocomplete="new-number";
+                }
+            }
+        }
+    }
+}
+
+/// Select for the unit of a quantity: the unit of the food or one of its
+/// servings. Servings of other foods are hidden and shown by `main.js` when
+/// the selected consumable changes.
+fn serving_select(
+    consumable: Option<&db::Consumable>,
+    servings: &[db::Serving],
+    selected: Option<&str>,
+) -> Markup {
+    let food_id = consumable
+        .filter(|c| c.ctype == db::ConsumableType::Food)
+        .map(|c| c.id.as_str());
+    html! {
+        div.input-group {
+            label for="input-serving" { "Unit" }
+            select id="input-serving" name="serving_id" data-serving-select {
+                option value="" data-serving-default {
+                    (consumable.and_then(|c| c.unit.as_deref()).unwrap_or("×"))
+                }
+                @for serving in servings {
+                    option value=(serving.id) data-food-id=(serving.food_id) hidden[food_id != Some(serving.food_id.as_str())] selected[selected == Some(serving.id.as_str())] {
+                        (serving.name)
+                    }
+                }
+            }
+        }
+    }
+}
+
+fn group_by_meal_slot(
+    consumptions: Vec<db::Consumption>,
+    meal_slots: &[db::MealSlot],
+) -> Vec<(String, Vec<db::Consumption>)> {
+    let mut groups = meal_slots
+        .iter()
+        .map(|s| (s.name.clone(), Vec::new()))
+        .collect::<Vec<_>>();
+    let mut other = Vec::new();
+
+    for consumption in consumptions {
+        match meal_slots
+            .iter()
+            .position(|s| Some(&s.id) == consumption.meal_slot_id.as_ref())
+        {
+            Some(index) => groups[index].1.push(consumption),
+            None => other.push(consumption),
+        }
+    }
+
+    groups.push(("Other".to_string(), other));
+    groups.retain(|(_, consumptions)| !consumptions.is_empty());
+    groups
+}
+
+fn meal_slot_group(name: &str, consumptions: &[db::Consumption], shown: &[&Nutrient]) -> Markup {
+    let mut subtotal = db::Macros {
+        kcal: 0.0,
+        fat: 0.0,
+        carbs: 0.0,
+        protein: 0.0,
+    };
+    let mut subtotal_nutrients = Nutrients::default();
+
+    for row in consumptions {
+        subtotal.kcal += row.kcal;
+        subtotal.fat += row.fat;
+        subtotal.carbs += row.carbs;
+        subtotal.protein += row.protein;
+        subtotal_nutrients = subtotal_nutrients.add(&row.nutrients);
+    }
+
+    html! {
+        div class="meal-slot-header" {
+            h2 { (name) }
+            div class="subtotal" {
+                span { (format!("{:.0}", subtotal.kcal)) " kcal" }
+                span { (format!("{:.0}", subtotal.fat)) " fat" }
+                span { (format!("{:.0}", subtotal.carbs)) " carbs" }
+                span { (format!("{:.0}", subtotal.protein)) " protein" }
+                @for nutrient in shown {
+                    span { (fmt_nutrient(subtotal_nutrients.get(nutrient.key), nutrient)) " " (nutrient.label.to_lowercase()) }
+                }
+            }
+        }
+        div class="macro-cards" {
+            @for consumption in consumptions {
+                (consumption_card(consumption, false, shown))
+            }
+        }
+    }
+}
+
+fn goal_status(value: f64, target: f64) -> &'static str {
+    if value > target {
+        "over"
+    } else {
+        "under"
+    }
+}
+
+fn goal_progress_row(label: &str, value: f64, target: f64) -> Markup {
+    let percent = if target > 0.0 {
+        value / target * 100.0
+    } else {
+        0.0
+    };
+    html! {
+        div class=(format!("goal-row {}", goal_status(value, target))) {
+            span class="label" { (label) }
+            div class="bar" {
+                div style=(format!("width: {:.0}%", percent.min(100.0))) {}
+            }
+            span class="percent" { (format!("{:.0}%", percent)) }
+            span class="remaining" { (fmt_remaining(value, target)) }
+        }
+    }
+}
+
+/// Amount that is left to reach the goal, or by which it was exceeded.
+fn fmt_remaining(value: f64, target: f64) -> String {
+    if value > target {
+        format!("{} over", fmt_macro((value - target).round()))
+    } else {
+        format!("{} left", fmt_macro((target - value).round()))
+    }
+}
+
+fn goal_cell(value: Option<f64>, target: Option<f64>) -> Markup {
+    match (value, target) {
+        (Some(value), Some(target)) if target > 0.0 => html! {
+            td class=(goal_status(value, target)) {
+                (fmt_macro(value)) " "
+                small {
+                    "(" (format!("{:.0}%", value / target * 100.0)) ", "
+                    (fmt_remaining(value, target)) ")"
+                }
+            }
+        },
+        _ => html! {
+            td { (value.map(fmt_macro).unwrap_or_default()) }
+        },
+    }
+}
+
+/// Line chart of the trend with the measured weights as dots. The chart is
+/// plain SVG, scaled to the points of the selected range.
+fn weight_chart(points: &[TrendPoint]) -> Markup {
+    const WIDTH: f64 = 600.0;
+    const HEIGHT: f64 = 240.0;
+    const LEFT: f64 = 40.0;
+    const RIGHT: f64 = 10.0;
+    const TOP: f64 = 10.0;
+    const BOTTOM: f64 = 24.0;
+
+    let (Some(first), Some(last)) = (points.first(), points.last()) else {
+        return html! { div class="text-center" { "No data." } };
+    };
+
+    let values = points.iter().flat_map(|p| [p.weight, p.trend]);
+    let min = values.clone().fold(f64::INFINITY, f64::min).floor() - 0.5;
+    let max = values.fold(f64::NEG_INFINITY, f64::max).ceil() + 0.5;
+    let days = (last.date - first.date).num_days() as f64;
+
+    let x = |date: chrono::NaiveDate| {
+        if days == 0.0 {
+            LEFT + (WIDTH - LEFT - RIGHT) / 2.0
+        } else {
+            LEFT + (date - first.date).num_days() as f64 / days * (WIDTH - LEFT - RIGHT)
+        }
+    };
+    let y = |value: f64| TOP + (max - value) / (max - min) * (HEIGHT - TOP - BOTTOM);
+
+    let trend_line = points
+        .iter()
+        .map(|p| format!("{:.1},{:.1}", x(p.date), y(p.trend)))
+        .collect::<Vec<_>>()
+        .join(" ");
+
+    html! {
+        svg class="weight-chart" viewBox=(format!("0 0 {} {}", WIDTH, HEIGHT)) xmlns="http://www.w3.org/2000/svg" {
+            @for value in [min, (min + max) / 2.0, max] {
+                @let value_y = format!("{:.1}", y(value));
+                line class="grid" x1=(LEFT) x2=(WIDTH - RIGHT) y1=(value_y) y2=(value_y) {}
+                text class="label" x=(LEFT - 4.0) y=(format!("{:.1}", y(value) + 4.0)) text-anchor="end" { (format!("{:.1}", value)) }
+            }
+            text class="label" x=(LEFT) y=(HEIGHT - 6.0) text-anchor="start" { (first.date) }
+            @if days > 0.0 {
+                text class="label" x=(WIDTH - RIGHT) y=(HEIGHT - 6.0) text-anchor="end" { (last.date) }
+            }
+            @for point in points {
+                circle class="weight" cx=(format!("{:.1}", x(point.date))) cy=(format!("{:.1}", y(point.weight))) r="3" {}
+            }
+            polyline class="trend" points=(trend_line) {}
+        }
+    }
+}
+
+/// Formats a quantity with its unit, or as a multiplier if there is none.
+fn fmt_quantity(quantity: f64, unit: Option<&str>) -> String {
+    match unit {
+        Some(unit) => format!("{} {}", quantity, unit),
+        None => format!("×{}", quantity),
+    }
+}
+
+/// Time of day without seconds, empty if there is none.
+/// Date of `at` in the user's time zone.
+fn local_date(at: chrono::DateTime<chrono::Utc>, time_zone: Tz) -> chrono::NaiveDate {
+    at.with_timezone(&time_zone).date_naive()
+}
+
+fn fmt_time(time: Option<chrono::NaiveTime>) -> String {
+    time.map(|time| time.format("%H:%M").to_string())
+        .unwrap_or_default()
+}
+
+/// Value with unit, or a dash if no food in it has the nutrient.
+fn fmt_nutrient(value: Option<f64>, nutrient: &Nutrient) -> String {
+    match value {
+        Some(value) => format!("{} {}", fmt_macro(value), nutrient.unit),
+        None => "–".to_string(),
+    }
+}
+
+/// Totals of the nutrients the user has chosen to see.
+fn nutrient_summary(shown: &[&Nutrient], nutrients: &Nutrients) -> Markup {
+    html! {
+        @if !shown.is_empty() {
+            div class="nutrient-summary" {
+                @for nutrient in shown {
+                    div { span { (fmt_nutrient(nutrients.get(nutrient.key), nutrient)) } span { (nutrient.label.to_lowercase()) } }
+                }
+            }
+        }
+    }
+}
+
 fn fmt_macro(macro_value: f64) -> String {
     if macro_value.fract() == 0.0 {
         format!("{:.0}", macro_value)
@@ -530,6 +2020,7 @@ enum PhosphorIcon {
     X,
     Star,
     ArrowSquareUpRight,
+    Globe,
 }
 
 // Icons by https://phosphoricons.com/
@@ -548,7 +2039,8 @@ impl Render for PhosphorIcon {
             PhosphorIcon::MagnifyingGlass => r#"<svg xmlns="http://www.w3.org/2000/svg" viewBox="0 0 256 256"><rect width="256" height="256" fill="none"/><circle cx="112" cy="112" r="80" fill="none" stroke="currentColor" stroke-linecap="round" stroke-linejoin="round" stroke-width="16"/><line x1="168.57" y1="168.57" x2="224" y2="224" fill="none" stroke="currentColor" stroke-linecap="round" stroke-linejoin="round" stroke-width="16"/></svg>"#,
             PhosphorIcon::X => r#"<svg xmlns="http://www.w3.org/2000/svg" viewBox="0 0 256 256"><rect width="256" height="256" fill="none"/><line x1="200" y1="56" x2="56" y2="200" stroke="currentColor" stroke-linecap="round" stroke-linejoin="round" stroke-width="16"/><line x1="200" y1="200" x2="56" y2="56" stroke="currentColor" stroke-linecap="round" stroke-linejoin="round" stroke-width="16"/></svg>"#,
             PhosphorIcon::Star => r#"<svg xmlns="http://www.w3.org/2000/svg" viewBox="0 0 256 256"><rect width="256" height="256" fill="none"/><path d="M128,189.09l54.72,33.65a8.4,8.4,0,0,0,12.52-9.17l-14.88-62.79,48.7-42A8.46,8.46,0,0,0,224.27,94L160.36,88.8,135.74,29.2a8.36,8.36,0,0,0-15.48,0L95.64,88.8,31.73,94a8.46,8.46,0,0,0-4.79,14.83l48.7,42L60.76,213.57a8.4,8.4,0,0,0,12.52,9.17Z" fill="none" stroke="currentColor" stroke-linecap="round" stroke-linejoin="round" stroke-width="16"/></svg>"#,
-            PhosphorIcon::ArrowSquareUpRight => r#"<svg xmlns="http://www.w3.org/2000/svg" viewBox="0 0 256 256"><rect width="256" height="256" fill="none"/><rect x="40" y="40" width="176" height="176" rx="8" transform="translate(0 256) rotate(-90)" fill="none" stroke="currentColor" stroke-linecap="round" stroke-linejoin="round" stroke-width="16"/><line x1="160" y1="96" x2="96" y2="160" fill="none" stroke="currentColor" stroke-linecap="round" stroke-linejoin="round" stroke-width="16"/><polyline points="112 96 160 96 160 144" fill="none" stroke="currentColor" stroke-linecap="round" stroke-linejoin="round" stroke-width="16"/></svg>"#
+            PhosphorIcon::ArrowSquareUpRight => r#"<svg xmlns="http://www.w3.org/2000/svg" viewBox="0 0 256 256"><rect width="256" height="256" fill="none"/><rect x="40" y="40" width="176" height="176" rx="8" transform="translate(0 256) rotate(-90)" fill="none" stroke="currentColor" stroke-linecap="round" stroke-linejoin="round" stroke-width="16"/><line x1="160" y1="96" x2="96" y2="160" fill="none" stroke="currentColor" stroke-linecap="round" stroke-linejoin="round" stroke-width="16"/><polyline points="112 96 160 96 160 144" fill="none" stroke="currentColor" stroke-linecap="round" stroke-linejoin="round" stroke-width="16"/></svg>"#,
+            PhosphorIcon::Globe => r#"<svg xmlns="http://www.w3.org/2000/svg" viewBox="0 0 256 256"><rect width="256" height="256" fill="none"/><circle cx="128" cy="128" r="96" fill="none" stroke="currentColor" stroke-linecap="round" stroke-linejoin="round" stroke-width="16"/><line x1="37.46" y1="96" x2="218.54" y2="96" fill="none" stroke="currentColor" stroke-linecap="round" stroke-linejoin="round" stroke-width="16"/><line x1="37.46" y1="160" x2="218.54" y2="160" fill="none" stroke="currentColor" stroke-linecap="round" stroke-linejoin="round" stroke-width="16"/><ellipse cx="128" cy="128" rx="40" ry="93.4" fill="none" stroke="currentColor" stroke-linecap="round" stroke-linejoin="round" stroke-width="16"/></svg>"#
         }.to_string())
     }
 }
@@ -588,7 +2080,7 @@ fn food_select_dialog(consumables: Vec<db::Consumable>) -> Markup {
                 }
                 div.options {
                     @for consumable in consumables {
-                        button.option type="button" data-consumable-id=(consumable.id) data-consumable-type=(consumable.ctype.as_str()) data-consumable-name=(consumable.name) data-consumable-url=(consumable_url(&consumable)) {
+                        button.option type="button" data-consumable-id=(consumable.id) data-consumable-aliases=[consumable.aliases.as_deref()] data-consumable-type=(consumable.ctype.as_str()) data-consumable-name=(consumable.name) data-consumable-unit=(consumable.unit.as_deref().unwrap_or("×")) data-consumable-url=(consumable_url(&consumable)) {
                             span class="name" {
                                 (consumable.name)
                             }
@@ -597,6 +2089,11 @@ fn food_select_dialog(consumables: Vec<db::Consumable>) -> Markup {
                                     (PhosphorIcon::Star)
                                 }
                             }
+                            @if consumable.is_shared {
+                                span class="shared" title="Shared" {
+                                    (PhosphorIcon::Globe)
+                                }
+                            }
                             @if consumable.ctype == db::ConsumableType::Recipe {
                                 span class="recipe" {
                                     (PhosphorIcon::CookingPot)
@@ -610,14 +2107,30 @@ fn food_select_dialog(consumables: Vec<db::Consumable>) -> Markup {
     )
 }
 
+/// Offers to update the saved values of the user's consumptions of a food or
+/// recipe, if there are any.
+fn recalculate_form(url: AppUrl, consumptions: &[db::Consumption]) -> Markup {
+    html! {
+        @if !consumptions.is_empty() {
+            form method="post" action=(url) class="recalculate" {
+                (csrf_input())
+                p { "Consumptions keep the values from when they were logged." }
+                button type="submit" class="gray" { "Recalculate from current values" };
+            }
+        }
+    }
+}
+
 fn macro_card(
     name: &str,
     url: AppUrl,
-    quantity: Option<f64>,
+    quantity: Option<String>,
     date: Option<chrono::NaiveDate>,
     macros: db::Macros,
+    shown: &[&Nutrient],
+    nutrients: &Nutrients,
 ) -> Markup {
-    let classes = match (quantity, date) {
+    let classes = match (&quantity, date) {
         (Some(_), Some(_)) => "macro-card with-quantity with-date",
         (Some(_), None) => "macro-card with-quantity",
         (None, Some(_)) => "macro-card with-date",
@@ -630,21 +2143,34 @@ fn macro_card(
             }
             a class="name" href=(url) { (name) }
             @if let Some(quantity) = quantity {
-                div class="quantity" { "×" (quantity) }
+                div class="quantity" { (quantity) }
             }
             div class="kcal" { span { (fmt_macro(macros.kcal)) } span { "kcal" } }
             div class="fat" { span { (fmt_macro(macros.fat)) } span { "fat" } }
             div class="carbs" { span { (fmt_macro(macros.carbs)) } span { "carbs" } }
             div class="protein" { span { (fmt_macro(macros.protein)) } span { "protein" } }
+            @for nutrient in shown {
+                div class="nutrient" {
+                    span { (fmt_nutrient(nutrients.get(nutrient.key), nutrient)) }
+                    span { (nutrient.label.to_lowercase()) }
+                }
+            }
         }
     }
 }
 
-fn consumption_card(consumption: &db::Consumption, date: bool) -> Markup {
+fn consumption_card(consumption: &db::Consumption, date: bool, shown: &[&Nutrient]) -> Markup {
     macro_card(
         consumption.consumable_name.as_str(),
         AppUrl::ConsumptionsId(consumption.id.clone()),
-        Some(consumption.quantity),
+        Some(match consumption.consumed_time {
+            Some(_) => format!(
+                "{} at {}",
+                fmt_quantity(consumption.quantity, consumption.unit.as_deref()),
+                fmt_time(consumption.consumed_time)
+            ),
+            None => fmt_quantity(consumption.quantity, consumption.unit.as_deref()),
+        }),
         if date {
             Some(consumption.consumed_at)
         } else {
@@ -656,14 +2182,19 @@ fn consumption_card(consumption: &db::Consumption, date: bool) -> Markup {
             carbs: consumption.carbs,
             protein: consumption.protein,
         },
+        shown,
+        &consumption.nutrients,
     )
 }
 
-fn ingredient_card(ingredient: &db::Ingredient) -> Markup {
+fn ingredient_card(ingredient: &db::Ingredient, shown: &[&Nutrient]) -> Markup {
     macro_card(
-        ingredient.food_name.as_str(),
+        ingredient.consumable_name.as_str(),
         AppUrl::IngredientsId(ingredient.id.clone()),
-        Some(ingredient.quantity),
+        Some(fmt_quantity(
+            ingredient.quantity,
+            ingredient.unit.as_deref(),
+        )),
         None,
         db::Macros {
             kcal: ingredient.kcal,
@@ -671,6 +2202,8 @@ fn ingredient_card(ingredient: &db::Ingredient) -> Markup {
             carbs: ingredient.carbs,
             protein: ingredient.protein,
         },
+        shown,
+        &ingredient.nutrients,
     )
 }
 
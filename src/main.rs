@@ -1,8 +1,9 @@
 use std::fmt::Display;
 
 use axum::{
-    extract::FromRequestParts,
-    http::{request::Parts, StatusCode},
+    extract::{multipart::MultipartError, DefaultBodyLimit, FromRequestParts, Request, State},
+    http::{header, request::Parts, HeaderName, HeaderValue, Method, StatusCode},
+    middleware::{self, Next},
     response::{Html, IntoResponse, Redirect, Response},
     routing, Router,
 };
@@ -10,13 +11,39 @@ use axum_extra::extract::cookie::{Cookie, CookieJar};
 use maud::{html, Markup, Render};
 use sqlx::SqlitePool;
 use tokio_cron_scheduler::{Job, JobScheduler};
-use tower_http::{compression::CompressionLayer, services::ServeDir, trace::TraceLayer};
-use tracing::{error, info};
+use tower_http::{
+    compression::CompressionLayer,
+    request_id::{MakeRequestUuid, PropagateRequestIdLayer, RequestId, SetRequestIdLayer},
+    services::ServeDir,
+    trace::TraceLayer,
+};
+use tracing::{debug_span, error, info};
 use tracing_subscriber::prelude::*;
 
+mod api;
+mod csrf;
 mod db;
+mod export;
+mod form;
 mod handler;
 mod html;
+mod nutrients;
+mod products;
+mod tdee;
+#[cfg(test)]
+mod tests;
+mod throttle;
+mod trend;
+
+const IMPORT_LIMIT: usize = 64 * 1024 * 1024;
+
+/// Header with the ID of a request, set by a reverse proxy or generated, and
+/// sent back with the response.
+const REQUEST_ID_HEADER: HeaderName = HeaderName::from_static("x-request-id");
+
+/// Interval of the job that deletes expired sessions and login attempts,
+/// unless `SESSION_SWEEP_MINUTES` is set.
+const DEFAULT_SESSION_SWEEP_MINUTES: u64 = 60;
 
 #[derive(Clone)]
 struct AppState {
@@ -54,13 +81,146 @@ async fn main() {
     if let Some(arg1) = std::env::args().nth(1) {
         if arg1 == "create-user" {
             let username = std::env::args().nth(2).expect("Missing username argument");
+            let is_admin = std::env::args().nth(3).is_some_and(|arg| arg == "--admin");
             let password = rpassword::prompt_password("Password: ").unwrap();
 
-            db::Db::new(db_pool.clone())
-                .create_user(&username, &password)
+            let db = db::Db::new(db_pool.clone());
+            let user_id = db.create_user(&username, &password).await.unwrap();
+            if is_admin {
+                db.set_user_admin(&user_id, true).await.unwrap();
+            }
+
+            return;
+        }
+
+        if arg1 == "list-users" {
+            let db = db::Db::new(db_pool.clone());
+            for user in db.get_users().await.unwrap() {
+                let time_zone = db.get_time_zone(&user.id).await.unwrap();
+                println!(
+                    "{}\t{}{}\t{}",
+                    user.username,
+                    if user.is_admin { "admin" } else { "user" },
+                    if user.disabled_at.is_some() {
+                        ", disabled"
+                    } else {
+                        ""
+                    },
+                    user.created_at.with_timezone(&time_zone).date_naive()
+                );
+            }
+
+            return;
+        }
+
+        if arg1 == "reset-password" {
+            let username = std::env::args().nth(2).expect("Missing username argument");
+
+            let db = db::Db::new(db_pool.clone());
+            let user = db
+                .get_user(&username)
+                .await
+                .unwrap()
+                .expect("Unknown username");
+            let password = rpassword::prompt_password("New password: ").unwrap();
+            db.set_password(&user.id, &password).await.unwrap();
+            db.delete_user_sessions(&user.id).await.unwrap();
+
+            return;
+        }
+
+        if arg1 == "delete-user" {
+            let username = std::env::args().nth(2).expect("Missing username argument");
+
+            let db = db::Db::new(db_pool.clone());
+            let user = db
+                .get_user(&username)
+                .await
+                .unwrap()
+                .expect("Unknown username");
+            let used_by_others = db
+                .count_user_entries_used_by_others(&user.id)
                 .await
                 .unwrap();
+            if used_by_others > 0 {
+                eprintln!(
+                    "Shared entries of {} are used {} times by other users",
+                    username, used_by_others
+                );
+                std::process::exit(1);
+            }
+            db.delete_user(&user.id).await.unwrap();
+
+            return;
+        }
+
+        if arg1 == "import" {
+            let username = std::env::args().nth(2).expect("Missing username argument");
+            let path = std::env::args().nth(3).expect("Missing file argument");
+            let mode = std::env::args().nth(4).unwrap_or("merge".to_string());
+            let mode = export::ImportMode::parse(&mode).expect("Mode must be merge or replace");
+
+            let db = db::Db::new(db_pool.clone());
+            let user = db
+                .get_user(&username)
+                .await
+                .unwrap()
+                .expect("Unknown username");
+            let result = match export::ExportData::parse(&std::fs::read(path).unwrap()) {
+                Ok(data) => db.import_data(&user.id, data, mode).await,
+                Err(e) => Err(e),
+            };
+
+            if let Err(e) = result {
+                eprintln!("Import failed: {}", e);
+                std::process::exit(1);
+            }
+
+            return;
+        }
+
+        if arg1 == "import-products" {
+            let path = std::env::args().nth(2).expect("Missing file argument");
+            let products = std::fs::File::open(&path).and_then(|file| {
+                products::read(
+                    std::io::BufReader::new(file),
+                    products::Format::from_path(&path),
+                )
+            });
+            let products = match products {
+                Ok(products) => products,
+                Err(e) => {
+                    eprintln!("Can't read {}: {}", path, e);
+                    std::process::exit(1);
+                }
+            };
+
+            let db = db::Db::new(db_pool.clone());
+            let mut batch = Vec::new();
+            let mut imported = 0;
+            let mut skipped = 0;
+            let mut malformed = 0;
+            for product in products {
+                match product {
+                    Ok(Some(product)) => batch.push(product),
+                    Ok(None) => skipped += 1,
+                    // Lines that aren't UTF-8. Other errors would repeat.
+                    Err(e) if e.kind() == std::io::ErrorKind::InvalidData => malformed += 1,
+                    Err(e) => {
+                        eprintln!("Can't read {}: {}", path, e);
+                        std::process::exit(1);
+                    }
+                }
+                if batch.len() == 1000 {
+                    db.import_products(&batch).await.unwrap();
+                    imported += batch.len();
+                    batch.clear();
+                }
+            }
+            db.import_products(&batch).await.unwrap();
+            imported += batch.len();
 
+            info!(imported, skipped, malformed, "imported products");
             return;
         }
     }
@@ -73,7 +233,38 @@ async fn main() {
 
     let app_state = AppState { db };
 
-    let app = Router::new()
+    let app = app(app_state, &assets_path);
+
+    let sweep_minutes = std::env::var("SESSION_SWEEP_MINUTES")
+        .map(|minutes| minutes.parse::<u64>().unwrap())
+        .unwrap_or(DEFAULT_SESSION_SWEEP_MINUTES);
+
+    let sched = JobScheduler::new().await.unwrap();
+    sched
+        .add(
+            Job::new_repeated_async(
+                std::time::Duration::from_secs(sweep_minutes * 60),
+                move |_uuid, _l| {
+                    let sched_db = db::Db::new(db_pool.clone());
+                    Box::pin(async move {
+                        sched_db.delete_expired_sessions().await.unwrap();
+                        sched_db.delete_stale_login_attempts().await.unwrap();
+                    })
+                },
+            )
+            .unwrap(),
+        )
+        .await
+        .unwrap();
+    sched.start().await.unwrap();
+
+    let listener = tokio::net::TcpListener::bind(("127.0.0.1", port)).await.unwrap();
+    info!(addr = ?listener.local_addr().unwrap(), "starting");
+    axum::serve(listener, app).await.unwrap();
+}
+
+fn app(app_state: AppState, assets_path: &str) -> Router {
+    Router::new()
         .route("/", routing::get(handler::index))
         .route("/days/{date}", routing::get(handler::days_read))
         .route("/consumptions", routing::post(handler::consumptions_create))
@@ -89,8 +280,13 @@ async fn main() {
             "/consumptions/{id}/delete",
             routing::post(handler::consumptions_delete),
         )
+        .route(
+            "/consumptions/{id}/recalculate",
+            routing::post(handler::consumptions_recalculate),
+        )
         .route("/weights", routing::get(handler::weights_index))
         .route("/weights", routing::post(handler::weights_create))
+        .route("/weights/rule", routing::post(handler::weights_rule_update))
         .route("/weights/{id}", routing::get(handler::weights_read))
         .route("/weights/{id}", routing::post(handler::weights_update))
         .route(
@@ -99,9 +295,32 @@ async fn main() {
         )
         .route("/foods", routing::get(handler::foods_index))
         .route("/foods", routing::post(handler::foods_create))
+        .route(
+            "/foods/barcode/{code}",
+            routing::get(handler::foods_barcode_read),
+        )
         .route("/foods/{id}", routing::get(handler::foods_read))
         .route("/foods/{id}", routing::post(handler::foods_update))
         .route("/foods/{id}/delete", routing::post(handler::foods_delete))
+        .route("/foods/{id}/clone", routing::post(handler::foods_clone))
+        .route("/foods/{id}/hide", routing::post(handler::foods_hide))
+        .route("/foods/{id}/merge", routing::post(handler::foods_merge))
+        .route(
+            "/foods/{id}/recalculate",
+            routing::post(handler::foods_recalculate),
+        )
+        .route(
+            "/foods/{id}/servings",
+            routing::post(handler::servings_create),
+        )
+        .route(
+            "/servings/{id}/delete",
+            routing::post(handler::servings_delete),
+        )
+        .route(
+            "/food-aliases/{id}/delete",
+            routing::post(handler::food_aliases_delete),
+        )
         .route("/recipes", routing::get(handler::recipes_index))
         .route("/recipes", routing::post(handler::recipes_create))
         .route("/recipes/{id}", routing::get(handler::recipes_read))
@@ -110,6 +329,13 @@ async fn main() {
             "/recipes/{id}/delete",
             routing::post(handler::recipes_delete),
         )
+        .route("/recipes/{id}/clone", routing::post(handler::recipes_clone))
+        .route("/recipes/{id}/hide", routing::post(handler::recipes_hide))
+        .route(
+            "/recipes/{id}/recalculate",
+            routing::post(handler::recipes_recalculate),
+        )
+        .route("/catalog", routing::get(handler::catalog_index))
         .route("/ingredients", routing::post(handler::ingredients_create))
         .route("/ingredients/{id}", routing::get(handler::ingredients_read))
         .route(
@@ -120,37 +346,137 @@ async fn main() {
             "/ingredients/{id}/delete",
             routing::post(handler::ingredients_delete),
         )
+        .route("/stats", routing::get(handler::stats_read))
         .route("/account", routing::get(handler::account_read))
         .route("/account/login", routing::get(handler::account_login_form))
         .route("/account/login", routing::post(handler::account_login))
         .route("/account/logout", routing::post(handler::account_logout))
         .route("/account/export", routing::post(handler::account_export))
+        .route(
+            "/account/import",
+            routing::get(handler::account_import_form),
+        )
+        .route(
+            "/account/import",
+            routing::post(handler::account_import).layer(DefaultBodyLimit::max(IMPORT_LIMIT)),
+        )
+        .route("/account/goals", routing::get(handler::account_goals_read))
+        .route(
+            "/account/goals",
+            routing::post(handler::account_goals_update),
+        )
+        .route(
+            "/account/goals/delete",
+            routing::post(handler::account_goals_delete),
+        )
+        .route(
+            "/account/meal-slots",
+            routing::get(handler::account_meal_slots_read),
+        )
+        .route(
+            "/account/meal-slots",
+            routing::post(handler::account_meal_slots_create),
+        )
+        .route(
+            "/account/meal-slots/{id}",
+            routing::post(handler::account_meal_slots_update),
+        )
+        .route(
+            "/account/meal-slots/{id}/delete",
+            routing::post(handler::account_meal_slots_delete),
+        )
+        .route(
+            "/account/nutrients",
+            routing::get(handler::account_nutrients_read),
+        )
+        .route(
+            "/account/nutrients",
+            routing::post(handler::account_nutrients_update),
+        )
+        .route(
+            "/account/time-zone",
+            routing::get(handler::account_time_zone_read),
+        )
+        .route(
+            "/account/time-zone",
+            routing::post(handler::account_time_zone_update),
+        )
+        .route(
+            "/account/password",
+            routing::get(handler::account_password_read),
+        )
+        .route(
+            "/account/password",
+            routing::post(handler::account_password_update),
+        )
+        .route(
+            "/account/sessions",
+            routing::get(handler::account_sessions_read),
+        )
+        .route(
+            "/account/sessions/delete-others",
+            routing::post(handler::account_sessions_delete_others),
+        )
+        .route(
+            "/account/sessions/{id}/delete",
+            routing::post(handler::account_sessions_delete),
+        )
+        .route(
+            "/account/api-tokens",
+            routing::get(handler::account_api_tokens_read),
+        )
+        .route(
+            "/account/api-tokens",
+            routing::post(handler::account_api_tokens_create),
+        )
+        .route(
+            "/account/api-tokens/{id}/delete",
+            routing::post(handler::account_api_tokens_delete),
+        )
+        .route("/admin/users", routing::get(handler::admin_users_index))
+        .route("/admin/users", routing::post(handler::admin_users_create))
+        .route("/admin/users/{id}", routing::get(handler::admin_users_read))
+        .route(
+            "/admin/users/{id}",
+            routing::post(handler::admin_users_update),
+        )
+        .route(
+            "/admin/users/{id}/password",
+            routing::post(handler::admin_users_password),
+        )
+        .route(
+            "/admin/users/{id}/delete",
+            routing::post(handler::admin_users_delete),
+        )
+        .route_layer(middleware::from_fn_with_state(
+            app_state.clone(),
+            csrf::verify,
+        ))
+        .route_layer(middleware::from_fn_with_state(
+            app_state.clone(),
+            rotate_session,
+        ))
+        .nest("/api/v1", api::router())
         .nest_service("/assets", ServeDir::new(assets_path))
+        .fallback(handler::not_found)
         .with_state(app_state)
+        .layer(middleware::from_fn(render_error_page))
         .layer(CompressionLayer::new())
-        .layer(TraceLayer::new_for_http());
-
-    let sched = JobScheduler::new().await.unwrap();
-    sched
-        .add(
-            Job::new_async("1/7 * * * * *", move |_uuid, _l| {
-                let sched_db = db::Db::new(db_pool.clone());
-                Box::pin(async move {
-                    sched_db.delete_expired_sessions().await.unwrap();
-                })
-            })
-            .unwrap(),
+        .layer(
+            TraceLayer::new_for_http().make_span_with(|request: &Request| {
+                let request_id = request
+                    .headers()
+                    .get(REQUEST_ID_HEADER)
+                    .and_then(|id| id.to_str().ok())
+                    .unwrap_or_default();
+                debug_span!("request", method = %request.method(), uri = %request.uri(), request_id)
+            }),
         )
-        .await
-        .unwrap();
-    sched.start().await.unwrap();
-
-    let listener = tokio::net::TcpListener::bind(("127.0.0.1", port)).await.unwrap();
-    info!(addr = ?listener.local_addr().unwrap(), "starting");
-    axum::serve(listener, app).await.unwrap();
+        .layer(PropagateRequestIdLayer::new(REQUEST_ID_HEADER))
+        .layer(SetRequestIdLayer::new(REQUEST_ID_HEADER, MakeRequestUuid))
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 enum AppError {
     InvalidDate,
     SQLError,
@@ -160,30 +486,130 @@ enum AppError {
     PasswordHashError,
     Forbidden,
     InvalidConsumableType,
+    InvalidWeekday,
+    InvalidTimeZone,
+    RecipeCycle,
+    InvalidUpload,
+    NotFound,
+    InvalidApiToken,
+    InvalidUnit,
+    InvalidAmount,
+    InvalidServing,
+    InvalidBarcode,
+    InvalidUsername,
+    EmptyPassword,
+    UserInUse,
+    InUse,
+    InvalidMerge,
+    InvalidCsrfToken,
+    InvalidForm,
+}
+
+impl AppError {
+    fn status(&self) -> StatusCode {
+        match self {
+            AppError::NoSessionCookie | AppError::UnknownSessionId | AppError::InvalidApiToken => {
+                StatusCode::UNAUTHORIZED
+            }
+            AppError::Forbidden | AppError::InvalidCsrfToken => StatusCode::FORBIDDEN,
+            AppError::NotFound => StatusCode::NOT_FOUND,
+            AppError::UserInUse | AppError::InUse => StatusCode::CONFLICT,
+            AppError::InvalidDate
+            | AppError::InvalidWeekday
+            | AppError::InvalidTimeZone
+            | AppError::InvalidConsumableType
+            | AppError::RecipeCycle
+            | AppError::InvalidUpload
+            | AppError::InvalidUnit
+            | AppError::InvalidAmount
+            | AppError::InvalidServing
+            | AppError::InvalidBarcode
+            | AppError::InvalidUsername
+            | AppError::EmptyPassword
+            | AppError::InvalidMerge
+            | AppError::InvalidForm => StatusCode::UNPROCESSABLE_ENTITY,
+            AppError::SQLError | AppError::HTTPError | AppError::PasswordHashError => {
+                StatusCode::INTERNAL_SERVER_ERROR
+            }
+        }
+    }
+
+    /// Explanation for the user, shown on the error page or above a form.
+    fn message(&self) -> &'static str {
+        match self {
+            AppError::NoSessionCookie | AppError::UnknownSessionId => "Please log in.",
+            AppError::InvalidApiToken => "The API token is missing or invalid.",
+            AppError::Forbidden => "This belongs to another user.",
+            AppError::InvalidCsrfToken => {
+                "The form has expired or was sent from another site. Please reload the page and try again."
+            }
+            AppError::NotFound => "This page doesn't exist. Maybe it was deleted.",
+            AppError::UserInUse => {
+                "Other users use shared entries of this user, so it can't be deleted."
+            }
+            AppError::InUse => {
+                "This is still used by consumptions or recipes, so it can't be deleted."
+            }
+            AppError::InvalidDate => "The date isn't valid.",
+            AppError::InvalidWeekday => "The weekday isn't valid.",
+            AppError::InvalidTimeZone => "The time zone isn't known.",
+            AppError::InvalidConsumableType => "Please choose a food or a recipe.",
+            AppError::RecipeCycle => "A recipe can't contain itself.",
+            AppError::InvalidUpload => "The upload couldn't be read.",
+            AppError::InvalidUnit => "Please choose one of the units.",
+            AppError::InvalidAmount => "The basis and serving amounts must be greater than zero.",
+            AppError::InvalidServing => "The serving doesn't belong to the food.",
+            AppError::InvalidBarcode => {
+                "The barcode has to be an EAN-8, UPC-A, EAN-13 or GTIN-14 code."
+            }
+            AppError::InvalidUsername => "The username is empty or already taken.",
+            AppError::EmptyPassword => "The password must not be empty.",
+            AppError::InvalidMerge => {
+                "A food can only be merged into another of your foods with the same unit."
+            }
+            AppError::InvalidForm => "Some values are missing or invalid.",
+            AppError::SQLError | AppError::HTTPError | AppError::PasswordHashError => {
+                "An error occurred. Please try again later."
+            }
+        }
+    }
 }
 
+/// The page is rendered by [`render_error_page`], which knows the request ID.
 impl IntoResponse for AppError {
     fn into_response(self) -> Response {
         match self {
             AppError::NoSessionCookie => redirect_to(AppUrl::AccountLogin),
             AppError::UnknownSessionId => redirect_to(AppUrl::AccountLogin),
-            AppError::InvalidDate => (
-                StatusCode::BAD_REQUEST,
-                Html(html::error_page().into_string()),
-            )
-                .into_response(),
             e => {
-                error!(error = ?e, "app error");
-                (
-                    StatusCode::INTERNAL_SERVER_ERROR,
-                    Html(html::error_page().into_string()),
-                )
-                    .into_response()
+                if e.status().is_server_error() {
+                    error!(error = ?e, "app error");
+                }
+                let mut response = e.status().into_response();
+                response.extensions_mut().insert(e);
+                response
             }
         }
     }
 }
 
+/// Renders the page of an [`AppError`] response, with the ID of the request
+/// so that it can be found in the logs.
+async fn render_error_page(request: Request, next: Next) -> Response {
+    let request_id = request
+        .extensions()
+        .get::<RequestId>()
+        .and_then(|id| id.header_value().to_str().ok())
+        .map(str::to_string);
+    let mut response = next.run(request).await;
+    if let Some(e) = response.extensions_mut().remove::<AppError>() {
+        let page = html::error_page(e.status(), e.message(), request_id.as_deref());
+        let (parts, _) = response.into_parts();
+        response = (parts, Html(page.into_string())).into_response();
+    }
+    response
+}
+
 impl From<chrono::ParseError> for AppError {
     fn from(e: chrono::ParseError) -> Self {
         error!(error = ?e, "Chrono error");
@@ -193,6 +619,9 @@ impl From<chrono::ParseError> for AppError {
 
 impl From<sqlx::Error> for AppError {
     fn from(e: sqlx::Error) -> Self {
+        if let sqlx::Error::RowNotFound = e {
+            return AppError::NotFound;
+        }
         error!(error = ?e, "SQL error");
         AppError::SQLError
     }
@@ -212,6 +641,13 @@ impl From<axum::http::Error> for AppError {
     }
 }
 
+impl From<MultipartError> for AppError {
+    fn from(e: MultipartError) -> Self {
+        error!(error = ?e, "Multipart error");
+        AppError::InvalidUpload
+    }
+}
+
 impl FromRequestParts<AppState> for Session {
     type Rejection = AppError;
 
@@ -258,28 +694,95 @@ impl FromRequestParts<AppState> for Option<Session> {
     }
 }
 
+/// Gives the session a new id once it's due, see [`db::Db::rotate_session`].
+/// Only safe requests rotate. The new id is used for the rest of the request
+/// and is sent as the new cookie.
+async fn rotate_session(
+    State(state): State<AppState>,
+    mut request: Request,
+    next: Next,
+) -> Result<Response, AppError> {
+    let session_id = CookieJar::from_headers(request.headers())
+        .get("MATRAFL_SESSION")
+        .map(|cookie| cookie.value().to_string());
+    let rotated = match session_id {
+        Some(session_id) if matches!(*request.method(), Method::GET | Method::HEAD) => {
+            state.db.rotate_session(&session_id).await?
+        }
+        _ => None,
+    };
+    let Some((session_id, remember)) = rotated else {
+        return Ok(next.run(request).await);
+    };
+
+    request.headers_mut().insert(
+        header::COOKIE,
+        HeaderValue::try_from(format!("MATRAFL_SESSION={}", session_id)).unwrap(),
+    );
+    let mut response = next.run(request).await;
+    response.headers_mut().append(
+        header::SET_COOKIE,
+        handler::session_cookie(Some(&session_id), remember),
+    );
+    Ok(response)
+}
+
 enum AppUrl {
     Home,
     DaySummary(chrono::NaiveDate),
     Consumptions,
     ConsumptionsId(String),
     ConsumptionsIdDelete(String),
+    ConsumptionsIdRecalculate(String),
     Weights,
+    WeightsRange(trend::TrendRange),
+    WeightsRule,
     WeightsId(String),
     WeightsIdDelete(String),
     Foods,
     FoodsId(String),
     FoodsIdDelete(String),
+    FoodsIdClone(String),
+    FoodsIdHide(String),
+    FoodsIdMerge(String),
+    FoodsIdRecalculate(String),
+    FoodsIdServings(String),
+    ServingsIdDelete(String),
+    FoodAliasesIdDelete(String),
     Recipes,
     RecipesId(String),
     RecipesIdDelete(String),
+    RecipesIdClone(String),
+    RecipesIdHide(String),
+    RecipesIdRecalculate(String),
+    Catalog,
     Ingredients,
     IngredientsId(String),
     IngredientsIdDelete(String),
+    Stats,
     Account,
+    AccountUntil(chrono::NaiveDate),
     AccountLogin,
     AccountLogout,
     AccountExport,
+    AccountImport,
+    AccountGoals,
+    AccountGoalsDelete,
+    AccountMealSlots,
+    AccountMealSlotsId(String),
+    AccountMealSlotsIdDelete(String),
+    AccountNutrients,
+    AccountTimeZone,
+    AccountPassword,
+    AccountSessions,
+    AccountSessionsDeleteOthers,
+    AccountSessionsIdDelete(String),
+    AccountApiTokens,
+    AccountApiTokensIdDelete(String),
+    AdminUsers,
+    AdminUsersId(String),
+    AdminUsersIdPassword(String),
+    AdminUsersIdDelete(String),
 }
 
 impl Render for AppUrl {
@@ -301,22 +804,61 @@ impl Display for AppUrl {
                 AppUrl::Consumptions => "/consumptions".to_string(),
                 AppUrl::ConsumptionsId(id) => format!("/consumptions/{}", id),
                 AppUrl::ConsumptionsIdDelete(id) => format!("/consumptions/{}/delete", id),
+                AppUrl::ConsumptionsIdRecalculate(id) => {
+                    format!("/consumptions/{}/recalculate", id)
+                }
                 AppUrl::Weights => "/weights".to_string(),
+                AppUrl::WeightsRange(range) => format!("/weights?range={}", range.as_str()),
+                AppUrl::WeightsRule => "/weights/rule".to_string(),
                 AppUrl::WeightsId(id) => format!("/weights/{}", id),
                 AppUrl::WeightsIdDelete(id) => format!("/weights/{}/delete", id),
                 AppUrl::Foods => "/foods".to_string(),
                 AppUrl::FoodsId(id) => format!("/foods/{}", id),
                 AppUrl::FoodsIdDelete(id) => format!("/foods/{}/delete", id),
+                AppUrl::FoodsIdClone(id) => format!("/foods/{}/clone", id),
+                AppUrl::FoodsIdHide(id) => format!("/foods/{}/hide", id),
+                AppUrl::FoodsIdMerge(id) => format!("/foods/{}/merge", id),
+                AppUrl::FoodsIdRecalculate(id) => format!("/foods/{}/recalculate", id),
+                AppUrl::FoodsIdServings(id) => format!("/foods/{}/servings", id),
+                AppUrl::ServingsIdDelete(id) => format!("/servings/{}/delete", id),
+                AppUrl::FoodAliasesIdDelete(id) => format!("/food-aliases/{}/delete", id),
                 AppUrl::Recipes => "/recipes".to_string(),
                 AppUrl::RecipesId(id) => format!("/recipes/{}", id),
                 AppUrl::RecipesIdDelete(id) => format!("/recipes/{}/delete", id),
+                AppUrl::RecipesIdClone(id) => format!("/recipes/{}/clone", id),
+                AppUrl::RecipesIdHide(id) => format!("/recipes/{}/hide", id),
+                AppUrl::RecipesIdRecalculate(id) => format!("/recipes/{}/recalculate", id),
+                AppUrl::Catalog => "/catalog".to_string(),
                 AppUrl::Ingredients => "/ingredients".to_string(),
                 AppUrl::IngredientsId(id) => format!("/ingredients/{}", id),
                 AppUrl::IngredientsIdDelete(id) => format!("/ingredients/{}/delete", id),
+                AppUrl::Stats => "/stats".to_string(),
                 AppUrl::Account => "/account".to_string(),
+                AppUrl::AccountUntil(date) => format!("/account?until={}", date),
                 AppUrl::AccountLogin => "/account/login".to_string(),
                 AppUrl::AccountLogout => "/account/logout".to_string(),
                 AppUrl::AccountExport => "/account/export".to_string(),
+                AppUrl::AccountImport => "/account/import".to_string(),
+                AppUrl::AccountGoals => "/account/goals".to_string(),
+                AppUrl::AccountGoalsDelete => "/account/goals/delete".to_string(),
+                AppUrl::AccountMealSlots => "/account/meal-slots".to_string(),
+                AppUrl::AccountMealSlotsId(id) => format!("/account/meal-slots/{}", id),
+                AppUrl::AccountMealSlotsIdDelete(id) =>
+                    format!("/account/meal-slots/{}/delete", id),
+                AppUrl::AccountNutrients => "/account/nutrients".to_string(),
+                AppUrl::AccountTimeZone => "/account/time-zone".to_string(),
+                AppUrl::AccountPassword => "/account/password".to_string(),
+                AppUrl::AccountSessions => "/account/sessions".to_string(),
+                AppUrl::AccountSessionsDeleteOthers =>
+                    "/account/sessions/delete-others".to_string(),
+                AppUrl::AccountSessionsIdDelete(id) => format!("/account/sessions/{}/delete", id),
+                AppUrl::AccountApiTokens => "/account/api-tokens".to_string(),
+                AppUrl::AccountApiTokensIdDelete(id) =>
+                    format!("/account/api-tokens/{}/delete", id),
+                AppUrl::AdminUsers => "/admin/users".to_string(),
+                AppUrl::AdminUsersId(id) => format!("/admin/users/{}", id),
+                AppUrl::AdminUsersIdPassword(id) => format!("/admin/users/{}/password", id),
+                AppUrl::AdminUsersIdDelete(id) => format!("/admin/users/{}/delete", id),
             }
         )
     }
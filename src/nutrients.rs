@@ -0,0 +1,237 @@
+//! Optional nutrients beyond the four macros. Foods may leave any of them
+//! empty. Sums only include the foods that have a value, so a total is only
+//! `None` if no food in it has one.
+
+use serde::{de::Error, Deserialize, Deserializer};
+
+pub struct Nutrient {
+    pub key: &'static str,
+    pub label: &'static str,
+    pub unit: &'static str,
+}
+
+/// All nutrients, in the order they are shown. The keys are the column names
+/// in `foods` and the field names of [`Nutrients`].
+pub const NUTRIENTS: [Nutrient; 9] = [
+    Nutrient {
+        key: "fiber",
+        label: "Fiber",
+        unit: "g",
+    },
+    Nutrient {
+        key: "sugar",
+        label: "Sugar",
+        unit: "g",
+    },
+    Nutrient {
+        key: "saturated_fat",
+        label: "Saturated fat",
+        unit: "g",
+    },
+    Nutrient {
+        key: "salt",
+        label: "Salt",
+        unit: "g",
+    },
+    Nutrient {
+        key: "alcohol",
+        label: "Alcohol",
+        unit: "g",
+    },
+    Nutrient {
+        key: "potassium",
+        label: "Potassium",
+        unit: "mg",
+    },
+    Nutrient {
+        key: "calcium",
+        label: "Calcium",
+        unit: "mg",
+    },
+    Nutrient {
+        key: "iron",
+        label: "Iron",
+        unit: "mg",
+    },
+    Nutrient {
+        key: "vitamin_c",
+        label: "Vitamin C",
+        unit: "mg",
+    },
+];
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, sqlx::FromRow, serde::Serialize, Deserialize)]
+pub struct Nutrients {
+    #[serde(default, deserialize_with = "optional_number")]
+    pub fiber: Option<f64>,
+    #[serde(default, deserialize_with = "optional_number")]
+    pub sugar: Option<f64>,
+    #[serde(default, deserialize_with = "optional_number")]
+    pub saturated_fat: Option<f64>,
+    #[serde(default, deserialize_with = "optional_number")]
+    pub salt: Option<f64>,
+    #[serde(default, deserialize_with = "optional_number")]
+    pub alcohol: Option<f64>,
+    #[serde(default, deserialize_with = "optional_number")]
+    pub potassium: Option<f64>,
+    #[serde(default, deserialize_with = "optional_number")]
+    pub calcium: Option<f64>,
+    #[serde(default, deserialize_with = "optional_number")]
+    pub iron: Option<f64>,
+    #[serde(default, deserialize_with = "optional_number")]
+    pub vitamin_c: Option<f64>,
+}
+
+impl Nutrients {
+    /// Values in the order of [`NUTRIENTS`].
+    pub fn values(&self) -> [Option<f64>; 9] {
+        [
+            self.fiber,
+            self.sugar,
+            self.saturated_fat,
+            self.salt,
+            self.alcohol,
+            self.potassium,
+            self.calcium,
+            self.iron,
+            self.vitamin_c,
+        ]
+    }
+
+    /// Inverse of [`Nutrients::values`].
+    pub fn from_values(values: [Option<f64>; 9]) -> Nutrients {
+        let [fiber, sugar, saturated_fat, salt, alcohol, potassium, calcium, iron, vitamin_c] =
+            values;
+        Nutrients {
+            fiber,
+            sugar,
+            saturated_fat,
+            salt,
+            alcohol,
+            potassium,
+            calcium,
+            iron,
+            vitamin_c,
+        }
+    }
+
+    pub fn get(&self, key: &str) -> Option<f64> {
+        NUTRIENTS
+            .iter()
+            .position(|n| n.key == key)
+            .and_then(|i| self.values()[i])
+    }
+
+    /// Adds up values that are present on either side.
+    pub fn add(&self, other: &Nutrients) -> Nutrients {
+        let add = |a: Option<f64>, b: Option<f64>| match (a, b) {
+            (None, None) => None,
+            (a, b) => Some(a.unwrap_or(0.0) + b.unwrap_or(0.0)),
+        };
+        Nutrients {
+            fiber: add(self.fiber, other.fiber),
+            sugar: add(self.sugar, other.sugar),
+            saturated_fat: add(self.saturated_fat, other.saturated_fat),
+            salt: add(self.salt, other.salt),
+            alcohol: add(self.alcohol, other.alcohol),
+            potassium: add(self.potassium, other.potassium),
+            calcium: add(self.calcium, other.calcium),
+            iron: add(self.iron, other.iron),
+            vitamin_c: add(self.vitamin_c, other.vitamin_c),
+        }
+    }
+}
+
+/// Parses a comma separated list of nutrient keys, skipping unknown ones.
+pub fn parse_keys(keys: &str) -> Vec<&'static Nutrient> {
+    let keys = keys.split(',').map(str::trim).collect::<Vec<_>>();
+    NUTRIENTS.iter().filter(|n| keys.contains(&n.key)).collect()
+}
+
+/// One SQL expression per nutrient, joined by commas. `{n}` in `template` is
+/// replaced with the nutrient key.
+pub fn sql_columns(template: &str) -> String {
+    NUTRIENTS
+        .iter()
+        .map(|n| template.replace("{n}", n.key))
+        .collect::<Vec<_>>()
+        .join(",\n")
+}
+
+/// Accepts numbers as well as form values, where an empty field means that
+/// the nutrient isn't known.
+fn optional_number<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<f64>, D::Error> {
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum Value {
+        Number(f64),
+        Text(String),
+    }
+
+    match Option::<Value>::deserialize(deserializer)? {
+        None => Ok(None),
+        Some(Value::Number(number)) => Ok(Some(number)),
+        Some(Value::Text(text)) if text.trim().is_empty() => Ok(None),
+        Some(Value::Text(text)) => text.trim().parse().map(Some).map_err(D::Error::custom),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_form_values() {
+        let nutrients: Nutrients =
+            serde_json::from_str(r#"{"fiber": "2.5", "sugar": "", "iron": " 0.4"}"#).unwrap();
+
+        assert_eq!(nutrients.fiber, Some(2.5));
+        assert_eq!(nutrients.sugar, None);
+        assert_eq!(nutrients.iron, Some(0.4));
+        assert_eq!(nutrients.salt, None);
+    }
+
+    #[test]
+    fn parse_json_values() {
+        let nutrients: Nutrients = serde_json::from_str(r#"{"fiber": 2.5, "salt": null}"#).unwrap();
+
+        assert_eq!(nutrients.fiber, Some(2.5));
+        assert_eq!(nutrients.salt, None);
+        assert!(serde_json::from_str::<Nutrients>(r#"{"fiber": "a lot"}"#).is_err());
+    }
+
+    #[test]
+    fn add_keeps_unknown_values_empty() {
+        let a = Nutrients {
+            fiber: Some(1.0),
+            ..Default::default()
+        };
+        let b = Nutrients {
+            fiber: Some(2.0),
+            sugar: Some(3.0),
+            ..Default::default()
+        };
+
+        let sum = a.add(&b);
+        assert_eq!(sum.fiber, Some(3.0));
+        assert_eq!(sum.sugar, Some(3.0));
+        assert_eq!(sum.salt, None);
+    }
+
+    #[test]
+    fn keys_are_fields() {
+        let json = serde_json::to_value(Nutrients::default()).unwrap();
+        for nutrient in &NUTRIENTS {
+            assert!(json.get(nutrient.key).is_some(), "{}", nutrient.key);
+        }
+        assert_eq!(json.as_object().unwrap().len(), NUTRIENTS.len());
+    }
+
+    #[test]
+    fn parse_known_keys() {
+        let keys = parse_keys("iron, fiber,unknown,");
+        let keys = keys.iter().map(|n| n.key).collect::<Vec<_>>();
+
+        assert_eq!(keys, ["fiber", "iron"]);
+    }
+}
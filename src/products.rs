@@ -0,0 +1,274 @@
+//! Local product database for barcode lookups, loaded from an Open Food Facts
+//! dump by the `import-products` command. The app only reads from it, so a
+//! lookup works without network access.
+//!
+//! Open Food Facts publishes a tab separated CSV export and a JSONL export
+//! with one product per line. Both use the same field names and give all
+//! values per 100 g.
+
+use std::collections::HashMap;
+use std::io::{self, BufRead};
+
+use crate::nutrients::Nutrients;
+
+/// Energy in kJ per kcal, for products that only state kJ.
+const KJ_PER_KCAL: f64 = 4.184;
+
+/// Density of ethanol in g/ml. Open Food Facts gives alcohol in % vol.
+const ALCOHOL_DENSITY: f64 = 0.789;
+
+/// Open Food Facts fields of the nutrients in the order of
+/// [`crate::nutrients::NUTRIENTS`], with the factor to the unit used here.
+/// Minerals and vitamins are given in g and shown in mg.
+const NUTRIENT_FIELDS: [(&str, f64); 9] = [
+    ("fiber_100g", 1.0),
+    ("sugars_100g", 1.0),
+    ("saturated-fat_100g", 1.0),
+    ("salt_100g", 1.0),
+    ("alcohol_100g", ALCOHOL_DENSITY),
+    ("potassium_100g", 1000.0),
+    ("calcium_100g", 1000.0),
+    ("iron_100g", 1000.0),
+    ("vitamin-c_100g", 1000.0),
+];
+
+/// Product with values per 100 g.
+#[derive(Debug, Clone, PartialEq, sqlx::FromRow)]
+pub struct Product {
+    pub barcode: String,
+    pub name: String,
+    pub kcal: f64,
+    pub fat: f64,
+    pub carbs: f64,
+    pub protein: f64,
+    #[sqlx(flatten)]
+    pub nutrients: Nutrients,
+}
+
+/// Unit and basis of the values of a [`Product`].
+pub const PRODUCT_UNIT: &str = "g";
+pub const PRODUCT_BASIS: f64 = 100.0;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Format {
+    Csv,
+    Jsonl,
+}
+
+impl Format {
+    /// Guesses the format from the file extension, CSV unless it's `.jsonl`
+    /// or `.json`.
+    pub fn from_path(path: &str) -> Format {
+        if path.ends_with(".jsonl") || path.ends_with(".json") {
+            Format::Jsonl
+        } else {
+            Format::Csv
+        }
+    }
+}
+
+/// Normalizes an EAN-8, UPC-A, EAN-13 or GTIN-14 code. UPC-A codes are
+/// turned into EAN-13 by prepending a zero, as scanners return either form.
+/// Returns `None` for anything else.
+pub fn normalize_barcode(code: &str) -> Option<String> {
+    let code = code.trim();
+    if !code.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    match code.len() {
+        8 | 13 | 14 => Some(code.to_string()),
+        12 => Some(format!("0{}", code)),
+        _ => None,
+    }
+}
+
+/// Reads the products of a dump. Lines that aren't usable, e.g. because of
+/// an invalid barcode or missing macros, are yielded as `None`.
+pub fn read<R: BufRead + 'static>(
+    reader: R,
+    format: Format,
+) -> io::Result<Box<dyn Iterator<Item = io::Result<Option<Product>>>>> {
+    let mut lines = reader.lines();
+    match format {
+        Format::Jsonl => Ok(Box::new(
+            lines.map(|line| line.map(|line| parse_json_line(&line))),
+        )),
+        Format::Csv => {
+            let header = lines.next().transpose()?.unwrap_or_default();
+            let delimiter = if header.contains('\t') { '\t' } else { ',' };
+            let columns = split_fields(&header, delimiter)
+                .into_iter()
+                .enumerate()
+                .map(|(i, name)| (name, i))
+                .collect::<HashMap<_, _>>();
+            Ok(Box::new(lines.map(move |line| {
+                line.map(|line| parse_csv_line(&columns, delimiter, &line))
+            })))
+        }
+    }
+}
+
+fn parse_csv_line(
+    columns: &HashMap<String, usize>,
+    delimiter: char,
+    line: &str,
+) -> Option<Product> {
+    let fields = split_fields(line, delimiter);
+    let field = |name: &str| {
+        columns
+            .get(name)
+            .and_then(|i| fields.get(*i))
+            .map(String::as_str)
+    };
+    product(field("code")?, field("product_name")?, |name| {
+        field(name).and_then(|value| value.trim().parse().ok())
+    })
+}
+
+fn parse_json_line(line: &str) -> Option<Product> {
+    let json: serde_json::Value = serde_json::from_str(line).ok()?;
+    let nutriments = &json["nutriments"];
+    // Values are numbers in recent dumps and strings in older ones.
+    let number = |name: &str| match &nutriments[name] {
+        serde_json::Value::Number(number) => number.as_f64(),
+        serde_json::Value::String(text) => text.trim().parse().ok(),
+        _ => None,
+    };
+    let code = match &json["code"] {
+        serde_json::Value::Number(number) => number.to_string(),
+        value => value.as_str()?.to_string(),
+    };
+    product(&code, json["product_name"].as_str()?, number)
+}
+
+fn product(code: &str, name: &str, number: impl Fn(&str) -> Option<f64>) -> Option<Product> {
+    let value = |name: &str| number(name).filter(|v| v.is_finite() && *v >= 0.0);
+    let name = name.trim();
+    if name.is_empty() {
+        return None;
+    }
+
+    Some(Product {
+        barcode: normalize_barcode(code)?,
+        name: name.to_string(),
+        kcal: value("energy-kcal_100g").or(value("energy_100g").map(|kj| kj / KJ_PER_KCAL))?,
+        fat: value("fat_100g")?,
+        carbs: value("carbohydrates_100g")?,
+        protein: value("proteins_100g")?,
+        nutrients: Nutrients::from_values(
+            NUTRIENT_FIELDS.map(|(name, factor)| value(name).map(|v| v * factor)),
+        ),
+    })
+}
+
+/// Splits a CSV line. Fields may be quoted with `"`, a quote within a quoted
+/// field is written as `""`. Line breaks within fields aren't supported.
+fn split_fields(line: &str, delimiter: char) -> Vec<String> {
+    let mut fields = Vec::new();
+    let mut field = String::new();
+    let mut quoted = false;
+    let mut chars = line.trim_end_matches(['\r', '\n']).chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '"' if quoted && chars.peek() == Some(&'"') => {
+                field.push('"');
+                chars.next();
+            }
+            '"' if quoted => quoted = false,
+            '"' if field.is_empty() => quoted = true,
+            c if c == delimiter && !quoted => fields.push(std::mem::take(&mut field)),
+            c => field.push(c),
+        }
+    }
+    fields.push(field);
+    fields
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn read_all(input: &'static str, format: Format) -> Vec<Option<Product>> {
+        read(io::Cursor::new(input), format)
+            .unwrap()
+            .map(Result::unwrap)
+            .collect()
+    }
+
+    #[test]
+    fn normalize_barcodes() {
+        assert_eq!(
+            normalize_barcode("4006381333931").as_deref(),
+            Some("4006381333931")
+        );
+        assert_eq!(normalize_barcode(" 96385074 ").as_deref(), Some("96385074"));
+        assert_eq!(
+            normalize_barcode("036000291452").as_deref(),
+            Some("0036000291452")
+        );
+        assert_eq!(normalize_barcode("12345"), None);
+        assert_eq!(normalize_barcode("40063813339a1"), None);
+        assert_eq!(normalize_barcode(""), None);
+    }
+
+    #[test]
+    fn read_tab_separated_csv() {
+        let products = read_all(
+            "code\tproduct_name\tenergy-kcal_100g\tfat_100g\tcarbohydrates_100g\tproteins_100g\tfiber_100g\tiron_100g\n\
+             4006381333931\tOats\t370\t7\t59\t13.5\t10\t0.0042\n\
+             4006381333948\tNo macros\t\t\t\t\t\t\n",
+            Format::Csv,
+        );
+
+        assert_eq!(products.len(), 2);
+        let oats = products[0].as_ref().unwrap();
+        assert_eq!(oats.barcode, "4006381333931");
+        assert_eq!(oats.name, "Oats");
+        assert_eq!(oats.kcal, 370.0);
+        assert_eq!(oats.protein, 13.5);
+        assert_eq!(oats.nutrients.fiber, Some(10.0));
+        assert!((oats.nutrients.iron.unwrap() - 4.2).abs() < 1e-9);
+        assert_eq!(oats.nutrients.sugar, None);
+        assert_eq!(products[1], None);
+    }
+
+    #[test]
+    fn read_quoted_csv() {
+        let products = read_all(
+            "code,product_name,energy_100g,fat_100g,carbohydrates_100g,proteins_100g\n\
+             96385074,\"Milk, \"\"whole\"\"\",4184,3.5,4.8,3.4\n",
+            Format::Csv,
+        );
+
+        let milk = products[0].as_ref().unwrap();
+        assert_eq!(milk.name, "Milk, \"whole\"");
+        assert_eq!(milk.kcal, 1000.0);
+    }
+
+    #[test]
+    fn read_jsonl() {
+        let products = read_all(
+            r#"{"code": "036000291452", "product_name": "Beer", "nutriments": {"energy-kcal_100g": 43, "fat_100g": 0, "carbohydrates_100g": "3.6", "proteins_100g": 0.5, "alcohol_100g": 5}}
+{"code": "4006381333931", "nutriments": {}}
+not json"#,
+            Format::Jsonl,
+        );
+
+        assert_eq!(products.len(), 3);
+        let beer = products[0].as_ref().unwrap();
+        assert_eq!(beer.barcode, "0036000291452");
+        assert_eq!(beer.carbs, 3.6);
+        assert!((beer.nutrients.alcohol.unwrap() - 3.945).abs() < 1e-9);
+        assert_eq!(products[1], None);
+        assert_eq!(products[2], None);
+    }
+
+    #[test]
+    fn format_from_path() {
+        assert_eq!(Format::from_path("products.jsonl"), Format::Jsonl);
+        assert_eq!(
+            Format::from_path("en.openfoodfacts.org.products.csv"),
+            Format::Csv
+        );
+    }
+}
@@ -0,0 +1,216 @@
+//! Estimation of the total daily energy expenditure (TDEE) from the logged
+//! intake and the weight trend. Whatever was eaten and didn't show up as a
+//! change of the trend weight must have been used up:
+//!
+//! `TDEE = average intake - trend change per day * KCAL_PER_KG`
+//!
+//! The estimate adapts to the user because it only looks at the last
+//! [`WINDOW_DAYS`] days.
+
+use chrono::NaiveDate;
+
+use crate::trend::{self, TrendPoint};
+
+/// Energy stored in a kilogram of body weight, mostly fat.
+pub const KCAL_PER_KG: f64 = 7700.0;
+
+/// Number of days the estimate is based on.
+pub const WINDOW_DAYS: i64 = 28;
+
+/// Minimum number of days with logged intake within the window.
+pub const MIN_LOGGED_DAYS: usize = 14;
+
+/// Minimum number of days between the first and the last weight within the
+/// window.
+pub const MIN_WEIGHT_DAYS: i64 = 14;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TdeeEstimate {
+    pub tdee: f64,
+    pub average_intake: f64,
+    /// Change of the trend weight in kg per week.
+    pub weekly_change: f64,
+    pub logged_days: usize,
+}
+
+/// Estimates the TDEE for the window that ends on `end` (inclusive).
+///
+/// `intake` holds the total kcal of every logged day and `trend` the smoothed
+/// weights, both in any order. Days without logged intake are left out of the
+/// average instead of counting as zero. Returns `None` if there isn't enough
+/// data in the window.
+pub fn estimate(
+    intake: &[(NaiveDate, f64)],
+    trend: &[TrendPoint],
+    end: NaiveDate,
+) -> Option<TdeeEstimate> {
+    let start = end - chrono::Duration::days(WINDOW_DAYS - 1);
+    let in_window = |date: NaiveDate| start <= date && date <= end;
+
+    let logged = intake
+        .iter()
+        .filter(|(date, _)| in_window(*date))
+        .map(|(_, kcal)| *kcal)
+        .collect::<Vec<_>>();
+    if logged.len() < MIN_LOGGED_DAYS {
+        return None;
+    }
+    let average_intake = logged.iter().sum::<f64>() / logged.len() as f64;
+
+    let mut points = trend
+        .iter()
+        .filter(|p| in_window(p.date))
+        .copied()
+        .collect::<Vec<_>>();
+    points.sort_by_key(|p| p.date);
+    let weight_days = (points.last()?.date - points.first()?.date).num_days();
+    if weight_days < MIN_WEIGHT_DAYS {
+        return None;
+    }
+    let weekly_change = trend::weekly_rate(&points)?;
+
+    Some(TdeeEstimate {
+        tdee: average_intake - weekly_change / 7.0 * KCAL_PER_KG,
+        average_intake,
+        weekly_change,
+        logged_days: logged.len(),
+    })
+}
+
+/// Daily kcal target to change the weight by `weekly_change` kg per week.
+pub fn suggested_target(tdee: f64, weekly_change: f64) -> f64 {
+    tdee + weekly_change / 7.0 * KCAL_PER_KG
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::Datelike;
+
+    use super::*;
+
+    fn date(day: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(2024, 1, day).unwrap()
+    }
+
+    fn assert_close(actual: f64, expected: f64) {
+        assert!(
+            (actual - expected).abs() < 1e-6,
+            "expected {}, got {}",
+            expected,
+            actual
+        );
+    }
+
+    /// Trend points on every day from `first` to `last`, changing linearly by
+    /// `daily_change` kg.
+    fn linear_trend(first: u32, last: u32, daily_change: f64) -> Vec<TrendPoint> {
+        (first..=last)
+            .map(|day| {
+                let weight = 80.0 + daily_change * (day - first) as f64;
+                TrendPoint {
+                    date: date(day),
+                    weight,
+                    trend: weight,
+                }
+            })
+            .collect()
+    }
+
+    fn constant_intake(first: u32, last: u32, kcal: f64) -> Vec<(NaiveDate, f64)> {
+        (first..=last).map(|day| (date(day), kcal)).collect()
+    }
+
+    #[test]
+    fn stable_weight_means_intake_equals_tdee() {
+        let estimate = estimate(
+            &constant_intake(1, 28, 2000.0),
+            &linear_trend(1, 28, 0.0),
+            date(28),
+        )
+        .unwrap();
+
+        assert_close(estimate.tdee, 2000.0);
+        assert_close(estimate.average_intake, 2000.0);
+        assert_close(estimate.weekly_change, 0.0);
+        assert_eq!(estimate.logged_days, 28);
+    }
+
+    #[test]
+    fn weight_loss_adds_deficit() {
+        let estimate = estimate(
+            &constant_intake(1, 28, 2000.0),
+            &linear_trend(1, 28, -0.5 / 7.0),
+            date(28),
+        )
+        .unwrap();
+
+        assert_close(estimate.weekly_change, -0.5);
+        assert_close(estimate.tdee, 2550.0);
+    }
+
+    #[test]
+    fn weight_gain_subtracts_surplus() {
+        let estimate = estimate(
+            &constant_intake(1, 28, 3000.0),
+            &linear_trend(1, 28, 0.25 / 7.0),
+            date(28),
+        )
+        .unwrap();
+
+        assert_close(estimate.tdee, 2725.0);
+    }
+
+    #[test]
+    fn days_without_intake_are_left_out() {
+        let intake = constant_intake(1, 28, 2000.0)
+            .into_iter()
+            .filter(|(date, _)| date.day0() % 2 == 0)
+            .collect::<Vec<_>>();
+        let estimate = estimate(&intake, &linear_trend(1, 28, 0.0), date(28)).unwrap();
+
+        assert_eq!(estimate.logged_days, 14);
+        assert_close(estimate.average_intake, 2000.0);
+    }
+
+    #[test]
+    fn data_outside_of_window_is_ignored() {
+        let mut intake = constant_intake(1, 28, 2000.0);
+        intake.push((date(29), 5000.0));
+        let mut trend = linear_trend(1, 28, 0.0);
+        trend.extend(linear_trend(29, 31, 1.0));
+
+        let estimate = estimate(&intake, &trend, date(28)).unwrap();
+
+        assert_close(estimate.tdee, 2000.0);
+    }
+
+    #[test]
+    fn too_little_intake_gives_no_estimate() {
+        assert_eq!(
+            estimate(
+                &constant_intake(1, 13, 2000.0),
+                &linear_trend(1, 28, 0.0),
+                date(28)
+            ),
+            None
+        );
+    }
+
+    #[test]
+    fn too_few_weights_give_no_estimate() {
+        let intake = constant_intake(1, 28, 2000.0);
+
+        assert_eq!(estimate(&intake, &[], date(28)), None);
+        assert_eq!(
+            estimate(&intake, &linear_trend(20, 28, 0.0), date(28)),
+            None
+        );
+    }
+
+    #[test]
+    fn target_for_weekly_change() {
+        assert_close(suggested_target(2500.0, -0.5), 1950.0);
+        assert_close(suggested_target(2500.0, 0.0), 2500.0);
+        assert_close(suggested_target(2500.0, 0.25), 2775.0);
+    }
+}
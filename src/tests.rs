@@ -0,0 +1,2693 @@
+use axum::body::Body;
+use axum::http::{header, Request, StatusCode};
+use axum::response::Response;
+use axum::Router;
+use http_body_util::BodyExt;
+use sqlx::sqlite::{SqliteConnectOptions, SqlitePoolOptions};
+use sqlx::SqlitePool;
+use tower::ServiceExt;
+
+use crate::csrf;
+use crate::db::{ConsumptionFilter, ConsumptionParams, Db, EntryFlags, FoodParams, Macros, UserId};
+use crate::export::{ImportError, ImportMode};
+use crate::nutrients::Nutrients;
+use crate::products::Product;
+use crate::throttle;
+use crate::trend::WeightRule;
+use crate::{app, AppState};
+
+pub async fn test_db() -> Db {
+    Db::new(test_pool().await)
+}
+
+/// In-memory database with all migrations applied. The single connection is
+/// kept open, as the database is gone once it closes.
+async fn test_pool() -> SqlitePool {
+    let db_pool = SqlitePoolOptions::new()
+        .max_connections(1)
+        .idle_timeout(None)
+        .max_lifetime(None)
+        .connect_with(
+            SqliteConnectOptions::new()
+                .in_memory(true)
+                .foreign_keys(true),
+        )
+        .await
+        .unwrap();
+    sqlx::migrate!("./migrations").run(&db_pool).await.unwrap();
+    db_pool
+}
+
+struct TestApp {
+    router: Router,
+    db: Db,
+    /// For changing rows in ways the app doesn't, e.g. to age sessions.
+    db_pool: SqlitePool,
+}
+
+impl TestApp {
+    async fn new() -> Self {
+        let db_pool = test_pool().await;
+        let db = Db::new(db_pool.clone());
+        let router = app(AppState { db: db.clone() }, "assets");
+        TestApp {
+            router,
+            db,
+            db_pool,
+        }
+    }
+
+    async fn create_user(&self, username: &str) -> UserId {
+        self.db.create_user(username, "password").await.unwrap();
+        self.db.get_user(username).await.unwrap().unwrap().id
+    }
+
+    async fn send(&self, request: Request<Body>) -> Response {
+        self.router.clone().oneshot(request).await.unwrap()
+    }
+
+    async fn get(&self, uri: &str, cookie: &str) -> Response {
+        self.send(
+            Request::get(uri)
+                .header(header::COOKIE, cookie)
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await
+    }
+
+    /// Posts a form with the CSRF token of the session in the cookie.
+    async fn post(&self, uri: &str, cookie: &str, form: &str) -> Response {
+        let form = match cookie.strip_prefix("MATRAFL_SESSION=") {
+            Some(_) => format!("{}&csrf_token={}", form, self.csrf_token(cookie).await),
+            None => form.to_string(),
+        };
+        self.post_without_csrf_token(uri, cookie, &form).await
+    }
+
+    async fn csrf_token(&self, cookie: &str) -> String {
+        let session_id = cookie.strip_prefix("MATRAFL_SESSION=").unwrap();
+        let secret = self.db.get_csrf_secret(session_id).await.unwrap().unwrap();
+        csrf::session_token(&secret)
+    }
+
+    async fn post_without_csrf_token(&self, uri: &str, cookie: &str, form: &str) -> Response {
+        self.send(
+            Request::post(uri)
+                .header(header::COOKIE, cookie)
+                .header(header::CONTENT_TYPE, "application/x-www-form-urlencoded")
+                .body(Body::from(form.to_string()))
+                .unwrap(),
+        )
+        .await
+    }
+
+    /// Sends a JSON request to the API with the token as bearer token.
+    async fn api(&self, method: &str, uri: &str, token: &str, body: Option<&str>) -> Response {
+        let request = Request::builder()
+            .method(method)
+            .uri(uri)
+            .header(header::AUTHORIZATION, format!("Bearer {}", token));
+        let request = match body {
+            Some(body) => request
+                .header(header::CONTENT_TYPE, "application/json")
+                .body(Body::from(body.to_string())),
+            None => request.body(Body::empty()),
+        };
+        self.send(request.unwrap()).await
+    }
+
+    /// Logs in through the login form and returns the session cookie.
+    async fn login(&self, username: &str) -> String {
+        let response = self
+            .post(
+                "/account/login",
+                "",
+                &format!("username={}&password=password", username),
+            )
+            .await;
+        assert_eq!(response.status(), StatusCode::SEE_OTHER);
+        set_cookie(&response).split(';').next().unwrap().to_string()
+    }
+}
+
+fn set_cookie(response: &Response) -> &str {
+    response
+        .headers()
+        .get(header::SET_COOKIE)
+        .expect("no Set-Cookie header")
+        .to_str()
+        .unwrap()
+}
+
+fn location(response: &Response) -> &str {
+    response
+        .headers()
+        .get(header::LOCATION)
+        .expect("no Location header")
+        .to_str()
+        .unwrap()
+}
+
+async fn body_string(response: Response) -> String {
+    let bytes = response.into_body().collect().await.unwrap().to_bytes();
+    String::from_utf8(bytes.to_vec()).unwrap()
+}
+
+fn macros(kcal: f64, fat: f64, carbs: f64, protein: f64) -> Macros {
+    Macros {
+        kcal,
+        fat,
+        carbs,
+        protein,
+    }
+}
+
+fn assert_close(actual: f64, expected: f64) {
+    assert!(
+        (actual - expected).abs() < 1e-9,
+        "expected {}, got {}",
+        expected,
+        actual
+    );
+}
+
+#[tokio::test]
+async fn login_sets_session_cookie() {
+    let app = TestApp::new().await;
+    app.create_user("alice").await;
+
+    let response = app
+        .post("/account/login", "", "username=alice&password=password")
+        .await;
+
+    assert_eq!(response.status(), StatusCode::SEE_OTHER);
+    assert_eq!(location(&response), "/");
+    let cookie = set_cookie(&response);
+    assert!(cookie.starts_with("MATRAFL_SESSION="));
+    assert!(cookie.contains("HttpOnly"));
+    assert!(cookie.contains("SameSite=Lax"));
+    assert!(cookie.contains("Path=/"));
+
+    let session = cookie.split(';').next().unwrap();
+    let response = app.get("/", session).await;
+    assert_eq!(response.status(), StatusCode::OK);
+}
+
+#[tokio::test]
+async fn remember_me_sets_persistent_cookie() {
+    let app = TestApp::new().await;
+    app.create_user("alice").await;
+
+    let response = app
+        .post("/account/login", "", "username=alice&password=password")
+        .await;
+    assert!(!set_cookie(&response).contains("Expires"));
+
+    let response = app
+        .post(
+            "/account/login",
+            "",
+            "username=alice&password=password&remember=true",
+        )
+        .await;
+    assert!(set_cookie(&response).contains("Expires"));
+}
+
+#[tokio::test]
+async fn sessions_expire_after_inactivity() {
+    let app = TestApp::new().await;
+    app.create_user("alice").await;
+    let cookie = app.login("alice").await;
+    let response = app
+        .post(
+            "/account/login",
+            "",
+            "username=alice&password=password&remember=true",
+        )
+        .await;
+    let remembered_cookie = set_cookie(&response).split(';').next().unwrap().to_string();
+
+    let set_idle_days = |days: i64| {
+        sqlx::query("UPDATE sessions SET last_seen_at = ?")
+            .bind(chrono::Utc::now() - chrono::Duration::days(days))
+            .execute(&app.db_pool)
+    };
+
+    // Using a session extends it.
+    set_idle_days(0).await.unwrap();
+    assert_eq!(app.get("/", &cookie).await.status(), StatusCode::OK);
+
+    set_idle_days(2).await.unwrap();
+    let response = app.get("/", &cookie).await;
+    assert_eq!(response.status(), StatusCode::SEE_OTHER);
+    assert_eq!(location(&response), "/account/login");
+    assert_eq!(
+        app.get("/", &remembered_cookie).await.status(),
+        StatusCode::OK
+    );
+
+    set_idle_days(31).await.unwrap();
+    let response = app.get("/", &remembered_cookie).await;
+    assert_eq!(response.status(), StatusCode::SEE_OTHER);
+
+    app.db.delete_expired_sessions().await.unwrap();
+    let count: i64 = sqlx::query_scalar("SELECT count(*) FROM sessions")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(count, 0);
+}
+
+#[tokio::test]
+async fn session_cookie_is_rotated() {
+    let app = TestApp::new().await;
+    app.create_user("alice").await;
+    let cookie = app.login("alice").await;
+    let token = app.csrf_token(&cookie).await;
+
+    // Posts don't rotate.
+    sqlx::query("UPDATE sessions SET created_at = ?")
+        .bind(chrono::Utc::now() - chrono::Duration::hours(25))
+        .execute(&app.db_pool)
+        .await
+        .unwrap();
+    let response = app
+        .post("/weights", &cookie, "measured_at=2024-01-01&weight=80")
+        .await;
+    assert_eq!(response.status(), StatusCode::SEE_OTHER);
+    assert!(response.headers().get(header::SET_COOKIE).is_none());
+
+    let response = app.get("/weights", &cookie).await;
+    assert_eq!(response.status(), StatusCode::OK);
+    let new_cookie = set_cookie(&response).split(';').next().unwrap().to_string();
+    assert_ne!(new_cookie, cookie);
+    assert!(body_string(response).await.contains(&token));
+
+    // Forms rendered before the rotation can still be posted.
+    let response = app
+        .post_without_csrf_token(
+            "/weights",
+            &new_cookie,
+            &format!("measured_at=2024-01-02&weight=81&csrf_token={}", token),
+        )
+        .await;
+    assert_eq!(response.status(), StatusCode::SEE_OTHER);
+
+    // The previous id works until requests with it should have arrived.
+    let response = app.get("/weights", &cookie).await;
+    assert_eq!(response.status(), StatusCode::OK);
+    assert!(response.headers().get(header::SET_COOKIE).is_none());
+    sqlx::query("UPDATE sessions SET rotated_at = ?")
+        .bind(chrono::Utc::now() - chrono::Duration::minutes(2))
+        .execute(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(
+        app.get("/weights", &cookie).await.status(),
+        StatusCode::SEE_OTHER
+    );
+
+    let response = app.get("/weights", &new_cookie).await;
+    assert_eq!(response.status(), StatusCode::OK);
+    assert!(response.headers().get(header::SET_COOKIE).is_none());
+}
+
+#[tokio::test]
+async fn login_with_wrong_password_fails() {
+    let app = TestApp::new().await;
+    app.create_user("alice").await;
+
+    for form in [
+        "username=alice&password=wrong",
+        "username=bob&password=password",
+    ] {
+        let response = app.post("/account/login", "", form).await;
+
+        assert_eq!(response.status(), StatusCode::OK);
+        assert!(response.headers().get(header::SET_COOKIE).is_none());
+        assert!(body_string(response)
+            .await
+            .contains("Invalid username or password."));
+    }
+}
+
+#[tokio::test]
+async fn repeated_login_failures_are_throttled() {
+    let app = TestApp::new().await;
+    app.create_user("alice").await;
+
+    for _ in 0..throttle::USERNAME_FREE_FAILURES {
+        let response = app
+            .post("/account/login", "", "username=alice&password=wrong")
+            .await;
+        assert_eq!(response.status(), StatusCode::OK);
+    }
+
+    // Locked even with the right password.
+    let response = app
+        .post("/account/login", "", "username=alice&password=password")
+        .await;
+    assert_eq!(response.status(), StatusCode::TOO_MANY_REQUESTS);
+    assert!(response.headers().get(header::SET_COOKIE).is_none());
+    assert!(body_string(response)
+        .await
+        .contains("Too many failed logins. Please try again in 1 minute."));
+
+    // Other usernames aren't affected.
+    app.create_user("bob").await;
+    app.login("bob").await;
+}
+
+#[tokio::test]
+async fn login_failures_from_one_ip_are_throttled() {
+    let app = TestApp::new().await;
+    app.create_user("alice").await;
+    let login = |username: &str, forwarded_for: &str| {
+        Request::post("/account/login")
+            .header(header::CONTENT_TYPE, "application/x-www-form-urlencoded")
+            .header("x-forwarded-for", forwarded_for)
+            .body(Body::from(format!(
+                "username={}&password=password",
+                username
+            )))
+            .unwrap()
+    };
+
+    for i in 0..throttle::IP_FREE_FAILURES {
+        let response = app
+            .send(login(&format!("user{}", i), "192.0.2.1, 198.51.100.1"))
+            .await;
+        assert_eq!(response.status(), StatusCode::OK);
+    }
+
+    // Only the last entry, which was added by the proxy, counts.
+    let response = app.send(login("alice", "192.0.2.2, 198.51.100.1")).await;
+    assert_eq!(response.status(), StatusCode::TOO_MANY_REQUESTS);
+    let response = app.send(login("alice", "198.51.100.2")).await;
+    assert_eq!(response.status(), StatusCode::SEE_OTHER);
+}
+
+#[tokio::test]
+async fn successful_login_resets_failures() {
+    let app = TestApp::new().await;
+    app.create_user("alice").await;
+
+    for _ in 0..2 {
+        for _ in 1..throttle::USERNAME_FREE_FAILURES {
+            let response = app
+                .post("/account/login", "", "username=alice&password=wrong")
+                .await;
+            assert_eq!(response.status(), StatusCode::OK);
+        }
+        app.login("alice").await;
+    }
+}
+
+#[tokio::test]
+async fn forms_require_csrf_token() {
+    let app = TestApp::new().await;
+    app.create_user("alice").await;
+    let cookie = app.login("alice").await;
+    let token = app.csrf_token(&cookie).await;
+
+    let body = body_string(app.get("/account", &cookie).await).await;
+    assert!(body.contains(&format!(r#"name="csrf_token" value="{}""#, token)));
+
+    for form in [
+        "measured_at=2024-01-01&weight=80".to_string(),
+        "measured_at=2024-01-01&weight=80&csrf_token=wrong".to_string(),
+        format!(
+            "measured_at=2024-01-01&weight=80&csrf_token={}",
+            csrf::session_token("other")
+        ),
+    ] {
+        let response = app
+            .post_without_csrf_token("/weights", &cookie, &form)
+            .await;
+        assert_eq!(response.status(), StatusCode::FORBIDDEN);
+        assert!(body_string(response)
+            .await
+            .contains("The form has expired or was sent from another site."));
+    }
+    let response = app
+        .post_without_csrf_token("/account/logout", &cookie, "")
+        .await;
+    assert_eq!(response.status(), StatusCode::FORBIDDEN);
+
+    let response = app
+        .post("/weights", &cookie, "measured_at=2024-01-01&weight=80")
+        .await;
+    assert_eq!(response.status(), StatusCode::SEE_OTHER);
+}
+
+#[tokio::test]
+async fn import_requires_csrf_token() {
+    let app = TestApp::new().await;
+    app.create_user("alice").await;
+    let cookie = app.login("alice").await;
+    let token = app.csrf_token(&cookie).await;
+    let export = body_string(app.post("/account/export", &cookie, "").await).await;
+    let import = |token: &str| {
+        let body = format!(
+            "--boundary\r\n\
+             Content-Disposition: form-data; name=\"csrf_token\"\r\n\r\n\
+             {token}\r\n\
+             --boundary\r\n\
+             Content-Disposition: form-data; name=\"mode\"\r\n\r\n\
+             merge\r\n\
+             --boundary\r\n\
+             Content-Disposition: form-data; name=\"file\"; filename=\"export.json\"\r\n\
+             Content-Type: application/json\r\n\r\n\
+             {export}\r\n\
+             --boundary--\r\n"
+        );
+        Request::post("/account/import")
+            .header(header::COOKIE, &cookie)
+            .header(
+                header::CONTENT_TYPE,
+                "multipart/form-data; boundary=boundary",
+            )
+            .body(Body::from(body))
+            .unwrap()
+    };
+
+    let response = app.send(import("wrong")).await;
+    assert_eq!(response.status(), StatusCode::FORBIDDEN);
+
+    let response = app.send(import(&token)).await;
+    assert_eq!(response.status(), StatusCode::SEE_OTHER);
+    assert_eq!(location(&response), "/account");
+}
+
+#[tokio::test]
+async fn errors_have_pages_with_request_id() {
+    let app = TestApp::new().await;
+    let alice = app.create_user("alice").await;
+    app.create_user("bob").await;
+    let session = app.login("bob").await;
+    let food_id = app
+        .db
+        .add_food(
+            &alice,
+            FoodParams {
+                name: "Oats",
+                macros: macros(370.0, 7.0, 59.0, 13.5),
+                nutrients: Nutrients::default(),
+                unit: "g",
+                basis: 100.0,
+                barcode: None,
+                flags: EntryFlags::default(),
+            },
+        )
+        .await
+        .unwrap();
+
+    for (uri, status, message) in [
+        (
+            "/foods/unknown".to_string(),
+            StatusCode::NOT_FOUND,
+            "This page doesn't exist.",
+        ),
+        (
+            "/unknown".to_string(),
+            StatusCode::NOT_FOUND,
+            "This page doesn't exist.",
+        ),
+        (
+            format!("/foods/{}", food_id),
+            StatusCode::FORBIDDEN,
+            "This belongs to another user.",
+        ),
+    ] {
+        let response = app.get(&uri, &session).await;
+        assert_eq!(response.status(), status, "GET {}", uri);
+        let request_id = response.headers()["x-request-id"]
+            .to_str()
+            .unwrap()
+            .to_string();
+        let body = body_string(response).await;
+        assert!(body.contains(message), "GET {}", uri);
+        assert!(body.contains(&request_id), "GET {}", uri);
+    }
+
+    let response = app
+        .post(
+            "/consumptions",
+            &session,
+            "consumable_id=x&consumable_type=drink&quantity=1&consumed_at=2024-01-01",
+        )
+        .await;
+    assert_eq!(response.status(), StatusCode::UNPROCESSABLE_ENTITY);
+    assert!(body_string(response)
+        .await
+        .contains("Please choose a food or a recipe."));
+
+    // The API answers with JSON.
+    let response = app.get("/api/v1/unknown", "").await;
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    assert!(response.headers().contains_key("x-request-id"));
+}
+
+#[tokio::test]
+async fn invalid_food_form_is_shown_again() {
+    let app = TestApp::new().await;
+    app.create_user("alice").await;
+    let session = app.login("alice").await;
+    let response = app
+        .post(
+            "/foods",
+            &session,
+            "name=Oats&kcal=370&fat=7&carbs=59&protein=13.5&unit=g&basis=100&fiber=10",
+        )
+        .await;
+    assert_eq!(response.status(), StatusCode::SEE_OTHER);
+    let food = app
+        .db
+        .get_foods(&app.db.get_user("alice").await.unwrap().unwrap().id)
+        .await
+        .unwrap()
+        .remove(0);
+
+    let response = app
+        .post(
+            &format!("/foods/{}", food.id),
+            &session,
+            "name=Rolled+oats&kcal=lots&fat=7&carbs=59&protein=13.5&unit=g&basis=100&fiber=9&starred=true",
+        )
+        .await;
+    assert_eq!(response.status(), StatusCode::UNPROCESSABLE_ENTITY);
+    let body = body_string(response).await;
+    assert!(body.contains("Some values are missing or invalid."));
+    assert!(body.contains(r#"value="Rolled oats""#));
+    assert!(body.contains(r#"value="lots""#));
+    assert!(body.contains(r#"name="fiber" value="9""#));
+    assert!(body.contains(r#"name="starred" checked"#));
+    assert_eq!(app.db.get_food(&food.id).await.unwrap().name, "Oats");
+
+    let response = app
+        .post(
+            "/foods",
+            &session,
+            "name=Milk&kcal=64&fat=3.5&carbs=4.8&protein=3.4&unit=gallon&basis=100",
+        )
+        .await;
+    assert_eq!(response.status(), StatusCode::UNPROCESSABLE_ENTITY);
+    let body = body_string(response).await;
+    assert!(body.contains("Please choose one of the units."));
+    assert!(body.contains(r#"value="Milk""#));
+    // The create form is open.
+    assert!(body.contains(r#"class="food" autocomplete="off""#));
+
+    // Bases and serving amounts have to be positive.
+    let response = app
+        .post(
+            "/foods",
+            &session,
+            "name=Milk&kcal=64&fat=3.5&carbs=4.8&protein=3.4&unit=ml&basis=0",
+        )
+        .await;
+    assert_eq!(response.status(), StatusCode::UNPROCESSABLE_ENTITY);
+    assert!(body_string(response)
+        .await
+        .contains("The basis and serving amounts must be greater than zero."));
+    let response = app
+        .post(
+            &format!("/foods/{}/servings", food.id),
+            &session,
+            "name=Cup&amount=-80",
+        )
+        .await;
+    assert_eq!(response.status(), StatusCode::UNPROCESSABLE_ENTITY);
+    assert!(app.db.get_servings(&food.id).await.unwrap().is_empty());
+
+    let token = app
+        .db
+        .create_api_token(&food.user_id, "Phone")
+        .await
+        .unwrap();
+    let response = app
+        .api(
+            "POST",
+            "/api/v1/foods",
+            &token,
+            Some(r#"{"name": "Milk", "kcal": 64, "fat": 3.5, "carbs": 4.8, "protein": 3.4, "basis": -1}"#),
+        )
+        .await;
+    assert_eq!(response.status(), StatusCode::UNPROCESSABLE_ENTITY);
+    assert_eq!(
+        body_string(response).await,
+        r#"{"error":"The basis and serving amounts must be greater than zero."}"#
+    );
+    let response = app
+        .api(
+            "POST",
+            &format!("/api/v1/foods/{}/servings", food.id),
+            &token,
+            Some(r#"{"name": "Cup", "amount": 0}"#),
+        )
+        .await;
+    assert_eq!(response.status(), StatusCode::UNPROCESSABLE_ENTITY);
+    assert_eq!(app.db.get_foods(&food.user_id).await.unwrap().len(), 1);
+}
+
+#[tokio::test]
+async fn invalid_forms_are_shown_again() {
+    let app = TestApp::new().await;
+    let alice = app.create_user("alice").await;
+    let session = app.login("alice").await;
+
+    let response = app
+        .post(
+            "/weights",
+            &session,
+            "weight=heavy&measured_at=2024-01-02&measured_time=07:30",
+        )
+        .await;
+    assert_eq!(response.status(), StatusCode::UNPROCESSABLE_ENTITY);
+    assert!(response.headers().contains_key("x-request-id"));
+    let body = body_string(response).await;
+    assert!(body.contains("Some values are missing or invalid."));
+    assert!(body.contains(r#"value="heavy""#));
+    assert!(body.contains(r#"value="2024-01-02""#));
+    assert!(body.contains(r#"value="07:30""#));
+    assert!(app.db.get_weights(&alice).await.unwrap().is_empty());
+
+    // The weight form of a day summary is shown again on that day.
+    let response = app
+        .post(
+            "/weights",
+            &session,
+            "weight=&measured_at=2024-01-03&redirect_to=DaySummary",
+        )
+        .await;
+    assert_eq!(response.status(), StatusCode::UNPROCESSABLE_ENTITY);
+    assert!(body_string(response)
+        .await
+        .contains("<span>2024-01-03</span>"));
+
+    // Consumptions are shown again on their day, with the form open.
+    let response = app
+        .post(
+            "/consumptions",
+            &session,
+            "consumable_id=x&consumable_type=food&quantity=&consumed_at=2024-01-04&meal_slot_id=",
+        )
+        .await;
+    assert_eq!(response.status(), StatusCode::UNPROCESSABLE_ENTITY);
+    let body = body_string(response).await;
+    assert!(body.contains("<span>2024-01-04</span>"));
+    assert!(body.contains("data-add-consumption-form>"));
+    assert!(body.contains(r#"value="2024-01-04""#));
+
+    let response = app
+        .post("/account/meal-slots", &session, "position=1")
+        .await;
+    assert_eq!(response.status(), StatusCode::UNPROCESSABLE_ENTITY);
+    assert!(body_string(response)
+        .await
+        .contains("Some values are missing or invalid."));
+}
+
+#[tokio::test]
+async fn logout_clears_session() {
+    let app = TestApp::new().await;
+    app.create_user("alice").await;
+    let session = app.login("alice").await;
+
+    let response = app.post("/account/logout", &session, "").await;
+
+    assert_eq!(response.status(), StatusCode::SEE_OTHER);
+    let cookie = set_cookie(&response);
+    assert!(cookie.starts_with("MATRAFL_SESSION=;"));
+    assert!(cookie.contains("Expires="));
+
+    // The old session id must not work anymore, even if a client keeps it.
+    let response = app.get("/", &session).await;
+    assert_eq!(response.status(), StatusCode::SEE_OTHER);
+    assert_eq!(location(&response), "/account/login");
+}
+
+#[tokio::test]
+async fn pages_require_session() {
+    let app = TestApp::new().await;
+
+    for uri in ["/", "/weights", "/foods", "/recipes", "/account"] {
+        let response = app.get(uri, "").await;
+        assert_eq!(response.status(), StatusCode::SEE_OTHER, "{}", uri);
+        assert_eq!(location(&response), "/account/login", "{}", uri);
+
+        let response = app.get(uri, "MATRAFL_SESSION=unknown").await;
+        assert_eq!(response.status(), StatusCode::SEE_OTHER, "{}", uri);
+        assert_eq!(location(&response), "/account/login", "{}", uri);
+    }
+}
+
+#[tokio::test]
+async fn other_users_entries_are_forbidden() {
+    let app = TestApp::new().await;
+    let alice = app.create_user("alice").await;
+    let bob = app.create_user("bob").await;
+    let bob_session = app.login("bob").await;
+
+    let date = chrono::NaiveDate::from_ymd_opt(2024, 1, 1).unwrap();
+    let weight_id = app.db.add_weight(&alice, 80.0, &date, None).await.unwrap();
+    let food_id = app
+        .db
+        .add_food(
+            &alice,
+            FoodParams {
+                name: "Oats",
+                macros: macros(3.7, 0.07, 0.6, 0.13),
+                nutrients: Nutrients::default(),
+                unit: "g",
+                basis: 1.0,
+                barcode: None,
+                flags: EntryFlags::default(),
+            },
+        )
+        .await
+        .unwrap();
+    let recipe_id = app
+        .db
+        .add_recipe(&alice, "Porridge", 2.0, EntryFlags::default())
+        .await
+        .unwrap();
+    let ingredient_id = app
+        .db
+        .add_ingredient(&alice, &recipe_id, Some(&food_id), None, 100.0, None)
+        .await
+        .unwrap();
+    let consumption_id = app
+        .db
+        .add_consumption(
+            &alice,
+            ConsumptionParams {
+                food_id: Some(&food_id),
+                recipe_id: None,
+                quantity: 50.0,
+                serving_id: None,
+                consumed_at: date,
+                consumed_time: None,
+                meal_slot_id: None,
+            },
+        )
+        .await
+        .unwrap();
+    let meal_slot_id = app.db.get_meal_slots(&alice).await.unwrap()[0].id.clone();
+    app.db.create_api_token(&alice, "phone").await.unwrap();
+    let api_token_id = app.db.get_api_tokens(&alice).await.unwrap()[0].id.clone();
+
+    let bob_food_id = app
+        .db
+        .add_food(
+            &bob,
+            FoodParams {
+                name: "Milk",
+                macros: macros(0.5, 0.03, 0.05, 0.035),
+                nutrients: Nutrients::default(),
+                unit: "g",
+                basis: 1.0,
+                barcode: None,
+                flags: EntryFlags::default(),
+            },
+        )
+        .await
+        .unwrap();
+    let bob_consumable = format!("consumable_id={}&consumable_type=food", bob_food_id);
+
+    let reads = [
+        format!("/weights/{}", weight_id),
+        format!("/foods/{}", food_id),
+        format!("/recipes/{}", recipe_id),
+        format!("/ingredients/{}", ingredient_id),
+        format!("/consumptions/{}", consumption_id),
+    ];
+    for uri in &reads {
+        let response = app.get(uri, &bob_session).await;
+        assert_eq!(response.status(), StatusCode::FORBIDDEN, "GET {}", uri);
+    }
+
+    let writes = [
+        (
+            format!("/weights/{}", weight_id),
+            "weight=1&measured_at=2024-01-02".to_string(),
+        ),
+        (format!("/weights/{}/delete", weight_id), String::new()),
+        (
+            format!("/foods/{}", food_id),
+            "name=Mine&kcal=1&fat=1&carbs=1&protein=1&unit=g&basis=1".to_string(),
+        ),
+        (format!("/foods/{}/delete", food_id), String::new()),
+        (
+            format!("/recipes/{}", recipe_id),
+            "name=Mine&quantity=1".to_string(),
+        ),
+        (format!("/recipes/{}/delete", recipe_id), String::new()),
+        (
+            format!("/ingredients/{}", ingredient_id),
+            format!("{}&quantity=1", bob_consumable),
+        ),
+        (
+            format!("/ingredients/{}/delete", ingredient_id),
+            String::new(),
+        ),
+        (
+            format!("/consumptions/{}", consumption_id),
+            format!("{}&quantity=1&consumed_at=2024-01-02", bob_consumable),
+        ),
+        (
+            format!("/consumptions/{}/delete", consumption_id),
+            String::new(),
+        ),
+        (
+            format!("/account/meal-slots/{}", meal_slot_id),
+            "name=Mine&position=1".to_string(),
+        ),
+        (
+            format!("/account/meal-slots/{}/delete", meal_slot_id),
+            String::new(),
+        ),
+        (
+            format!("/account/api-tokens/{}/delete", api_token_id),
+            String::new(),
+        ),
+        // Bob's own entries must not reference Alice's entries either.
+        (
+            "/consumptions".to_string(),
+            format!(
+                "consumable_id={}&consumable_type=food&quantity=1&consumed_at=2024-01-02",
+                food_id
+            ),
+        ),
+        (
+            "/consumptions".to_string(),
+            format!(
+                "{}&quantity=1&consumed_at=2024-01-02&meal_slot_id={}",
+                bob_consumable, meal_slot_id
+            ),
+        ),
+        (
+            "/ingredients".to_string(),
+            format!("recipe_id={}&{}&quantity=1", recipe_id, bob_consumable),
+        ),
+    ];
+    for (uri, form) in &writes {
+        let response = app.post(uri, &bob_session, form).await;
+        assert_eq!(response.status(), StatusCode::FORBIDDEN, "POST {}", uri);
+    }
+
+    // Nothing of Alice's data was changed or deleted.
+    let weight = app.db.get_weight(&weight_id).await.unwrap();
+    assert_eq!(weight.weight, 80.0);
+    assert_eq!(app.db.get_food(&food_id).await.unwrap().name, "Oats");
+    let recipe = app.db.get_recipe(&recipe_id).await.unwrap();
+    assert_eq!(recipe.name, "Porridge");
+    assert_eq!(recipe.quantity, 2.0);
+    let ingredient = app.db.get_ingredient(&ingredient_id).await.unwrap();
+    assert_eq!(ingredient.consumable_id, food_id);
+    assert_eq!(ingredient.quantity, 100.0);
+    let consumption = app.db.get_consumption(&consumption_id).await.unwrap();
+    assert_eq!(consumption.consumable_id, food_id);
+    assert_eq!(consumption.quantity, 50.0);
+    assert_eq!(
+        app.db.get_meal_slot(&meal_slot_id).await.unwrap().name,
+        "Breakfast"
+    );
+    assert_eq!(app.db.get_api_tokens(&alice).await.unwrap().len(), 1);
+    let bob_consumptions = app
+        .db
+        .get_consumptions(&bob, ConsumptionFilter::None)
+        .await
+        .unwrap();
+    assert!(bob_consumptions.is_empty());
+}
+
+#[tokio::test]
+async fn api_requires_a_token_of_the_owner() {
+    let app = TestApp::new().await;
+    let alice = app.create_user("alice").await;
+    let bob = app.create_user("bob").await;
+    let session = app.login("alice").await;
+    let token = app.db.create_api_token(&alice, "Phone").await.unwrap();
+    let bobs_token = app.db.create_api_token(&bob, "Phone").await.unwrap();
+    let weight = app
+        .db
+        .add_weight(&alice, 80.0, &"2024-01-01".parse().unwrap(), None)
+        .await
+        .unwrap();
+    let uri = format!("/api/v1/weights/{}", weight);
+    let body = r#"{"weight": 79.5, "measured_at": "2024-01-01"}"#;
+
+    let response = app.api("GET", &uri, &token, None).await;
+    assert_eq!(response.status(), StatusCode::OK);
+    assert!(body_string(response).await.contains("80"));
+
+    for token in ["", "wrong"] {
+        let response = app.api("GET", &uri, token, None).await;
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+        assert_eq!(response.headers()[header::WWW_AUTHENTICATE], "Bearer");
+        assert_eq!(
+            body_string(response).await,
+            r#"{"error":"The API token is missing or invalid."}"#
+        );
+    }
+    // Session cookies aren't enough.
+    let response = app.get("/api/v1/weights", &session).await;
+    assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+
+    for (method, body) in [("GET", None), ("PUT", Some(body)), ("DELETE", None)] {
+        let response = app.api(method, &uri, &bobs_token, body).await;
+        assert_eq!(response.status(), StatusCode::FORBIDDEN, "{}", method);
+        assert_eq!(
+            body_string(response).await,
+            r#"{"error":"This belongs to another user."}"#
+        );
+    }
+    assert_eq!(app.db.get_weight(&weight).await.unwrap().weight, 80.0);
+
+    // Rejected bodies and paths are JSON errors as well.
+    let response = app.api("PUT", &uri, &token, Some("{")).await;
+    assert_eq!(response.status(), StatusCode::UNPROCESSABLE_ENTITY);
+    assert_eq!(
+        body_string(response).await,
+        r#"{"error":"Some values are missing or invalid."}"#
+    );
+    let response = app
+        .api("GET", "/api/v1/days/2024-13-01", &token, None)
+        .await;
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    assert!(body_string(response).await.starts_with(r#"{"error":"#));
+
+    let response = app.api("PUT", &uri, &token, Some(body)).await;
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(app.db.get_weight(&weight).await.unwrap().weight, 79.5);
+
+    // Revoked tokens stop working.
+    let id = app.db.get_api_tokens(&alice).await.unwrap().remove(0).id;
+    let response = app
+        .post(&format!("/account/api-tokens/{}/delete", id), &session, "")
+        .await;
+    assert_eq!(response.status(), StatusCode::SEE_OTHER);
+    let response = app.api("GET", &uri, &token, None).await;
+    assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+    let response = app.api("GET", &uri, &bobs_token, None).await;
+    assert_eq!(response.status(), StatusCode::FORBIDDEN);
+}
+
+#[tokio::test]
+async fn consumptions_include_recipe_macros() {
+    let app = TestApp::new().await;
+    let alice = app.create_user("alice").await;
+    let session = app.login("alice").await;
+    let date = chrono::NaiveDate::from_ymd_opt(2024, 1, 1).unwrap();
+
+    let oats = app
+        .db
+        .add_food(
+            &alice,
+            FoodParams {
+                name: "Oats",
+                macros: macros(3.7, 0.07, 0.6, 0.13),
+                nutrients: Nutrients::default(),
+                unit: "g",
+                basis: 1.0,
+                barcode: None,
+                flags: EntryFlags::default(),
+            },
+        )
+        .await
+        .unwrap();
+    let milk = app
+        .db
+        .add_food(
+            &alice,
+            FoodParams {
+                name: "Milk",
+                macros: macros(0.5, 0.03, 0.05, 0.035),
+                nutrients: Nutrients::default(),
+                unit: "g",
+                basis: 1.0,
+                barcode: None,
+                flags: EntryFlags::default(),
+            },
+        )
+        .await
+        .unwrap();
+
+    // Two portions of porridge: 370 + 150 kcal in total.
+    let porridge = app
+        .db
+        .add_recipe(&alice, "Porridge", 2.0, EntryFlags::default())
+        .await
+        .unwrap();
+    app.db
+        .add_ingredient(&alice, &porridge, Some(&oats), None, 100.0, None)
+        .await
+        .unwrap();
+    app.db
+        .add_ingredient(&alice, &porridge, Some(&milk), None, 300.0, None)
+        .await
+        .unwrap();
+
+    // One bowl: a portion of porridge (260 kcal) and 50 g oats (185 kcal).
+    let bowl = app
+        .db
+        .add_recipe(&alice, "Bowl", 1.0, EntryFlags::default())
+        .await
+        .unwrap();
+    app.db
+        .add_ingredient(&alice, &bowl, None, Some(&porridge), 1.0, None)
+        .await
+        .unwrap();
+    app.db
+        .add_ingredient(&alice, &bowl, Some(&oats), None, 50.0, None)
+        .await
+        .unwrap();
+
+    let recipe = app.db.get_recipe(&porridge).await.unwrap();
+    assert_close(recipe.kcal, 520.0);
+    assert_close(recipe.protein, 13.0 + 10.5);
+    let recipe = app.db.get_recipe(&bowl).await.unwrap();
+    assert_close(recipe.kcal, 445.0);
+
+    let porridge_consumption = app
+        .db
+        .add_consumption(
+            &alice,
+            ConsumptionParams {
+                food_id: None,
+                recipe_id: Some(&porridge),
+                quantity: 1.5,
+                serving_id: None,
+                consumed_at: date,
+                consumed_time: None,
+                meal_slot_id: None,
+            },
+        )
+        .await
+        .unwrap();
+    let bowl_consumption = app
+        .db
+        .add_consumption(
+            &alice,
+            ConsumptionParams {
+                food_id: None,
+                recipe_id: Some(&bowl),
+                quantity: 1.0,
+                serving_id: None,
+                consumed_at: date,
+                consumed_time: None,
+                meal_slot_id: None,
+            },
+        )
+        .await
+        .unwrap();
+    let milk_consumption = app
+        .db
+        .add_consumption(
+            &alice,
+            ConsumptionParams {
+                food_id: Some(&milk),
+                recipe_id: None,
+                quantity: 200.0,
+                serving_id: None,
+                consumed_at: date,
+                consumed_time: None,
+                meal_slot_id: None,
+            },
+        )
+        .await
+        .unwrap();
+
+    let consumptions = app
+        .db
+        .get_consumptions(&alice, ConsumptionFilter::ConsumedAt(date))
+        .await
+        .unwrap();
+    assert_eq!(consumptions.len(), 3);
+    let kcal = |id: &str| consumptions.iter().find(|c| c.id == id).unwrap().kcal;
+    assert_close(kcal(&porridge_consumption), 390.0);
+    assert_close(kcal(&bowl_consumption), 445.0);
+    assert_close(kcal(&milk_consumption), 100.0);
+
+    let carbs = consumptions.iter().map(|c| c.carbs).sum::<f64>();
+    assert_close(carbs, (60.0 + 15.0) / 2.0 * 1.5 + (37.5 + 30.0) + 10.0);
+
+    let response = app.get("/days/2024-01-01", &session).await;
+    assert_eq!(response.status(), StatusCode::OK);
+    assert!(body_string(response).await.contains("<span>935</span>"));
+}
+
+#[tokio::test]
+async fn consumptions_convert_servings() {
+    let app = TestApp::new().await;
+    let alice = app.create_user("alice").await;
+    let session = app.login("alice").await;
+    let date = chrono::NaiveDate::from_ymd_opt(2024, 1, 1).unwrap();
+
+    let bread = app
+        .db
+        .add_food(
+            &alice,
+            FoodParams {
+                name: "Bread",
+                macros: macros(250.0, 3.0, 48.0, 9.0),
+                nutrients: Nutrients::default(),
+                unit: "g",
+                basis: 100.0,
+                barcode: None,
+                flags: EntryFlags::default(),
+            },
+        )
+        .await
+        .unwrap();
+    let slice = app
+        .db
+        .add_serving(&alice, &bread, "Slice", 40.0)
+        .await
+        .unwrap();
+    let eggs = app
+        .db
+        .add_food(
+            &alice,
+            FoodParams {
+                name: "Egg",
+                macros: macros(80.0, 5.5, 0.5, 7.0),
+                nutrients: Nutrients::default(),
+                unit: "piece",
+                basis: 1.0,
+                barcode: None,
+                flags: EntryFlags::default(),
+            },
+        )
+        .await
+        .unwrap();
+
+    let response = app
+        .post(
+            "/consumptions",
+            &session,
+            &format!(
+                "consumable_id={}&consumable_type=food&quantity=2&serving_id={}&consumed_at=2024-01-01",
+                bread, slice
+            ),
+        )
+        .await;
+    assert_eq!(response.status(), StatusCode::SEE_OTHER);
+    let grams = app
+        .db
+        .add_consumption(
+            &alice,
+            ConsumptionParams {
+                food_id: Some(&bread),
+                recipe_id: None,
+                quantity: 50.0,
+                serving_id: None,
+                consumed_at: date,
+                consumed_time: None,
+                meal_slot_id: None,
+            },
+        )
+        .await
+        .unwrap();
+    app.db
+        .add_consumption(
+            &alice,
+            ConsumptionParams {
+                food_id: Some(&eggs),
+                recipe_id: None,
+                quantity: 2.0,
+                serving_id: None,
+                consumed_at: date,
+                consumed_time: None,
+                meal_slot_id: None,
+            },
+        )
+        .await
+        .unwrap();
+
+    // A serving of another food is rejected.
+    let response = app
+        .post(
+            "/consumptions",
+            &session,
+            &format!(
+                "consumable_id={}&consumable_type=food&quantity=1&serving_id={}&consumed_at=2024-01-01",
+                eggs, slice
+            ),
+        )
+        .await;
+    assert_eq!(response.status(), StatusCode::UNPROCESSABLE_ENTITY);
+
+    let sandwich = app
+        .db
+        .add_recipe(&alice, "Sandwich", 1.0, EntryFlags::default())
+        .await
+        .unwrap();
+    app.db
+        .add_ingredient(&alice, &sandwich, Some(&bread), None, 2.0, Some(&slice))
+        .await
+        .unwrap();
+    app.db
+        .add_ingredient(&alice, &sandwich, Some(&eggs), None, 1.0, None)
+        .await
+        .unwrap();
+    assert_close(app.db.get_recipe(&sandwich).await.unwrap().kcal, 280.0);
+
+    let consumptions = app
+        .db
+        .get_consumptions(&alice, ConsumptionFilter::ConsumedAt(date))
+        .await
+        .unwrap();
+    let slices = consumptions
+        .iter()
+        .find(|c| c.serving_id.as_deref() == Some(&slice))
+        .unwrap();
+    assert_close(slices.kcal, 200.0);
+    assert_eq!(slices.unit.as_deref(), Some("Slice"));
+    let kcal = |id: &str| consumptions.iter().find(|c| c.id == id).unwrap().kcal;
+    assert_close(kcal(&grams), 125.0);
+    assert_close(consumptions.iter().map(|c| c.kcal).sum::<f64>(), 485.0);
+
+    // Deleting the serving keeps the macros by converting to grams.
+    app.db.delete_serving(&slice).await.unwrap();
+    let consumption = app.db.get_consumption(&slices.id).await.unwrap();
+    assert_eq!(consumption.serving_id, None);
+    assert_close(consumption.quantity, 80.0);
+    assert_close(consumption.kcal, 200.0);
+    assert_close(app.db.get_recipe(&sandwich).await.unwrap().kcal, 280.0);
+}
+
+#[tokio::test]
+async fn nutrients_are_aggregated() {
+    let app = TestApp::new().await;
+    let alice = app.create_user("alice").await;
+    let session = app.login("alice").await;
+    let date = chrono::NaiveDate::from_ymd_opt(2024, 1, 1).unwrap();
+
+    let response = app
+        .post(
+            "/foods",
+            &session,
+            "name=Bread&kcal=250&fat=3&carbs=48&protein=9&unit=g&basis=100&fiber=6&salt=1.2&sugar=",
+        )
+        .await;
+    assert_eq!(response.status(), StatusCode::SEE_OTHER);
+    let bread = app.db.get_foods(&alice).await.unwrap().pop().unwrap();
+    assert_eq!(bread.nutrients.fiber, Some(6.0));
+    assert_eq!(bread.nutrients.salt, Some(1.2));
+    assert_eq!(bread.nutrients.sugar, None);
+
+    // Fiber is unknown for butter, salt is known.
+    let butter = app
+        .db
+        .add_food(
+            &alice,
+            FoodParams {
+                name: "Butter",
+                macros: macros(7.4, 0.8, 0.0, 0.0),
+                nutrients: Nutrients {
+                    salt: Some(0.01),
+                    ..Default::default()
+                },
+                unit: "g",
+                basis: 1.0,
+                barcode: None,
+                flags: EntryFlags::default(),
+            },
+        )
+        .await
+        .unwrap();
+
+    // Two sandwiches: 100 g bread and 20 g butter.
+    let sandwich = app
+        .db
+        .add_recipe(&alice, "Sandwich", 2.0, EntryFlags::default())
+        .await
+        .unwrap();
+    app.db
+        .add_ingredient(&alice, &sandwich, Some(&bread.id), None, 100.0, None)
+        .await
+        .unwrap();
+    app.db
+        .add_ingredient(&alice, &sandwich, Some(&butter), None, 20.0, None)
+        .await
+        .unwrap();
+
+    let recipe = app.db.get_recipe(&sandwich).await.unwrap();
+    assert_close(recipe.nutrients.fiber.unwrap(), 6.0);
+    assert_close(recipe.nutrients.salt.unwrap(), 1.2 + 0.2);
+    assert_eq!(recipe.nutrients.sugar, None);
+
+    app.db
+        .add_consumption(
+            &alice,
+            ConsumptionParams {
+                food_id: None,
+                recipe_id: Some(&sandwich),
+                quantity: 1.0,
+                serving_id: None,
+                consumed_at: date,
+                consumed_time: None,
+                meal_slot_id: None,
+            },
+        )
+        .await
+        .unwrap();
+    app.db
+        .add_consumption(
+            &alice,
+            ConsumptionParams {
+                food_id: Some(&bread.id),
+                recipe_id: None,
+                quantity: 50.0,
+                serving_id: None,
+                consumed_at: date,
+                consumed_time: None,
+                meal_slot_id: None,
+            },
+        )
+        .await
+        .unwrap();
+
+    let consumptions = app
+        .db
+        .get_consumptions(&alice, ConsumptionFilter::ConsumedAt(date))
+        .await
+        .unwrap();
+    let fiber = consumptions
+        .iter()
+        .map(|c| c.nutrients.fiber.unwrap())
+        .collect::<Vec<_>>();
+    assert_eq!(fiber.len(), 2);
+    assert_close(fiber.iter().sum(), 3.0 + 3.0);
+
+    let total = app
+        .db
+        .get_daily_totals(&alice, date..=date)
+        .await
+        .unwrap()
+        .pop()
+        .unwrap();
+    assert_close(total.nutrients.fiber.unwrap(), 6.0);
+    assert_close(total.nutrients.salt.unwrap(), 0.7 + 0.6);
+    assert_eq!(total.nutrients.sugar, None);
+
+    // Nutrients are only shown once they are chosen in the settings.
+    let response = app.get("/days/2024-01-01", &session).await;
+    assert!(!body_string(response).await.contains("fiber"));
+
+    let response = app
+        .post(
+            "/account/nutrients",
+            &session,
+            "fiber=on&iron=on&unknown=on",
+        )
+        .await;
+    assert_eq!(response.status(), StatusCode::SEE_OTHER);
+    let shown = app.db.get_shown_nutrients(&alice).await.unwrap();
+    assert_eq!(
+        shown.iter().map(|n| n.key).collect::<Vec<_>>(),
+        ["fiber", "iron"]
+    );
+
+    let response = app.get("/days/2024-01-01", &session).await;
+    let body = body_string(response).await;
+    assert!(body.contains("fiber"));
+    assert!(!body.contains("salt"));
+}
+
+#[tokio::test]
+async fn referenced_entries_are_hidden_instead_of_deleted() {
+    let app = TestApp::new().await;
+    let alice = app.create_user("alice").await;
+    let session = app.login("alice").await;
+    let date = chrono::NaiveDate::from_ymd_opt(2024, 1, 1).unwrap();
+
+    let oats = app
+        .db
+        .add_food(
+            &alice,
+            FoodParams {
+                name: "Oats",
+                macros: macros(3.7, 0.07, 0.6, 0.13),
+                nutrients: Nutrients::default(),
+                unit: "g",
+                basis: 1.0,
+                barcode: None,
+                flags: EntryFlags::default(),
+            },
+        )
+        .await
+        .unwrap();
+    let porridge = app
+        .db
+        .add_recipe(&alice, "Porridge", 1.0, EntryFlags::default())
+        .await
+        .unwrap();
+    app.db
+        .add_ingredient(&alice, &porridge, Some(&oats), None, 100.0, None)
+        .await
+        .unwrap();
+    let consumption = app
+        .db
+        .add_consumption(
+            &alice,
+            ConsumptionParams {
+                food_id: None,
+                recipe_id: Some(&porridge),
+                quantity: 1.0,
+                serving_id: None,
+                consumed_at: date,
+                consumed_time: None,
+                meal_slot_id: None,
+            },
+        )
+        .await
+        .unwrap();
+
+    // The food is blocked by the recipe, the recipe by the consumption.
+    let response = app
+        .post(&format!("/foods/{}/delete", oats), &session, "")
+        .await;
+    assert_eq!(response.status(), StatusCode::CONFLICT);
+    let body = body_string(response).await;
+    assert!(body.contains(&format!("href=\"/recipes/{}\"", porridge)));
+    assert!(body.contains(&format!("action=\"/foods/{}/hide\"", oats)));
+
+    let response = app
+        .post(&format!("/recipes/{}/delete", porridge), &session, "")
+        .await;
+    assert_eq!(response.status(), StatusCode::CONFLICT);
+    let body = body_string(response).await;
+    assert!(body.contains(&format!("href=\"/consumptions/{}\"", consumption)));
+    assert!(body.contains("2024-01-01"));
+
+    let token = app.db.create_api_token(&alice, "Test").await.unwrap();
+    let response = app
+        .send(
+            Request::delete(format!("/api/v1/foods/{}", oats))
+                .header(header::AUTHORIZATION, format!("Bearer {}", token))
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await;
+    assert_eq!(response.status(), StatusCode::CONFLICT);
+
+    // Hiding keeps the macros of the consumption.
+    let response = app
+        .post(&format!("/recipes/{}/hide", porridge), &session, "")
+        .await;
+    assert_eq!(response.status(), StatusCode::SEE_OTHER);
+    let response = app
+        .post(&format!("/foods/{}/hide", oats), &session, "")
+        .await;
+    assert_eq!(response.status(), StatusCode::SEE_OTHER);
+    assert!(app.db.get_food(&oats).await.unwrap().hidden_at.is_some());
+    assert!(app
+        .db
+        .get_recipe(&porridge)
+        .await
+        .unwrap()
+        .hidden_at
+        .is_some());
+    let consumptions = app
+        .db
+        .get_consumptions(&alice, ConsumptionFilter::ConsumedAt(date))
+        .await
+        .unwrap();
+    assert_close(consumptions[0].kcal, 370.0);
+
+    // Without the consumption, the recipe is deleted with its ingredients,
+    // which frees the food.
+    app.db.delete_consumption(&consumption).await.unwrap();
+    let response = app
+        .post(&format!("/recipes/{}/delete", porridge), &session, "")
+        .await;
+    assert_eq!(location(&response), "/recipes");
+    let response = app
+        .post(&format!("/foods/{}/delete", oats), &session, "")
+        .await;
+    assert_eq!(location(&response), "/foods");
+    assert!(app.db.get_food(&oats).await.is_err());
+}
+
+#[tokio::test]
+async fn duplicate_foods_can_be_merged() {
+    let app = TestApp::new().await;
+    let alice = app.create_user("alice").await;
+    let bob = app.create_user("bob").await;
+    let session = app.login("alice").await;
+    let date = chrono::NaiveDate::from_ymd_opt(2024, 1, 1).unwrap();
+
+    let add_food = |user_id: UserId, name: &'static str, unit: &'static str| {
+        let db = app.db.clone();
+        async move {
+            db.add_food(
+                &user_id,
+                FoodParams {
+                    name,
+                    macros: macros(3.7, 0.07, 0.6, 0.13),
+                    nutrients: Nutrients::default(),
+                    unit,
+                    basis: 1.0,
+                    barcode: None,
+                    flags: EntryFlags::default(),
+                },
+            )
+            .await
+            .unwrap()
+        }
+    };
+    let oats = add_food(alice.clone(), "Oats", "g").await;
+    let flakes = add_food(alice.clone(), "Oat flakes", "g").await;
+    let milk = add_food(alice.clone(), "Milk", "ml").await;
+    let bobs_oats = add_food(bob.clone(), "Oats", "g").await;
+
+    let cup = app
+        .db
+        .add_serving(&alice, &flakes, "Cup", 80.0)
+        .await
+        .unwrap();
+    let porridge = app
+        .db
+        .add_recipe(&alice, "Porridge", 1.0, EntryFlags::default())
+        .await
+        .unwrap();
+    app.db
+        .add_ingredient(&alice, &porridge, Some(&flakes), None, 100.0, None)
+        .await
+        .unwrap();
+    app.db
+        .add_consumption(
+            &alice,
+            ConsumptionParams {
+                food_id: Some(&flakes),
+                recipe_id: None,
+                quantity: 1.0,
+                serving_id: Some(&cup),
+                consumed_at: date,
+                consumed_time: None,
+                meal_slot_id: None,
+            },
+        )
+        .await
+        .unwrap();
+
+    // Only foods of the user with the same unit are offered and accepted.
+    let body = body_string(app.get(&format!("/foods/{}", flakes), &session).await).await;
+    assert!(body.contains(&format!("value=\"{}\"", oats)));
+    assert!(!body.contains(&format!("value=\"{}\"", milk)));
+    assert!(!body.contains(&format!("value=\"{}\"", bobs_oats)));
+    for into_id in [&milk, &bobs_oats, &flakes] {
+        let response = app
+            .post(
+                &format!("/foods/{}/merge", flakes),
+                &session,
+                &format!("into_id={}", into_id),
+            )
+            .await;
+        assert_eq!(response.status(), StatusCode::UNPROCESSABLE_ENTITY);
+    }
+
+    let response = app
+        .post(
+            &format!("/foods/{}/merge", flakes),
+            &session,
+            &format!("into_id={}&keep_alias=true", oats),
+        )
+        .await;
+    assert_eq!(location(&response), format!("/foods/{}", oats));
+    assert!(app.db.get_food(&flakes).await.is_err());
+
+    let consumptions = app
+        .db
+        .get_consumptions(&alice, ConsumptionFilter::FoodId(oats.clone()))
+        .await
+        .unwrap();
+    assert_eq!(consumptions.len(), 1);
+    assert_close(consumptions[0].kcal, 3.7 * 80.0);
+    let references = app.db.get_food_references(&alice, &oats).await.unwrap();
+    assert_eq!(references.recipes.len(), 1);
+    assert_eq!(app.db.get_servings(&oats).await.unwrap().len(), 1);
+
+    // The old name is kept as an alias, which the picker search matches.
+    let aliases = app.db.get_food_aliases(&oats).await.unwrap();
+    assert_eq!(aliases.len(), 1);
+    assert_eq!(aliases[0].name, "Oat flakes");
+    let body = body_string(app.get("/days/2024-01-01", &session).await).await;
+    assert!(body.contains("data-consumable-aliases=\"Oat flakes\""));
+
+    // Aliases are exported and imported with the food.
+    let data = app.db.export_data(&alice).await.unwrap();
+    assert_eq!(data.food_aliases.len(), 1);
+    app.db
+        .import_data(&bob, data, ImportMode::Replace)
+        .await
+        .unwrap();
+    let imported = app
+        .db
+        .get_foods(&bob)
+        .await
+        .unwrap()
+        .into_iter()
+        .find(|f| f.name == "Oats")
+        .unwrap();
+    assert_eq!(
+        app.db.get_food_aliases(&imported.id).await.unwrap()[0].name,
+        "Oat flakes"
+    );
+
+    let response = app
+        .post(
+            &format!("/food-aliases/{}/delete", aliases[0].id),
+            &session,
+            "",
+        )
+        .await;
+    assert_eq!(location(&response), format!("/foods/{}", oats));
+    assert!(app.db.get_food_aliases(&oats).await.unwrap().is_empty());
+}
+
+#[tokio::test]
+async fn consumptions_keep_values_from_when_they_were_logged() {
+    let app = TestApp::new().await;
+    let alice = app.create_user("alice").await;
+    let session = app.login("alice").await;
+    let date = chrono::NaiveDate::from_ymd_opt(2024, 1, 1).unwrap();
+
+    let oats = app
+        .db
+        .add_food(
+            &alice,
+            FoodParams {
+                name: "Oats",
+                macros: macros(3.7, 0.07, 0.6, 0.13),
+                nutrients: Nutrients::default(),
+                unit: "g",
+                basis: 1.0,
+                barcode: None,
+                flags: EntryFlags::default(),
+            },
+        )
+        .await
+        .unwrap();
+    let porridge = app
+        .db
+        .add_recipe(&alice, "Porridge", 1.0, EntryFlags::default())
+        .await
+        .unwrap();
+    app.db
+        .add_ingredient(&alice, &porridge, Some(&oats), None, 100.0, None)
+        .await
+        .unwrap();
+    let oats_consumption = app
+        .db
+        .add_consumption(
+            &alice,
+            ConsumptionParams {
+                food_id: Some(&oats),
+                recipe_id: None,
+                quantity: 100.0,
+                serving_id: None,
+                consumed_at: date,
+                consumed_time: None,
+                meal_slot_id: None,
+            },
+        )
+        .await
+        .unwrap();
+    let porridge_consumption = app
+        .db
+        .add_consumption(
+            &alice,
+            ConsumptionParams {
+                food_id: None,
+                recipe_id: Some(&porridge),
+                quantity: 1.0,
+                serving_id: None,
+                consumed_at: date,
+                consumed_time: None,
+                meal_slot_id: None,
+            },
+        )
+        .await
+        .unwrap();
+
+    // The food is corrected to 400 kcal per 100 g.
+    app.db
+        .update_food(
+            &oats,
+            FoodParams {
+                name: "Oats",
+                macros: macros(4.0, 0.07, 0.6, 0.13),
+                nutrients: Nutrients::default(),
+                unit: "g",
+                basis: 1.0,
+                barcode: None,
+                flags: EntryFlags::default(),
+            },
+        )
+        .await
+        .unwrap();
+    let kcal = |id: String| {
+        let db = app.db.clone();
+        async move { db.get_consumption(&id).await.unwrap().kcal }
+    };
+    assert_close(kcal(oats_consumption.clone()).await, 370.0);
+    assert_close(kcal(porridge_consumption.clone()).await, 370.0);
+    let totals = app.db.get_daily_totals(&alice, date..=date).await.unwrap();
+    assert_close(totals[0].kcal, 740.0);
+
+    // Changing the quantity keeps the saved values.
+    app.db
+        .update_consumption(
+            &oats_consumption,
+            ConsumptionParams {
+                food_id: Some(&oats),
+                recipe_id: None,
+                quantity: 50.0,
+                serving_id: None,
+                consumed_at: date,
+                consumed_time: None,
+                meal_slot_id: None,
+            },
+        )
+        .await
+        .unwrap();
+    assert_close(kcal(oats_consumption.clone()).await, 185.0);
+
+    let response = app
+        .post(
+            &format!("/consumptions/{}/recalculate", oats_consumption),
+            &session,
+            "",
+        )
+        .await;
+    assert_eq!(location(&response), "/days/2024-01-01");
+    assert_close(kcal(oats_consumption.clone()).await, 200.0);
+    assert_close(kcal(porridge_consumption.clone()).await, 370.0);
+
+    let body = body_string(app.get(&format!("/recipes/{}", porridge), &session).await).await;
+    assert!(body.contains(&format!("action=\"/recipes/{}/recalculate\"", porridge)));
+    let response = app
+        .post(&format!("/recipes/{}/recalculate", porridge), &session, "")
+        .await;
+    assert_eq!(location(&response), format!("/recipes/{}", porridge));
+    assert_close(kcal(porridge_consumption.clone()).await, 400.0);
+
+    // Exports without saved values get the current ones on import.
+    let mut data = app.db.export_data(&alice).await.unwrap();
+    assert_eq!(data.consumptions[0].unit_kcal, Some(4.0));
+    data.consumptions
+        .iter_mut()
+        .for_each(|c| c.unit_kcal = None);
+    data.foods[0].kcal = 3.0;
+    // Exports without meal slots keep the existing ones.
+    data.meal_slots.clear();
+    app.db
+        .import_data(&alice, data, ImportMode::Replace)
+        .await
+        .unwrap();
+    assert_close(kcal(oats_consumption).await, 150.0);
+    assert_eq!(app.db.get_meal_slots(&alice).await.unwrap().len(), 4);
+}
+
+#[tokio::test]
+async fn times_of_day_order_the_day_and_pick_its_weight() {
+    let app = TestApp::new().await;
+    let alice = app.create_user("alice").await;
+    let session = app.login("alice").await;
+    let date = chrono::NaiveDate::from_ymd_opt(2024, 1, 1).unwrap();
+
+    let oats = app
+        .db
+        .add_food(
+            &alice,
+            FoodParams {
+                name: "Oats",
+                macros: macros(3.7, 0.07, 0.6, 0.13),
+                nutrients: Nutrients::default(),
+                unit: "g",
+                basis: 1.0,
+                barcode: None,
+                flags: EntryFlags::default(),
+            },
+        )
+        .await
+        .unwrap();
+    let milk = app
+        .db
+        .add_food(
+            &alice,
+            FoodParams {
+                name: "Milk",
+                macros: macros(0.64, 0.035, 0.048, 0.034),
+                nutrients: Nutrients::default(),
+                unit: "ml",
+                basis: 1.0,
+                barcode: None,
+                flags: EntryFlags::default(),
+            },
+        )
+        .await
+        .unwrap();
+    for form in [
+        format!("consumable_id={}&quantity=200&consumed_time=12:30", milk),
+        format!("consumable_id={}&quantity=50&consumed_time=07:15", oats),
+        format!("consumable_id={}&quantity=10&consumed_time=", oats),
+    ] {
+        let response = app
+            .post(
+                "/consumptions",
+                &session,
+                &format!("{}&consumable_type=food&consumed_at=2024-01-01", form),
+            )
+            .await;
+        assert_eq!(response.status(), StatusCode::SEE_OTHER);
+    }
+    for form in [
+        "measured_time=07:00&weight=81",
+        "measured_time=21:00&weight=82",
+        "measured_time=12:00&weight=80",
+    ] {
+        let response = app
+            .post(
+                "/weights",
+                &session,
+                &format!("measured_at=2024-01-01&{}", form),
+            )
+            .await;
+        assert_eq!(response.status(), StatusCode::SEE_OTHER);
+    }
+
+    let consumptions = app
+        .db
+        .get_consumptions(&alice, ConsumptionFilter::ConsumedAt(date))
+        .await
+        .unwrap();
+    let order = consumptions
+        .iter()
+        .map(|c| (c.quantity, c.consumed_time.map(|t| t.to_string())))
+        .collect::<Vec<_>>();
+    assert_eq!(
+        order,
+        [
+            (10.0, None),
+            (50.0, Some("07:15:00".to_string())),
+            (200.0, Some("12:30:00".to_string()))
+        ]
+    );
+    let body = body_string(app.get("/days/2024-01-01", &session).await).await;
+    assert!(body.contains("50 g at 07:15"));
+    assert!(body.find("50 g at 07:15") < body.find("200 ml at 12:30"));
+    assert!(body.contains("<span>81 kg</span>"));
+
+    let response = app
+        .post("/weights/rule", &session, "weight_rule=lowest")
+        .await;
+    assert_eq!(response.status(), StatusCode::SEE_OTHER);
+    let body = body_string(app.get("/days/2024-01-01", &session).await).await;
+    assert!(body.contains("<span>80 kg</span>"));
+    app.db
+        .set_weight_rule(&alice, WeightRule::Average)
+        .await
+        .unwrap();
+    let body = body_string(app.get("/days/2024-01-01", &session).await).await;
+    assert!(body.contains("<span>81 kg</span>"));
+    // The account table links days with several weigh-ins to the day.
+    let body = body_string(app.get("/account?until=2024-01-01", &session).await).await;
+    assert!(body.contains("<a href=\"/days/2024-01-01\">81</a>"));
+
+    // Times survive an export and import.
+    let data = app.db.export_data(&alice).await.unwrap();
+    let json = serde_json::to_value(&data).unwrap();
+    assert!(json["weights"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .any(|w| w["measured_time"] == "21:00:00"));
+    app.db
+        .import_data(&alice, data, ImportMode::Replace)
+        .await
+        .unwrap();
+    let weights = app.db.get_weights(&alice).await.unwrap();
+    let times = weights
+        .iter()
+        .map(|w| w.measured_time.map(|t| t.to_string()))
+        .collect::<Vec<_>>();
+    assert_eq!(
+        times,
+        [
+            Some("21:00:00".to_string()),
+            Some("12:00:00".to_string()),
+            Some("07:00:00".to_string())
+        ]
+    );
+    let consumptions = app
+        .db
+        .get_consumptions(&alice, ConsumptionFilter::ConsumedAt(date))
+        .await
+        .unwrap();
+    assert_eq!(
+        consumptions[2].consumed_time.unwrap().to_string(),
+        "12:30:00"
+    );
+}
+
+#[tokio::test]
+async fn time_zone_decides_today() {
+    let app = TestApp::new().await;
+    let alice = app.create_user("alice").await;
+    let session = app.login("alice").await;
+    let today_in = |time_zone: chrono_tz::Tz| {
+        chrono::Utc::now()
+            .with_timezone(&time_zone)
+            .date_naive()
+            .to_string()
+    };
+
+    assert_eq!(
+        app.db.today(&alice).await.unwrap().to_string(),
+        today_in(chrono_tz::UTC)
+    );
+
+    // UTC+14 and UTC-11 are always on different dates.
+    for time_zone in [
+        chrono_tz::Pacific::Kiritimati,
+        chrono_tz::Pacific::Pago_Pago,
+    ] {
+        let response = app
+            .post(
+                "/account/time-zone",
+                &session,
+                &format!("time_zone={}", time_zone.name()),
+            )
+            .await;
+        assert_eq!(response.status(), StatusCode::SEE_OTHER);
+        let body = body_string(app.get("/account/time-zone", &session).await).await;
+        assert!(body.contains(&format!(r#"value="{}" selected"#, time_zone.name())));
+
+        let body = body_string(app.get("/", &session).await).await;
+        assert!(body.contains(&format!("<span>{}</span>", today_in(time_zone))));
+        let body = body_string(app.get("/weights", &session).await).await;
+        assert!(body.contains(&format!(r#"value="{}""#, today_in(time_zone))));
+        let body = body_string(app.get("/account/sessions", &session).await).await;
+        assert!(body.contains(&format!("<td>{}</td>", today_in(time_zone))));
+    }
+
+    let response = app
+        .post(
+            "/account/time-zone",
+            &session,
+            "time_zone=Mars/Olympus_Mons",
+        )
+        .await;
+    assert_eq!(response.status(), StatusCode::UNPROCESSABLE_ENTITY);
+    assert_eq!(
+        app.db.get_time_zone(&alice).await.unwrap(),
+        chrono_tz::Pacific::Pago_Pago
+    );
+}
+
+#[tokio::test]
+async fn barcode_lookup_prefers_own_foods() {
+    let app = TestApp::new().await;
+    app.create_user("alice").await;
+    let session = app.login("alice").await;
+
+    app.db
+        .import_products(&[Product {
+            barcode: "0036000291452".to_string(),
+            name: "Oats".to_string(),
+            kcal: 370.0,
+            fat: 7.0,
+            carbs: 59.0,
+            protein: 13.5,
+            nutrients: Nutrients {
+                fiber: Some(10.0),
+                ..Default::default()
+            },
+        }])
+        .await
+        .unwrap();
+
+    // Scanners return UPC-A codes without the leading zero.
+    let response = app.get("/foods/barcode/036000291452", &session).await;
+    assert_eq!(response.status(), StatusCode::OK);
+    let json: serde_json::Value = serde_json::from_str(&body_string(response).await).unwrap();
+    assert!(json["food_id"].is_null());
+    assert_eq!(json["barcode"], "0036000291452");
+    assert_eq!(json["name"], "Oats");
+    assert_eq!(json["basis"], 100.0);
+    assert_eq!(json["unit"], "g");
+    assert_eq!(json["fiber"], 10.0);
+
+    let response = app
+        .post(
+            "/foods",
+            &session,
+            "name=My+oats&kcal=3.7&fat=0.07&carbs=0.59&protein=0.135&unit=g&basis=1&barcode=036000291452",
+        )
+        .await;
+    assert_eq!(response.status(), StatusCode::SEE_OTHER);
+
+    let response = app.get("/foods/barcode/0036000291452", &session).await;
+    let json: serde_json::Value = serde_json::from_str(&body_string(response).await).unwrap();
+    assert!(json["food_id"].is_string());
+    assert_eq!(json["name"], "My oats");
+
+    let response = app.get("/foods/barcode/4006381333931", &session).await;
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    let response = app.get("/foods/barcode/abc", &session).await;
+    assert_eq!(response.status(), StatusCode::UNPROCESSABLE_ENTITY);
+    let response = app
+        .post(
+            "/foods",
+            &session,
+            "name=Oats&kcal=1&fat=1&carbs=1&protein=1&unit=g&basis=1&barcode=123",
+        )
+        .await;
+    assert_eq!(response.status(), StatusCode::UNPROCESSABLE_ENTITY);
+    let body = body_string(response).await;
+    assert!(body.contains("The barcode has to be an EAN-8, UPC-A, EAN-13 or GTIN-14 code."));
+    assert!(body.contains(r#"value="123""#));
+}
+
+#[tokio::test]
+async fn export_contains_all_entries() {
+    let app = TestApp::new().await;
+    let alice = app.create_user("alice").await;
+    let session = app.login("alice").await;
+    let date = chrono::NaiveDate::from_ymd_opt(2024, 1, 1).unwrap();
+
+    app.db.add_weight(&alice, 80.0, &date, None).await.unwrap();
+    let food_id = app
+        .db
+        .add_food(
+            &alice,
+            FoodParams {
+                name: "Oats",
+                macros: macros(3.7, 0.07, 0.6, 0.13),
+                nutrients: Nutrients::default(),
+                unit: "g",
+                basis: 1.0,
+                barcode: None,
+                flags: EntryFlags {
+                    hidden: false,
+                    starred: true,
+                    shared: false,
+                },
+            },
+        )
+        .await
+        .unwrap();
+    let recipe_id = app
+        .db
+        .add_recipe(&alice, "Porridge", 2.0, EntryFlags::default())
+        .await
+        .unwrap();
+    app.db
+        .add_ingredient(&alice, &recipe_id, Some(&food_id), None, 100.0, None)
+        .await
+        .unwrap();
+    app.db
+        .add_consumption(
+            &alice,
+            ConsumptionParams {
+                food_id: Some(&food_id),
+                recipe_id: None,
+                quantity: 50.0,
+                serving_id: None,
+                consumed_at: date,
+                consumed_time: None,
+                meal_slot_id: None,
+            },
+        )
+        .await
+        .unwrap();
+    app.db
+        .set_goal(&alice, None, macros(2000.0, 70.0, 250.0, 120.0))
+        .await
+        .unwrap();
+
+    let response = app.post("/account/export", &session, "").await;
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(
+        response.headers().get(header::CONTENT_TYPE).unwrap(),
+        "application/json"
+    );
+    assert!(response
+        .headers()
+        .get(header::CONTENT_DISPOSITION)
+        .unwrap()
+        .to_str()
+        .unwrap()
+        .starts_with("attachment; filename="));
+
+    let json: serde_json::Value = serde_json::from_str(&body_string(response).await).unwrap();
+    let keys = |value: &serde_json::Value| {
+        let mut keys = value
+            .as_object()
+            .unwrap()
+            .keys()
+            .cloned()
+            .collect::<Vec<_>>();
+        keys.sort();
+        keys
+    };
+
+    assert_eq!(
+        keys(&json),
+        [
+            "consumptions",
+            "exported_at",
+            "food_aliases",
+            "foods",
+            "goals",
+            "ingredients",
+            "meal_slots",
+            "recipes",
+            "servings",
+            "user_id",
+            "weights"
+        ]
+    );
+    assert_eq!(json["user_id"], alice.0);
+    assert_eq!(json["weights"].as_array().unwrap().len(), 1);
+    assert_eq!(json["foods"].as_array().unwrap().len(), 1);
+    assert_eq!(json["recipes"].as_array().unwrap().len(), 1);
+    assert_eq!(json["ingredients"].as_array().unwrap().len(), 1);
+    assert_eq!(json["consumptions"].as_array().unwrap().len(), 1);
+    assert_eq!(json["goals"].as_array().unwrap().len(), 1);
+    assert_eq!(json["meal_slots"].as_array().unwrap().len(), 4);
+
+    let food = &json["foods"][0];
+    assert_eq!(
+        keys(food),
+        [
+            "alcohol",
+            "barcode",
+            "basis",
+            "calcium",
+            "carbs",
+            "created_at",
+            "fat",
+            "fiber",
+            "hidden_at",
+            "id",
+            "iron",
+            "kcal",
+            "name",
+            "potassium",
+            "protein",
+            "salt",
+            "saturated_fat",
+            "shared_at",
+            "starred_at",
+            "sugar",
+            "unit",
+            "updated_at",
+            "vitamin_c"
+        ]
+    );
+    assert_eq!(food["name"], "Oats");
+    assert!(food["hidden_at"].is_null());
+    assert!(food["starred_at"].is_string());
+    assert_eq!(json["consumptions"][0]["food_id"], food_id);
+    assert_eq!(json["consumptions"][0]["consumed_at"], "2024-01-01");
+    assert_eq!(json["ingredients"][0]["recipe_id"], recipe_id);
+}
+
+#[tokio::test]
+async fn shared_entries_can_be_used_by_other_users() {
+    let app = TestApp::new().await;
+    let alice = app.create_user("alice").await;
+    let bob = app.create_user("bob").await;
+    let bob_session = app.login("bob").await;
+
+    let food_id = app
+        .db
+        .add_food(
+            &alice,
+            FoodParams {
+                name: "Oats",
+                macros: macros(3.7, 0.07, 0.6, 0.13),
+                nutrients: Nutrients::default(),
+                unit: "g",
+                basis: 1.0,
+                barcode: None,
+                flags: EntryFlags {
+                    hidden: false,
+                    starred: true,
+                    shared: true,
+                },
+            },
+        )
+        .await
+        .unwrap();
+    let serving_id = app
+        .db
+        .add_serving(&alice, &food_id, "Cup", 80.0)
+        .await
+        .unwrap();
+    let secret_id = app
+        .db
+        .add_food(
+            &alice,
+            FoodParams {
+                name: "Secret",
+                macros: macros(1.0, 0.0, 0.0, 0.0),
+                nutrients: Nutrients::default(),
+                unit: "g",
+                basis: 1.0,
+                barcode: None,
+                flags: EntryFlags::default(),
+            },
+        )
+        .await
+        .unwrap();
+    let recipe_id = app
+        .db
+        .add_recipe(
+            &alice,
+            "Porridge",
+            2.0,
+            EntryFlags {
+                hidden: false,
+                starred: false,
+                shared: true,
+            },
+        )
+        .await
+        .unwrap();
+    app.db
+        .add_ingredient(&alice, &recipe_id, Some(&secret_id), None, 100.0, None)
+        .await
+        .unwrap();
+
+    let consumables = app.db.get_consumables(&bob).await.unwrap();
+    let names = consumables
+        .iter()
+        .map(|c| (c.name.as_str(), c.is_shared, c.is_starred))
+        .collect::<Vec<_>>();
+    assert_eq!(names, [("Oats", true, false), ("Porridge", true, false)]);
+
+    let response = app.get(&format!("/foods/{}", food_id), &bob_session).await;
+    assert_eq!(response.status(), StatusCode::OK);
+    assert!(body_string(response).await.contains("Clone"));
+    let response = app
+        .get(&format!("/recipes/{}", recipe_id), &bob_session)
+        .await;
+    assert_eq!(response.status(), StatusCode::OK);
+    let response = app
+        .get(&format!("/foods/{}", secret_id), &bob_session)
+        .await;
+    assert_eq!(response.status(), StatusCode::FORBIDDEN);
+    let response = app
+        .post(
+            &format!("/foods/{}", food_id),
+            &bob_session,
+            "name=Mine&kcal=1&fat=0&carbs=0&protein=0&unit=g&basis=1",
+        )
+        .await;
+    assert_eq!(response.status(), StatusCode::FORBIDDEN);
+
+    let response = app
+        .post(
+            "/consumptions",
+            &bob_session,
+            &format!(
+                "consumed_at=2024-01-01&consumable_id={}&consumable_type=food&quantity=1&serving_id={}",
+                food_id, serving_id
+            ),
+        )
+        .await;
+    assert_eq!(response.status(), StatusCode::SEE_OTHER);
+    let response = app
+        .post(
+            "/consumptions",
+            &bob_session,
+            &format!(
+                "consumed_at=2024-01-01&consumable_id={}&consumable_type=recipe&quantity=1",
+                recipe_id
+            ),
+        )
+        .await;
+    assert_eq!(response.status(), StatusCode::SEE_OTHER);
+    let response = app
+        .post(
+            "/consumptions",
+            &bob_session,
+            &format!(
+                "consumed_at=2024-01-01&consumable_id={}&consumable_type=food&quantity=1",
+                secret_id
+            ),
+        )
+        .await;
+    assert_eq!(response.status(), StatusCode::FORBIDDEN);
+    let consumptions = app
+        .db
+        .get_consumptions(&bob, ConsumptionFilter::None)
+        .await
+        .unwrap();
+    let kcal = consumptions.iter().map(|c| c.kcal).sum::<f64>();
+    assert_close(kcal, 3.7 * 80.0 + 50.0);
+
+    // Alice's consumptions of her own entries are not affected.
+    assert!(app
+        .db
+        .get_consumptions(&alice, ConsumptionFilter::None)
+        .await
+        .unwrap()
+        .is_empty());
+
+    let data = app.db.export_data(&bob).await.unwrap();
+    assert_eq!(data.foods.len(), 2);
+    assert!(data.foods.iter().all(|f| f.shared_at.is_none()));
+    assert_eq!(data.recipes.len(), 1);
+    assert_eq!(data.ingredients.len(), 1);
+    assert_eq!(data.servings.len(), 1);
+    app.db
+        .import_data(&bob, data, ImportMode::Replace)
+        .await
+        .unwrap();
+    assert_eq!(
+        app.db
+            .get_consumptions(&bob, ConsumptionFilter::None)
+            .await
+            .unwrap()
+            .len(),
+        2
+    );
+
+    // Alice can't replace her data while bob uses her shared entries.
+    let data = app.db.export_data(&alice).await.unwrap();
+    let result = app.db.import_data(&alice, data, ImportMode::Replace).await;
+    assert!(matches!(result, Err(ImportError::Invalid(_))));
+    assert_eq!(app.db.get_foods(&alice).await.unwrap().len(), 2);
+
+    let response = app
+        .post(&format!("/recipes/{}/clone", recipe_id), &bob_session, "")
+        .await;
+    assert_eq!(response.status(), StatusCode::SEE_OTHER);
+    let clone_id = location(&response)
+        .trim_start_matches("/recipes/")
+        .to_string();
+    let clone = app.db.get_recipe(&clone_id).await.unwrap();
+    assert_eq!(clone.user_id, bob);
+    assert_eq!(clone.name, "Porridge");
+    assert!(clone.shared_at.is_none());
+    assert_close(clone.kcal, 100.0);
+    // The ingredient that bob can't see is copied as well.
+    let ingredients = app.db.get_ingredients(&clone_id).await.unwrap();
+    assert_eq!(ingredients.len(), 1);
+    assert_ne!(ingredients[0].consumable_id, secret_id);
+    let food = app
+        .db
+        .get_food(&ingredients[0].consumable_id)
+        .await
+        .unwrap();
+    assert_eq!(food.user_id, bob);
+
+    let response = app
+        .post(&format!("/foods/{}/clone", food_id), &bob_session, "")
+        .await;
+    assert_eq!(response.status(), StatusCode::SEE_OTHER);
+    let clone_id = location(&response)
+        .trim_start_matches("/foods/")
+        .to_string();
+    assert_eq!(app.db.get_food(&clone_id).await.unwrap().user_id, bob);
+    assert_eq!(app.db.get_servings(&clone_id).await.unwrap().len(), 1);
+    let response = app
+        .post(&format!("/foods/{}/clone", secret_id), &bob_session, "")
+        .await;
+    assert_eq!(response.status(), StatusCode::FORBIDDEN);
+}
+
+#[tokio::test]
+async fn admins_manage_users() {
+    let app = TestApp::new().await;
+    let alice = app.create_user("alice").await;
+    let bob = app.create_user("bob").await;
+    app.db.set_user_admin(&alice, true).await.unwrap();
+    let alice_session = app.login("alice").await;
+    let bob_session = app.login("bob").await;
+
+    let response = app.get("/admin/users", &bob_session).await;
+    assert_eq!(response.status(), StatusCode::FORBIDDEN);
+    let response = app.get("/admin/users", &alice_session).await;
+    assert_eq!(response.status(), StatusCode::OK);
+    assert!(body_string(response).await.contains("bob"));
+
+    let response = app
+        .post(
+            "/admin/users",
+            &alice_session,
+            "username=carol&password=secret",
+        )
+        .await;
+    assert_eq!(response.status(), StatusCode::SEE_OTHER);
+    let carol = app.db.get_user("carol").await.unwrap().unwrap().id;
+    let response = app
+        .post("/admin/users", &alice_session, "username=carol&password=x")
+        .await;
+    assert_eq!(response.status(), StatusCode::UNPROCESSABLE_ENTITY);
+    let body = body_string(response).await;
+    assert!(body.contains("The username is empty or already taken."));
+    assert!(body.contains(r#"value="carol""#));
+    let response = app
+        .post("/admin/users", &alice_session, "username=dave&password=")
+        .await;
+    assert_eq!(response.status(), StatusCode::UNPROCESSABLE_ENTITY);
+    assert!(body_string(response)
+        .await
+        .contains("The password must not be empty."));
+    assert!(app.db.get_user("dave").await.unwrap().is_none());
+
+    // Disabling ends sessions and prevents logins.
+    let response = app
+        .post(
+            &format!("/admin/users/{}", bob.0),
+            &alice_session,
+            "disabled=true",
+        )
+        .await;
+    assert_eq!(response.status(), StatusCode::SEE_OTHER);
+    let response = app.get("/", &bob_session).await;
+    assert_eq!(location(&response), "/account/login");
+    let response = app
+        .post("/account/login", "", "username=bob&password=password")
+        .await;
+    assert_eq!(response.status(), StatusCode::OK);
+
+    let response = app
+        .post(
+            &format!("/admin/users/{}/password", bob.0),
+            &alice_session,
+            "password=",
+        )
+        .await;
+    assert_eq!(response.status(), StatusCode::UNPROCESSABLE_ENTITY);
+    assert!(body_string(response)
+        .await
+        .contains("The password must not be empty."));
+    let response = app
+        .post(
+            &format!("/admin/users/{}/password", bob.0),
+            &alice_session,
+            "password=new",
+        )
+        .await;
+    assert_eq!(response.status(), StatusCode::SEE_OTHER);
+    app.post(&format!("/admin/users/{}", bob.0), &alice_session, "")
+        .await;
+    let response = app
+        .post("/account/login", "", "username=bob&password=new")
+        .await;
+    assert_eq!(response.status(), StatusCode::SEE_OTHER);
+
+    // Admins can't lock themselves out.
+    let response = app
+        .post(
+            &format!("/admin/users/{}", alice.0),
+            &alice_session,
+            "disabled=true",
+        )
+        .await;
+    assert_eq!(response.status(), StatusCode::FORBIDDEN);
+    let response = app
+        .post(
+            &format!("/admin/users/{}/delete", alice.0),
+            &alice_session,
+            "",
+        )
+        .await;
+    assert_eq!(response.status(), StatusCode::FORBIDDEN);
+
+    // Users whose shared entries others use can't be deleted.
+    let food_id = app
+        .db
+        .add_food(
+            &carol,
+            FoodParams {
+                name: "Oats",
+                macros: macros(3.7, 0.07, 0.6, 0.13),
+                nutrients: Nutrients::default(),
+                unit: "g",
+                basis: 1.0,
+                barcode: None,
+                flags: EntryFlags {
+                    hidden: false,
+                    starred: false,
+                    shared: true,
+                },
+            },
+        )
+        .await
+        .unwrap();
+    let date = chrono::NaiveDate::from_ymd_opt(2024, 1, 1).unwrap();
+    let consumption_id = app
+        .db
+        .add_consumption(
+            &bob,
+            ConsumptionParams {
+                food_id: Some(&food_id),
+                recipe_id: None,
+                quantity: 50.0,
+                serving_id: None,
+                consumed_at: date,
+                consumed_time: None,
+                meal_slot_id: None,
+            },
+        )
+        .await
+        .unwrap();
+    let response = app
+        .post(
+            &format!("/admin/users/{}/delete", carol.0),
+            &alice_session,
+            "",
+        )
+        .await;
+    assert_eq!(response.status(), StatusCode::CONFLICT);
+
+    app.db.delete_consumption(&consumption_id).await.unwrap();
+    let response = app
+        .post(
+            &format!("/admin/users/{}/delete", carol.0),
+            &alice_session,
+            "",
+        )
+        .await;
+    assert_eq!(response.status(), StatusCode::SEE_OTHER);
+    assert!(app.db.get_user("carol").await.unwrap().is_none());
+    assert!(app.db.get_food(&food_id).await.is_err());
+}
+
+#[tokio::test]
+async fn password_change_logs_out_other_sessions() {
+    let app = TestApp::new().await;
+    app.create_user("alice").await;
+    let session = app.login("alice").await;
+    let other_session = app.login("alice").await;
+
+    let response = app
+        .post(
+            "/account/password",
+            &session,
+            "current_password=wrong&new_password=new&new_password_confirmation=new",
+        )
+        .await;
+    assert_eq!(response.status(), StatusCode::UNPROCESSABLE_ENTITY);
+    assert!(body_string(response)
+        .await
+        .contains("The current password is wrong."));
+
+    let response = app
+        .post(
+            "/account/password",
+            &session,
+            "current_password=password&new_password=new&new_password_confirmation=old",
+        )
+        .await;
+    assert_eq!(response.status(), StatusCode::UNPROCESSABLE_ENTITY);
+    assert!(body_string(response)
+        .await
+        .contains("passwords don't match"));
+
+    let response = app
+        .post(
+            "/account/password",
+            &session,
+            "current_password=password&new_password=new&new_password_confirmation=new",
+        )
+        .await;
+    assert_eq!(response.status(), StatusCode::SEE_OTHER);
+    assert_eq!(app.get("/", &session).await.status(), StatusCode::OK);
+    let response = app.get("/", &other_session).await;
+    assert_eq!(location(&response), "/account/login");
+
+    let response = app
+        .post("/account/login", "", "username=alice&password=new")
+        .await;
+    assert_eq!(response.status(), StatusCode::SEE_OTHER);
+}
+
+#[tokio::test]
+async fn sessions_can_be_logged_out() {
+    let app = TestApp::new().await;
+    let alice = app.create_user("alice").await;
+    app.create_user("bob").await;
+    let bob_session = app.login("bob").await;
+    let response = app
+        .send(
+            Request::post("/account/login")
+                .header(header::CONTENT_TYPE, "application/x-www-form-urlencoded")
+                .header(header::USER_AGENT, "Phone")
+                .body(Body::from("username=alice&password=password"))
+                .unwrap(),
+        )
+        .await;
+    let phone_session = set_cookie(&response).split(';').next().unwrap().to_string();
+    let session = app.login("alice").await;
+    let third_session = app.login("alice").await;
+
+    let sessions = app.db.get_sessions(&alice).await.unwrap();
+    assert_eq!(sessions.len(), 3);
+    let phone = sessions
+        .iter()
+        .find(|s| s.user_agent.as_deref() == Some("Phone"))
+        .unwrap();
+    let response = app.get("/account/sessions", &session).await;
+    assert!(body_string(response).await.contains("Phone"));
+
+    let response = app
+        .post(
+            &format!("/account/sessions/{}/delete", phone.id),
+            &bob_session,
+            "",
+        )
+        .await;
+    assert_eq!(response.status(), StatusCode::FORBIDDEN);
+    let response = app
+        .post(
+            &format!("/account/sessions/{}/delete", phone.id),
+            &session,
+            "",
+        )
+        .await;
+    assert_eq!(response.status(), StatusCode::SEE_OTHER);
+    let response = app.get("/", &phone_session).await;
+    assert_eq!(location(&response), "/account/login");
+
+    let response = app
+        .post("/account/sessions/delete-others", &session, "")
+        .await;
+    assert_eq!(response.status(), StatusCode::SEE_OTHER);
+    let response = app.get("/", &third_session).await;
+    assert_eq!(location(&response), "/account/login");
+    assert_eq!(app.get("/", &session).await.status(), StatusCode::OK);
+    assert_eq!(app.get("/", &bob_session).await.status(), StatusCode::OK);
+}
@@ -0,0 +1,58 @@
+//! Throttling of failed logins. Failures are counted per username and per
+//! client IP. After a number of free failures each further one locks the
+//! key, for twice as long as the previous lock, up to [`MAX_LOCK_MINUTES`].
+//! Counters start over after a day without failures or after a successful
+//! login with the username.
+
+use chrono::Duration;
+
+/// Failed logins with a username before it gets locked.
+pub const USERNAME_FREE_FAILURES: i64 = 5;
+
+/// Failed logins from an IP before it gets locked. Higher than for
+/// usernames, as several users may share an IP.
+pub const IP_FREE_FAILURES: i64 = 20;
+
+/// Duration of the first lock.
+pub const FIRST_LOCK_SECONDS: i64 = 30;
+
+pub const MAX_LOCK_MINUTES: i64 = 60;
+
+/// Time without failures after which the counter of a key starts over.
+pub const RESET_HOURS: i64 = 24;
+
+pub fn username_key(username: &str) -> String {
+    format!("username:{}", username)
+}
+
+pub fn ip_key(ip: &str) -> String {
+    format!("ip:{}", ip)
+}
+
+/// How long a key is locked after its `failures`th failed login in a row.
+pub fn lock_duration(failures: i64, free_failures: i64) -> Option<Duration> {
+    if failures < free_failures {
+        return None;
+    }
+    let doublings = (failures - free_failures).min(16) as u32;
+    Some(
+        Duration::seconds(FIRST_LOCK_SECONDS * 2_i64.pow(doublings))
+            .min(Duration::minutes(MAX_LOCK_MINUTES)),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn lock_doubles_up_to_max() {
+        assert_eq!(lock_duration(0, 5), None);
+        assert_eq!(lock_duration(4, 5), None);
+        assert_eq!(lock_duration(5, 5), Some(Duration::seconds(30)));
+        assert_eq!(lock_duration(6, 5), Some(Duration::seconds(60)));
+        assert_eq!(lock_duration(9, 5), Some(Duration::seconds(480)));
+        assert_eq!(lock_duration(12, 5), Some(Duration::minutes(60)));
+        assert_eq!(lock_duration(1000, 5), Some(Duration::minutes(60)));
+    }
+}
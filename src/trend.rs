@@ -0,0 +1,305 @@
+//! Smoothing of the weight history. Daily weights fluctuate by a kilogram or
+//! more because of water and food, so the trend is an exponential moving
+//! average that reacts to a change over about a week.
+
+use chrono::NaiveDate;
+
+/// Number of days the moving average is based on.
+pub const TREND_DAYS: f64 = 7.0;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, serde::Deserialize)]
+pub enum TrendRange {
+    #[serde(rename = "30")]
+    Days30,
+    #[default]
+    #[serde(rename = "90")]
+    Days90,
+    #[serde(rename = "365")]
+    Days365,
+    #[serde(rename = "all")]
+    All,
+}
+
+impl TrendRange {
+    pub const ALL: [TrendRange; 4] = [
+        TrendRange::Days30,
+        TrendRange::Days90,
+        TrendRange::Days365,
+        TrendRange::All,
+    ];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            TrendRange::Days30 => "30",
+            TrendRange::Days90 => "90",
+            TrendRange::Days365 => "365",
+            TrendRange::All => "all",
+        }
+    }
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            TrendRange::Days30 => "30 days",
+            TrendRange::Days90 => "90 days",
+            TrendRange::Days365 => "1 year",
+            TrendRange::All => "All",
+        }
+    }
+
+    /// First day that is part of the range when it ends on `today`.
+    pub fn start(&self, today: NaiveDate) -> Option<NaiveDate> {
+        let days = match self {
+            TrendRange::Days30 => 30,
+            TrendRange::Days90 => 90,
+            TrendRange::Days365 => 365,
+            TrendRange::All => return None,
+        };
+        Some(today - chrono::Duration::days(days - 1))
+    }
+}
+
+/// Which weigh-in counts as the weight of a day that has several.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, serde::Deserialize, sqlx::Type)]
+#[serde(rename_all = "lowercase")]
+#[sqlx(rename_all = "lowercase")]
+pub enum WeightRule {
+    #[default]
+    First,
+    Last,
+    Lowest,
+    Average,
+}
+
+impl WeightRule {
+    pub const ALL: [WeightRule; 4] = [
+        WeightRule::First,
+        WeightRule::Last,
+        WeightRule::Lowest,
+        WeightRule::Average,
+    ];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            WeightRule::First => "first",
+            WeightRule::Last => "last",
+            WeightRule::Lowest => "lowest",
+            WeightRule::Average => "average",
+        }
+    }
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            WeightRule::First => "First of the day",
+            WeightRule::Last => "Last of the day",
+            WeightRule::Lowest => "Lowest of the day",
+            WeightRule::Average => "Average of the day",
+        }
+    }
+
+    /// Weight of a day from its weigh-ins, sorted by time. `None` if there
+    /// are none.
+    pub fn apply(&self, weights: &[f64]) -> Option<f64> {
+        match self {
+            WeightRule::First => weights.first().copied(),
+            WeightRule::Last => weights.last().copied(),
+            WeightRule::Lowest => weights.iter().copied().reduce(f64::min),
+            WeightRule::Average => {
+                (!weights.is_empty()).then(|| weights.iter().sum::<f64>() / weights.len() as f64)
+            }
+        }
+    }
+}
+
+/// Combines the weigh-ins, sorted by date and time, oldest first, into one
+/// weight per day.
+pub fn daily_weights(weights: &[(NaiveDate, f64)], rule: WeightRule) -> Vec<(NaiveDate, f64)> {
+    weights
+        .chunk_by(|a, b| a.0 == b.0)
+        .filter_map(|day| {
+            let values = day.iter().map(|(_, weight)| *weight).collect::<Vec<_>>();
+            Some((day[0].0, rule.apply(&values)?))
+        })
+        .collect()
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TrendPoint {
+    pub date: NaiveDate,
+    pub weight: f64,
+    pub trend: f64,
+}
+
+/// Computes the moving average for weights sorted by date, oldest first.
+///
+/// The smoothing factor `2 / (days + 1)` applies per day, so a gap of several
+/// days moves the trend as far as daily measurements of the same weight
+/// would have.
+pub fn exponential_moving_average(weights: &[(NaiveDate, f64)], days: f64) -> Vec<TrendPoint> {
+    let alpha = 2.0 / (days + 1.0);
+    let mut points: Vec<TrendPoint> = Vec::with_capacity(weights.len());
+
+    for &(date, weight) in weights {
+        let trend = match points.last() {
+            None => weight,
+            Some(prev) => {
+                let elapsed = (date - prev.date).num_days().max(0) as f64;
+                let factor = 1.0 - (1.0 - alpha).powf(elapsed);
+                prev.trend + factor * (weight - prev.trend)
+            }
+        };
+        points.push(TrendPoint {
+            date,
+            weight,
+            trend,
+        });
+    }
+
+    points
+}
+
+/// Change of the trend in kg per week, as the least-squares slope over all
+/// points. Returns `None` if the points don't span at least one day.
+pub fn weekly_rate(points: &[TrendPoint]) -> Option<f64> {
+    let first = points.first()?.date;
+    let xs = points
+        .iter()
+        .map(|p| (p.date - first).num_days() as f64)
+        .collect::<Vec<_>>();
+    let n = points.len() as f64;
+    let mean_x = xs.iter().sum::<f64>() / n;
+    let mean_y = points.iter().map(|p| p.trend).sum::<f64>() / n;
+
+    let mut covariance = 0.0;
+    let mut variance = 0.0;
+    for (x, point) in xs.iter().zip(points) {
+        covariance += (x - mean_x) * (point.trend - mean_y);
+        variance += (x - mean_x) * (x - mean_x);
+    }
+
+    if variance == 0.0 {
+        return None;
+    }
+
+    Some(covariance / variance * 7.0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn date(day: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(2024, 1, day).unwrap()
+    }
+
+    fn assert_close(actual: f64, expected: f64) {
+        assert!(
+            (actual - expected).abs() < 1e-9,
+            "expected {}, got {}",
+            expected,
+            actual
+        );
+    }
+
+    #[test]
+    fn empty_weights_have_no_trend() {
+        assert!(exponential_moving_average(&[], TREND_DAYS).is_empty());
+        assert_eq!(weekly_rate(&[]), None);
+    }
+
+    #[test]
+    fn trend_starts_at_first_weight() {
+        let points = exponential_moving_average(&[(date(1), 80.0)], TREND_DAYS);
+
+        assert_eq!(points.len(), 1);
+        assert_close(points[0].trend, 80.0);
+    }
+
+    #[test]
+    fn constant_weight_keeps_trend() {
+        let weights = (1..=10).map(|d| (date(d), 80.0)).collect::<Vec<_>>();
+        let points = exponential_moving_average(&weights, TREND_DAYS);
+
+        for point in &points {
+            assert_close(point.trend, 80.0);
+        }
+        assert_close(weekly_rate(&points).unwrap(), 0.0);
+    }
+
+    #[test]
+    fn trend_follows_a_step() {
+        let points = exponential_moving_average(&[(date(1), 80.0), (date(2), 84.0)], TREND_DAYS);
+
+        // alpha = 2 / (7 + 1) = 0.25
+        assert_close(points[1].trend, 81.0);
+        assert_close(points[1].weight, 84.0);
+    }
+
+    #[test]
+    fn gaps_count_as_missed_days() {
+        let daily = exponential_moving_average(
+            &[(date(1), 80.0), (date(2), 84.0), (date(3), 84.0)],
+            TREND_DAYS,
+        );
+        let gap = exponential_moving_average(&[(date(1), 80.0), (date(3), 84.0)], TREND_DAYS);
+
+        assert_close(gap[1].trend, daily[2].trend);
+    }
+
+    #[test]
+    fn same_day_weights_do_not_move_trend() {
+        let points = exponential_moving_average(&[(date(1), 80.0), (date(1), 84.0)], TREND_DAYS);
+
+        assert_close(points[1].trend, 80.0);
+    }
+
+    #[test]
+    fn weekly_rate_of_linear_trend() {
+        let points = (1..=15)
+            .map(|d| TrendPoint {
+                date: date(d),
+                weight: 0.0,
+                trend: 90.0 - 0.1 * d as f64,
+            })
+            .collect::<Vec<_>>();
+
+        assert_close(weekly_rate(&points).unwrap(), -0.7);
+    }
+
+    #[test]
+    fn weekly_rate_needs_more_than_one_day() {
+        let points = exponential_moving_average(&[(date(1), 80.0), (date(1), 81.0)], TREND_DAYS);
+
+        assert_eq!(weekly_rate(&points), None);
+    }
+
+    #[test]
+    fn daily_weights_by_rule() {
+        let weights = [
+            (date(1), 81.0),
+            (date(1), 80.0),
+            (date(1), 80.5),
+            (date(3), 79.0),
+        ];
+
+        assert_eq!(
+            daily_weights(&weights, WeightRule::First),
+            [(date(1), 81.0), (date(3), 79.0)]
+        );
+        assert_eq!(
+            daily_weights(&weights, WeightRule::Last),
+            [(date(1), 80.5), (date(3), 79.0)]
+        );
+        assert_eq!(
+            daily_weights(&weights, WeightRule::Lowest),
+            [(date(1), 80.0), (date(3), 79.0)]
+        );
+        assert_close(daily_weights(&weights, WeightRule::Average)[0].1, 80.5);
+        assert!(daily_weights(&[], WeightRule::Average).is_empty());
+    }
+
+    #[test]
+    fn range_start() {
+        assert_eq!(TrendRange::Days30.start(date(30)), Some(date(1)));
+        assert_eq!(TrendRange::All.start(date(30)), None);
+    }
+}